@@ -0,0 +1,278 @@
+//! Removing unreferenced lump entries
+//!
+//! Entries are only removed, never reordered, so every range like the surfedges of a face
+//! stays contiguous and only needs its first index remapped.
+use std::collections::HashMap;
+
+use crate::Bsp;
+
+/// Old index to new index of the entries kept.
+struct Remap(Vec<Option<usize>>);
+
+impl Remap {
+    fn new(used: &[bool]) -> Self {
+        let mut next = 0;
+
+        Self(
+            used.iter()
+                .map(|&used| {
+                    used.then(|| {
+                        next += 1;
+                        next - 1
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// New index of a kept entry, none if the index is out of range.
+    fn get(&self, idx: usize) -> Option<usize> {
+        self.0.get(idx).copied().flatten()
+    }
+
+    fn retain<T>(&self, items: &mut Vec<T>) {
+        let mut idx = 0;
+
+        items.retain(|_| {
+            idx += 1;
+            self.0[idx - 1].is_some()
+        });
+    }
+}
+
+// indices out of range are not marked and keep their value
+fn mark(used: &mut [bool], idx: usize) {
+    if let Some(used) = used.get_mut(idx) {
+        *used = true;
+    }
+}
+
+// name of the animated or random tiling texture without the frame prefix
+fn sequence_name(name: &str) -> Option<&str> {
+    if name.starts_with(['+', '-']) {
+        name.get(2..)
+    } else {
+        None
+    }
+}
+
+impl Bsp {
+    /// Removes planes, texinfo, textures, vertices, edges and surfedges that nothing refers to,
+    /// and lighting that no face uses.
+    ///
+    /// Frames of animated and random tiling textures are kept as long as one frame is used
+    /// because the engine finds them by name.
+    ///
+    /// Indices out of range are left as they are, see [`Bsp::validate`].
+    pub fn compact(&mut self) {
+        self.compact_planes();
+        self.compact_textures();
+        self.compact_geometry();
+        self.compact_lighting();
+    }
+
+    fn compact_planes(&mut self) {
+        let mut used = vec![false; self.planes.len()];
+
+        self.nodes
+            .iter()
+            .for_each(|node| mark(&mut used, node.plane as usize));
+        self.clipnodes
+            .iter()
+            .filter_map(|clipnode| usize::try_from(clipnode.plane).ok())
+            .for_each(|plane| mark(&mut used, plane));
+        self.faces
+            .iter()
+            .for_each(|face| mark(&mut used, face.plane as usize));
+
+        let remap = Remap::new(&used);
+
+        remap.retain(&mut self.planes);
+
+        self.nodes.iter_mut().for_each(|node| {
+            if let Some(plane) = remap.get(node.plane as usize) {
+                node.plane = plane as u32;
+            }
+        });
+        self.clipnodes.iter_mut().for_each(|clipnode| {
+            if let Some(plane) = usize::try_from(clipnode.plane)
+                .ok()
+                .and_then(|plane| remap.get(plane))
+            {
+                clipnode.plane = plane as i32;
+            }
+        });
+        self.faces.iter_mut().for_each(|face| {
+            if let Some(plane) = remap.get(face.plane as usize) {
+                face.plane = plane as u16;
+            }
+        });
+    }
+
+    fn compact_textures(&mut self) {
+        let mut used = vec![false; self.texinfo.len()];
+
+        self.faces
+            .iter()
+            .for_each(|face| mark(&mut used, face.texinfo as usize));
+
+        let remap = Remap::new(&used);
+
+        remap.retain(&mut self.texinfo);
+
+        self.faces.iter_mut().for_each(|face| {
+            if let Some(texinfo) = remap.get(face.texinfo as usize) {
+                face.texinfo = texinfo as u16;
+            }
+        });
+
+        let mut used = vec![false; self.textures.len()];
+
+        self.texinfo
+            .iter()
+            .for_each(|texinfo| mark(&mut used, texinfo.texture_index as usize));
+
+        let used_sequences = self
+            .textures
+            .iter()
+            .zip(&used)
+            .filter(|(_, &used)| used)
+            .filter_map(|(texture, _)| {
+                sequence_name(&texture.texture_name.get_string_standard()).map(str::to_string)
+            })
+            .collect::<Vec<_>>();
+
+        self.textures
+            .iter()
+            .zip(used.iter_mut())
+            .for_each(|(texture, used)| {
+                if sequence_name(&texture.texture_name.get_string_standard())
+                    .is_some_and(|name| used_sequences.iter().any(|used| used == name))
+                {
+                    *used = true;
+                }
+            });
+
+        let remap = Remap::new(&used);
+
+        remap.retain(&mut self.textures);
+
+        self.texinfo.iter_mut().for_each(|texinfo| {
+            if let Some(texture) = remap.get(texinfo.texture_index as usize) {
+                texinfo.texture_index = texture as u32;
+            }
+        });
+    }
+
+    fn compact_geometry(&mut self) {
+        let mut used = vec![false; self.surf_edges.len()];
+
+        self.faces.iter().for_each(|face| {
+            if let Some(surf_edges) = usize::try_from(face.first_edge)
+                .ok()
+                .and_then(|first| used.get_mut(first..first + face.edge_count as usize))
+            {
+                surf_edges.fill(true);
+            }
+        });
+
+        let remap = Remap::new(&used);
+
+        remap.retain(&mut self.surf_edges);
+
+        self.faces
+            .iter_mut()
+            .filter(|face| face.edge_count > 0)
+            .for_each(|face| {
+                if let Some(first) = usize::try_from(face.first_edge)
+                    .ok()
+                    .and_then(|first| remap.get(first))
+                {
+                    face.first_edge = first as i32;
+                }
+            });
+
+        let mut used = vec![false; self.edges.len()];
+
+        // edge 0 cannot be negated so it is never used but it has to stay first
+        if let Some(first) = used.first_mut() {
+            *first = true;
+        }
+
+        self.surf_edges
+            .iter()
+            .for_each(|&surf_edge| mark(&mut used, surf_edge.unsigned_abs() as usize));
+
+        let remap = Remap::new(&used);
+
+        remap.retain(&mut self.edges);
+
+        self.surf_edges.iter_mut().for_each(|surf_edge| {
+            if let Some(edge) = remap.get(surf_edge.unsigned_abs() as usize) {
+                let edge = edge as i32;
+
+                *surf_edge = if *surf_edge < 0 { -edge } else { edge };
+            }
+        });
+
+        let mut used = vec![false; self.vertices.len()];
+
+        self.edges.iter().flatten().for_each(|&vertex| {
+            mark(&mut used, vertex as usize);
+        });
+
+        let remap = Remap::new(&used);
+
+        remap.retain(&mut self.vertices);
+
+        self.edges.iter_mut().flatten().for_each(|vertex| {
+            if let Some(new_vertex) = remap.get(*vertex as usize) {
+                *vertex = new_vertex as u16;
+            }
+        });
+    }
+
+    fn compact_lighting(&mut self) {
+        let infos = (0..self.faces.len())
+            .map(|face_idx| self.face_lightmap_info(face_idx))
+            .collect::<Vec<_>>();
+
+        // lighting of a face cannot be measured so it is unknown what is unused
+        if self
+            .faces
+            .iter()
+            .zip(&infos)
+            .any(|(face, info)| face.lightmap_offset >= 0 && info.is_none())
+        {
+            return;
+        }
+
+        // same order as before so nothing moves if there is nothing to remove
+        let mut faces = infos
+            .into_iter()
+            .enumerate()
+            .filter_map(|(face_idx, info)| Some((face_idx, info?)))
+            .collect::<Vec<_>>();
+
+        faces.sort_by_key(|(_, info)| info.offset);
+
+        let mut lightmap = vec![];
+        // faces could share the same lightmap
+        let mut new_offsets = HashMap::new();
+
+        faces.into_iter().for_each(|(face_idx, info)| {
+            let offset = *new_offsets.entry(info.offset).or_insert_with(|| {
+                let offset = lightmap.len();
+                let range = info.offset..info.style_range(info.style_count - 1).end;
+
+                lightmap.extend_from_slice(&self.lightmap[range]);
+
+                offset
+            });
+
+            self.faces[face_idx].lightmap_offset = (offset * self.variant.luxel_size()) as i32;
+        });
+
+        self.lightmap = lightmap;
+    }
+}
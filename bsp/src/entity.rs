@@ -0,0 +1,219 @@
+//! Entities in the entity lump
+//!
+//! Key-value pairs are kept in the same order as in the lump and duplicate keys are not merged
+//! because entities like `multi_manager` read every pair in order.
+use std::{
+    ffi::OsStr,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::BspError,
+    parser::{parse_entities, parse_entities_str},
+    Bsp,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entity(Vec<(String, String)>);
+
+impl Entity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Value of the key.
+    ///
+    /// With duplicate keys, the last one is returned because that is the value the game ends up with.
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut String> {
+        self.0
+            .iter_mut()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Every value of the key in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.0.iter().filter(move |(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.iter().any(|(k, _)| k == key)
+    }
+
+    /// Sets the value of the key in place or appends the pair if the key does not exist.
+    ///
+    /// Returns the old value.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        let key = key.into();
+        let value = value.into();
+
+        match self.get_mut(&key) {
+            Some(old) => Some(std::mem::replace(old, value)),
+            None => {
+                self.0.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Appends the pair even if the key already exists.
+    pub fn push(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.push((key.into(), value.into()));
+    }
+
+    /// Removes every pair with the key.
+    ///
+    /// Returns the last value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let res = self.get(key).cloned();
+
+        self.0.retain(|(k, _)| k != key);
+
+        res
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut String)> {
+        self.0.iter_mut().map(|(k, v)| (&*k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(k, _)| k)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn pairs(&self) -> &[(String, String)] {
+        &self.0
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Entity {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+impl IntoIterator for Entity {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Entity {
+    type Item = (&'a String, &'a String);
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, String)>,
+        fn(&'a (String, String)) -> (&'a String, &'a String),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter().map(|(k, v)| (k, v))
+    }
+}
+
+// same format as the compilers, without the null at the end of the lump
+fn write_entities(entities: &[Entity]) -> String {
+    let mut res = String::new();
+
+    entities.iter().for_each(|entity| {
+        res += "{\n";
+
+        entity.iter().for_each(|(key, value)| {
+            res += format!("\"{}\" \"{}\"\n", key, value).as_str();
+        });
+
+        res += "}\n";
+    });
+
+    res
+}
+
+impl Bsp {
+    /// Entity lump as text, the same as a `.ent` file from ripent.
+    pub fn ent_string(&self) -> String {
+        write_entities(&self.entities)
+    }
+
+    /// Entity lump as it is written into the BSP, without the null at the end.
+    ///
+    /// Characters up to U+00FF are written as one byte, the same way the lump is parsed.
+    /// Anything above is written as UTF-8.
+    pub fn ent_bytes(&self) -> Vec<u8> {
+        let mut res = vec![];
+        let mut buf = [0u8; 4];
+
+        self.ent_string().chars().for_each(|c| {
+            if (c as u32) <= 0xff {
+                res.push(c as u8);
+            } else {
+                res.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        });
+
+        res
+    }
+
+    /// Replaces every entity with the ones in the text of a `.ent` file.
+    ///
+    /// Entities are left untouched if the text cannot be parsed.
+    pub fn set_ent_string(&mut self, s: &str) -> Result<(), BspError> {
+        self.entities =
+            parse_entities_str(s).map_err(|source| BspError::ParseEntities { source })?;
+
+        Ok(())
+    }
+
+    /// Writes the entity lump into a `.ent` file.
+    pub fn export_ent(&self, path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+
+        file.write_all(&self.ent_bytes())?;
+
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Replaces every entity with the ones from a `.ent` file.
+    pub fn import_ent(&mut self, path: impl AsRef<Path> + AsRef<OsStr>) -> Result<(), BspError> {
+        let path: &Path = path.as_ref();
+
+        let bytes = std::fs::read(path).map_err(|op| BspError::IOError {
+            source: op,
+            path: path.to_path_buf(),
+        })?;
+
+        self.entities =
+            parse_entities(&bytes).map_err(|source| BspError::ParseEntities { source })?;
+
+        Ok(())
+    }
+}
@@ -2,8 +2,27 @@ use std::path::PathBuf;
 
 #[derive(Debug, thiserror::Error)]
 pub enum BspEntitiesError {
-    #[error("Cannot parse all entities")]
-    Parse,
+    #[error("Cannot parse entities after byte {offset}")]
+    Parse { offset: usize },
+}
+
+/// Why a lump cannot be parsed.
+#[derive(Debug, thiserror::Error)]
+pub enum BspLumpError {
+    #[error("offset {offset} and length {length} are outside of the file of {file_size} bytes")]
+    OutOfBounds {
+        offset: i32,
+        length: i32,
+        file_size: usize,
+    },
+    #[error("length {length} is not a multiple of the entry size {entry_size}")]
+    Length { length: usize, entry_size: usize },
+    #[error("entry {index} has invalid values")]
+    InvalidEntry { index: usize },
+    #[error("texture count {count} is more than what the lump has")]
+    TextureCount { count: u32 },
+    #[error("texture {index} is truncated or has invalid offsets")]
+    InvalidTexture { index: usize },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -13,40 +32,26 @@ pub enum BspError {
         #[source]
         source: BspEntitiesError,
     },
-    #[error("Cannot parse planes")]
-    ParsePlanes,
-    #[error("Cannot parse textures")]
-    ParseTextures,
-    #[error("Cannot parse vertices")]
-    ParseVertices,
-    #[error("Cannot parse visibility")]
-    ParseVisibility,
-    #[error("Cannot parse nodes")]
-    ParseNodes,
-    #[error("Cannot parse texinfo")]
-    ParseTexInfo,
-    #[error("Cannot parse faces")]
-    ParseFaces,
-    #[error("Cannot parse lightmap")]
-    ParseLightmap,
-    #[error("Cannot parse clipnodes")]
-    ParseClipNodes,
-    #[error("Cannot parse leaves")]
-    ParseLeaves,
-    #[error("Cannot parse mark surfaces")]
-    ParseMarkSurfaces,
-    #[error("Cannot parse edges")]
-    ParseEdges,
-    #[error("Cannot parse surface edges")]
-    ParseSurfEdges,
-    #[error("Cannot parse models")]
-    ParseModels,
-    #[error("Failed to parse a lump section")]
-    LumpParseError, // Generic error for the `rest` call or unhandled parsing
-    #[error("Generic failture to parse with nom")]
-    NomParsingError,
-    #[error("Bsp version is not 30: {version}")]
+    #[error("Cannot parse {lump} lump at byte {offset}: {source}")]
+    ParseLump {
+        lump: &'static str,
+        /// From the start of the file.
+        offset: usize,
+        #[source]
+        source: BspLumpError,
+    },
+    #[error("File is too small for the header: {file_size} bytes")]
+    Header { file_size: usize },
+    #[error("Bsp version is not 29 or 30: {version}")]
     BspVersion { version: i32 },
+    #[error("Cannot find texture `{name}`")]
+    TextureNotFound { name: String },
+    #[error("Invalid texture name `{name}`: {reason}")]
+    InvalidTextureName { name: String, reason: String },
+    #[error("Face {face} would be {extents:?} luxels, more than the engine allows")]
+    SurfaceExtents { face: usize, extents: [i32; 2] },
+    #[error("Lighting lump would be {size} bytes, more than the {max} bytes allowed")]
+    LightingLimit { size: usize, max: usize },
     #[error("Cannot read file `{path}`: {source}")]
     IOError {
         #[source]
@@ -66,3 +71,9 @@ impl BspEntitiesError {
         Err(self)
     }
 }
+
+impl BspLumpError {
+    pub fn to_result<T>(self) -> Result<T, Self> {
+        Err(self)
+    }
+}
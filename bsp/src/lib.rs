@@ -1,12 +1,28 @@
-mod constants;
+mod compact;
+pub mod constants;
+mod entity;
 pub mod error;
+mod lightmap;
 mod parser;
+mod stats;
+mod texture;
+mod trace;
 mod types;
 mod utils;
+mod vis;
 mod writer;
 
+pub use entity::Entity;
+pub use lightmap::{
+    sample_luxel, FaceLightmapInfo, LIGHTMAP_SCALE, MAX_LIGHTMAPS, MAX_SURFACE_EXTENT,
+    NO_LIGHT_STYLE,
+};
 pub use parser::parse_bsp;
+pub use stats::{BspIssue, BspStats, LumpStats};
+pub use texture::{is_missing_texture, missing_texture};
+pub use trace::{TraceResult, HULL_CROUCHING, HULL_LARGE, HULL_POINT, HULL_SIZES, HULL_STANDING};
 pub use types::Bsp;
+pub use vis::{compress_vis_row, decompress_vis_row, Pvs};
 
 pub use types::*;
 
@@ -38,21 +54,16 @@ mod test {
 
         let res = bsp.write_to_bytes();
 
-        println!("file {} res {}", file.len(), res.len());
-
-        // assert_eq!(file, res.as_slice());
-        bsp.write_to_file("/home/khang/gchimp/bsp/src/tests/bsp_out.bsp")
-            .unwrap()
+        assert_eq!(file, res.as_slice());
     }
 
     #[test]
     fn parse_write2() {
         let file = include_bytes!("tests/normal.bsp");
         let bsp = Bsp::from_bytes(file).unwrap();
-        bsp.write_to_file("/home/khang/gchimp/bsp/src/tests/normal_out.bsp")
-            .unwrap();
+        let res = bsp.write_to_bytes();
 
-        // assert_eq!(file, res.as_slice());
+        assert_eq!(file, res.as_slice());
     }
 
     #[test]
@@ -83,6 +94,819 @@ mod test {
         let file = include_bytes!("tests/datacore.bsp");
         let bsp = Bsp::from_bytes(file).unwrap();
         let out_byte = bsp.write_to_bytes();
-        let _bsp = Bsp::from_bytes(&out_byte).unwrap();
+
+        assert_eq!(file, out_byte.as_slice());
+    }
+
+    #[test]
+    fn parse_write_c1a3d() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+        let out_byte = bsp.write_to_bytes();
+
+        assert_eq!(file, out_byte.as_slice());
+    }
+
+    #[test]
+    fn write_lump_order() {
+        // written in header order by an older version of the writer
+        let file = include_bytes!("tests/normal_out.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+        let out_byte = bsp.write_to_bytes();
+
+        let out_bsp = Bsp::from_bytes(&out_byte).unwrap();
+        assert_eq!(out_bsp.lump_order, bsp.lump_order);
+        assert_eq!(out_bsp.write_to_bytes(), out_byte);
+    }
+
+    #[test]
+    fn write_blue_shift() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+        bsp.variant = BspVariant::BlueShift;
+
+        let out_byte = bsp.write_to_bytes();
+
+        // entities and planes are swapped in the header
+        assert_eq!(out_byte[4..12], file[12..20]);
+        assert_eq!(out_byte[12..20], file[4..12]);
+
+        let blue_shift = Bsp::from_bytes(&out_byte).unwrap();
+        assert_eq!(blue_shift.variant, BspVariant::BlueShift);
+        assert_eq!(blue_shift.entities, bsp.entities);
+        assert_eq!(blue_shift.write_to_bytes(), out_byte);
+    }
+
+    #[test]
+    fn parse_write_blue_shift() {
+        // compiler output with the header laid out like Blue Shift, not made by our writer
+        let file = include_bytes!("tests/normal.bsp");
+        let mut blue_shift_file = file.to_vec();
+        blue_shift_file[4..12].copy_from_slice(&file[12..20]);
+        blue_shift_file[12..20].copy_from_slice(&file[4..12]);
+
+        let bsp = Bsp::from_bytes(&blue_shift_file).unwrap();
+        assert_eq!(bsp.variant, BspVariant::BlueShift);
+        assert_eq!(bsp.entities, Bsp::from_bytes(file).unwrap().entities);
+        assert_eq!(bsp.write_to_bytes(), blue_shift_file);
+    }
+
+    #[test]
+    fn write_quake() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+        bsp.variant = BspVariant::Quake;
+
+        // one byte for each luxel
+        bsp.lightmap
+            .iter_mut()
+            .for_each(|luxel| *luxel = [luxel[0]; 3]);
+        bsp.faces
+            .iter_mut()
+            .filter(|face| face.lightmap_offset > 0)
+            .for_each(|face| face.lightmap_offset /= 3);
+
+        let out_byte = bsp.write_to_bytes();
+        assert_eq!(i32::from_le_bytes(out_byte[..4].try_into().unwrap()), 29);
+
+        let quake = Bsp::from_bytes(&out_byte).unwrap();
+        assert_eq!(quake.variant, BspVariant::Quake);
+        assert_eq!(quake.lightmap, bsp.lightmap);
+        assert_eq!(quake.face_lightmap_info(0), bsp.face_lightmap_info(0));
+
+        // no palette after the mipmaps
+        let texture = &quake.textures[1];
+        assert!(texture.palette.get_bytes().is_empty());
+        assert_eq!(
+            texture.mip_images[3].data.get_bytes(),
+            bsp.textures[1].mip_images[3].data.get_bytes()
+        );
+
+        assert_eq!(quake.write_to_bytes(), out_byte);
+    }
+
+    #[test]
+    fn parse_write_quake() {
+        // BSP29 laid out by hand from the Quake format, not made by our writer
+        let entities = b"{\n\"classname\" \"worldspawn\"\n}\n\0".to_vec();
+
+        let mut textures = vec![];
+        textures.extend(1u32.to_le_bytes());
+        textures.extend(8i32.to_le_bytes());
+        textures.extend(b"wall\0\0\0\0\0\0\0\0\0\0\0\0");
+        textures.extend(16u32.to_le_bytes());
+        textures.extend(16u32.to_le_bytes());
+        [40u32, 296, 360, 376]
+            .iter()
+            .for_each(|offset| textures.extend(offset.to_le_bytes()));
+        // mipmaps without a palette
+        textures.extend((0..256 + 64 + 16 + 4).map(|i| i as u8));
+
+        // one byte per luxel
+        let lighting = vec![10u8, 20, 30, 40];
+
+        let mut file = 29i32.to_le_bytes().to_vec();
+        file.resize(4 + constants::HEADER_LUMPS * 8, 0);
+
+        constants::LUMP_ORDER.iter().for_each(|&lump| {
+            let data = match lump {
+                constants::LUMP_ENTITIES => entities.as_slice(),
+                constants::LUMP_TEXTURES => textures.as_slice(),
+                constants::LUMP_LIGHTING => lighting.as_slice(),
+                _ => &[],
+            };
+
+            file.resize(file.len().next_multiple_of(4), 0);
+
+            let header = 4 + lump * 8;
+            let offset = file.len() as i32;
+            file[header..header + 4].copy_from_slice(&offset.to_le_bytes());
+            file[header + 4..header + 8].copy_from_slice(&(data.len() as i32).to_le_bytes());
+            file.extend_from_slice(data);
+        });
+
+        let bsp = Bsp::from_bytes(&file).unwrap();
+        assert_eq!(bsp.variant, BspVariant::Quake);
+        assert_eq!(bsp.lightmap, vec![[10; 3], [20; 3], [30; 3], [40; 3]]);
+
+        let texture = &bsp.textures[0];
+        assert_eq!(texture.texture_name.get_string(), "wall");
+        assert!(texture.palette.get_bytes().is_empty());
+        assert_eq!(texture.mip_images[3].data.get_bytes(), &[80u8, 81, 82, 83]);
+
+        assert_eq!(bsp.write_to_bytes(), file);
+    }
+
+    #[test]
+    fn missing_texture_entry() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.textures[0] = missing_texture();
+
+        let out_bytes = bsp.write_to_bytes();
+
+        // first texture offset right after the texture count
+        let lump_offset = 4 + constants::LUMP_TEXTURES * 8;
+        let lump_start =
+            i32::from_le_bytes(out_bytes[lump_offset..lump_offset + 4].try_into().unwrap())
+                as usize;
+        let first_offset = &out_bytes[lump_start + 4..lump_start + 8];
+        assert_eq!(i32::from_le_bytes(first_offset.try_into().unwrap()), -1);
+
+        let bsp = Bsp::from_bytes(&out_bytes).unwrap();
+        assert!(is_missing_texture(&bsp.textures[0]));
+        assert!(!is_missing_texture(&bsp.textures[1]));
+        assert_eq!(bsp.write_to_bytes(), out_bytes);
+    }
+
+    #[test]
+    fn stats_c1a3d() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let stats = bsp.stats();
+        assert_eq!(stats.total_size, file.len());
+
+        let planes = stats
+            .lumps
+            .iter()
+            .find(|lump| lump.name == "planes")
+            .unwrap();
+        assert_eq!(planes.count, Some(bsp.planes.len()));
+        assert_eq!(planes.size, bsp.planes.len() * 20);
+        assert_eq!(planes.max_size, 32767 * 20);
+
+        let entdata = stats
+            .lumps
+            .iter()
+            .find(|lump| lump.name == "entdata")
+            .unwrap();
+        assert_eq!(entdata.size, 31557);
+
+        assert!(stats.to_string().contains("entdata"));
+        assert!(bsp.validate().is_empty());
+    }
+
+    #[test]
+    fn validate_broken_references() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.faces[0].texinfo = bsp.texinfo.len() as u16;
+        bsp.edges[1][0] = u16::MAX;
+        bsp.mark_surfaces.truncate(1);
+
+        let issues = bsp.validate();
+
+        assert!(issues.contains(&BspIssue::OutOfRange {
+            lump: "face",
+            index: 0,
+            field: "texinfo",
+            value: bsp.texinfo.len() as i64,
+            count: bsp.texinfo.len(),
+        }));
+        assert!(issues.iter().any(|issue| matches!(
+            issue,
+            BspIssue::OutOfRange {
+                lump: "edge",
+                index: 1,
+                ..
+            }
+        )));
+        assert!(issues.iter().any(|issue| matches!(
+            issue,
+            BspIssue::OutOfRange {
+                lump: "leaf",
+                field: "marksurface",
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn sample_luxel_edges() {
+        let luxels = [[0, 0, 0], [100, 100, 100], [200, 200, 200], [0, 0, 0]];
+
+        assert_eq!(sample_luxel(&luxels, 2, 0.5, 0.), [50.; 3]);
+        // clamped to the last luxel
+        assert_eq!(sample_luxel(&luxels, 2, 5., 5.), [0.; 3]);
+        assert_eq!(sample_luxel(&luxels, 2, 0., 1.), [200.; 3]);
+
+        assert_eq!(sample_luxel(&[], 2, 0., 0.), [0.; 3]);
+        assert_eq!(sample_luxel(&luxels, 0, 0., 0.), [0.; 3]);
+    }
+
+    #[test]
+    fn vis_row_compress() {
+        let row = [0b101, 0, 0, 0, 0xff, 0, 1];
+        let compressed = compress_vis_row(&row);
+
+        assert_eq!(compressed, vec![0b101, 0, 3, 0xff, 0, 1, 1]);
+        assert_eq!(decompress_vis_row(&compressed, row.len() * 8), row);
+    }
+
+    #[test]
+    fn pvs_c1a3d() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let pvs = bsp.pvs();
+
+        assert_eq!(
+            pvs.vis_leaf_count(),
+            bsp.models[0].vis_leaves_count as usize
+        );
+        assert!(pvs.row(0).is_none());
+
+        // every leaf that sees another leaf is usually seen by it
+        let leaf = (1..=pvs.vis_leaf_count())
+            .find(|&leaf| !pvs.visible_leaves(leaf).is_empty())
+            .unwrap();
+        let other = pvs.visible_leaves(leaf)[0];
+        assert!(pvs.can_see(other, leaf));
+
+        bsp.set_pvs(&pvs);
+        assert_eq!(bsp.pvs(), pvs);
+
+        // still parsable after writing the new lump
+        let bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+        assert_eq!(bsp.pvs(), pvs);
+    }
+
+    #[test]
+    fn pvs_edit() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let mut pvs = bsp.pvs();
+        let count = pvs.vis_leaf_count();
+
+        assert!(count >= 2);
+
+        pvs.set_mutual(1, count, false);
+        assert!(!pvs.can_see(1, count));
+        assert!(!pvs.can_see(count, 1));
+
+        pvs.set_visible_leaves(1, &[1, 2]);
+        assert_eq!(pvs.visible_leaves(1), vec![1, 2]);
+
+        bsp.set_pvs(&pvs);
+
+        let bsp = Bsp::from_bytes(&bsp.write_to_bytes()).unwrap();
+        assert_eq!(bsp.pvs(), pvs);
+    }
+
+    fn player_start(bsp: &Bsp) -> Vec3 {
+        let origin = bsp
+            .entities
+            .iter()
+            .find(|entity| {
+                entity
+                    .get("classname")
+                    .is_some_and(|classname| classname == "info_player_start")
+            })
+            .and_then(|entity| entity.get("origin"))
+            .unwrap();
+
+        let origin = origin
+            .split_ascii_whitespace()
+            .map(|v| v.parse::<f32>().unwrap())
+            .collect::<Vec<f32>>();
+
+        Vec3::from_slice(&origin)
+    }
+
+    #[test]
+    fn point_contents_c1a3d() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let start = player_start(&bsp);
+
+        assert!(matches!(
+            bsp.point_contents(HULL_STANDING, start),
+            LeafContent::ContentsEmpty
+        ));
+        assert!(matches!(
+            bsp.point_contents(HULL_POINT, start),
+            LeafContent::ContentsEmpty
+        ));
+        assert_ne!(bsp.leaf_at(start), 0);
+
+        // way outside of the map
+        let outside = Vec3::new(9999., 9999., 9999.);
+        assert!(matches!(
+            bsp.point_contents(HULL_STANDING, outside),
+            LeafContent::ContentsSolid
+        ));
+        assert_eq!(bsp.leaf_at(outside), 0);
+    }
+
+    #[test]
+    fn trace_c1a3d() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let start = player_start(&bsp);
+
+        // falls to the floor
+        let trace = bsp.trace(HULL_STANDING, start, start - Vec3::new(0., 0., 4096.));
+
+        assert!(trace.hit());
+        assert!(!trace.start_solid);
+        assert!(trace.plane_normal.z >= 0.7);
+        assert!(trace.end_pos.z < start.z);
+        assert!(bsp.is_on_ground(trace.end_pos, false));
+        assert!(!bsp.is_on_ground(trace.end_pos + Vec3::new(0., 0., 16.), false));
+
+        // trace that does not move hits nothing
+        let trace = bsp.trace(HULL_STANDING, start, start);
+        assert!(!trace.hit());
+        assert_eq!(trace.end_pos, start);
+
+        // starting from solid
+        let outside = Vec3::new(9999., 9999., 9999.);
+        let trace = bsp.trace(HULL_POINT, outside, outside + Vec3::X);
+        assert!(trace.all_solid);
+
+        // the line stops at the floor, same as the trace
+        let hit = bsp.test_line(start, start - Vec3::new(0., 0., 4096.));
+        assert!(matches!(hit, Some((LeafContent::ContentsSolid, _))));
+        assert!(bsp.test_line(start, start + Vec3::X).is_none());
+    }
+
+    #[test]
+    fn test_line_looping_tree() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let start = player_start(&bsp);
+        let head_node = bsp.models[0].head_nodes[HULL_POINT] as usize;
+
+        // the head node points back to itself
+        bsp.nodes[head_node].children = [head_node as i16; 2];
+
+        let hit = bsp.test_line(start, start - Vec3::new(0., 0., 4096.));
+        assert!(matches!(hit, Some((LeafContent::ContentsSolid, _))));
+    }
+
+    #[test]
+    fn trace_looping_tree() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let start = player_start(&bsp);
+        let head_node = bsp.models[0].head_nodes[HULL_STANDING] as usize;
+
+        // the head clipnode points back to itself
+        bsp.clipnodes[head_node].children = [head_node as i16; 2];
+
+        let trace = bsp.trace(HULL_STANDING, start, start - Vec3::new(0., 0., 4096.));
+        assert!(trace.start_solid);
+        assert!(trace.all_solid);
+    }
+
+    #[test]
+    fn lightmap_c1a3d() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let mut ranges = (0..bsp.faces.len())
+            .filter_map(|face_idx| bsp.face_lightmap_info(face_idx))
+            .map(|info| info.offset..info.style_range(info.style_count - 1).end)
+            .collect::<Vec<_>>();
+
+        assert!(!ranges.is_empty());
+
+        // every lightmap is packed back to back without overlapping
+        ranges.sort_by_key(|range| range.start);
+        ranges.dedup();
+
+        ranges.windows(2).for_each(|pair| {
+            assert_eq!(pair[0].end, pair[1].start);
+        });
+
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, bsp.lightmap.len());
+    }
+
+    #[test]
+    fn entity_order() {
+        let text = "{\n\"classname\" \"multi_manager\"\n\"targetname\" \"mm\"\n\"b\" \"1\"\n\"a\" \"0.5\"\n\"b\" \"2\"\n}\n";
+
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.set_ent_string(text).unwrap();
+
+        let entity = &bsp.entities[0];
+        assert_eq!(
+            entity.keys().collect::<Vec<_>>(),
+            ["classname", "targetname", "b", "a", "b"]
+        );
+        assert_eq!(entity.get("b").unwrap(), "2");
+        assert_eq!(entity.get_all("b").collect::<Vec<_>>(), ["1", "2"]);
+
+        assert_eq!(bsp.ent_string(), text);
+
+        // braces inside values are not entities
+        bsp.set_ent_string("{\n\"texture\" \"{blue\"\n}\n").unwrap();
+        assert_eq!(bsp.entities.len(), 1);
+        assert_eq!(bsp.entities[0].get("texture").unwrap(), "{blue");
+
+        assert!(bsp.set_ent_string("{\n\"classname\"").is_err());
+    }
+
+    #[test]
+    fn entity_lump_round_trip() {
+        // c1a3d has a non ASCII character in the title
+        for file in [
+            &include_bytes!("tests/c1a3d.bsp")[..],
+            &include_bytes!("tests/datacore.bsp")[..],
+            &include_bytes!("tests/normal.bsp")[..],
+        ] {
+            let bsp = Bsp::from_bytes(file).unwrap();
+
+            let offset = i32::from_le_bytes(file[4..8].try_into().unwrap()) as usize;
+            let length = i32::from_le_bytes(file[8..12].try_into().unwrap()) as usize;
+            let lump = &file[offset..offset + length];
+
+            let mut written = bsp.ent_bytes();
+            written.push(0);
+
+            assert_eq!(written, lump);
+        }
+    }
+
+    #[test]
+    fn unembed_embed_textures() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let removed = bsp.unembed_textures(&["SKY"], "normal.wad");
+
+        assert_eq!(removed.len(), 1);
+        assert!(bsp.textures[0].is_external());
+        assert!(!bsp.textures[1].is_external());
+        assert_eq!(bsp.wad_paths(), vec!["normal.wad"]);
+
+        let mut wad = wad::types::Wad::new();
+        wad.entries = removed
+            .into_iter()
+            .map(|miptex| wad::types::Entry {
+                directory_entry: wad::types::DirectoryEntry::new(miptex.texture_name.get_string()),
+                file_entry: wad::types::FileEntry::MipTex(miptex),
+            })
+            .collect();
+
+        assert_eq!(bsp.embed_textures(&[]), vec!["sky"]);
+        assert!(bsp.embed_textures(&[&wad]).is_empty());
+        assert!(bsp.wad_paths().is_empty());
+
+        assert_eq!(file, bsp.write_to_bytes().as_slice());
+    }
+
+    #[test]
+    fn wad_paths() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        assert_eq!(bsp.wad_paths().len(), 4);
+        assert_eq!(bsp.wad_paths()[1], "\\quiver\\valve\\decals.wad");
+
+        // nothing to embed, the key stays
+        assert_eq!(bsp.embed_textures(&[]).len(), bsp.textures.len());
+        assert_eq!(bsp.wad_paths().len(), 4);
+    }
+
+    #[test]
+    fn replace_texture_rescale() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let texinfo_idx = bsp
+            .texinfo
+            .iter()
+            .position(|texinfo| texinfo.texture_index == 1)
+            .unwrap();
+        let face_idx = bsp
+            .faces
+            .iter()
+            .position(|face| face.texinfo as usize == texinfo_idx)
+            .unwrap();
+
+        let old_u = bsp.texinfo[texinfo_idx].u;
+        let old_info = bsp.face_lightmap_info(face_idx).unwrap();
+        let old_corner = bsp.face_lightmap(face_idx, 0).unwrap()[0];
+
+        // twice as big
+        let images = [256 * 256, 128 * 128, 64 * 64, 32 * 32].map(|size| vec![0u8; size]);
+        let texture = wad::types::MipTex::new(
+            "dev_gray_hd",
+            (256, 256),
+            &images.each_ref().map(|image| image.as_slice()),
+            vec![[128u8; 3]; 256],
+        );
+
+        bsp.replace_texture("DEV_GRAY_10_128", texture).unwrap();
+
+        assert_eq!(bsp.textures[1].texture_name.get_string(), "dev_gray_hd");
+        assert_eq!(bsp.texinfo[texinfo_idx].u, old_u * 2.);
+
+        let new_info = bsp.face_lightmap_info(face_idx).unwrap();
+
+        assert!(new_info.width > old_info.width);
+        assert!(new_info.height > old_info.height);
+        assert_eq!(bsp.face_lightmap(face_idx, 0).unwrap()[0], old_corner);
+        assert!(bsp.validate().is_empty());
+
+        assert!(bsp
+            .replace_texture("missing", bsp.textures[0].clone())
+            .is_err());
+    }
+
+    #[test]
+    fn replace_texture_limits() {
+        let file = include_bytes!("tests/normal.bsp");
+        let original = Bsp::from_bytes(file).unwrap();
+
+        let texture = |size: u32| {
+            let images = [1, 2, 4, 8].map(|div| vec![0u8; (size / div * size / div) as usize]);
+
+            wad::types::MipTex::new(
+                "dev_gray_hd",
+                (size, size),
+                &images.each_ref().map(|image| image.as_slice()),
+                vec![[128u8; 3]; 256],
+            )
+        };
+
+        // faces would be too big for the engine
+        let mut bsp = original.clone();
+        assert!(matches!(
+            bsp.replace_texture("dev_gray_10_128", texture(2048)),
+            Err(error::BspError::SurfaceExtents { .. })
+        ));
+        assert!(bsp.write_to_bytes() == original.write_to_bytes());
+
+        let mut bsp = original.clone();
+        bsp.lightmap.resize(constants::MAX_MAP_LIGHTING / 3, [0; 3]);
+        let full = bsp.clone();
+        assert!(matches!(
+            bsp.replace_texture("dev_gray_10_128", texture(256)),
+            Err(error::BspError::LightingLimit { .. })
+        ));
+        assert!(bsp.write_to_bytes() == full.write_to_bytes());
+    }
+
+    #[test]
+    fn rename_texture() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        bsp.rename_texture("+0BUTTON1", "+0button2").unwrap();
+
+        assert_eq!(bsp.textures[0].texture_name.get_string(), "+0button2");
+        assert!(bsp
+            .rename_texture("+0button2", "way_too_long_name")
+            .is_err());
+        assert!(bsp.rename_texture("+0button1", "whatever").is_err());
+    }
+
+    #[test]
+    fn compact() {
+        let file = include_bytes!("tests/c1a3d.bsp");
+        let original = Bsp::from_bytes(file).unwrap();
+        let mut bsp = original.clone();
+
+        bsp.compact();
+
+        assert!(bsp.validate().is_empty());
+        assert!(bsp.planes.len() < original.planes.len());
+        assert!(bsp.textures.len() < original.textures.len());
+        // nothing is wasted in a freshly compiled map
+        assert_eq!(bsp.lightmap, original.lightmap);
+
+        // animated frames are kept even without texinfo
+        assert!(bsp
+            .textures
+            .iter()
+            .any(|texture| texture.texture_name.get_string() == "+abutton1"));
+
+        (0..bsp.faces.len()).for_each(|face_idx| {
+            assert_eq!(
+                bsp.face_vertices(face_idx),
+                original.face_vertices(face_idx)
+            );
+
+            let texture_name = |bsp: &Bsp| {
+                let texinfo = &bsp.texinfo[bsp.faces[face_idx].texinfo as usize];
+                bsp.textures[texinfo.texture_index as usize]
+                    .texture_name
+                    .get_string()
+            };
+
+            assert_eq!(texture_name(&bsp), texture_name(&original));
+            assert_eq!(
+                bsp.face_lightmap(face_idx, 0),
+                original.face_lightmap(face_idx, 0)
+            );
+        });
+
+        // same thing again does nothing
+        let compacted = bsp.write_to_bytes();
+        bsp.compact();
+        assert_eq!(compacted, bsp.write_to_bytes());
+    }
+
+    #[test]
+    fn compact_broken_references() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        assert!(bsp.faces[2].edge_count > 1);
+
+        bsp.faces[0].plane = bsp.planes.len() as u16;
+        bsp.faces[1].texinfo = u16::MAX;
+        // the last surfedge is still in range
+        bsp.faces[2].first_edge = -1;
+        bsp.edges[1][0] = u16::MAX;
+        bsp.surf_edges[0] = i32::MAX;
+
+        assert!(bsp.validate().contains(&BspIssue::OutOfRange {
+            lump: "face",
+            index: 2,
+            field: "first surfedge",
+            value: -1,
+            count: bsp.surf_edges.len(),
+        }));
+
+        bsp.compact();
+
+        assert_eq!(bsp.faces[2].first_edge, -1);
+        assert_eq!(bsp.faces[1].texinfo, u16::MAX);
+    }
+
+    #[test]
+    fn compact_over_limit() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let plane_count = bsp.planes.len();
+        let unused = bsp.planes[0].clone();
+
+        bsp.planes.resize(constants::MAX_MAP_PLANES + 1, unused);
+
+        let issues = bsp.validate();
+        assert!(!issues.is_empty());
+        assert!(issues
+            .iter()
+            .all(|issue| matches!(issue, BspIssue::OverLimit { name: "planes", .. })));
+
+        bsp.compact();
+
+        assert!(bsp.planes.len() <= plane_count);
+        assert!(bsp.validate().is_empty());
+    }
+
+    #[test]
+    fn compact_replaced_lightmap() {
+        let file = include_bytes!("tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let images = [64 * 64, 32 * 32, 16 * 16, 8 * 8].map(|size| vec![0u8; size]);
+        let texture = wad::types::MipTex::new(
+            "dev_gray_lo",
+            (64, 64),
+            &images.each_ref().map(|image| image.as_slice()),
+            vec![[128u8; 3]; 256],
+        );
+
+        let original_len = bsp.lightmap.len();
+
+        bsp.replace_texture("dev_gray_10_128", texture).unwrap();
+
+        // smaller lightmaps go where the old ones were
+        let replaced_len = bsp.lightmap.len();
+        assert_eq!(replaced_len, original_len);
+
+        bsp.compact();
+
+        assert!(bsp.lightmap.len() < replaced_len);
+        assert!(bsp.validate().is_empty());
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let file = include_bytes!("tests/normal.bsp");
+
+        assert!(matches!(
+            parse_bsp(&file[..20]),
+            Err(error::BspError::Header { file_size: 20 })
+        ));
+
+        // the last lump is cut short
+        let err = parse_bsp(&file[..file.len() - 1]).unwrap_err();
+        assert!(matches!(
+            err,
+            error::BspError::ParseLump {
+                source: error::BspLumpError::OutOfBounds { .. },
+                ..
+            }
+        ));
+        assert!(err
+            .to_string()
+            .starts_with("Cannot parse textures lump at byte"));
+
+        (0..file.len()).for_each(|len| {
+            assert!(parse_bsp(&file[..len]).is_err());
+        });
+    }
+
+    #[test]
+    fn parse_fuzz() {
+        let file = include_bytes!("tests/normal.bsp");
+
+        // xorshift so every run is the same
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        let interesting = [0, 1, 3, -1, i32::MIN, i32::MAX, file.len() as i32];
+
+        // lump headers and the start of every lump, which has the texture offsets and miptex headers
+        let targets = (4..4 + constants::HEADER_LUMPS * 8)
+            .chain((0..constants::HEADER_LUMPS).flat_map(|lump| {
+                let offset =
+                    i32::from_le_bytes(file[4 + lump * 8..8 + lump * 8].try_into().unwrap());
+                offset as usize..(offset as usize + 64).min(file.len())
+            }))
+            .collect::<Vec<_>>();
+
+        (0..2000).for_each(|_| {
+            let mut file = file.to_vec();
+
+            (0..1 + next() % 4).for_each(|_| {
+                let target = targets[next() as usize % targets.len()] & !3;
+
+                let value = if next() % 2 == 0 {
+                    interesting[next() as usize % interesting.len()]
+                } else {
+                    next() as i32
+                };
+
+                if target + 4 <= file.len() {
+                    file[target..target + 4].copy_from_slice(&value.to_le_bytes());
+                }
+            });
+
+            // only must not panic
+            let _ = parse_bsp(&file);
+        });
     }
 }
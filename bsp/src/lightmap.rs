@@ -0,0 +1,210 @@
+//! Lightmap of each face
+//!
+//! A face has one lightmap for every light style it uses. The lightmaps of a face are next to each other in the lighting lump
+//! and each of them is a grid of luxels where one luxel covers 16 texels.
+use crate::{Bsp, Vec3};
+
+/// Texels covered by one luxel.
+pub const LIGHTMAP_SCALE: i32 = 16;
+/// Light styles a face can have.
+pub const MAX_LIGHTMAPS: usize = 4;
+/// Unused style slot.
+pub const NO_LIGHT_STYLE: u8 = 255;
+/// Luxels between the mins and maxs of a lit face, same as `MAX_SURFACE_EXTENT` in the compilers.
+///
+/// The engine stops with "Bad surface extents" on faces going over it.
+pub const MAX_SURFACE_EXTENT: i32 = 16;
+
+/// Where the lightmaps of a face are and how big they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceLightmapInfo {
+    /// Texture space mins in luxels.
+    pub mins: [i32; 2],
+    /// Luxels along the texture S axis.
+    pub width: usize,
+    /// Luxels along the texture T axis.
+    pub height: usize,
+    /// Number of styles in use, which is also the number of lightmaps.
+    pub style_count: usize,
+    /// Index of the first luxel in [`Bsp::lightmap`].
+    ///
+    /// The lump offset in the face is in bytes while this is in luxels.
+    /// One luxel is 3 bytes except for Quake.
+    pub offset: usize,
+}
+
+impl FaceLightmapInfo {
+    /// Luxels in one lightmap.
+    pub fn luxel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Luxel range in [`Bsp::lightmap`] of the lightmap at the style slot.
+    pub fn style_range(&self, style_slot: usize) -> std::ops::Range<usize> {
+        let start = self.offset + style_slot * self.luxel_count();
+
+        start..start + self.luxel_count()
+    }
+}
+
+/// Bilinear sample of a lightmap at luxel coordinates, clamped to the edges.
+///
+/// An empty lightmap is black.
+pub fn sample_luxel(luxels: &[[u8; 3]], width: usize, s: f64, t: f64) -> [f64; 3] {
+    if width == 0 || luxels.len() < width {
+        return [0.; 3];
+    }
+
+    let height = luxels.len() / width;
+
+    let s = s.clamp(0., (width - 1) as f64);
+    let t = t.clamp(0., (height - 1) as f64);
+
+    let (x0, y0) = (s.floor() as usize, t.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fs, ft) = (s - x0 as f64, t - y0 as f64);
+
+    let luxel = |x: usize, y: usize, channel: usize| luxels[y * width + x][channel] as f64;
+
+    std::array::from_fn(|channel| {
+        let top = luxel(x0, y0, channel) * (1. - fs) + luxel(x1, y0, channel) * fs;
+        let bottom = luxel(x0, y1, channel) * (1. - fs) + luxel(x1, y1, channel) * fs;
+
+        top * (1. - ft) + bottom * ft
+    })
+}
+
+impl Bsp {
+    /// Vertices of the face in winding order.
+    ///
+    /// Invalid edges are skipped.
+    pub fn face_vertices(&self, face_idx: usize) -> Vec<Vec3> {
+        let Some(face) = self.faces.get(face_idx) else {
+            return vec![];
+        };
+
+        (0..face.edge_count as usize)
+            .filter_map(|idx| {
+                let surf_edge = *self
+                    .surf_edges
+                    .get((face.first_edge as usize).checked_add(idx)?)?;
+
+                let vertex = if surf_edge >= 0 {
+                    self.edges.get(surf_edge as usize)?[0]
+                } else {
+                    self.edges.get(surf_edge.unsigned_abs() as usize)?[1]
+                };
+
+                self.vertices.get(vertex as usize).copied()
+            })
+            .collect()
+    }
+
+    /// Normal of the plane of the face, flipped when the face is on the back of the plane.
+    pub fn face_normal(&self, face_idx: usize) -> Option<Vec3> {
+        let face = self.faces.get(face_idx)?;
+        let normal = self.planes.get(face.plane as usize)?.normal;
+
+        Some(if face.side == 0 { normal } else { -normal })
+    }
+
+    /// Texture space bounds of the face in luxels, same as `CalcSurfaceExtents`.
+    ///
+    /// Returns the mins and maxs.
+    pub fn face_extents(&self, face_idx: usize) -> Option<([i32; 2], [i32; 2])> {
+        let face = self.faces.get(face_idx)?;
+        let texinfo = self.texinfo.get(face.texinfo as usize)?;
+
+        let vertices = self.face_vertices(face_idx);
+
+        if vertices.is_empty() {
+            return None;
+        }
+
+        // doubles like the compiler, floats would shift the bounds by one luxel on some faces
+        let axes = [
+            (texinfo.u.as_dvec3(), texinfo.u_offset as f64),
+            (texinfo.v.as_dvec3(), texinfo.v_offset as f64),
+        ];
+
+        let mut mins = [0i32; 2];
+        let mut maxs = [0i32; 2];
+
+        for (idx, (axis, offset)) in axes.iter().enumerate() {
+            let values = vertices
+                .iter()
+                .map(|vertex| vertex.as_dvec3().dot(*axis) + offset);
+
+            let min = values.clone().fold(f64::INFINITY, f64::min);
+            let max = values.fold(f64::NEG_INFINITY, f64::max);
+
+            mins[idx] = (min / LIGHTMAP_SCALE as f64).floor() as i32;
+            maxs[idx] = (max / LIGHTMAP_SCALE as f64).ceil() as i32;
+        }
+
+        Some((mins, maxs))
+    }
+
+    /// Lightmap layout of the face.
+    ///
+    /// Returns `None` for faces without lightmap like sky or when the lightmap does not fit in the lump.
+    pub fn face_lightmap_info(&self, face_idx: usize) -> Option<FaceLightmapInfo> {
+        let face = self.faces.get(face_idx)?;
+
+        if face.lightmap_offset < 0 {
+            return None;
+        }
+
+        let style_count = face
+            .styles
+            .iter()
+            .take_while(|&&style| style != NO_LIGHT_STYLE)
+            .count();
+
+        if style_count == 0 {
+            return None;
+        }
+
+        let (mins, maxs) = self.face_extents(face_idx)?;
+
+        let res = FaceLightmapInfo {
+            mins,
+            width: (maxs[0] - mins[0]) as usize + 1,
+            height: (maxs[1] - mins[1]) as usize + 1,
+            style_count,
+            offset: face.lightmap_offset as usize / self.variant.luxel_size(),
+        };
+
+        if res.style_range(style_count - 1).end > self.lightmap.len() {
+            return None;
+        }
+
+        Some(res)
+    }
+
+    /// Luxels of the face lightmap at the style slot, row by row.
+    pub fn face_lightmap(&self, face_idx: usize, style_slot: usize) -> Option<&[[u8; 3]]> {
+        let info = self.face_lightmap_info(face_idx)?;
+
+        if style_slot >= info.style_count {
+            return None;
+        }
+
+        Some(&self.lightmap[info.style_range(style_slot)])
+    }
+
+    /// Mutable luxels of the face lightmap at the style slot, row by row.
+    pub fn face_lightmap_mut(
+        &mut self,
+        face_idx: usize,
+        style_slot: usize,
+    ) -> Option<&mut [[u8; 3]]> {
+        let info = self.face_lightmap_info(face_idx)?;
+
+        if style_slot >= info.style_count {
+            return None;
+        }
+
+        Some(&mut self.lightmap[info.style_range(style_slot)])
+    }
+}
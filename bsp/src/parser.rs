@@ -1,25 +1,32 @@
 use glam::Vec3;
 use nom::{
+    bytes::complete::tag,
     character::complete::multispace0,
-    combinator::{all_consuming, map},
+    combinator::{fail, map, map_res},
     multi::{count, many0},
     number::complete::{le_f32, le_i16, le_i32, le_u16, le_u32, le_u8},
-    sequence::{delimited, tuple},
+    sequence::{delimited, preceded, tuple},
+};
+use wad::{
+    parse_miptex,
+    types::{MipMap, MipTex, Palette, TextureName},
 };
-use wad::parse_miptex;
 
 use crate::{
     constants::{
         BSP_VERSION, HEADER_LUMPS, LUMP_CLIPNODES, LUMP_EDGES, LUMP_ENTITIES, LUMP_FACES,
-        LUMP_LEAVES, LUMP_LIGHTING, LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NODES, LUMP_PLANES,
-        LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY, MAX_MAP_HULLS,
+        LUMP_LEAVES, LUMP_LIGHTING, LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NAMES, LUMP_NODES,
+        LUMP_ORDER, LUMP_PLANES, LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES,
+        LUMP_VISIBILITY, MAX_MAP_HULLS, QUAKE_BSP_VERSION,
     },
-    error::{BspEntitiesError, BspError},
+    entity::Entity,
+    error::{BspEntitiesError, BspError, BspLumpError},
+    texture::missing_texture,
     types::{
-        Bsp, ClipNode, Edge, Entity, Face, IResult, Leaf, LightMap, LumpHeader, MarkSurface, Model,
-        Node, Plane, SResult, SurfEdge, TexInfo, Texture, Vertex,
+        Bsp, BspVariant, ClipNode, Edge, Face, IResult, Leaf, LightMap, LumpHeader, MarkSurface,
+        Model, Node, Plane, SResult, SurfEdge, TexInfo, Texture, Vertex,
     },
-    utils::{between_braces, quoted_text},
+    utils::quoted_text,
 };
 
 fn parse_lump_header(i: &[u8]) -> IResult<LumpHeader> {
@@ -32,64 +39,146 @@ fn parse_lump_header(i: &[u8]) -> IResult<LumpHeader> {
 // parse_entity takes in &str, not &[u8]
 // this is to make things more convenient to parse
 fn parse_entity(i: &str) -> SResult<Entity> {
-    let mut res = Entity::new();
-
     let parser = |i| delimited(multispace0, quoted_text, multispace0)(i);
 
-    let (i, list) = all_consuming(many0(tuple((parser, parser))))(i)?;
+    map(
+        delimited(
+            tuple((multispace0, tag("{"))),
+            many0(tuple((parser, parser))),
+            tuple((multispace0, tag("}"), multispace0)),
+        ),
+        |pairs| pairs.into_iter().collect(),
+    )(i)
+}
 
-    list.into_iter().for_each(|(key, value)| {
-        res.insert(key.to_string(), value.to_string());
-    });
+pub(crate) fn parse_entities_str(s: &str) -> Result<Vec<Entity>, BspEntitiesError> {
+    // the lump ends with a null
+    let s = s.trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
 
-    Ok((i, res))
-}
+    let (rest, res) = preceded(multispace0, many0(parse_entity))(s)
+        .map_err(|_: nom::Err<nom::error::Error<&str>>| BspEntitiesError::Parse { offset: 0 })?;
 
-// hacky stuffs to avoid parsing bytes :DD
-fn parse_entities(i: &[u8]) -> Result<Vec<Entity>, BspEntitiesError> {
-    // HOLY FUCKING RETARD
-    let s = String::from_utf8_lossy(i).replace(std::char::REPLACEMENT_CHARACTER, "");
+    if !rest.is_empty() {
+        // one char is one byte in the lump
+        let offset = s[..s.len() - rest.len()].chars().count();
 
-    let (_, res) =
-        many0(between_braces(parse_entity))(s.as_str()).map_err(|_| BspEntitiesError::Parse)?;
+        return BspEntitiesError::Parse { offset }.to_result();
+    }
 
     Ok(res)
 }
 
+// The lump is usually ASCII but some maps have text in their own code page.
+// Every byte becomes the char of the same value so writing it back gives the same bytes.
+pub(crate) fn parse_entities(i: &[u8]) -> Result<Vec<Entity>, BspEntitiesError> {
+    parse_entities_str(&i.iter().map(|&c| c as char).collect::<String>())
+}
+
 fn parse_plane(i: &[u8]) -> IResult<Plane> {
-    map(
+    map_res(
         tuple((le_f32, le_f32, le_f32, le_f32, le_i32)),
-        |(x, y, z, distance, type_)| Plane {
-            normal: Vec3::new(x, y, z),
-            distance,
-            type_: type_.try_into().unwrap(),
+        |(x, y, z, distance, type_)| {
+            Ok::<_, &str>(Plane {
+                normal: Vec3::new(x, y, z),
+                distance,
+                type_: type_.try_into()?,
+            })
         },
     )(i)
 }
 
-fn parse_planes(i: &[u8]) -> IResult<Vec<Plane>> {
-    all_consuming(many0(parse_plane))(i)
-}
+// Quake textures use the palette of the game so there is nothing after the mipmaps
+fn parse_quake_miptex(i: &[u8]) -> IResult<MipTex> {
+    let struct_start = i;
+
+    let (i, texture_name) = count(le_u8, 16)(i)?;
+    let (i, (width, height)) = tuple((le_u32, le_u32))(i)?;
+    let (i, mip_offsets) = count(le_u32, 4)(i)?;
+
+    let mut mip_images = vec![];
+
+    if mip_offsets[0] != 0 {
+        let Some(mip0_len) = width.checked_mul(height) else {
+            return fail(i);
+        };
 
-fn parse_textures(i: &[u8]) -> IResult<Vec<Texture>> {
-    let (header, tex_count) = le_u32(i)?;
-    let (_, offsets) = count(le_i32, tex_count as usize)(header)?;
+        for (level, &offset) in mip_offsets.iter().enumerate() {
+            let Some(mip_start) = struct_start.get(offset as usize..) else {
+                return fail(i);
+            };
+            let (_, data) = count(le_u8, mip0_len as usize >> (level * 2))(mip_start)?;
 
-    let mut miptexes: Vec<Texture> = vec![];
+            mip_images.push(MipMap::new(data));
+        }
+    }
 
-    for offset in offsets {
-        let (_, res) = parse_miptex(&i[(offset as usize)..])?;
+    Ok((
+        i,
+        MipTex {
+            texture_name: TextureName(texture_name),
+            width,
+            height,
+            mip_offsets,
+            mip_images,
+            colors_used: 0,
+            palette: Palette(vec![]),
+        },
+    ))
+}
 
-        miptexes.push(res);
+// offset of the error is from the start of the lump
+fn parse_textures(i: &[u8], variant: BspVariant) -> Result<Vec<Texture>, (usize, BspLumpError)> {
+    // empty lump has no texture count
+    if i.is_empty() {
+        return Ok(vec![]);
     }
 
-    Ok((&[], miptexes))
+    let (header, tex_count) = le_u32(i).map_err(|_: FUCKOFF| {
+        (
+            0,
+            BspLumpError::Length {
+                length: i.len(),
+                entry_size: 4,
+            },
+        )
+    })?;
+    let (_, offsets) = count(le_i32, tex_count as usize)(header)
+        .map_err(|_: FUCKOFF| (0, BspLumpError::TextureCount { count: tex_count }))?;
+
+    offsets
+        .into_iter()
+        .enumerate()
+        .map(|(index, offset)| {
+            let invalid = || {
+                (
+                    offset.max(0) as usize,
+                    BspLumpError::InvalidTexture { index },
+                )
+            };
+
+            if offset == -1 {
+                return Ok(missing_texture());
+            }
+
+            let miptex_bytes = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| i.get(offset..))
+                .ok_or_else(invalid)?;
+
+            let res = match variant {
+                BspVariant::Quake => parse_quake_miptex(miptex_bytes),
+                BspVariant::GoldSrc | BspVariant::BlueShift => parse_miptex(miptex_bytes),
+            };
+
+            res.map(|(_, miptex)| miptex).map_err(|_| invalid())
+        })
+        .collect()
 }
 
-fn parse_vertices(i: &[u8]) -> IResult<Vec<Vertex>> {
-    all_consuming(many0(map(tuple((le_f32, le_f32, le_f32)), |(x, y, z)| {
+fn parse_vertex(i: &[u8]) -> IResult<Vertex> {
+    map(tuple((le_f32, le_f32, le_f32)), |(x, y, z)| {
         Vec3::new(x, y, z)
-    })))(i)
+    })(i)
 }
 
 fn parse_node(i: &[u8]) -> IResult<Node> {
@@ -114,10 +203,6 @@ fn parse_node(i: &[u8]) -> IResult<Node> {
     )(i)
 }
 
-fn parse_nodes(i: &[u8]) -> IResult<Vec<Node>> {
-    all_consuming(many0(parse_node))(i)
-}
-
 fn parse_texinfo_singular(i: &[u8]) -> IResult<TexInfo> {
     map(
         tuple((
@@ -139,10 +224,6 @@ fn parse_texinfo_singular(i: &[u8]) -> IResult<TexInfo> {
     )(i)
 }
 
-fn parse_texinfo(i: &[u8]) -> IResult<Vec<TexInfo>> {
-    all_consuming(many0(parse_texinfo_singular))(i)
-}
-
 fn parse_face(i: &[u8]) -> IResult<Face> {
     map(
         tuple((
@@ -166,19 +247,28 @@ fn parse_face(i: &[u8]) -> IResult<Face> {
     )(i)
 }
 
-fn parse_faces(i: &[u8]) -> IResult<Vec<Face>> {
-    all_consuming(many0(parse_face))(i)
-}
-
-fn parse_lightmap(i: &[u8]) -> IResult<LightMap> {
+fn parse_lightmap(i: &[u8], variant: BspVariant) -> Result<LightMap, BspLumpError> {
     // map with zero lightmap will have lump with size of 1
     if i.len() == 1 {
-        return Ok((&[], vec![]));
+        return Ok(vec![]);
+    }
+
+    // Quake lighting is white so one byte for each luxel
+    if variant == BspVariant::Quake {
+        return Ok(i.iter().map(|&v| [v, v, v]).collect());
+    }
+
+    if !i.len().is_multiple_of(3) {
+        return BspLumpError::Length {
+            length: i.len(),
+            entry_size: 3,
+        }
+        .to_result();
     }
 
-    all_consuming(many0(map(count(le_u8, 3), |lightmap| {
-        [lightmap[0], lightmap[1], lightmap[2]]
-    })))(i)
+    Ok(i.chunks_exact(3)
+        .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+        .collect())
 }
 
 fn parse_clipnode(i: &[u8]) -> IResult<ClipNode> {
@@ -191,12 +281,8 @@ fn parse_clipnode(i: &[u8]) -> IResult<ClipNode> {
     )(i)
 }
 
-fn parse_clipnodes(i: &[u8]) -> IResult<Vec<ClipNode>> {
-    all_consuming(many0(parse_clipnode))(i)
-}
-
 fn parse_leaf(i: &[u8]) -> IResult<Leaf> {
-    map(
+    map_res(
         tuple((
             le_i32,
             le_i32,
@@ -214,37 +300,35 @@ fn parse_leaf(i: &[u8]) -> IResult<Leaf> {
             first_mark_surface,
             mark_surface_count,
             ambient_levels,
-        )| Leaf {
-            contents: contents.try_into().unwrap(),
-            vis_offset,
-            mins: [mins[0], mins[1], mins[2]],
-            maxs: [maxs[0], maxs[1], maxs[2]],
-            first_mark_surface,
-            mark_surface_count,
-            ambient_levels: [
-                ambient_levels[0],
-                ambient_levels[1],
-                ambient_levels[2],
-                ambient_levels[3],
-            ],
+        )| {
+            Ok::<_, &str>(Leaf {
+                contents: contents.try_into()?,
+                vis_offset,
+                mins: [mins[0], mins[1], mins[2]],
+                maxs: [maxs[0], maxs[1], maxs[2]],
+                first_mark_surface,
+                mark_surface_count,
+                ambient_levels: [
+                    ambient_levels[0],
+                    ambient_levels[1],
+                    ambient_levels[2],
+                    ambient_levels[3],
+                ],
+            })
         },
     )(i)
 }
 
-fn parse_leaves(i: &[u8]) -> IResult<Vec<Leaf>> {
-    all_consuming(many0(parse_leaf))(i)
-}
-
-fn parse_mark_surfaces(i: &[u8]) -> IResult<Vec<MarkSurface>> {
-    all_consuming(many0(le_u16))(i)
+fn parse_mark_surface(i: &[u8]) -> IResult<MarkSurface> {
+    le_u16(i)
 }
 
-fn parse_edges(i: &[u8]) -> IResult<Vec<Edge>> {
-    all_consuming(many0(map(tuple((le_u16, le_u16)), |(p1, p2)| [p1, p2])))(i)
+fn parse_edge(i: &[u8]) -> IResult<Edge> {
+    map(tuple((le_u16, le_u16)), |(p1, p2)| [p1, p2])(i)
 }
 
-fn parse_surf_edges(i: &[u8]) -> IResult<Vec<SurfEdge>> {
-    all_consuming(many0(le_i32))(i)
+fn parse_surf_edge(i: &[u8]) -> IResult<SurfEdge> {
+    le_i32(i)
 }
 
 fn parse_model(i: &[u8]) -> IResult<Model> {
@@ -270,52 +354,134 @@ fn parse_model(i: &[u8]) -> IResult<Model> {
     )(i)
 }
 
-fn parse_models(i: &[u8]) -> IResult<Vec<Model>> {
-    all_consuming(many0(parse_model))(i)
+// Blue Shift swaps the entity and plane lump headers, the entity lump always starts with a brace
+fn is_blue_shift(i: &[u8], lumps: &[LumpHeader]) -> bool {
+    let starts_with_brace = |lump: &LumpHeader| {
+        i.get(lump.offset as usize..)
+            .and_then(|lump_bytes| lump_bytes.get(..lump.length as usize))
+            .and_then(|lump_bytes| lump_bytes.iter().find(|c| !c.is_ascii_whitespace()))
+            == Some(&b'{')
+    };
+
+    !starts_with_brace(&lumps[LUMP_ENTITIES]) && starts_with_brace(&lumps[LUMP_PLANES])
 }
 
 type FUCKOFF<'a> = nom::Err<nom::error::Error<&'a [u8]>>;
 
+fn lump_error(lump: usize, offset: usize, source: BspLumpError) -> BspError {
+    BspError::ParseLump {
+        lump: LUMP_NAMES[lump],
+        offset,
+        source,
+    }
+}
+
+// the whole lump has to be entries of the same size
+fn parse_lump_entries<'a, T>(
+    file: &'a [u8],
+    lumps: &[LumpHeader],
+    lump: usize,
+    entry_size: usize,
+    parser: impl Fn(&'a [u8]) -> IResult<'a, T>,
+) -> Result<Vec<T>, BspError> {
+    let lump_start = lumps[lump].offset as usize;
+    let i = &file[lump_start..lump_start + lumps[lump].length as usize];
+
+    if !i.len().is_multiple_of(entry_size) {
+        return Err(lump_error(
+            lump,
+            lump_start,
+            BspLumpError::Length {
+                length: i.len(),
+                entry_size,
+            },
+        ));
+    }
+
+    i.chunks_exact(entry_size)
+        .enumerate()
+        .map(|(index, entry)| {
+            parser(entry).map(|(_, res)| res).map_err(|_| {
+                lump_error(
+                    lump,
+                    lump_start + index * entry_size,
+                    BspLumpError::InvalidEntry { index },
+                )
+            })
+        })
+        .collect()
+}
+
 pub fn parse_bsp(i: &[u8]) -> Result<Bsp, BspError> {
-    let (beginning, version) = le_i32(i).map_err(|_: FUCKOFF| BspError::NomParsingError)?;
+    let header_error = || BspError::Header { file_size: i.len() };
+
+    let (beginning, version) = le_i32(i).map_err(|_: FUCKOFF| header_error())?;
 
-    if version != BSP_VERSION {
-        return BspError::BspVersion { version }.to_result();
+    let (_, mut lumps) =
+        count(parse_lump_header, HEADER_LUMPS)(beginning).map_err(|_: FUCKOFF| header_error())?;
+
+    let variant = match version {
+        BSP_VERSION if is_blue_shift(i, &lumps) => BspVariant::BlueShift,
+        BSP_VERSION => BspVariant::GoldSrc,
+        QUAKE_BSP_VERSION => BspVariant::Quake,
+        _ => return BspError::BspVersion { version }.to_result(),
+    };
+
+    if variant == BspVariant::BlueShift {
+        lumps.swap(LUMP_ENTITIES, LUMP_PLANES);
     }
 
-    let (_, lumps) =
-        count(parse_lump_header, HEADER_LUMPS)(beginning).map_err(|_| BspError::NomParsingError)?;
+    // every lump is sliced from here on so they must be inside of the file
+    if let Some((idx, lump)) = lumps.iter().enumerate().find(|(_, lump)| {
+        lump.offset < 0 || lump.length < 0 || lump.offset as usize + lump.length as usize > i.len()
+    }) {
+        return Err(lump_error(
+            idx,
+            lump.offset.max(0) as usize,
+            BspLumpError::OutOfBounds {
+                offset: lump.offset,
+                length: lump.length,
+                file_size: i.len(),
+            },
+        ));
+    }
+
+    // empty lumps share the offset with the next lump so the compiler order breaks the tie
+    let mut lump_order = LUMP_ORDER;
+    lump_order.sort_by_key(|&idx| (lumps[idx].offset, lumps[idx].length != 0));
 
     let lump_section = |idx: usize| {
-        &i[(lumps[idx].offset as usize)..((lumps[idx].offset + lumps[idx].length) as usize)]
+        let offset = lumps[idx].offset as usize;
+
+        &i[offset..offset + lumps[idx].length as usize]
     };
 
     let entities = parse_entities(lump_section(LUMP_ENTITIES))
         .map_err(|source| BspError::ParseEntities { source })?;
-    let (_, planes) = parse_planes(lump_section(LUMP_PLANES)).map_err(|_| BspError::ParsePlanes)?;
-    let (_, textures) =
-        parse_textures(lump_section(LUMP_TEXTURES)).map_err(|_| BspError::ParseTextures)?;
-    let (_, vertices) =
-        parse_vertices(lump_section(LUMP_VERTICES)).map_err(|_| BspError::ParseVertices)?;
-    // TODO visibility
-    // let (_, visibility) =
-    //     rest(lump_section(LUMP_VISIBILITY)).map_err(|_| BspError::ParseVisibility)?;
+    let planes = parse_lump_entries(i, &lumps, LUMP_PLANES, 20, parse_plane)?;
+    let textures =
+        parse_textures(lump_section(LUMP_TEXTURES), variant).map_err(|(offset, source)| {
+            lump_error(
+                LUMP_TEXTURES,
+                lumps[LUMP_TEXTURES].offset as usize + offset,
+                source,
+            )
+        })?;
+    let vertices = parse_lump_entries(i, &lumps, LUMP_VERTICES, 12, parse_vertex)?;
+    // visibility is kept compressed, see `Bsp::pvs`
     let visibility = lump_section(LUMP_VISIBILITY);
-    let (_, nodes) = parse_nodes(lump_section(LUMP_NODES)).map_err(|_| BspError::ParseNodes)?;
-    let (_, texinfo) =
-        parse_texinfo(lump_section(LUMP_TEXINFO)).map_err(|_| BspError::ParseTexInfo)?;
-    let (_, faces) = parse_faces(lump_section(LUMP_FACES)).map_err(|_| BspError::ParseFaces)?;
-    let (_, lightmap) =
-        parse_lightmap(lump_section(LUMP_LIGHTING)).map_err(|_| BspError::ParseLightmap)?;
-    let (_, clipnodes) =
-        parse_clipnodes(lump_section(LUMP_CLIPNODES)).map_err(|_| BspError::ParseClipNodes)?;
-    let (_, leaves) = parse_leaves(lump_section(LUMP_LEAVES)).map_err(|_| BspError::ParseLeaves)?;
-    let (_, mark_surfaces) = parse_mark_surfaces(lump_section(LUMP_MARKSURFACES))
-        .map_err(|_| BspError::ParseMarkSurfaces)?;
-    let (_, edges) = parse_edges(lump_section(LUMP_EDGES)).map_err(|_| BspError::ParseEdges)?;
-    let (_, surf_edges) =
-        parse_surf_edges(lump_section(LUMP_SURFEDGES)).map_err(|_| BspError::ParseSurfEdges)?;
-    let (_, models) = parse_models(lump_section(LUMP_MODELS)).map_err(|_| BspError::ParseModels)?;
+    let nodes = parse_lump_entries(i, &lumps, LUMP_NODES, 24, parse_node)?;
+    let texinfo = parse_lump_entries(i, &lumps, LUMP_TEXINFO, 40, parse_texinfo_singular)?;
+    let faces = parse_lump_entries(i, &lumps, LUMP_FACES, 20, parse_face)?;
+    let lightmap = parse_lightmap(lump_section(LUMP_LIGHTING), variant).map_err(|source| {
+        lump_error(LUMP_LIGHTING, lumps[LUMP_LIGHTING].offset as usize, source)
+    })?;
+    let clipnodes = parse_lump_entries(i, &lumps, LUMP_CLIPNODES, 8, parse_clipnode)?;
+    let leaves = parse_lump_entries(i, &lumps, LUMP_LEAVES, 28, parse_leaf)?;
+    let mark_surfaces = parse_lump_entries(i, &lumps, LUMP_MARKSURFACES, 2, parse_mark_surface)?;
+    let edges = parse_lump_entries(i, &lumps, LUMP_EDGES, 4, parse_edge)?;
+    let surf_edges = parse_lump_entries(i, &lumps, LUMP_SURFEDGES, 4, parse_surf_edge)?;
+    let models = parse_lump_entries(i, &lumps, LUMP_MODELS, 64, parse_model)?;
 
     Ok(Bsp {
         entities,
@@ -333,5 +499,7 @@ pub fn parse_bsp(i: &[u8]) -> Result<Bsp, BspError> {
         edges,
         surf_edges,
         models,
+        lump_order,
+        variant,
     })
 }
@@ -0,0 +1,430 @@
+//! Lump usage against the engine limits and checks for broken references
+//!
+//! The usage table is the same one `hlbsp` prints after compiling.
+use std::fmt;
+
+use byte_writer::ByteWriter;
+
+use crate::{
+    constants::{
+        LUMP_CLIPNODES, LUMP_EDGES, LUMP_ENTITIES, LUMP_FACES, LUMP_LEAVES, LUMP_LIGHTING,
+        LUMP_MARKSURFACES, LUMP_MODELS, LUMP_NODES, LUMP_PLANES, LUMP_SURFEDGES, LUMP_TEXINFO,
+        LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY, MAX_MAP_CLIPNODES, MAX_MAP_EDGES,
+        MAX_MAP_ENTITIES, MAX_MAP_ENTSTRING, MAX_MAP_FACES, MAX_MAP_LEAFS, MAX_MAP_LIGHTING,
+        MAX_MAP_MARKSURFACES, MAX_MAP_MIPTEX, MAX_MAP_MODELS, MAX_MAP_NODES, MAX_MAP_PLANES,
+        MAX_MAP_SURFEDGES, MAX_MAP_TEXINFO, MAX_MAP_TEXTURES, MAX_MAP_VERTS, MAX_MAP_VISIBILITY,
+    },
+    Bsp,
+};
+
+/// Usage of one lump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LumpStats {
+    pub name: &'static str,
+    /// Number of entries, `None` for lumps without fixed size entries like lighting.
+    pub count: Option<usize>,
+    pub max_count: Option<usize>,
+    /// Bytes written into the file.
+    pub size: usize,
+    pub max_size: usize,
+}
+
+impl LumpStats {
+    /// Fullness in percentage, counted by entries if the lump has them.
+    pub fn usage(&self) -> f64 {
+        match (self.count, self.max_count) {
+            (Some(count), Some(max_count)) => count as f64 / max_count as f64 * 100.,
+            _ => self.size as f64 / self.max_size as f64 * 100.,
+        }
+    }
+
+    pub fn is_over_limit(&self) -> bool {
+        match (self.count, self.max_count) {
+            (Some(count), Some(max_count)) => count > max_count,
+            _ => self.size > self.max_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BspStats {
+    pub lumps: Vec<LumpStats>,
+    /// Size of the whole file.
+    pub total_size: usize,
+}
+
+impl fmt::Display for BspStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Object names  Objects/Maxobjs  Memory / Maxmem  Fullness"
+        )?;
+        writeln!(
+            f,
+            "------------  ---------------  ---------------  --------"
+        )?;
+
+        for lump in &self.lumps {
+            match (lump.count, lump.max_count) {
+                (Some(count), Some(max_count)) => write!(
+                    f,
+                    "{:<12}  {:>7}/{:<7}  {:>8}/{:<8}  ({:>4.1}%)",
+                    lump.name,
+                    count,
+                    max_count,
+                    lump.size,
+                    lump.max_size,
+                    lump.usage()
+                )?,
+                _ => write!(
+                    f,
+                    "{:<12}     [variable]    {:>8}/{:<8}  ({:>4.1}%)",
+                    lump.name,
+                    lump.size,
+                    lump.max_size,
+                    lump.usage()
+                )?,
+            }
+
+            if lump.is_over_limit() {
+                write!(f, "  SIZE OVERFLOW!!!")?;
+            } else if lump.usage() > 80. {
+                write!(f, "  VERY FULL")?;
+            }
+
+            writeln!(f)?;
+        }
+
+        write!(
+            f,
+            "=== Total BSP file data space used: {} bytes ===",
+            self.total_size
+        )
+    }
+}
+
+/// Problem found by [`Bsp::validate`].
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum BspIssue {
+    #[error("{name} is over the limit: {value}/{max}")]
+    OverLimit {
+        name: &'static str,
+        value: usize,
+        max: usize,
+    },
+    #[error("{lump} {index} has {field} {value} but there are only {count}")]
+    OutOfRange {
+        lump: &'static str,
+        index: usize,
+        field: &'static str,
+        value: i64,
+        count: usize,
+    },
+}
+
+impl Bsp {
+    fn lump_size(&self, lump: usize) -> usize {
+        let mut writer = ByteWriter::new();
+
+        self.write_lump(lump, &mut writer);
+
+        writer.data.len()
+    }
+
+    /// Lump usage against the GoldSrc limits.
+    pub fn stats(&self) -> BspStats {
+        // same order as hlbsp, the last number is the size of one entry
+        let arrays = [
+            ("models", LUMP_MODELS, self.models.len(), MAX_MAP_MODELS, 64),
+            ("planes", LUMP_PLANES, self.planes.len(), MAX_MAP_PLANES, 20),
+            (
+                "vertexes",
+                LUMP_VERTICES,
+                self.vertices.len(),
+                MAX_MAP_VERTS,
+                12,
+            ),
+            ("nodes", LUMP_NODES, self.nodes.len(), MAX_MAP_NODES, 24),
+            (
+                "texinfos",
+                LUMP_TEXINFO,
+                self.texinfo.len(),
+                MAX_MAP_TEXINFO,
+                40,
+            ),
+            ("faces", LUMP_FACES, self.faces.len(), MAX_MAP_FACES, 20),
+            (
+                "clipnodes",
+                LUMP_CLIPNODES,
+                self.clipnodes.len(),
+                MAX_MAP_CLIPNODES,
+                8,
+            ),
+            ("leaves", LUMP_LEAVES, self.leaves.len(), MAX_MAP_LEAFS, 28),
+            (
+                "marksurfaces",
+                LUMP_MARKSURFACES,
+                self.mark_surfaces.len(),
+                MAX_MAP_MARKSURFACES,
+                2,
+            ),
+            (
+                "surfedges",
+                LUMP_SURFEDGES,
+                self.surf_edges.len(),
+                MAX_MAP_SURFEDGES,
+                4,
+            ),
+            ("edges", LUMP_EDGES, self.edges.len(), MAX_MAP_EDGES, 4),
+        ];
+
+        let globs = [
+            ("texdata", LUMP_TEXTURES, MAX_MAP_MIPTEX),
+            ("lightdata", LUMP_LIGHTING, MAX_MAP_LIGHTING),
+            ("visdata", LUMP_VISIBILITY, MAX_MAP_VISIBILITY),
+            ("entdata", LUMP_ENTITIES, MAX_MAP_ENTSTRING),
+        ];
+
+        let mut lumps = arrays
+            .into_iter()
+            .map(|(name, lump, count, max_count, entry_size)| LumpStats {
+                name,
+                count: Some(count),
+                max_count: Some(max_count),
+                size: self.lump_size(lump),
+                max_size: max_count * entry_size,
+            })
+            .collect::<Vec<_>>();
+
+        lumps.extend(globs.into_iter().map(|(name, lump, max_size)| LumpStats {
+            name,
+            count: None,
+            max_count: None,
+            size: self.lump_size(lump),
+            max_size,
+        }));
+
+        BspStats {
+            lumps,
+            total_size: self.write_to_bytes().len(),
+        }
+    }
+
+    /// Finds lumps over the limits and indices pointing outside of the lump they refer to.
+    ///
+    /// The engine would crash or show garbage with these problems.
+    pub fn validate(&self) -> Vec<BspIssue> {
+        let mut issues = self
+            .stats()
+            .lumps
+            .into_iter()
+            .filter(|lump| lump.is_over_limit())
+            .map(|lump| match (lump.count, lump.max_count) {
+                (Some(count), Some(max_count)) => BspIssue::OverLimit {
+                    name: lump.name,
+                    value: count,
+                    max: max_count,
+                },
+                _ => BspIssue::OverLimit {
+                    name: lump.name,
+                    value: lump.size,
+                    max: lump.max_size,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        [
+            ("entities", self.entities.len(), MAX_MAP_ENTITIES),
+            ("textures", self.textures.len(), MAX_MAP_TEXTURES),
+        ]
+        .into_iter()
+        .filter(|(_, value, max)| value > max)
+        .for_each(|(name, value, max)| issues.push(BspIssue::OverLimit { name, value, max }));
+
+        let mut check =
+            |lump: &'static str, index: usize, field: &'static str, value: i64, count: usize| {
+                if value < 0 || value as usize >= count {
+                    issues.push(BspIssue::OutOfRange {
+                        lump,
+                        index,
+                        field,
+                        value,
+                        count,
+                    });
+                }
+            };
+
+        // only the last index of a range needs checking
+        let last = |first: i64, count: i64| first + count - 1;
+
+        self.faces.iter().enumerate().for_each(|(idx, face)| {
+            check("face", idx, "plane", face.plane as i64, self.planes.len());
+            check(
+                "face",
+                idx,
+                "texinfo",
+                face.texinfo as i64,
+                self.texinfo.len(),
+            );
+
+            if face.edge_count > 0 {
+                check(
+                    "face",
+                    idx,
+                    "first surfedge",
+                    face.first_edge as i64,
+                    self.surf_edges.len(),
+                );
+                check(
+                    "face",
+                    idx,
+                    "surfedge",
+                    last(face.first_edge as i64, face.edge_count as i64),
+                    self.surf_edges.len(),
+                );
+            }
+        });
+
+        self.surf_edges
+            .iter()
+            .enumerate()
+            .for_each(|(idx, &surf_edge)| {
+                check(
+                    "surfedge",
+                    idx,
+                    "edge",
+                    (surf_edge as i64).abs(),
+                    self.edges.len(),
+                );
+            });
+
+        self.edges.iter().enumerate().for_each(|(idx, edge)| {
+            edge.iter().for_each(|&vertex| {
+                check("edge", idx, "vertex", vertex as i64, self.vertices.len());
+            });
+        });
+
+        self.texinfo.iter().enumerate().for_each(|(idx, texinfo)| {
+            check(
+                "texinfo",
+                idx,
+                "texture",
+                texinfo.texture_index as i64,
+                self.textures.len(),
+            );
+        });
+
+        self.leaves.iter().enumerate().for_each(|(idx, leaf)| {
+            if leaf.mark_surface_count > 0 {
+                check(
+                    "leaf",
+                    idx,
+                    "marksurface",
+                    last(
+                        leaf.first_mark_surface as i64,
+                        leaf.mark_surface_count as i64,
+                    ),
+                    self.mark_surfaces.len(),
+                );
+            }
+        });
+
+        self.mark_surfaces
+            .iter()
+            .enumerate()
+            .for_each(|(idx, &face)| {
+                check("marksurface", idx, "face", face as i64, self.faces.len());
+            });
+
+        self.nodes.iter().enumerate().for_each(|(idx, node)| {
+            check("node", idx, "plane", node.plane as i64, self.planes.len());
+
+            node.children.iter().for_each(|&child| {
+                if child >= 0 {
+                    check("node", idx, "child node", child as i64, self.nodes.len());
+                } else {
+                    check(
+                        "node",
+                        idx,
+                        "child leaf",
+                        -1 - child as i64,
+                        self.leaves.len(),
+                    );
+                }
+            });
+
+            if node.face_count > 0 {
+                check(
+                    "node",
+                    idx,
+                    "face",
+                    last(node.first_face as i64, node.face_count as i64),
+                    self.faces.len(),
+                );
+            }
+        });
+
+        self.clipnodes
+            .iter()
+            .enumerate()
+            .for_each(|(idx, clipnode)| {
+                check(
+                    "clipnode",
+                    idx,
+                    "plane",
+                    clipnode.plane as i64,
+                    self.planes.len(),
+                );
+
+                // negative children are contents
+                clipnode
+                    .children
+                    .iter()
+                    .filter(|&&child| child >= 0)
+                    .for_each(|&child| {
+                        check(
+                            "clipnode",
+                            idx,
+                            "child clipnode",
+                            child as i64,
+                            self.clipnodes.len(),
+                        );
+                    });
+            });
+
+        self.models.iter().enumerate().for_each(|(idx, model)| {
+            check(
+                "model",
+                idx,
+                "head node",
+                model.head_nodes[0] as i64,
+                self.nodes.len(),
+            );
+
+            model.head_nodes[1..]
+                .iter()
+                .filter(|&&head_node| head_node >= 0)
+                .for_each(|&head_node| {
+                    check(
+                        "model",
+                        idx,
+                        "head clipnode",
+                        head_node as i64,
+                        self.clipnodes.len(),
+                    );
+                });
+
+            if model.face_count > 0 {
+                check(
+                    "model",
+                    idx,
+                    "face",
+                    last(model.first_face as i64, model.face_count as i64),
+                    self.faces.len(),
+                );
+            }
+        });
+
+        issues
+    }
+}
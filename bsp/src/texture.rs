@@ -0,0 +1,345 @@
+//! Moving textures between the BSP and WAD files
+use wad::types::{FileEntry, MipTex, Palette, TextureName, Wad};
+
+use crate::{
+    constants::MAX_MAP_LIGHTING,
+    error::BspError,
+    lightmap::{sample_luxel, MAX_SURFACE_EXTENT},
+    Bsp, FaceLightmapInfo, Texture,
+};
+
+const WAD_KEY: &str = "wad";
+
+/// Stand-in for a texture directory entry of -1 so the texture indices after it stay the same.
+///
+/// Quake compilers write -1 for textures they could not find and the engine skips them.
+pub fn missing_texture() -> Texture {
+    MipTex {
+        texture_name: TextureName(vec![]),
+        width: 0,
+        height: 0,
+        mip_offsets: vec![0; 4],
+        mip_images: vec![],
+        colors_used: 0,
+        palette: Palette(vec![]),
+    }
+}
+
+/// Texture written as a directory entry of -1, see [`missing_texture`].
+pub fn is_missing_texture(texture: &Texture) -> bool {
+    texture.texture_name.get_bytes().is_empty() && texture.width == 0 && texture.height == 0
+}
+
+// face index, old layout and the new luxels of every style
+type ResampledLightmap = (usize, FaceLightmapInfo, Vec<[u8; 3]>);
+
+impl Bsp {
+    /// WAD paths in the worldspawn `wad` key.
+    pub fn wad_paths(&self) -> Vec<String> {
+        self.entities
+            .first()
+            .and_then(|worldspawn| worldspawn.get(WAD_KEY))
+            .map(|wads| {
+                wads.split(';')
+                    .filter(|path| !path.is_empty())
+                    .map(|path| path.to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Replaces the worldspawn `wad` key. The key is removed if there is no path.
+    pub fn set_wad_paths(&mut self, paths: &[impl AsRef<str>]) {
+        let Some(worldspawn) = self.entities.first_mut() else {
+            return;
+        };
+
+        if paths.is_empty() {
+            worldspawn.remove(WAD_KEY);
+            return;
+        }
+
+        let wads = paths
+            .iter()
+            .map(|path| path.as_ref())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        worldspawn.insert(WAD_KEY, wads);
+    }
+
+    /// Embeds external textures with the image from the first WAD having the same name and dimensions.
+    ///
+    /// Returns names of the textures which are still external.
+    /// Once every texture is embedded, the `wad` key is removed.
+    pub fn embed_textures(&mut self, wads: &[&Wad]) -> Vec<String> {
+        let mut missing = vec![];
+
+        self.textures
+            .iter_mut()
+            .filter(|texture| texture.is_external() && !is_missing_texture(texture))
+            .for_each(|texture| {
+                let name = texture.texture_name.get_string_standard();
+
+                let found = wads
+                    .iter()
+                    .flat_map(|wad| wad.entries.iter())
+                    .find_map(|entry| match &entry.file_entry {
+                        FileEntry::MipTex(miptex)
+                            if entry.texture_name_standard() == name
+                                && miptex.width == texture.width
+                                && miptex.height == texture.height
+                                && !miptex.is_external() =>
+                        {
+                            Some(miptex)
+                        }
+                        _ => None,
+                    });
+
+                let Some(miptex) = found else {
+                    missing.push(texture.texture_name.get_string());
+                    return;
+                };
+
+                // name stays the same in case the WAD has different casing
+                *texture = MipTex {
+                    texture_name: texture.texture_name.clone(),
+                    ..miptex.clone()
+                };
+            });
+
+        if missing.is_empty() {
+            self.set_wad_paths(&[] as &[&str]);
+        }
+
+        missing
+    }
+
+    /// Makes the textures external and adds `wad_path` to the `wad` key.
+    ///
+    /// Every embedded texture is made external if `names` is empty.
+    /// Returns the textures as they were before so they could be written into the WAD.
+    pub fn unembed_textures(
+        &mut self,
+        names: &[impl AsRef<str>],
+        wad_path: impl AsRef<str>,
+    ) -> Vec<MipTex> {
+        let names = names
+            .iter()
+            .map(|name| name.as_ref().to_uppercase())
+            .collect::<Vec<_>>();
+
+        let removed = self
+            .textures
+            .iter_mut()
+            .filter(|texture| !texture.is_external())
+            .filter(|texture| {
+                names.is_empty() || names.contains(&texture.texture_name.get_string_standard())
+            })
+            .map(|texture| {
+                let external = MipTex {
+                    texture_name: texture.texture_name.clone(),
+                    width: texture.width,
+                    height: texture.height,
+                    mip_offsets: vec![0; 4],
+                    mip_images: vec![],
+                    colors_used: 0,
+                    palette: Palette(vec![]),
+                };
+
+                std::mem::replace(texture, external)
+            })
+            .collect::<Vec<_>>();
+
+        if removed.is_empty() {
+            return removed;
+        }
+
+        let mut wad_paths = self.wad_paths();
+
+        if !wad_paths
+            .iter()
+            .any(|path| path.eq_ignore_ascii_case(wad_path.as_ref()))
+        {
+            wad_paths.push(wad_path.as_ref().to_string());
+            self.set_wad_paths(&wad_paths);
+        }
+
+        removed
+    }
+
+    fn find_texture(&self, name: &str) -> Option<usize> {
+        self.textures
+            .iter()
+            .position(|texture| texture.texture_name.get_string().eq_ignore_ascii_case(name))
+    }
+
+    /// Replaces the texture with the same name, embedded or not, with `texture`, including its name.
+    ///
+    /// If the dimensions are different, the texinfo of the texture is scaled so the texture still covers
+    /// the same area. Lightmaps of the affected faces are resampled to the new extents, in place when they
+    /// fit in the old lightmaps and appended to the lighting lump otherwise.
+    /// Faces are measured in texels for the lightmaps so a bigger texture means more luxels on the same face.
+    ///
+    /// Nothing is changed when a face would go over [`MAX_SURFACE_EXTENT`] or the lighting lump over
+    /// [`MAX_MAP_LIGHTING`].
+    pub fn replace_texture(&mut self, name: &str, texture: MipTex) -> Result<(), BspError> {
+        let Some(texture_idx) = self.find_texture(name) else {
+            return BspError::TextureNotFound {
+                name: name.to_string(),
+            }
+            .to_result();
+        };
+
+        let old_texture = &self.textures[texture_idx];
+        let scale = [
+            texture.width as f32 / old_texture.width as f32,
+            texture.height as f32 / old_texture.height as f32,
+        ];
+
+        if scale == [1., 1.] {
+            self.textures[texture_idx] = texture;
+            return Ok(());
+        }
+
+        let texinfos = self
+            .texinfo
+            .iter()
+            .enumerate()
+            .filter(|(_, texinfo)| texinfo.texture_index as usize == texture_idx)
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+
+        // layouts in the old texture space
+        let lightmaps = self
+            .faces
+            .iter()
+            .enumerate()
+            .filter(|(_, face)| texinfos.contains(&(face.texinfo as usize)))
+            .filter_map(|(face_idx, _)| Some((face_idx, self.face_lightmap_info(face_idx)?)))
+            .collect::<Vec<_>>();
+
+        let old_texinfo = self.texinfo.clone();
+
+        texinfos.iter().for_each(|&idx| {
+            let texinfo = &mut self.texinfo[idx];
+
+            texinfo.u *= scale[0];
+            texinfo.u_offset *= scale[0];
+            texinfo.v *= scale[1];
+            texinfo.v_offset *= scale[1];
+        });
+
+        let resampled = match self.resample_lightmaps(&lightmaps, scale) {
+            Ok(resampled) => resampled,
+            Err(err) => {
+                self.texinfo = old_texinfo;
+                return Err(err);
+            }
+        };
+
+        self.textures[texture_idx] = texture;
+
+        for (face_idx, old_info, luxels) in resampled {
+            let offset = if luxels.len() <= old_info.luxel_count() * old_info.style_count {
+                old_info.offset
+            } else {
+                self.lightmap.len()
+            };
+
+            if offset == self.lightmap.len() {
+                self.lightmap.extend(luxels);
+            } else {
+                self.lightmap[offset..offset + luxels.len()].copy_from_slice(&luxels);
+            }
+
+            self.faces[face_idx].lightmap_offset = (offset * self.variant.luxel_size()) as i32;
+        }
+
+        Ok(())
+    }
+
+    /// Lightmaps of the faces in the current texinfo, sampled from the old layouts.
+    fn resample_lightmaps(
+        &self,
+        lightmaps: &[(usize, FaceLightmapInfo)],
+        scale: [f32; 2],
+    ) -> Result<Vec<ResampledLightmap>, BspError> {
+        let mut res = vec![];
+        let mut appended = 0;
+
+        for &(face_idx, old_info) in lightmaps {
+            let Some((mins, maxs)) = self.face_extents(face_idx) else {
+                continue;
+            };
+
+            let extents = [maxs[0] - mins[0], maxs[1] - mins[1]];
+
+            if extents.iter().any(|&extent| extent > MAX_SURFACE_EXTENT) {
+                return BspError::SurfaceExtents {
+                    face: face_idx,
+                    extents,
+                }
+                .to_result();
+            }
+
+            let width = extents[0] as usize + 1;
+            let height = extents[1] as usize + 1;
+            let mut luxels = Vec::with_capacity(width * height * old_info.style_count);
+
+            (0..old_info.style_count).for_each(|style_slot| {
+                let old_luxels = &self.lightmap[old_info.style_range(style_slot)];
+
+                (0..height).for_each(|y| {
+                    (0..width).for_each(|x| {
+                        // luxel position in the old luxel grid
+                        let s =
+                            (mins[0] + x as i32) as f64 / scale[0] as f64 - old_info.mins[0] as f64;
+                        let t =
+                            (mins[1] + y as i32) as f64 / scale[1] as f64 - old_info.mins[1] as f64;
+
+                        let luxel = sample_luxel(old_luxels, old_info.width, s, t);
+
+                        luxels.push(luxel.map(|channel| channel.round() as u8));
+                    });
+                });
+            });
+
+            if luxels.len() > old_info.luxel_count() * old_info.style_count {
+                appended += luxels.len();
+            }
+
+            res.push((face_idx, old_info, luxels));
+        }
+
+        let size = (self.lightmap.len() + appended) * self.variant.luxel_size();
+
+        if size > MAX_MAP_LIGHTING {
+            return BspError::LightingLimit {
+                size,
+                max: MAX_MAP_LIGHTING,
+            }
+            .to_result();
+        }
+
+        Ok(res)
+    }
+
+    /// Renames the texture, which is how an external texture is pointed to another texture in the WADs.
+    pub fn rename_texture(&mut self, name: &str, new_name: &str) -> Result<(), BspError> {
+        let Some(texture_idx) = self.find_texture(name) else {
+            return BspError::TextureNotFound {
+                name: name.to_string(),
+            }
+            .to_result();
+        };
+
+        self.textures[texture_idx]
+            .texture_name
+            .set_name(new_name)
+            .map_err(|err| BspError::InvalidTextureName {
+                name: new_name.to_string(),
+                reason: err.to_string(),
+            })
+    }
+}
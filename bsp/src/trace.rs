@@ -0,0 +1,444 @@
+//! Point contents and hull traces
+//!
+//! Hull 0 is the node tree used for rendering. Hull 1, 2 and 3 are the clipnode trees where the brushes are already expanded
+//! by the size of the hull so tracing a point through them is the same as tracing a box.
+use glam::Vec3;
+
+use crate::{constants::MAX_MAP_HULLS, Bsp, LeafContent, Plane};
+
+/// Same as `DIST_EPSILON` in the engine.
+const DIST_EPSILON: f32 = 0.03125;
+
+/// Recursive walks stop here even if the tree has more nodes, so a looping tree does not overflow the stack.
+///
+/// Real trees are a few dozen nodes deep.
+const MAX_RECURSION_DEPTH: usize = 1024;
+
+/// Mins and maxs of the box represented by each hull.
+pub const HULL_SIZES: [[Vec3; 2]; MAX_MAP_HULLS] = [
+    [Vec3::ZERO, Vec3::ZERO],
+    // standing
+    [Vec3::new(-16., -16., -36.), Vec3::new(16., 16., 36.)],
+    // large
+    [Vec3::new(-32., -32., -32.), Vec3::new(32., 32., 32.)],
+    // crouching
+    [Vec3::new(-16., -16., -18.), Vec3::new(16., 16., 18.)],
+];
+
+pub const HULL_POINT: usize = 0;
+pub const HULL_STANDING: usize = 1;
+pub const HULL_LARGE: usize = 2;
+pub const HULL_CROUCHING: usize = 3;
+
+const CONTENTS_EMPTY: i32 = LeafContent::ContentsEmpty as i32;
+const CONTENTS_SOLID: i32 = LeafContent::ContentsSolid as i32;
+const CONTENTS_SKY: i32 = LeafContent::ContentsSky as i32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TraceResult {
+    /// The whole trace is inside solid.
+    pub all_solid: bool,
+    /// The trace starts inside solid.
+    pub start_solid: bool,
+    /// The trace goes through empty space.
+    pub in_open: bool,
+    /// The trace goes through non-solid contents that is not empty like water.
+    pub in_water: bool,
+    /// 1.0 means nothing is hit.
+    pub fraction: f32,
+    pub end_pos: Vec3,
+    /// Normal of the surface hit, pointing away from the solid.
+    pub plane_normal: Vec3,
+    pub plane_distance: f32,
+}
+
+impl TraceResult {
+    fn new(end: Vec3) -> Self {
+        Self {
+            all_solid: true,
+            start_solid: false,
+            in_open: false,
+            in_water: false,
+            fraction: 1.,
+            end_pos: end,
+            plane_normal: Vec3::ZERO,
+            plane_distance: 0.,
+        }
+    }
+
+    pub fn hit(&self) -> bool {
+        self.fraction < 1.
+    }
+}
+
+/// A node tree to walk on.
+///
+/// Negative children are contents, same as `hull_t` in the engine.
+struct Hull<'a> {
+    bsp: &'a Bsp,
+    hull: usize,
+    head_node: i32,
+}
+
+impl<'a> Hull<'a> {
+    fn new(bsp: &'a Bsp, model: usize, hull: usize) -> Option<Self> {
+        if hull >= MAX_MAP_HULLS {
+            return None;
+        }
+
+        let head_node = bsp.models.get(model)?.head_nodes[hull];
+
+        Some(Self {
+            bsp,
+            hull,
+            head_node,
+        })
+    }
+
+    // returns the plane and the two children
+    fn node(&self, num: i32) -> Option<(&'a Plane, [i32; 2])> {
+        if self.hull == HULL_POINT {
+            let node = self.bsp.nodes.get(num as usize)?;
+            let plane = self.bsp.planes.get(node.plane as usize)?;
+
+            // leaf contents are taken from the leaf itself
+            let child = |child: i16| -> i32 {
+                if child >= 0 {
+                    return child as i32;
+                }
+
+                self.bsp
+                    .leaves
+                    .get((-1 - child as i32) as usize)
+                    .map(|leaf| leaf.contents as i32)
+                    .unwrap_or(CONTENTS_SOLID)
+            };
+
+            Some((plane, [child(node.children[0]), child(node.children[1])]))
+        } else {
+            let node = self.bsp.clipnodes.get(num as usize)?;
+            let plane = self.bsp.planes.get(node.plane as usize)?;
+
+            Some((plane, [node.children[0] as i32, node.children[1] as i32]))
+        }
+    }
+
+    // a valid tree never goes deeper than it has nodes
+    fn max_depth(&self) -> usize {
+        self.bsp.nodes.len().max(self.bsp.clipnodes.len()) + 1
+    }
+
+    /// Same as `SV_HullPointContents`
+    fn point_contents(&self, mut num: i32, point: Vec3) -> i32 {
+        // malformed trees could loop forever
+        let mut steps = 0;
+        let max_steps = self.max_depth();
+
+        while num >= 0 {
+            let Some((plane, children)) = self.node(num) else {
+                return CONTENTS_SOLID;
+            };
+
+            num = if plane_distance(plane, point) < 0. {
+                children[1]
+            } else {
+                children[0]
+            };
+
+            steps += 1;
+
+            if steps > max_steps {
+                return CONTENTS_SOLID;
+            }
+        }
+
+        num
+    }
+
+    /// Same as `SV_RecursiveHullCheck`
+    ///
+    /// Returns false when the trace is stopped. Going deeper than the tree could be is treated as solid.
+    #[allow(clippy::too_many_arguments)]
+    fn recursive_hull_check(
+        &self,
+        num: i32,
+        p1f: f32,
+        p2f: f32,
+        p1: Vec3,
+        p2: Vec3,
+        trace: &mut TraceResult,
+        depth: usize,
+    ) -> bool {
+        let num = if depth > self.max_depth().min(MAX_RECURSION_DEPTH) {
+            CONTENTS_SOLID
+        } else {
+            num
+        };
+
+        if num < 0 {
+            if num != CONTENTS_SOLID {
+                trace.all_solid = false;
+
+                if num == CONTENTS_EMPTY {
+                    trace.in_open = true;
+                } else {
+                    trace.in_water = true;
+                }
+            } else {
+                trace.start_solid = true;
+            }
+
+            return true;
+        }
+
+        let Some((plane, children)) = self.node(num) else {
+            return true;
+        };
+
+        let t1 = plane_distance(plane, p1);
+        let t2 = plane_distance(plane, p2);
+
+        if t1 >= 0. && t2 >= 0. {
+            return self.recursive_hull_check(children[0], p1f, p2f, p1, p2, trace, depth + 1);
+        }
+
+        if t1 < 0. && t2 < 0. {
+            return self.recursive_hull_check(children[1], p1f, p2f, p1, p2, trace, depth + 1);
+        }
+
+        // put the crosspoint DIST_EPSILON pixels on the near side
+        let frac = if t1 < 0. {
+            (t1 + DIST_EPSILON) / (t1 - t2)
+        } else {
+            (t1 - DIST_EPSILON) / (t1 - t2)
+        };
+        let mut frac = frac.clamp(0., 1.);
+
+        let mut midf = p1f + (p2f - p1f) * frac;
+        let mut mid = p1 + (p2 - p1) * frac;
+
+        let side = (t1 < 0.) as usize;
+
+        // move up to the node
+        if !self.recursive_hull_check(children[side], p1f, midf, p1, mid, trace, depth + 1) {
+            return false;
+        }
+
+        if self.point_contents(children[side ^ 1], mid) != CONTENTS_SOLID {
+            // go past the node
+            return self.recursive_hull_check(
+                children[side ^ 1],
+                midf,
+                p2f,
+                mid,
+                p2,
+                trace,
+                depth + 1,
+            );
+        }
+
+        // never got out of the solid area
+        if trace.all_solid {
+            return false;
+        }
+
+        // the other side of the node is solid, this is the impact point
+        if side == 0 {
+            trace.plane_normal = plane.normal;
+            trace.plane_distance = plane.distance;
+        } else {
+            trace.plane_normal = -plane.normal;
+            trace.plane_distance = -plane.distance;
+        }
+
+        while self.point_contents(self.head_node, mid) == CONTENTS_SOLID {
+            // shouldn't really happen, but does occasionally
+            frac -= 0.1;
+
+            if frac < 0. {
+                trace.fraction = midf;
+                trace.end_pos = mid;
+
+                return false;
+            }
+
+            midf = p1f + (p2f - p1f) * frac;
+            mid = p1 + (p2 - p1) * frac;
+        }
+
+        trace.fraction = midf;
+        trace.end_pos = mid;
+
+        false
+    }
+
+    /// Same as `TestLine_r` in the compilers
+    ///
+    /// Returns the first solid or sky contents along the line and where the line enters it.
+    ///
+    /// Going deeper than the tree could be is treated as solid.
+    fn test_line(&self, num: i32, p1: Vec3, p2: Vec3, depth: usize) -> Option<(i32, Vec3)> {
+        if num < 0 {
+            return (num == CONTENTS_SOLID || num == CONTENTS_SKY).then_some((num, p1));
+        }
+
+        if depth > self.max_depth().min(MAX_RECURSION_DEPTH) {
+            return Some((CONTENTS_SOLID, p1));
+        }
+
+        let Some((plane, children)) = self.node(num) else {
+            return Some((CONTENTS_SOLID, p1));
+        };
+
+        let t1 = plane_distance(plane, p1);
+        let t2 = plane_distance(plane, p2);
+
+        if t1 >= 0. && t2 >= 0. {
+            return self.test_line(children[0], p1, p2, depth + 1);
+        }
+
+        if t1 < 0. && t2 < 0. {
+            return self.test_line(children[1], p1, p2, depth + 1);
+        }
+
+        let side = (t1 < 0.) as usize;
+        let mid = p1 + (p2 - p1) * (t1 / (t1 - t2));
+
+        self.test_line(children[side], p1, mid, depth + 1)
+            .or_else(|| self.test_line(children[side ^ 1], mid, p2, depth + 1))
+    }
+}
+
+fn plane_distance(plane: &Plane, point: Vec3) -> f32 {
+    let type_ = plane.type_ as usize;
+
+    // axial planes
+    if type_ < 3 {
+        point[type_] - plane.distance
+    } else {
+        plane.normal.dot(point) - plane.distance
+    }
+}
+
+impl Bsp {
+    /// Leaf index containing the point in the world.
+    pub fn leaf_at(&self, point: Vec3) -> usize {
+        self.model_leaf_at(0, point)
+    }
+
+    /// Leaf index containing the point in model space.
+    ///
+    /// Returns 0, the solid leaf, if the model does not exist.
+    pub fn model_leaf_at(&self, model: usize, point: Vec3) -> usize {
+        let Some(model) = self.models.get(model) else {
+            return 0;
+        };
+
+        let mut num = model.head_nodes[HULL_POINT];
+        let mut steps = 0;
+
+        while num >= 0 {
+            let Some(node) = self.nodes.get(num as usize) else {
+                return 0;
+            };
+            let Some(plane) = self.planes.get(node.plane as usize) else {
+                return 0;
+            };
+
+            num = if plane_distance(plane, point) < 0. {
+                node.children[1]
+            } else {
+                node.children[0]
+            } as i32;
+
+            steps += 1;
+
+            if steps > self.nodes.len() {
+                return 0;
+            }
+        }
+
+        (-1 - num) as usize
+    }
+
+    /// Contents of the point in the world with the given hull.
+    ///
+    /// Invalid data is treated as solid.
+    pub fn point_contents(&self, hull: usize, point: Vec3) -> LeafContent {
+        self.model_point_contents(0, hull, point)
+    }
+
+    /// Contents of the point in model space with the given hull.
+    ///
+    /// Point is relative to the model so subtract the entity origin before calling this.
+    pub fn model_point_contents(&self, model: usize, hull: usize, point: Vec3) -> LeafContent {
+        let Some(hull) = Hull::new(self, model, hull) else {
+            return LeafContent::ContentsSolid;
+        };
+
+        LeafContent::try_from(hull.point_contents(hull.head_node, point))
+            .unwrap_or(LeafContent::ContentsSolid)
+    }
+
+    /// Traces the box of the given hull from start to end in the world.
+    pub fn trace(&self, hull: usize, start: Vec3, end: Vec3) -> TraceResult {
+        self.model_trace(0, hull, start, end)
+    }
+
+    /// Traces the box of the given hull from start to end in model space.
+    ///
+    /// Points are relative to the model so subtract the entity origin before calling this.
+    pub fn model_trace(&self, model: usize, hull: usize, start: Vec3, end: Vec3) -> TraceResult {
+        let mut trace = TraceResult::new(end);
+
+        let Some(hull) = Hull::new(self, model, hull) else {
+            trace.start_solid = true;
+            trace.fraction = 0.;
+            trace.end_pos = start;
+
+            return trace;
+        };
+
+        hull.recursive_hull_check(hull.head_node, 0., 1., start, end, &mut trace, 0);
+
+        if trace.fraction == 1. {
+            trace.end_pos = end;
+        }
+
+        trace
+    }
+
+    /// First solid or sky along the line in the world node tree.
+    ///
+    /// Unlike [`Bsp::trace`], sky stops the line. This is what the compilers use to check if light can reach a point.
+    /// Returns `None` when the line is clear.
+    pub fn test_line(&self, start: Vec3, end: Vec3) -> Option<(LeafContent, Vec3)> {
+        let Some(hull) = Hull::new(self, 0, HULL_POINT) else {
+            return Some((LeafContent::ContentsSolid, start));
+        };
+
+        hull.test_line(hull.head_node, start, end, 0)
+            .map(|(contents, pos)| {
+                (
+                    LeafContent::try_from(contents).unwrap_or(LeafContent::ContentsSolid),
+                    pos,
+                )
+            })
+    }
+
+    /// Whether a player at the origin is standing on something in the world.
+    ///
+    /// Same check as `PM_CatagorizePosition` where the player is 2 units above a walkable surface.
+    pub fn is_on_ground(&self, origin: Vec3, crouching: bool) -> bool {
+        let hull = if crouching {
+            HULL_CROUCHING
+        } else {
+            HULL_STANDING
+        };
+
+        let trace = self.trace(hull, origin, origin - Vec3::new(0., 0., 2.));
+
+        // too steep or nothing is hit
+        trace.plane_normal.z >= 0.7
+    }
+}
@@ -1,11 +1,12 @@
-use std::collections::HashMap;
-
 use glam::Vec3;
 use wad::types::MipTex;
 
 use nom::IResult as _IResult;
 
-use crate::constants::MAX_MAP_HULLS;
+use crate::{
+    constants::{BSP_VERSION, HEADER_LUMPS, MAX_MAP_HULLS, QUAKE_BSP_VERSION},
+    entity::Entity,
+};
 
 pub type IResult<'a, T> = _IResult<&'a [u8], T>;
 pub type SResult<'a, T> = _IResult<&'a str, T>;
@@ -16,8 +17,6 @@ pub struct LumpHeader {
     pub length: i32,
 }
 
-pub type Entity = HashMap<String, String>;
-
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub enum PlaneType {
@@ -89,9 +88,8 @@ impl Plane {
 
 pub type Texture = MipTex;
 pub type Vertex = Vec3;
-// pub type BspVis = todo!();
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Node {
     pub plane: u32,
     pub children: [i16; 2],
@@ -101,7 +99,7 @@ pub struct Node {
     pub face_count: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TexInfo {
     pub u: Vec3,
     pub u_offset: f32,
@@ -111,7 +109,7 @@ pub struct TexInfo {
     pub flags: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Face {
     pub plane: u16,
     pub side: u16,
@@ -124,7 +122,7 @@ pub struct Face {
 
 pub type LightMap = Vec<[u8; 3]>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ClipNode {
     pub plane: i32,
     pub children: [i16; 2],
@@ -179,7 +177,7 @@ impl TryFrom<i32> for LeafContent {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Leaf {
     pub contents: LeafContent,
     pub vis_offset: i32,
@@ -194,7 +192,7 @@ pub type MarkSurface = u16;
 pub type Edge = [u16; 2];
 pub type SurfEdge = i32;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Model {
     pub mins: Vec3,
     pub maxs: Vec3,
@@ -205,13 +203,42 @@ pub struct Model {
     pub face_count: i32,
 }
 
-#[derive(Debug)]
+/// Format of the file, every variant is read into the same [`Bsp`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BspVariant {
+    /// Half-Life BSP30
+    #[default]
+    GoldSrc,
+    /// BSP30 with the entity and plane lump headers swapped
+    BlueShift,
+    /// Quake BSP29, textures use the game palette and lighting has one channel
+    Quake,
+}
+
+impl BspVariant {
+    pub fn version(&self) -> i32 {
+        match self {
+            Self::GoldSrc | Self::BlueShift => BSP_VERSION,
+            Self::Quake => QUAKE_BSP_VERSION,
+        }
+    }
+
+    /// Bytes of one luxel in the lighting lump.
+    pub fn luxel_size(&self) -> usize {
+        match self {
+            Self::GoldSrc | Self::BlueShift => 3,
+            Self::Quake => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Bsp {
     pub entities: Vec<Entity>,
     pub planes: Vec<Plane>,
     pub textures: Vec<Texture>,
     pub vertices: Vec<Vertex>,
-    // TODO vis
+    /// Compressed visibility lump, use [`Bsp::pvs`] to decompress
     pub visibility: Vec<u8>,
     pub nodes: Vec<Node>,
     pub texinfo: Vec<TexInfo>,
@@ -223,4 +250,11 @@ pub struct Bsp {
     pub edges: Vec<Edge>,
     pub surf_edges: Vec<SurfEdge>,
     pub models: Vec<Model>,
+    /// Order of the lumps in the file, the writer follows it so the file does not change
+    /// if nothing is edited.
+    pub lump_order: [usize; HEADER_LUMPS],
+    /// Format to write as, which is the same as the parsed file.
+    ///
+    /// Lightmap is always RGB in [`Bsp::lightmap`], Quake lighting is converted when writing.
+    pub variant: BspVariant,
 }
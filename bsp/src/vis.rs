@@ -0,0 +1,264 @@
+//! Potentially visible set
+//!
+//! Every visible leaf has a row of bits, one bit per visible leaf, compressed with run-length encoding of zero bytes.
+//!
+//! Bit `n` of a row is for leaf `n + 1` because leaf 0 is the shared solid leaf and it is never in the set.
+use std::collections::HashMap;
+
+use crate::Bsp;
+
+/// Decompressed visibility data of every visible leaf.
+///
+/// Indices here are leaf indices, the same ones used in [`Bsp::leaves`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pvs {
+    /// Number of leaves that have visibility information, without leaf 0.
+    vis_leaf_count: usize,
+    /// `rows[i]` is the decompressed row of leaf `i + 1`
+    rows: Vec<Vec<u8>>,
+}
+
+impl Pvs {
+    /// Creates a PVS where every leaf cannot see any other leaf.
+    pub fn new(vis_leaf_count: usize) -> Self {
+        Self {
+            vis_leaf_count,
+            rows: vec![vec![0u8; row_length(vis_leaf_count)]; vis_leaf_count],
+        }
+    }
+
+    /// Creates a PVS where every leaf can see every other leaf.
+    ///
+    /// This is how the game treats a map without visibility data.
+    pub fn all_visible(vis_leaf_count: usize) -> Self {
+        let mut res = Self::new(vis_leaf_count);
+
+        for row in res.rows.iter_mut() {
+            *row = full_row(vis_leaf_count);
+        }
+
+        res
+    }
+
+    pub fn vis_leaf_count(&self) -> usize {
+        self.vis_leaf_count
+    }
+
+    fn is_vis_leaf(&self, leaf: usize) -> bool {
+        leaf != 0 && leaf <= self.vis_leaf_count
+    }
+
+    /// Decompressed row of bits for the leaf.
+    ///
+    /// Returns `None` if the leaf does not have visibility information like leaf 0.
+    pub fn row(&self, leaf: usize) -> Option<&[u8]> {
+        if !self.is_vis_leaf(leaf) {
+            return None;
+        }
+
+        Some(&self.rows[leaf - 1])
+    }
+
+    /// Whether leaf `from` can see leaf `to`.
+    ///
+    /// Leaves without visibility information cannot see or be seen.
+    pub fn can_see(&self, from: usize, to: usize) -> bool {
+        if !self.is_vis_leaf(from) || !self.is_vis_leaf(to) {
+            return false;
+        }
+
+        let bit = to - 1;
+
+        self.rows[from - 1][bit >> 3] & (1 << (bit & 7)) != 0
+    }
+
+    /// Marks leaf `to` visible or not from leaf `from`.
+    ///
+    /// This does not make `from` visible from `to`. Use [`Pvs::set_mutual`] for that.
+    ///
+    /// Does nothing if either leaf does not have visibility information.
+    pub fn set(&mut self, from: usize, to: usize, visible: bool) {
+        if !self.is_vis_leaf(from) || !self.is_vis_leaf(to) {
+            return;
+        }
+
+        let bit = to - 1;
+        let byte = &mut self.rows[from - 1][bit >> 3];
+
+        if visible {
+            *byte |= 1 << (bit & 7);
+        } else {
+            *byte &= !(1 << (bit & 7));
+        }
+    }
+
+    /// Marks both leaves visible or not from each other.
+    pub fn set_mutual(&mut self, a: usize, b: usize, visible: bool) {
+        self.set(a, b, visible);
+        self.set(b, a, visible);
+    }
+
+    /// Leaf indices that the leaf can see.
+    pub fn visible_leaves(&self, leaf: usize) -> Vec<usize> {
+        if !self.is_vis_leaf(leaf) {
+            return vec![];
+        }
+
+        (1..=self.vis_leaf_count)
+            .filter(|&other| self.can_see(leaf, other))
+            .collect()
+    }
+
+    /// Replaces the whole visible set of a leaf.
+    pub fn set_visible_leaves(&mut self, leaf: usize, visible: &[usize]) {
+        if !self.is_vis_leaf(leaf) {
+            return;
+        }
+
+        self.rows[leaf - 1].fill(0);
+
+        visible
+            .iter()
+            .for_each(|&other| self.set(leaf, other, true));
+    }
+}
+
+/// Bytes needed for one decompressed row.
+pub fn row_length(vis_leaf_count: usize) -> usize {
+    (vis_leaf_count + 7) >> 3
+}
+
+// every bit is set but the bits past the last leaf are not
+fn full_row(vis_leaf_count: usize) -> Vec<u8> {
+    let mut row = vec![0xffu8; row_length(vis_leaf_count)];
+
+    if !vis_leaf_count.is_multiple_of(8) {
+        if let Some(last) = row.last_mut() {
+            *last = (1u8 << (vis_leaf_count % 8)) - 1;
+        }
+    }
+
+    row
+}
+
+/// Decompresses one row starting at the beginning of `i`.
+///
+/// Missing bytes from truncated data are treated as zeroes.
+///
+/// Same as `Mod_DecompressVis`.
+pub fn decompress_vis_row(i: &[u8], vis_leaf_count: usize) -> Vec<u8> {
+    let row_length = row_length(vis_leaf_count);
+    let mut res = Vec::with_capacity(row_length);
+    let mut i = i.iter();
+
+    while res.len() < row_length {
+        let Some(&byte) = i.next() else {
+            break;
+        };
+
+        if byte != 0 {
+            res.push(byte);
+            continue;
+        }
+
+        let Some(&zero_count) = i.next() else {
+            break;
+        };
+
+        res.extend(std::iter::repeat_n(0, zero_count as usize));
+    }
+
+    // a run of zeroes could go past the row
+    res.resize(row_length, 0);
+
+    res
+}
+
+/// Compresses one decompressed row.
+///
+/// Same as `CompressVis` in hlvis. A run of zero bytes is written as 0 followed by the run length.
+pub fn compress_vis_row(row: &[u8]) -> Vec<u8> {
+    let mut res = vec![];
+    let mut idx = 0;
+
+    while idx < row.len() {
+        let byte = row[idx];
+
+        res.push(byte);
+        idx += 1;
+
+        if byte != 0 {
+            continue;
+        }
+
+        let mut rep = 1u8;
+
+        while idx < row.len() && row[idx] == 0 && rep < 255 {
+            rep += 1;
+            idx += 1;
+        }
+
+        res.push(rep);
+    }
+
+    res
+}
+
+impl Bsp {
+    /// Number of leaves having visibility information, leaf 0 excluded.
+    pub fn vis_leaf_count(&self) -> usize {
+        self.models
+            .first()
+            .map(|world| world.vis_leaves_count.max(0) as usize)
+            // the leaf count also counts leaf 0
+            .unwrap_or(self.leaves.len().saturating_sub(1))
+            .min(self.leaves.len().saturating_sub(1))
+    }
+
+    /// Decompresses the visibility lump.
+    ///
+    /// Leaves without visibility data can see every leaf, same as the game.
+    pub fn pvs(&self) -> Pvs {
+        let vis_leaf_count = self.vis_leaf_count();
+        let mut res = Pvs::new(vis_leaf_count);
+
+        for leaf_idx in 1..=vis_leaf_count {
+            let vis_offset = self.leaves[leaf_idx].vis_offset;
+
+            res.rows[leaf_idx - 1] =
+                if vis_offset < 0 || vis_offset as usize >= self.visibility.len() {
+                    full_row(vis_leaf_count)
+                } else {
+                    decompress_vis_row(&self.visibility[vis_offset as usize..], vis_leaf_count)
+                };
+        }
+
+        res
+    }
+
+    /// Compresses the PVS back into the visibility lump and updates every visible leaf offset.
+    ///
+    /// Leaves with identical rows will share the same data.
+    pub fn set_pvs(&mut self, pvs: &Pvs) {
+        let vis_leaf_count = pvs.vis_leaf_count.min(self.leaves.len().saturating_sub(1));
+
+        let mut visibility: Vec<u8> = vec![];
+        let mut written: HashMap<&[u8], i32> = HashMap::new();
+
+        for leaf_idx in 1..=vis_leaf_count {
+            let row = pvs.rows[leaf_idx - 1].as_slice();
+
+            let vis_offset = *written.entry(row).or_insert_with(|| {
+                let offset = visibility.len() as i32;
+
+                visibility.extend(compress_vis_row(row));
+
+                offset
+            });
+
+            self.leaves[leaf_idx].vis_offset = vis_offset;
+        }
+
+        self.visibility = visibility;
+    }
+}
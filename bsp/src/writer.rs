@@ -14,7 +14,7 @@ use crate::{
         LUMP_SURFEDGES, LUMP_TEXINFO, LUMP_TEXTURES, LUMP_VERTICES, LUMP_VISIBILITY,
     },
     error::BspError,
-    parse_bsp, Bsp, ClipNode, Face, Leaf, Model, TexInfo,
+    is_missing_texture, parse_bsp, Bsp, BspVariant, ClipNode, Face, Leaf, Model, TexInfo, Texture,
 };
 
 impl Bsp {
@@ -49,392 +49,305 @@ impl Bsp {
     }
 
     pub fn write_to_bytes(&self) -> Vec<u8> {
-        // for most compilers, there's 7 bytes of trailing null at the end of the file
-        // not sure which lump it belongs to but entity lump is at the end if that clears up anything
-
         let mut writer = ByteWriter::new();
 
-        writer.append_i32(30); // version
+        writer.append_i32(self.variant.version());
 
         // will be writing the offset later on
         let lump_headers_offset = writer.get_offset();
         let lump_headers_padding = vec![0u8; HEADER_LUMP_SIZE * HEADER_LUMPS];
         writer.append_u8_slice(&lump_headers_padding);
 
-        // just writes all the lumps like normal then we go back to the lump header again
-        // this means if we have weird lump header order, this could be changed easily
-        // by changing the numbers in constants.rs
-
-        // write entities
-        {
-            let offset = writer.get_offset();
-            let mut entity_str = String::new();
-
-            self.entities.iter().for_each(|entity| {
-                // start with "{" then "\n"
-                // "\n" at the end of key-value pair
-                // " " to separate between key and value
-                // ends "}" and no need for "\n" because it is from previous pair
-                entity_str += "{\n";
-
-                entity.iter().for_each(|(key, value)| {
-                    entity_str += format!("\"{}\" \"{}\"\n", key, value).as_str()
-                });
-
-                // "\n" will separate entity
-                entity_str += "}\n";
-            });
-
-            // null at the end for some reasons
-            writer.append_string(entity_str.as_str());
-            writer.append_u8(0);
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_ENTITIES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write planes
-        {
-            let offset = writer.get_offset();
-
-            self.planes.iter().for_each(|plane| {
-                writer.append_f32(plane.normal.x);
-                writer.append_f32(plane.normal.y);
-                writer.append_f32(plane.normal.z);
-
-                writer.append_f32(plane.distance);
-                writer.append_i32(plane.type_ as i32);
-            });
+        // lumps are written in the same order as the file we parse from
+        // then we go back to the lump header to write the offset and length
+        self.lump_order.iter().for_each(|&lump| {
+            // compilers start every lump at 4 bytes alignment and pad with null
+            writer.append_u8_slice(&vec![0u8; (4 - writer.get_offset() % 4) % 4]);
 
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_PLANES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write textures
-        {
             let offset = writer.get_offset();
 
-            // texture count
-            writer.append_u32(self.textures.len() as u32);
-
-            // pad offset
-            let offsets_start = writer.get_offset();
-            (0..self.textures.len()).for_each(|_| {
-                writer.append_i32(0); // dummy
-            });
-
-            self.textures.iter().enumerate().for_each(|(idx, texture)| {
-                let texture_offset = writer.get_offset();
-
-                // texture offset is relative to where the lump starts
-                // for embedded texture, this is still needed
-                writer.replace_with_u32(offsets_start + idx * 4, (texture_offset - offset) as u32);
-
-                texture.write(&mut writer);
-            });
+            self.write_lump(lump, &mut writer);
 
             let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_TEXTURES * HEADER_LUMP_SIZE;
+            let header_lump = match (self.variant, lump) {
+                (BspVariant::BlueShift, LUMP_ENTITIES) => LUMP_PLANES,
+                (BspVariant::BlueShift, LUMP_PLANES) => LUMP_ENTITIES,
+                _ => lump,
+            };
+            let header = lump_headers_offset + header_lump * HEADER_LUMP_SIZE;
 
             writer.replace_with_i32(header, offset as i32);
             writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write vertices
-        {
-            let offset = writer.get_offset();
-
-            self.vertices.iter().for_each(|vertex| {
-                writer.append_f32(vertex.x);
-                writer.append_f32(vertex.y);
-                writer.append_f32(vertex.z);
-            });
+        });
 
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_VERTICES * HEADER_LUMP_SIZE;
+        writer.data
+    }
 
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
+    pub(crate) fn write_lump(&self, lump: usize, writer: &mut ByteWriter) {
+        match lump {
+            LUMP_ENTITIES => {
+                let entity_bytes = self.ent_bytes();
+
+                // null at the end for some reasons
+                writer.append_u8_slice(&entity_bytes);
+                writer.append_u8(0);
+            }
+            LUMP_PLANES => {
+                self.planes.iter().for_each(|plane| {
+                    writer.append_f32(plane.normal.x);
+                    writer.append_f32(plane.normal.y);
+                    writer.append_f32(plane.normal.z);
+
+                    writer.append_f32(plane.distance);
+                    writer.append_i32(plane.type_ as i32);
+                });
+            }
+            LUMP_TEXTURES => {
+                let offset = writer.get_offset();
 
-        // write visibility
-        {
-            let offset = writer.get_offset();
+                // texture count
+                writer.append_u32(self.textures.len() as u32);
 
-            // TODO
-            writer.append_u8_slice(&self.visibility);
+                // pad offset
+                let offsets_start = writer.get_offset();
+                (0..self.textures.len()).for_each(|_| {
+                    writer.append_i32(0); // dummy
+                });
 
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_VISIBILITY * HEADER_LUMP_SIZE;
+                self.textures.iter().enumerate().for_each(|(idx, texture)| {
+                    if is_missing_texture(texture) {
+                        writer.replace_with_i32(offsets_start + idx * 4, -1);
+                        return;
+                    }
 
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
+                    let texture_offset = writer.get_offset();
 
-        // write nodes
-        {
-            let offset = writer.get_offset();
+                    // texture offset is relative to where the lump starts
+                    // for embedded texture, this is still needed
+                    writer.replace_with_u32(
+                        offsets_start + idx * 4,
+                        (texture_offset - offset) as u32,
+                    );
 
-            self.nodes.iter().for_each(|node| {
-                writer.append_u32(node.plane);
-                writer.append_i16(node.children[0]);
-                writer.append_i16(node.children[1]);
+                    match self.variant {
+                        BspVariant::Quake => write_quake_miptex(texture, writer),
+                        BspVariant::GoldSrc | BspVariant::BlueShift => texture.write(writer),
+                    }
 
-                node.mins.iter().for_each(|&x| {
-                    writer.append_i16(x);
+                    // embedded textures are padded to 4 bytes like in WAD
+                    writer.append_u8_slice(&vec![0u8; (4 - writer.get_offset() % 4) % 4]);
                 });
-                node.maxs.iter().for_each(|&x| {
-                    writer.append_i16(x);
+            }
+            LUMP_VERTICES => {
+                self.vertices.iter().for_each(|vertex| {
+                    writer.append_f32(vertex.x);
+                    writer.append_f32(vertex.y);
+                    writer.append_f32(vertex.z);
                 });
-
-                writer.append_u16(node.first_face);
-                writer.append_u16(node.face_count);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_NODES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write texinfo
-        {
-            let offset = writer.get_offset();
-
-            self.texinfo.iter().for_each(
-                |TexInfo {
-                     u,
-                     u_offset,
-                     v,
-                     v_offset,
-                     texture_index,
-                     flags,
-                 }| {
-                    writer.append_f32(u.x);
-                    writer.append_f32(u.y);
-                    writer.append_f32(u.z);
-                    writer.append_f32(*u_offset);
-
-                    writer.append_f32(v.x);
-                    writer.append_f32(v.y);
-                    writer.append_f32(v.z);
-                    writer.append_f32(*v_offset);
-
-                    writer.append_u32(*texture_index);
-                    writer.append_u32(*flags);
-                },
-            );
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_TEXINFO * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write faces
-        {
-            let offset = writer.get_offset();
-
-            self.faces.iter().for_each(
-                |Face {
-                     plane,
-                     side,
-                     first_edge,
-                     edge_count,
-                     texinfo,
-                     styles,
-                     lightmap_offset,
-                 }| {
-                    writer.append_u16(*plane);
-                    writer.append_u16(*side);
-                    writer.append_i32(*first_edge);
-                    writer.append_u16(*edge_count);
-                    writer.append_u16(*texinfo);
-
-                    styles.iter().for_each(|&v| {
-                        writer.append_u8(v);
+            }
+            LUMP_VISIBILITY => {
+                writer.append_u8_slice(&self.visibility);
+            }
+            LUMP_NODES => {
+                self.nodes.iter().for_each(|node| {
+                    writer.append_u32(node.plane);
+                    writer.append_i16(node.children[0]);
+                    writer.append_i16(node.children[1]);
+
+                    node.mins.iter().for_each(|&x| {
+                        writer.append_i16(x);
+                    });
+                    node.maxs.iter().for_each(|&x| {
+                        writer.append_i16(x);
                     });
 
-                    writer.append_i32(*lightmap_offset);
-                },
-            );
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_FACES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write lightmap
-        {
-            let offset = writer.get_offset();
-
-            self.lightmap.iter().for_each(|lightmap| {
-                writer.append_u8_slice(lightmap);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_LIGHTING * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write clipnodes
-        {
-            let offset = writer.get_offset();
-
-            self.clipnodes
-                .iter()
-                .for_each(|ClipNode { plane, children }| {
-                    writer.append_i32(*plane);
-                    writer.append_i16(children[0]);
-                    writer.append_i16(children[1]);
+                    writer.append_u16(node.first_face);
+                    writer.append_u16(node.face_count);
                 });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_CLIPNODES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write leaves
-        {
-            let offset = writer.get_offset();
-
-            self.leaves.iter().for_each(
-                |Leaf {
-                     contents,
-                     vis_offset,
-                     mins,
-                     maxs,
-                     first_mark_surface,
-                     mark_surface_count,
-                     ambient_levels,
-                 }| {
-                    writer.append_i32(*contents as i32);
-                    writer.append_i32(*vis_offset);
-
-                    mins.iter().for_each(|&v| {
-                        writer.append_i16(v);
+            }
+            LUMP_TEXINFO => {
+                self.texinfo.iter().for_each(
+                    |TexInfo {
+                         u,
+                         u_offset,
+                         v,
+                         v_offset,
+                         texture_index,
+                         flags,
+                     }| {
+                        writer.append_f32(u.x);
+                        writer.append_f32(u.y);
+                        writer.append_f32(u.z);
+                        writer.append_f32(*u_offset);
+
+                        writer.append_f32(v.x);
+                        writer.append_f32(v.y);
+                        writer.append_f32(v.z);
+                        writer.append_f32(*v_offset);
+
+                        writer.append_u32(*texture_index);
+                        writer.append_u32(*flags);
+                    },
+                );
+            }
+            LUMP_FACES => {
+                self.faces.iter().for_each(
+                    |Face {
+                         plane,
+                         side,
+                         first_edge,
+                         edge_count,
+                         texinfo,
+                         styles,
+                         lightmap_offset,
+                     }| {
+                        writer.append_u16(*plane);
+                        writer.append_u16(*side);
+                        writer.append_i32(*first_edge);
+                        writer.append_u16(*edge_count);
+                        writer.append_u16(*texinfo);
+
+                        styles.iter().for_each(|&v| {
+                            writer.append_u8(v);
+                        });
+
+                        writer.append_i32(*lightmap_offset);
+                    },
+                );
+            }
+            LUMP_LIGHTING => {
+                // map with zero lightmap has lump with size of 1
+                if self.lightmap.is_empty() {
+                    writer.append_u8(0);
+                }
+
+                self.lightmap
+                    .iter()
+                    .for_each(|lightmap| match self.variant {
+                        // average because there is only one channel
+                        BspVariant::Quake => writer.append_u8(
+                            ((lightmap.iter().map(|&v| v as u32).sum::<u32>() + 1) / 3) as u8,
+                        ),
+                        BspVariant::GoldSrc | BspVariant::BlueShift => {
+                            writer.append_u8_slice(lightmap)
+                        }
                     });
-                    maxs.iter().for_each(|&v| {
-                        writer.append_i16(v);
+            }
+            LUMP_CLIPNODES => {
+                self.clipnodes
+                    .iter()
+                    .for_each(|ClipNode { plane, children }| {
+                        writer.append_i32(*plane);
+                        writer.append_i16(children[0]);
+                        writer.append_i16(children[1]);
                     });
-
-                    writer.append_u16(*first_mark_surface);
-                    writer.append_u16(*mark_surface_count);
-
-                    ambient_levels.iter().for_each(|&v| {
-                        writer.append_u8(v);
-                    });
-                },
-            );
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_LEAVES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write mark surfaces
-        {
-            let offset = writer.get_offset();
-
-            self.mark_surfaces.iter().for_each(|&v| {
-                writer.append_u16(v);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_MARKSURFACES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write edges
-        {
-            let offset = writer.get_offset();
-
-            self.edges.iter().for_each(|&[p1, p2]| {
-                writer.append_u16(p1);
-                writer.append_u16(p2);
-            });
-
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_EDGES * HEADER_LUMP_SIZE;
-
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
+            }
+            LUMP_LEAVES => {
+                self.leaves.iter().for_each(
+                    |Leaf {
+                         contents,
+                         vis_offset,
+                         mins,
+                         maxs,
+                         first_mark_surface,
+                         mark_surface_count,
+                         ambient_levels,
+                     }| {
+                        writer.append_i32(*contents as i32);
+                        writer.append_i32(*vis_offset);
+
+                        mins.iter().for_each(|&v| {
+                            writer.append_i16(v);
+                        });
+                        maxs.iter().for_each(|&v| {
+                            writer.append_i16(v);
+                        });
+
+                        writer.append_u16(*first_mark_surface);
+                        writer.append_u16(*mark_surface_count);
+
+                        ambient_levels.iter().for_each(|&v| {
+                            writer.append_u8(v);
+                        });
+                    },
+                );
+            }
+            LUMP_MARKSURFACES => {
+                self.mark_surfaces.iter().for_each(|&v| {
+                    writer.append_u16(v);
+                });
+            }
+            LUMP_EDGES => {
+                self.edges.iter().for_each(|&[p1, p2]| {
+                    writer.append_u16(p1);
+                    writer.append_u16(p2);
+                });
+            }
+            LUMP_SURFEDGES => {
+                self.surf_edges.iter().for_each(|&v| {
+                    writer.append_i32(v);
+                });
+            }
+            LUMP_MODELS => {
+                self.models.iter().for_each(
+                    |Model {
+                         mins,
+                         maxs,
+                         origin,
+                         head_nodes,
+                         vis_leaves_count,
+                         first_face,
+                         face_count,
+                     }| {
+                        writer.append_f32(mins.x);
+                        writer.append_f32(mins.y);
+                        writer.append_f32(mins.z);
+                        writer.append_f32(maxs.x);
+                        writer.append_f32(maxs.y);
+                        writer.append_f32(maxs.z);
+                        writer.append_f32(origin.x);
+                        writer.append_f32(origin.y);
+                        writer.append_f32(origin.z);
+
+                        head_nodes.iter().for_each(|&v| {
+                            writer.append_i32(v);
+                        });
+
+                        writer.append_i32(*vis_leaves_count);
+                        writer.append_i32(*first_face);
+                        writer.append_i32(*face_count);
+                    },
+                );
+            }
+            _ => unreachable!("lump index is out of bound"),
         }
+    }
+}
 
-        // write surf edges
-        {
-            let offset = writer.get_offset();
-
-            self.surf_edges.iter().for_each(|&v| {
-                writer.append_i32(v);
-            });
+// Quake textures do not have palette after the mipmaps
+fn write_quake_miptex(texture: &Texture, writer: &mut ByteWriter) {
+    let texture_name_bytes = texture.texture_name.get_bytes();
 
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_SURFEDGES * HEADER_LUMP_SIZE;
+    writer.append_u8_slice(texture_name_bytes);
+    writer.append_u8_slice(&vec![0u8; 16 - texture_name_bytes.len()]);
 
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
-
-        // write models
-        {
-            let offset = writer.get_offset();
+    writer.append_u32(texture.width);
+    writer.append_u32(texture.height);
 
-            self.models.iter().for_each(
-                |Model {
-                     mins,
-                     maxs,
-                     origin,
-                     head_nodes,
-                     vis_leaves_count,
-                     first_face,
-                     face_count,
-                 }| {
-                    writer.append_f32(mins.x);
-                    writer.append_f32(mins.y);
-                    writer.append_f32(mins.z);
-                    writer.append_f32(maxs.x);
-                    writer.append_f32(maxs.y);
-                    writer.append_f32(maxs.z);
-                    writer.append_f32(origin.x);
-                    writer.append_f32(origin.y);
-                    writer.append_f32(origin.z);
-
-                    head_nodes.iter().for_each(|&v| {
-                        writer.append_i32(v);
-                    });
-
-                    writer.append_i32(*vis_leaves_count);
-                    writer.append_i32(*first_face);
-                    writer.append_i32(*face_count);
-                },
-            );
+    if texture.is_external() {
+        (0..4).for_each(|_| writer.append_u32(0));
+        return;
+    }
 
-            let length = writer.get_offset() - offset;
-            let header = lump_headers_offset + LUMP_MODELS * HEADER_LUMP_SIZE;
+    // mipmaps are right after the header
+    let mut mip_offset = 16 + 4 + 4 + 4 * 4;
 
-            writer.replace_with_i32(header, offset as i32);
-            writer.replace_with_i32(header + 4, length as i32);
-        }
+    texture.mip_images.iter().for_each(|image| {
+        writer.append_u32(mip_offset);
+        mip_offset += image.data.get_bytes().len() as u32;
+    });
 
-        writer.data
-    }
+    texture.mip_images.iter().for_each(|image| {
+        writer.append_u8_slice(image.data.get_bytes());
+    });
 }
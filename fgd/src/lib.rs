@@ -0,0 +1,796 @@
+//! Forge Game Data, the entity definitions of the level editors.
+//!
+//! Only the GoldSrc parts are understood. Helpers that are not about how the entity is checked are kept as
+//! text so they are written back the same.
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use eyre::eyre;
+
+mod parser;
+
+use parser::parse_fgd;
+
+/// Keys the editors and compilers add by themselves.
+const IMPLICIT_KEYS: &[&str] = &[
+    "classname",
+    "origin",
+    "model",
+    "mapversion",
+    "wad",
+    "_generator",
+];
+
+// includes including each other
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassType {
+    /// Only for other classes to inherit.
+    Base,
+    Point,
+    Solid,
+    /// Classes from other engines, like `@NPCClass`, without the `@` and `Class`.
+    Other(String),
+}
+
+impl From<&str> for ClassType {
+    fn from(value: &str) -> Self {
+        let name = value.strip_suffix("Class").unwrap_or(value);
+
+        match name.to_lowercase().as_str() {
+            "base" => Self::Base,
+            "point" => Self::Point,
+            "solid" => Self::Solid,
+            _ => Self::Other(name.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ClassType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Base => write!(f, "@BaseClass"),
+            Self::Point => write!(f, "@PointClass"),
+            Self::Solid => write!(f, "@SolidClass"),
+            Self::Other(name) => write!(f, "@{}Class", name),
+        }
+    }
+}
+
+/// Parts between the class type and the class name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Helper {
+    /// `base(Targetname, Angles)`
+    Base(Vec<String>),
+    /// `size(-16 -16 0, 16 16 72)`, a single size is centered on the origin.
+    Size { mins: [f64; 3], maxs: [f64; 3] },
+    /// `color(255 0 255)`
+    Color([u8; 3]),
+    /// `studio("models/player.mdl")` or `studio()` to show the `model` key.
+    Studio(Option<String>),
+    /// `sprite("sprites/glow01.spr")` or `sprite()` to show the `model` key.
+    Sprite(Option<String>),
+    /// Anything else, like `iconsprite("sprites/light.spr")` or `flags(Angle)`.
+    Other { name: String, args: Option<String> },
+}
+
+impl fmt::Display for Helper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = |path: &Option<String>| {
+            path.as_ref()
+                .map(|path| format!("\"{}\"", path))
+                .unwrap_or_default()
+        };
+
+        match self {
+            Self::Base(bases) => write!(f, "base({})", bases.join(", ")),
+            Self::Size { mins, maxs } => write!(
+                f,
+                "size({} {} {}, {} {} {})",
+                mins[0], mins[1], mins[2], maxs[0], maxs[1], maxs[2]
+            ),
+            Self::Color([r, g, b]) => write!(f, "color({} {} {})", r, g, b),
+            Self::Studio(model) => write!(f, "studio({})", path(model)),
+            Self::Sprite(sprite) => write!(f, "sprite({})", path(sprite)),
+            Self::Other { name, args: None } => write!(f, "{}", name),
+            Self::Other {
+                name,
+                args: Some(args),
+            } => write!(f, "{}({})", name, args),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ValueType {
+    #[default]
+    String,
+    Integer,
+    Float,
+    Choices,
+    Flags,
+    /// `r g b` or `r g b brightness` from 0 to 255.
+    Color255,
+    /// `r g b` from 0 to 1.
+    Color1,
+    Studio,
+    Sprite,
+    Sound,
+    Decal,
+    TargetSource,
+    TargetDestination,
+    Other(String),
+}
+
+impl From<&str> for ValueType {
+    fn from(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "string" => Self::String,
+            "integer" => Self::Integer,
+            "float" => Self::Float,
+            "choices" => Self::Choices,
+            "flags" => Self::Flags,
+            "color255" => Self::Color255,
+            "color1" => Self::Color1,
+            "studio" => Self::Studio,
+            "sprite" => Self::Sprite,
+            "sound" => Self::Sound,
+            "decal" => Self::Decal,
+            "target_source" => Self::TargetSource,
+            "target_destination" => Self::TargetDestination,
+            _ => Self::Other(value.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Choices => "choices",
+            Self::Flags => "flags",
+            Self::Color255 => "color255",
+            Self::Color1 => "color1",
+            Self::Studio => "studio",
+            Self::Sprite => "sprite",
+            Self::Sound => "sound",
+            Self::Decal => "decal",
+            Self::TargetSource => "target_source",
+            Self::TargetDestination => "target_destination",
+            Self::Other(name) => name,
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl ValueType {
+    /// Whether the value can be read as this type. Choices and flags are only checked to be numbers.
+    pub fn accepts(&self, value: &str) -> bool {
+        let numbers = |range: std::ops::RangeInclusive<usize>| {
+            let numbers = value.split_whitespace().collect::<Vec<_>>();
+
+            range.contains(&numbers.len()) && numbers.iter().all(|n| n.parse::<f64>().is_ok())
+        };
+
+        match self {
+            Self::Integer | Self::Flags => value.trim().parse::<i64>().is_ok(),
+            Self::Float => value.trim().parse::<f64>().is_ok(),
+            Self::Color255 | Self::Color1 => numbers(3..=4),
+            _ => true,
+        }
+    }
+}
+
+/// Choice of a `choices` key or bit of a `flags` key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Choice {
+    pub value: String,
+    pub name: String,
+    /// Whether the flag is on by default. Choices do not have it.
+    pub enabled: Option<bool>,
+}
+
+impl fmt::Display for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.value.parse::<f64>().is_ok() {
+            write!(f, "{} : \"{}\"", self.value, self.name)?;
+        } else {
+            write!(f, "\"{}\" : \"{}\"", self.value, self.name)?;
+        }
+
+        if let Some(enabled) = self.enabled {
+            write!(f, " : {}", enabled as u8)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Key of a class.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub value_type: ValueType,
+    pub display_name: Option<String>,
+    pub default: Option<String>,
+    pub description: Option<String>,
+    /// For `choices` and `flags`.
+    pub choices: Vec<Choice>,
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.value_type)?;
+
+        // the fields are in order so the ones before have to be there
+        let fields = [
+            self.display_name
+                .as_ref()
+                .map(|name| format!("\"{}\"", name)),
+            self.default.as_ref().map(|default| {
+                let is_number = matches!(
+                    self.value_type,
+                    ValueType::Integer | ValueType::Float | ValueType::Choices
+                ) && default.parse::<f64>().is_ok();
+
+                if is_number {
+                    default.to_string()
+                } else {
+                    format!("\"{}\"", default)
+                }
+            }),
+            self.description
+                .as_ref()
+                .map(|description| format!("\"{}\"", description)),
+        ];
+
+        let count = fields
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |idx| idx + 1);
+
+        for field in &fields[..count] {
+            match field {
+                Some(field) => write!(f, " : {}", field)?,
+                None => write!(f, " :")?,
+            }
+        }
+
+        if !self.choices.is_empty() || matches!(self.value_type, ValueType::Flags) {
+            writeln!(f, " =")?;
+            writeln!(f, "\t[")?;
+
+            for choice in &self.choices {
+                writeln!(f, "\t\t{}", choice)?;
+            }
+
+            write!(f, "\t]")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub class_type: ClassType,
+    pub name: String,
+    pub description: Option<String>,
+    pub helpers: Vec<Helper>,
+    pub properties: Vec<Property>,
+}
+
+impl Class {
+    /// Names in every `base()` helper.
+    pub fn bases(&self) -> impl Iterator<Item = &String> {
+        self.helpers.iter().flat_map(|helper| match helper {
+            Helper::Base(bases) => bases.as_slice(),
+            _ => &[],
+        })
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.class_type)?;
+
+        for helper in &self.helpers {
+            write!(f, " {}", helper)?;
+        }
+
+        write!(f, " = {}", self.name)?;
+
+        if let Some(description) = &self.description {
+            write!(f, " : \"{}\"", description)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "[")?;
+
+        for property in &self.properties {
+            writeln!(f, "\t{}", property)?;
+        }
+
+        writeln!(f, "]")
+    }
+}
+
+/// Why an entity does not fit its class.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityIssue {
+    NoClassname,
+    UnknownClass(String),
+    /// Base classes cannot be placed.
+    BaseClass(String),
+    UnknownKey(String),
+    WrongType {
+        key: String,
+        value: String,
+        value_type: ValueType,
+    },
+    /// Value that is not a choice, or bits that are not flags.
+    InvalidChoice {
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for EntityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoClassname => write!(f, "entity has no classname"),
+            Self::UnknownClass(class) => write!(f, "class \"{}\" is not defined", class),
+            Self::BaseClass(class) => write!(f, "class \"{}\" is a base class", class),
+            Self::UnknownKey(key) => write!(f, "key \"{}\" is not in the class", key),
+            Self::WrongType {
+                key,
+                value,
+                value_type,
+            } => write!(
+                f,
+                "value \"{}\" of key \"{}\" is not {}",
+                value, key, value_type
+            ),
+            Self::InvalidChoice { key, value } => write!(
+                f,
+                "value \"{}\" of key \"{}\" is not one of the choices",
+                value, key
+            ),
+        }
+    }
+}
+
+fn same_number(a: &str, b: &str) -> bool {
+    matches!((a.trim().parse::<f64>(), b.trim().parse::<f64>()), (Ok(a), Ok(b)) if a == b)
+}
+
+fn check_choice(property: &Property, value: &str) -> Option<EntityIssue> {
+    let invalid = || EntityIssue::InvalidChoice {
+        key: property.name.clone(),
+        value: value.to_string(),
+    };
+
+    match property.value_type {
+        ValueType::Flags => {
+            let bits = value.trim().parse::<i64>().ok()?;
+            let known = property
+                .choices
+                .iter()
+                .filter_map(|choice| choice.value.trim().parse::<i64>().ok())
+                .fold(0, |acc, bit| acc | bit);
+
+            (bits & !known != 0).then(invalid)
+        }
+        _ if property.choices.is_empty() => None,
+        _ => {
+            let is_choice = property
+                .choices
+                .iter()
+                .any(|choice| choice.value == value || same_number(&choice.value, value));
+
+            (!is_choice).then(invalid)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fgd {
+    /// `@include` paths that are not loaded yet.
+    pub includes: Vec<String>,
+    /// `@mapsize(min, max)`
+    pub map_size: Option<(i32, i32)>,
+    pub classes: Vec<Class>,
+}
+
+impl Fgd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_text(text: &str) -> eyre::Result<Self> {
+        match parse_fgd(text) {
+            Ok((_, res)) => Ok(res),
+            Err(nom::Err::Error(err) | nom::Err::Failure(err)) => {
+                let line = text[..text.len() - err.input.len()].lines().count().max(1);
+
+                Err(eyre!("Cannot parse FGD at line {}", line))
+            }
+            Err(err) => Err(eyre!("Cannot parse FGD: {}", err)),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+
+        Self::from_text(&text)
+    }
+
+    /// Loads `@include` files relative to the directory and puts their classes before the classes here.
+    pub fn load_includes(&mut self, dir: impl AsRef<Path>) -> eyre::Result<()> {
+        self.load_includes_inner(dir.as_ref(), 0)
+    }
+
+    fn load_includes_inner(&mut self, dir: &Path, depth: usize) -> eyre::Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(eyre!("FGD includes go too deep"));
+        }
+
+        let mut res = Self::new();
+
+        for include in std::mem::take(&mut self.includes) {
+            let path = dir.join(&include);
+
+            if !path.exists() {
+                return Err(eyre!("Cannot find included FGD {}", path.display()));
+            }
+
+            let mut fgd = Self::from_file(&path)?;
+            fgd.load_includes_inner(path.parent().unwrap_or(dir), depth + 1)?;
+
+            res.extend(fgd);
+        }
+
+        res.extend(std::mem::take(self));
+        *self = res;
+
+        Ok(())
+    }
+
+    /// Adds the classes of the other FGD, which win over the classes with the same name here.
+    pub fn extend(&mut self, other: Self) {
+        self.includes.extend(other.includes);
+        self.map_size = other.map_size.or(self.map_size);
+        self.classes.extend(other.classes);
+    }
+
+    /// Class with the name, ignoring case like the editors.
+    ///
+    /// The last one is returned when the name is defined more than once.
+    pub fn get_class(&self, name: &str) -> Option<&Class> {
+        self.classes
+            .iter()
+            .rev()
+            .find(|class| class.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Properties of the class and its base classes.
+    ///
+    /// Properties of the class come after and replace properties of its bases. Missing base classes are skipped.
+    pub fn properties(&self, class_name: &str) -> Option<Vec<&Property>> {
+        let class = self.get_class(class_name)?;
+        let mut res = vec![];
+
+        self.collect_properties(class, &mut vec![], &mut res);
+
+        Some(res)
+    }
+
+    fn collect_properties<'a>(
+        &'a self,
+        class: &'a Class,
+        visited: &mut Vec<&'a str>,
+        res: &mut Vec<&'a Property>,
+    ) {
+        if visited
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&class.name))
+        {
+            return;
+        }
+
+        visited.push(&class.name);
+
+        for base in class.bases() {
+            if let Some(base) = self.get_class(base) {
+                self.collect_properties(base, visited, res);
+            }
+        }
+
+        for property in &class.properties {
+            res.retain(|other| !other.name.eq_ignore_ascii_case(&property.name));
+            res.push(property);
+        }
+    }
+
+    /// Checks the key-value pairs of an entity against its class.
+    ///
+    /// Empty values are not checked because the editors write them for keys that are not set.
+    pub fn check_entity(&self, pairs: &[(String, String)]) -> Vec<EntityIssue> {
+        let Some((_, class_name)) = pairs.iter().rev().find(|(key, _)| key == "classname") else {
+            return vec![EntityIssue::NoClassname];
+        };
+
+        let Some(class) = self.get_class(class_name) else {
+            return vec![EntityIssue::UnknownClass(class_name.to_string())];
+        };
+
+        let mut res = vec![];
+
+        if class.class_type == ClassType::Base {
+            res.push(EntityIssue::BaseClass(class_name.to_string()));
+        }
+
+        let properties = self.properties(class_name).unwrap_or_default();
+
+        for (key, value) in pairs {
+            let Some(property) = properties
+                .iter()
+                .find(|property| property.name.eq_ignore_ascii_case(key))
+            else {
+                // spawnflags 0 is written even without flags
+                let is_implicit = IMPLICIT_KEYS.contains(&key.as_str())
+                    || (key == "spawnflags" && same_number(value, "0"));
+
+                if !is_implicit {
+                    res.push(EntityIssue::UnknownKey(key.to_string()));
+                }
+
+                continue;
+            };
+
+            if value.is_empty() {
+                continue;
+            }
+
+            if !property.value_type.accepts(value) {
+                res.push(EntityIssue::WrongType {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    value_type: property.value_type.clone(),
+                });
+
+                continue;
+            }
+
+            res.extend(check_choice(property, value));
+        }
+
+        res
+    }
+
+    pub fn write(&self, path: impl AsRef<Path> + Into<PathBuf>) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut file = BufWriter::new(file);
+
+        file.write_all(self.write_to_string().as_bytes())?;
+
+        file.flush()?;
+
+        Ok(())
+    }
+
+    pub fn write_to_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Fgd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for include in &self.includes {
+            writeln!(f, "@include \"{}\"", include)?;
+        }
+
+        if let Some((min, max)) = self.map_size {
+            writeln!(f, "@mapsize({}, {})", min, max)?;
+        }
+
+        for (idx, class) in self.classes.iter().enumerate() {
+            if idx > 0 || !self.includes.is_empty() || self.map_size.is_some() {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", class)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FGD: &str = r#"// comment at the top
+@include "base.fgd"
+@mapsize(-4096, 4096)
+
+@BaseClass = Targetname [ targetname(target_source) : "Name" ]
+
+@BaseClass base(Targetname) = LightBase
+[
+	_light(color255) : "Brightness" : "255 255 128 200"
+	style(choices) : "Appearance" : 0 =
+	[
+		0 : "Normal"
+		10 : "Fluorescent flicker"
+	]
+]
+
+@PointClass size(16 16 16) color(255 255 0) iconsprite("sprites/light.spr") base(LightBase) = light : "Invisible " +
+	"light source"
+[
+	spawnflags(Flags) =
+	[
+		1 : "Initially dark" : 0
+	]
+	pitch(integer) readonly : "Pitch" : : "Up is positive" // trailing comment
+]
+
+@SolidClass base(Targetname) = func_wall : "Wall" []
+
+@PointClass studio("models/player.mdl") = info_player_start []
+"#;
+
+    #[test]
+    fn parse() {
+        let fgd = Fgd::from_text(FGD).unwrap();
+
+        assert_eq!(fgd.includes, vec!["base.fgd"]);
+        assert_eq!(fgd.map_size, Some((-4096, 4096)));
+        assert_eq!(fgd.classes.len(), 5);
+
+        let light = fgd.get_class("LIGHT").unwrap();
+        assert_eq!(light.class_type, ClassType::Point);
+        assert_eq!(light.description.as_deref(), Some("Invisible light source"));
+        assert_eq!(
+            light.helpers,
+            vec![
+                Helper::Size {
+                    mins: [-8., -8., -8.],
+                    maxs: [8., 8., 8.]
+                },
+                Helper::Color([255, 255, 0]),
+                Helper::Other {
+                    name: "iconsprite".to_string(),
+                    args: Some("\"sprites/light.spr\"".to_string())
+                },
+                Helper::Base(vec!["LightBase".to_string()]),
+            ]
+        );
+
+        let pitch = &light.properties[1];
+        assert_eq!(pitch.value_type, ValueType::Integer);
+        assert_eq!(pitch.display_name.as_deref(), Some("Pitch"));
+        assert_eq!(pitch.default, None);
+        assert_eq!(pitch.description.as_deref(), Some("Up is positive"));
+
+        assert_eq!(
+            light.properties[0].choices[0],
+            Choice {
+                value: "1".to_string(),
+                name: "Initially dark".to_string(),
+                enabled: Some(false)
+            }
+        );
+
+        assert_eq!(
+            fgd.get_class("info_player_start").unwrap().helpers,
+            vec![Helper::Studio(Some("models/player.mdl".to_string()))]
+        );
+
+        let names = fgd
+            .properties("light")
+            .unwrap()
+            .iter()
+            .map(|property| property.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["targetname", "_light", "style", "spawnflags", "pitch"]
+        );
+
+        assert!(Fgd::from_text("@PointClass = broken [ key(string) : ]]").is_err());
+    }
+
+    #[test]
+    fn write_read() {
+        let fgd = Fgd::from_text(FGD).unwrap();
+        let text = fgd.write_to_string();
+
+        assert_eq!(Fgd::from_text(&text).unwrap(), fgd);
+        assert!(text.contains("\tpitch(integer) : \"Pitch\" : : \"Up is positive\"\n"));
+        assert!(text.contains("\tstyle(choices) : \"Appearance\" : 0 =\n"));
+    }
+
+    #[test]
+    fn check() {
+        let fgd = Fgd::from_text(FGD).unwrap();
+
+        let entity = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(fgd
+            .check_entity(&entity(&[
+                ("classname", "light"),
+                ("origin", "0 0 0"),
+                ("targetname", "lamp"),
+                ("_light", "255 255 255 100"),
+                ("style", "10"),
+                ("spawnflags", "1"),
+                ("pitch", ""),
+            ]))
+            .is_empty());
+
+        assert_eq!(
+            fgd.check_entity(&entity(&[
+                ("classname", "light"),
+                ("_light", "bright"),
+                ("style", "3"),
+                ("spawnflags", "3"),
+                ("pitch", "-90.5"),
+                ("zhlt_lightflags", "2"),
+            ])),
+            vec![
+                EntityIssue::WrongType {
+                    key: "_light".to_string(),
+                    value: "bright".to_string(),
+                    value_type: ValueType::Color255
+                },
+                EntityIssue::InvalidChoice {
+                    key: "style".to_string(),
+                    value: "3".to_string()
+                },
+                EntityIssue::InvalidChoice {
+                    key: "spawnflags".to_string(),
+                    value: "3".to_string()
+                },
+                EntityIssue::WrongType {
+                    key: "pitch".to_string(),
+                    value: "-90.5".to_string(),
+                    value_type: ValueType::Integer
+                },
+                EntityIssue::UnknownKey("zhlt_lightflags".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            fgd.check_entity(&entity(&[("classname", "monster_gman")])),
+            vec![EntityIssue::UnknownClass("monster_gman".to_string())]
+        );
+        assert_eq!(
+            fgd.check_entity(&entity(&[("classname", "LightBase")])),
+            vec![EntityIssue::BaseClass("LightBase".to_string())]
+        );
+        assert_eq!(
+            fgd.check_entity(&entity(&[("targetname", "lamp")])),
+            vec![EntityIssue::NoClassname]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_till, take_while1},
+    character::complete::{char, multispace1},
+    combinator::{all_consuming, map, map_opt, opt, value, verify},
+    multi::{many0, separated_list1},
+    sequence::{delimited, preceded, terminated, tuple},
+    IResult as _IResult,
+};
+
+use crate::{Choice, Class, ClassType, Fgd, Helper, Property, ValueType};
+
+pub type IResult<'a, T> = _IResult<&'a str, T>;
+
+enum Item {
+    Include(String),
+    MapSize(i32, i32),
+    Class(Class),
+}
+
+fn comment(i: &str) -> IResult<'_, &str> {
+    preceded(tag("//"), take_till(|c| c == '\n'))(i)
+}
+
+// whitespace and comments
+fn sp(i: &str) -> IResult<'_, ()> {
+    value((), many0(alt((multispace1, comment))))(i)
+}
+
+fn token<'a, T>(f: impl FnMut(&'a str) -> IResult<'a, T>) -> impl FnMut(&'a str) -> IResult<'a, T> {
+    preceded(sp, f)
+}
+
+fn quoted(i: &str) -> IResult<'_, &str> {
+    delimited(char('"'), take_till(|c| c == '"'), char('"'))(i)
+}
+
+// long descriptions are split with `+`
+fn string(i: &str) -> IResult<'_, String> {
+    map(separated_list1(token(char('+')), token(quoted)), |parts| {
+        parts.concat()
+    })(i)
+}
+
+fn ident(i: &str) -> IResult<'_, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')(i)
+}
+
+// numbers and other values without quotes
+fn bare(i: &str) -> IResult<'_, &str> {
+    take_while1(|c: char| !c.is_whitespace() && !":=[]\"".contains(c))(i)
+}
+
+fn value_string(i: &str) -> IResult<'_, String> {
+    alt((string, map(token(bare), str::to_string)))(i)
+}
+
+fn colon<'a, T>(
+    f: impl FnMut(&'a str) -> IResult<'a, T>,
+) -> impl FnMut(&'a str) -> IResult<'a, Option<T>> {
+    preceded(token(char(':')), opt(f))
+}
+
+fn choice(i: &str) -> IResult<'_, Choice> {
+    map(
+        tuple((
+            value_string,
+            preceded(token(char(':')), string),
+            opt(preceded(token(char(':')), token(bare))),
+        )),
+        |(value, name, enabled)| Choice {
+            value,
+            name,
+            enabled: enabled.map(|enabled| enabled != "0"),
+        },
+    )(i)
+}
+
+fn property(i: &str) -> IResult<'_, Property> {
+    let (i, name) = token(ident)(i)?;
+    let (i, value_type) = delimited(token(char('(')), token(ident), token(char(')')))(i)?;
+    let (i, _) = many0(token(verify(ident, |flag: &str| {
+        flag.eq_ignore_ascii_case("readonly") || flag.eq_ignore_ascii_case("report")
+    })))(i)?;
+
+    // every field is optional but they are in order
+    let (i, display_name) = opt(colon(string))(i)?;
+    let (i, default) = match display_name {
+        Some(_) => opt(colon(value_string))(i)?,
+        None => (i, None),
+    };
+    let (i, description) = match default {
+        Some(_) => opt(colon(string))(i)?,
+        None => (i, None),
+    };
+
+    let (i, choices) = opt(preceded(
+        token(char('=')),
+        delimited(token(char('[')), many0(choice), token(char(']'))),
+    ))(i)?;
+
+    Ok((
+        i,
+        Property {
+            name: name.to_string(),
+            value_type: ValueType::from(value_type),
+            display_name: display_name.flatten(),
+            default: default.flatten(),
+            description: description.flatten(),
+            choices: choices.unwrap_or_default(),
+        },
+    ))
+}
+
+fn numbers(s: &str) -> Option<Vec<f64>> {
+    s.split_whitespace().map(|n| n.parse().ok()).collect()
+}
+
+fn helper_from(name: &str, args: Option<&str>) -> Helper {
+    let other = || Helper::Other {
+        name: name.to_string(),
+        args: args.map(str::to_string),
+    };
+
+    let Some(args) = args else {
+        return other();
+    };
+
+    let path = || {
+        let path = args.trim().trim_matches('"');
+        (!path.is_empty()).then(|| path.to_string())
+    };
+
+    match name.to_lowercase().as_str() {
+        "base" => Helper::Base(
+            args.split(',')
+                .map(str::trim)
+                .filter(|base| !base.is_empty())
+                .map(str::to_string)
+                .collect(),
+        ),
+        "size" => {
+            let parts = args.split(',').map(numbers).collect::<Option<Vec<_>>>();
+
+            match parts.as_deref() {
+                // one size is centered on the origin
+                Some([size]) if size.len() == 3 => Helper::Size {
+                    mins: [-size[0] / 2., -size[1] / 2., -size[2] / 2.],
+                    maxs: [size[0] / 2., size[1] / 2., size[2] / 2.],
+                },
+                Some([mins, maxs]) if mins.len() == 3 && maxs.len() == 3 => Helper::Size {
+                    mins: [mins[0], mins[1], mins[2]],
+                    maxs: [maxs[0], maxs[1], maxs[2]],
+                },
+                _ => other(),
+            }
+        }
+        "color" => {
+            let color = args
+                .split_whitespace()
+                .map(|n| n.parse::<u8>().ok())
+                .collect::<Option<Vec<_>>>();
+
+            match color.as_deref() {
+                Some(&[r, g, b]) => Helper::Color([r, g, b]),
+                _ => other(),
+            }
+        }
+        "studio" => Helper::Studio(path()),
+        "sprite" => Helper::Sprite(path()),
+        _ => other(),
+    }
+}
+
+fn helper(i: &str) -> IResult<'_, Helper> {
+    let (i, name) = token(ident)(i)?;
+    let (i, args) = opt(delimited(
+        token(char('(')),
+        take_till(|c| c == ')'),
+        char(')'),
+    ))(i)?;
+
+    Ok((i, helper_from(name, args)))
+}
+
+fn class(i: &str, class_type: ClassType) -> IResult<'_, Class> {
+    let (i, helpers) = many0(helper)(i)?;
+    let (i, name) = preceded(token(char('=')), token(ident))(i)?;
+    let (i, description) = opt(preceded(token(char(':')), string))(i)?;
+    let (i, properties) = delimited(token(char('[')), many0(property), token(char(']')))(i)?;
+
+    Ok((
+        i,
+        Class {
+            class_type,
+            name: name.to_string(),
+            description,
+            helpers,
+            properties,
+        },
+    ))
+}
+
+fn map_size(i: &str) -> IResult<'_, (i32, i32)> {
+    map_opt(
+        delimited(token(char('(')), take_till(|c| c == ')'), char(')')),
+        |args: &str| {
+            let (min, max) = args.split_once(',')?;
+
+            Some((min.trim().parse().ok()?, max.trim().parse().ok()?))
+        },
+    )(i)
+}
+
+fn item(i: &str) -> IResult<'_, Item> {
+    let (i, kind) = preceded(token(char('@')), ident)(i)?;
+
+    match kind.to_lowercase().as_str() {
+        "include" => map(string, Item::Include)(i),
+        "mapsize" => map(map_size, |(min, max)| Item::MapSize(min, max))(i),
+        class_type if class_type.ends_with("class") => {
+            map(|i| class(i, ClassType::from(kind)), Item::Class)(i)
+        }
+        _ => Err(nom::Err::Error(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
+pub fn parse_fgd(i: &str) -> IResult<'_, Fgd> {
+    let (i, items) = all_consuming(terminated(many0(item), sp))(i)?;
+
+    let mut res = Fgd::default();
+
+    for item in items {
+        match item {
+            Item::Include(path) => res.includes.push(path),
+            Item::MapSize(min, max) => res.map_size = Some((min, max)),
+            Item::Class(class) => res.classes.push(class),
+        }
+    }
+
+    Ok((i, res))
+}
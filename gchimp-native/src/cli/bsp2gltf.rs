@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use gchimp::modules::bsp2gltf::{bsp2gltf, Bsp2GltfOptions};
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Bsp2GltfCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "bsp2gltf")]
+    Bsp2Gltf {
+        /// Path to .bsp, output is written next to it
+        bsp: PathBuf,
+        /// Writes OBJ and MTL instead of glTF
+        #[arg(long)]
+        obj: bool,
+        /// Units are multiplied by this, 0.0254 turns inches into meters
+        #[arg(short, long, default_value_t = 1.)]
+        scale: f64,
+        /// WAD to take the textures from, after the ones in the `wad` key
+        ///
+        /// Could be reused multiple times for more WADs
+        #[arg(short, long, action = clap::ArgAction::Append)]
+        wad: Vec<PathBuf>,
+    },
+}
+
+pub struct Bsp2Gltf;
+impl Cli for Bsp2Gltf {
+    fn name(&self) -> &'static str {
+        "bsp2gltf"
+    }
+
+    fn cli(&self) -> CliRes {
+        let Commands::Bsp2Gltf {
+            bsp,
+            obj,
+            scale,
+            wad,
+        } = Bsp2GltfCli::parse().command;
+
+        if let Err(err) = bsp2gltf(bsp, &wad, obj, &Bsp2GltfOptions { scale }) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use gchimp::modules::bsp2map::bsp2map;
+
+use super::{Cli, CliRes};
+
+pub struct Bsp2Map;
+impl Cli for Bsp2Map {
+    fn name(&self) -> &'static str {
+        "bsp2map"
+    }
+
+    // In: path to .bsp
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() != 1 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let bsp_path = PathBuf::from(&args[0]);
+        if let Err(err) = bsp2map(bsp_path) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Decompiles a .bsp into <name>.decompiled.map
+
+<path to .bsp>
+"
+        )
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use gchimp::modules::bsp2mdl::{bsp2mdl, Bsp2MdlOptions};
+
+use crate::config::parse_config;
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Bsp2MdlCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "bsp2mdl")]
+    Bsp2Mdl {
+        /// Path to .bsp
+        bsp: PathBuf,
+        /// Model index like `3` or `*3`, or targetname of the brush entity
+        model: String,
+        /// Path to output .mdl, QC, SMD and textures are written next to it
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Bakes the lightmaps into the textures
+        #[arg(short, long)]
+        bake_lightmap: bool,
+        /// Keeps the model where it is in the map instead of moving it to the origin
+        #[arg(short, long)]
+        keep_position: bool,
+    },
+}
+
+pub struct Bsp2Mdl;
+impl Cli for Bsp2Mdl {
+    fn name(&self) -> &'static str {
+        "bsp2mdl"
+    }
+
+    fn cli(&self) -> CliRes {
+        let Commands::Bsp2Mdl {
+            bsp,
+            model,
+            out,
+            bake_lightmap,
+            keep_position,
+        } = Bsp2MdlCli::parse().command;
+
+        let Ok(config) = parse_config() else {
+            println!("Error parsing config.toml");
+            return CliRes::Err;
+        };
+
+        let options = Bsp2MdlOptions {
+            bake_lightmap,
+            move_to_origin: !keep_position,
+            studiomdl: PathBuf::from(config.studiomdl).into(),
+            #[cfg(target_os = "linux")]
+            wineprefix: config.wineprefix,
+            ..Default::default()
+        };
+
+        if let Err(err) = bsp2mdl(&bsp, &model, &out, &options) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use bsp::{Bsp, BspIssue};
+
+use super::{Cli, CliRes};
+
+pub struct BspCompact;
+impl Cli for BspCompact {
+    fn name(&self) -> &'static str {
+        "bsp_compact"
+    }
+
+    // In: path to .bsp, path to output .bsp
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() != 2 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let mut bsp = match Bsp::from_file(PathBuf::from(&args[0])) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        };
+
+        // maps over the limits are what compacting is for
+        let (broken, over_limit): (Vec<_>, Vec<_>) = bsp
+            .validate()
+            .into_iter()
+            .partition(|issue| matches!(issue, BspIssue::OutOfRange { .. }));
+
+        if !broken.is_empty() {
+            println!("BSP has broken references:");
+            broken.iter().for_each(|issue| println!("{}", issue));
+            return CliRes::Err;
+        }
+
+        over_limit
+            .iter()
+            .for_each(|issue| println!("Warning: {}", issue));
+
+        let old_size = bsp.stats().total_size;
+
+        bsp.compact();
+
+        let stats = bsp.stats();
+
+        println!("{}", stats);
+        println!("Removed {} bytes", old_size - stats.total_size);
+
+        if let Err(err) = bsp.write_to_file(PathBuf::from(&args[1])) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Removes unused planes, texinfo, textures, vertices, edges, surfedges and lighting of a .bsp
+
+<path to .bsp> <path to output .bsp>
+"
+        )
+    }
+}
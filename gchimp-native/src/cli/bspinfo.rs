@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use gchimp::modules::bspinfo::bspinfo;
+
+use super::{Cli, CliRes};
+
+pub struct BspInfo;
+impl Cli for BspInfo {
+    fn name(&self) -> &'static str {
+        "bspinfo"
+    }
+
+    // In: path to .bsp
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() != 1 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        let bsp_path = PathBuf::from(&args[0]);
+        match bspinfo(bsp_path) {
+            Ok((report, no_problem)) => {
+                println!("{}", report);
+
+                if !no_problem {
+                    return CliRes::Err;
+                }
+            }
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Prints lump usage against the engine limits and broken references of a .bsp
+
+<path to .bsp>
+"
+        )
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use gchimp::modules::check_entity::check_entity_file;
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct CheckEntityCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "check_entity")]
+    CheckEntity {
+        /// Path to .map, .rmf, .jmf or .bsp
+        map: PathBuf,
+        /// FGD with the entities, later ones win over earlier ones
+        ///
+        /// Could be reused multiple times for more FGDs
+        #[arg(short, long, action = clap::ArgAction::Append, required = true)]
+        fgd: Vec<PathBuf>,
+    },
+}
+
+pub struct CheckEntity;
+impl Cli for CheckEntity {
+    fn name(&self) -> &'static str {
+        "check_entity"
+    }
+
+    fn cli(&self) -> CliRes {
+        let Commands::CheckEntity { map, fgd } = CheckEntityCli::parse().command;
+
+        match check_entity_file(map, &fgd) {
+            Ok(issues) => {
+                for (index, issue) in &issues {
+                    println!("Entity {}: {}", index, issue);
+                }
+
+                println!("Found {} issues", issues.len());
+            }
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use bsp::Bsp;
+use clap::{Parser, Subcommand};
+use gchimp::modules::embed_texture::{embed_texture, find_wads, unembed_texture, KeyWad};
+use wad::types::Wad;
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct EmbedTextureCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "embed_texture")]
+    EmbedTexture {
+        /// Path to .bsp
+        bsp: PathBuf,
+        /// Path to output .bsp
+        #[arg(short, long)]
+        out: PathBuf,
+        /// WAD to take the textures from, the ones in the `wad` key are used if there is none
+        ///
+        /// Could be reused multiple times for more WADs
+        #[arg(short, long, action = clap::ArgAction::Append)]
+        wad: Vec<PathBuf>,
+    },
+    #[command(id = "unembed_texture")]
+    UnembedTexture {
+        /// Path to .bsp
+        bsp: PathBuf,
+        /// Path to output .bsp
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Path to output .wad with the textures taken out
+        #[arg(short, long)]
+        wad: PathBuf,
+        /// Texture to take out, every embedded texture is taken out if there is none
+        ///
+        /// Could be reused multiple times for more textures
+        #[arg(short, long, action = clap::ArgAction::Append)]
+        texture: Vec<String>,
+    },
+}
+
+pub struct EmbedTexture;
+impl Cli for EmbedTexture {
+    fn name(&self) -> &'static str {
+        "embed_texture"
+    }
+
+    fn cli(&self) -> CliRes {
+        run(EmbedTextureCli::parse().command)
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
+
+pub struct UnembedTexture;
+impl Cli for UnembedTexture {
+    fn name(&self) -> &'static str {
+        "unembed_texture"
+    }
+
+    fn cli(&self) -> CliRes {
+        run(EmbedTextureCli::parse().command)
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
+
+fn run(command: Commands) -> CliRes {
+    let res = match command {
+        Commands::EmbedTexture { bsp, out, wad } => run_embed(bsp, out, wad),
+        Commands::UnembedTexture {
+            bsp,
+            out,
+            wad,
+            texture,
+        } => run_unembed(bsp, out, wad, texture),
+    };
+
+    if let Err(err) = res {
+        println!("{}", err);
+        return CliRes::Err;
+    }
+
+    CliRes::Ok
+}
+
+fn run_embed(bsp_path: PathBuf, out: PathBuf, wad_paths: Vec<PathBuf>) -> eyre::Result<()> {
+    let mut bsp = Bsp::from_file(&bsp_path)?;
+
+    let wads = if wad_paths.is_empty() {
+        find_wads(&bsp, &bsp_path)
+    } else {
+        wad_paths
+            .iter()
+            .map(|path| {
+                Ok(KeyWad {
+                    key_path: path.display().to_string(),
+                    wad: Wad::from_file(path)?,
+                })
+            })
+            .collect::<eyre::Result<Vec<_>>>()?
+    };
+
+    let missing = embed_texture(&mut bsp, &wads);
+
+    if !missing.is_empty() {
+        println!("Cannot find these textures: {}", missing.join(", "));
+    }
+
+    bsp.write_to_file(out)
+}
+
+fn run_unembed(
+    bsp_path: PathBuf,
+    out: PathBuf,
+    wad_path: PathBuf,
+    textures: Vec<String>,
+) -> eyre::Result<()> {
+    let mut bsp = Bsp::from_file(&bsp_path)?;
+
+    let wad = unembed_texture(&mut bsp, &textures, &wad_path)?;
+
+    wad.write_to_file(wad_path)?;
+    bsp.write_to_file(out)
+}
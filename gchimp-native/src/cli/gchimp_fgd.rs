@@ -0,0 +1,37 @@
+use gchimp::entity::gchimp_fgd;
+
+use super::{Cli, CliRes};
+
+pub struct GchimpFgd;
+impl Cli for GchimpFgd {
+    fn name(&self) -> &'static str {
+        "gchimp_fgd"
+    }
+
+    // output .fgd
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        if args.len() != 1 {
+            self.cli_help();
+            return CliRes::Err;
+        }
+
+        if let Err(err) = gchimp_fgd().write(&args[0]) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Writes the FGD of gchimp entities.
+
+<output .fgd>
+"
+        )
+    }
+}
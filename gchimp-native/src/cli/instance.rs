@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use gchimp::modules::instance::expand_instances_file;
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct InstanceCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "instance")]
+    Instance {
+        /// Path to .map, .rmf or .jmf
+        map: PathBuf,
+        /// Path to output map, should be compiled instead of the original
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+}
+
+pub struct Instance;
+impl Cli for Instance {
+    fn name(&self) -> &'static str {
+        "instance"
+    }
+
+    fn cli(&self) -> CliRes {
+        let Commands::Instance { map, out } = InstanceCli::parse().command;
+
+        match expand_instances_file(map, out) {
+            Ok(count) => println!("Expanded {} instances", count),
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,44 @@
+use gchimp::modules::lightmap_atlas::{lightmap_export, lightmap_import};
+
+use super::{Cli, CliRes};
+
+pub struct LightmapAtlas;
+impl Cli for LightmapAtlas {
+    fn name(&self) -> &'static str {
+        "lightmap_atlas"
+    }
+
+    // In: export <path to .bsp>
+    // In: import <path to .bsp> <path to output .bsp>
+    fn cli(&self) -> CliRes {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+
+        let res = match args.iter().map(|s| s.as_str()).collect::<Vec<&str>>()[..] {
+            ["export", bsp_path] => lightmap_export(bsp_path),
+            ["import", bsp_path, out_path] => lightmap_import(bsp_path, out_path),
+            _ => {
+                self.cli_help();
+                return CliRes::Err;
+            }
+        };
+
+        if let Err(err) = res {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        println!(
+            "\
+Exports lightmaps of a .bsp into <name>.lightmap.png and <name>.lightmap.json
+Then imports the edited .png back into a new .bsp
+
+export <path to .bsp>
+import <path to .bsp> <path to output .bsp>
+"
+        )
+    }
+}
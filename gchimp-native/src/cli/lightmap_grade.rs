@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use bsp::{Bsp, Vec3};
+use clap::{Parser, Subcommand};
+use gchimp::modules::lightmap_grade::{lightmap_grade, LightmapGradeOptions};
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct LightmapGradeCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "lightmap_grade")]
+    LightmapGrade {
+        /// Path to .bsp
+        bsp: PathBuf,
+        /// Path to output .bsp
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Multiplies every luxel
+        #[arg(short, long, default_value_t = 1.)]
+        brightness: f64,
+        /// Values above 1 brighten the dark parts
+        #[arg(short, long, default_value_t = 1.)]
+        gamma: f64,
+        /// Multiplies R G B of every luxel
+        #[arg(short, long, num_args = 3, value_names = ["R", "G", "B"])]
+        tint: Option<Vec<f64>>,
+        /// Multiplies lightmaps of a light style, written as <style>:<scale>
+        ///
+        /// Could be reused multiple times for more styles
+        #[arg(short, long, action = clap::ArgAction::Append, value_parser = parse_style_scale)]
+        style: Vec<(u8, f64)>,
+        /// Only grades faces with this texture
+        ///
+        /// Could be reused multiple times for more textures
+        #[arg(long, action = clap::ArgAction::Append)]
+        texture: Vec<String>,
+        /// Only grades faces inside the box
+        #[arg(short, long, num_args = 6, value_names = ["MINX", "MINY", "MINZ", "MAXX", "MAXY", "MAXZ"], allow_hyphen_values = true)]
+        region: Option<Vec<f32>>,
+    },
+}
+
+fn parse_style_scale(s: &str) -> Result<(u8, f64), String> {
+    let Some((style, scale)) = s.split_once(':') else {
+        return Err(format!("expected <style>:<scale>, got {s}"));
+    };
+
+    let style = style.parse::<u8>().map_err(|err| err.to_string())?;
+    let scale = scale.parse::<f64>().map_err(|err| err.to_string())?;
+
+    Ok((style, scale))
+}
+
+pub struct LightmapGrade;
+impl Cli for LightmapGrade {
+    fn name(&self) -> &'static str {
+        "lightmap_grade"
+    }
+
+    fn cli(&self) -> CliRes {
+        let cli = LightmapGradeCli::parse();
+
+        let Commands::LightmapGrade {
+            bsp,
+            out,
+            brightness,
+            gamma,
+            tint,
+            style,
+            texture,
+            region,
+        } = cli.command;
+
+        let options = LightmapGradeOptions {
+            brightness,
+            gamma,
+            tint: tint
+                .map(|tint| [tint[0], tint[1], tint[2]])
+                .unwrap_or([1.; 3]),
+            style_scales: style,
+            textures: texture,
+            region: region.map(|region| {
+                (
+                    Vec3::new(region[0], region[1], region[2]),
+                    Vec3::new(region[3], region[4], region[5]),
+                )
+            }),
+        };
+
+        let mut bsp = match Bsp::from_file(bsp) {
+            Ok(bsp) => bsp,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        };
+
+        lightmap_grade(&mut bsp, &options);
+
+        if let Err(err) = bsp.write_to_file(out) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use gchimp::modules::map_diff::map_diff_file;
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct MapDiffCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "map_diff")]
+    MapDiff {
+        /// Path to the old .map, .rmf or .jmf
+        old: PathBuf,
+        /// Path to the new .map, .rmf or .jmf
+        new: PathBuf,
+        /// Prints JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub struct MapDiff;
+impl Cli for MapDiff {
+    fn name(&self) -> &'static str {
+        "map_diff"
+    }
+
+    fn cli(&self) -> CliRes {
+        let Commands::MapDiff { old, new, json } = MapDiffCli::parse().command;
+
+        let diff = match map_diff_file(old, new) {
+            Ok(diff) => diff,
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        };
+
+        if json {
+            match diff.to_json() {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    println!("{}", err);
+                    return CliRes::Err;
+                }
+            }
+        } else if diff.is_empty() {
+            println!("No changes");
+        } else {
+            print!("{}", diff);
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
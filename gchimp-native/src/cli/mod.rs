@@ -1,11 +1,25 @@
 use map::Map;
 
+mod bsp2gltf;
+mod bsp2map;
+mod bsp2mdl;
+mod bsp_compact;
+mod bspinfo;
+mod check_entity;
 mod check_illegal_brush;
 mod check_missing_texture;
 mod custom_script;
+mod embed_texture;
+mod gchimp_fgd;
+mod instance;
 mod light_scale;
+mod lightmap_atlas;
+mod lightmap_grade;
 mod loop_wave;
 mod map2mdl;
+mod map_diff;
+mod relight;
+mod replace_texture;
 mod resmake;
 mod rotate_prop_static;
 mod s2g;
@@ -65,6 +79,21 @@ pub fn cli() -> CliRes {
         &loop_wave::LoopWave,
         &resmake::ResMake,
         &smd_compile::SmdCompile,
+        &bsp2gltf::Bsp2Gltf,
+        &bsp2map::Bsp2Map,
+        &bsp2mdl::Bsp2Mdl,
+        &bspinfo::BspInfo,
+        &bsp_compact::BspCompact,
+        &embed_texture::EmbedTexture,
+        &embed_texture::UnembedTexture,
+        &replace_texture::ReplaceTexture,
+        &lightmap_atlas::LightmapAtlas,
+        &lightmap_grade::LightmapGrade,
+        &relight::Relight,
+        &instance::Instance,
+        &map_diff::MapDiff,
+        &check_entity::CheckEntity,
+        &gchimp_fgd::GchimpFgd,
     ];
 
     let help = || {
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use gchimp::modules::relight::{parse_lights_rad, relight_file, RelightOptions};
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct RelightCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "relight")]
+    Relight {
+        /// Path to .bsp
+        bsp: PathBuf,
+        /// Path to output .bsp
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Path to lights.rad, defaults to the one next to the .bsp
+        #[arg(short, long)]
+        rad: Option<PathBuf>,
+        /// Multiplies direct lighting
+        #[arg(short, long, default_value_t = 2.)]
+        scale: f64,
+        /// Gamma of the final light
+        #[arg(short, long, default_value_t = 0.55)]
+        gamma: f64,
+    },
+}
+
+pub struct Relight;
+impl Cli for Relight {
+    fn name(&self) -> &'static str {
+        "relight"
+    }
+
+    fn cli(&self) -> CliRes {
+        let Commands::Relight {
+            bsp,
+            out,
+            rad,
+            scale,
+            gamma,
+        } = RelightCli::parse().command;
+
+        let texlights = match rad.map(std::fs::read_to_string) {
+            Some(Ok(rad)) => parse_lights_rad(&rad),
+            Some(Err(err)) => {
+                println!("Cannot read lights.rad: {}", err);
+                return CliRes::Err;
+            }
+            None => Default::default(),
+        };
+
+        let options = RelightOptions {
+            direct_scale: scale,
+            gamma,
+            texlights,
+        };
+
+        match relight_file(bsp, out, &options) {
+            Ok(count) => println!("Relit with {} lights", count),
+            Err(err) => {
+                println!("{}", err);
+                return CliRes::Err;
+            }
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use bsp::Bsp;
+use clap::{Parser, Subcommand};
+use gchimp::modules::replace_texture::{replace_texture, TextureSource};
+use wad::types::Wad;
+
+use super::{Cli, CliRes};
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct ReplaceTextureCli {
+    // This is just dummy command because we are already in the command
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(id = "replace_texture")]
+    ReplaceTexture {
+        /// Path to .bsp
+        bsp: PathBuf,
+        /// Path to output .bsp
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Replaces a texture, written as <texture>=<path to .wad or image>
+        ///
+        /// Could be reused multiple times for more textures
+        #[arg(short, long, action = clap::ArgAction::Append, value_parser = parse_pair)]
+        replace: Vec<(String, String)>,
+        /// Renames a texture after replacing, written as <texture>=<new name>
+        ///
+        /// Could be reused multiple times for more textures
+        #[arg(short = 'n', long, action = clap::ArgAction::Append, value_parser = parse_pair)]
+        rename: Vec<(String, String)>,
+    },
+}
+
+fn parse_pair(s: &str) -> Result<(String, String), String> {
+    let Some((left, right)) = s.split_once('=') else {
+        return Err(format!("expected <texture>=<value>, got {s}"));
+    };
+
+    Ok((left.to_string(), right.to_string()))
+}
+
+pub struct ReplaceTexture;
+impl Cli for ReplaceTexture {
+    fn name(&self) -> &'static str {
+        "replace_texture"
+    }
+
+    fn cli(&self) -> CliRes {
+        let cli = ReplaceTextureCli::parse();
+
+        let Commands::ReplaceTexture {
+            bsp,
+            out,
+            replace,
+            rename,
+        } = cli.command;
+
+        if let Err(err) = run(bsp, out, replace, rename) {
+            println!("{}", err);
+            return CliRes::Err;
+        }
+
+        CliRes::Ok
+    }
+
+    fn cli_help(&self) {
+        unreachable!()
+    }
+}
+
+fn run(
+    bsp_path: PathBuf,
+    out: PathBuf,
+    replace: Vec<(String, String)>,
+    rename: Vec<(String, String)>,
+) -> eyre::Result<()> {
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    for (texture, path) in replace {
+        let path = PathBuf::from(path);
+
+        if path.extension().is_some_and(|ext| ext == "wad") {
+            let wads = [Wad::from_file(&path)?];
+
+            replace_texture(&mut bsp, &texture, TextureSource::Wads(&wads))?;
+        } else {
+            replace_texture(&mut bsp, &texture, TextureSource::Image(&path))?;
+        }
+    }
+
+    for (texture, new_name) in rename {
+        bsp.rename_texture(&texture, &new_name)?;
+    }
+
+    bsp.write_to_file(out)
+}
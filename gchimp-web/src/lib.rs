@@ -1,14 +1,20 @@
 use std::{path::Path, str::from_utf8};
 
+use serde::Deserialize;
 use smd::Smd;
 use utils::{zip_files, WasmFile};
+use wad::types::Wad;
 use wasm_bindgen::prelude::*;
 
 use bsp::Bsp;
 use gchimp::{
     modules::{
-        bsp2wad::bsp2wad_bytes,
-        dem2cam::{Dem2CamOptions, _dem2cam_string},
+        bsp2gltf::{bsp2gltf_bytes, bsp2obj_files, Bsp2GltfOptions},
+        bsp2map::bsp2map_bytes,
+        bsp2wad::{bsp2wad_bytes_with_palette, palette_from_lmp},
+        bspinfo::bspinfo_bytes,
+        dem2cam::{_dem2cam_string, Dem2CamOptions},
+        lightmap_grade::{lightmap_grade_bytes, LightmapGradeOptions},
         loop_wave::loop_wave_from_wave_bytes as _loop_wave,
         resmake::{resmake_single_bsp, ResMakeOptions},
     },
@@ -88,11 +94,148 @@ pub fn dem2cam(demo_bytes: Vec<u8>, filename: &str, override_fps: f32) -> Result
     }
 }
 
+/// `palette` is the bytes of `palette.lmp`, only Quake maps need it.
 #[wasm_bindgen]
-pub fn bsp2wad(bsp_bytes: Vec<u8>) -> Result<Vec<u8>, JsValue> {
+pub fn bsp2wad(bsp_bytes: Vec<u8>, palette: Option<Vec<u8>>) -> Result<Vec<u8>, JsValue> {
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 
-    match bsp2wad_bytes(&bsp_bytes) {
+    let palette = palette
+        .map(|bytes| {
+            palette_from_lmp(&bytes)
+                .ok_or_else(|| JsValue::from_str("palette.lmp is not 768 bytes"))
+        })
+        .transpose()?;
+
+    match bsp2wad_bytes_with_palette(&bsp_bytes, palette.as_deref()) {
+        Err(err) => Err(JsValue::from_str(err.to_string().as_str())),
+        Ok(ok) => Ok(ok),
+    }
+}
+
+#[wasm_bindgen]
+pub fn bsp2map(bsp_bytes: Vec<u8>) -> Result<Vec<u8>, JsValue> {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+
+    match bsp2map_bytes(&bsp_bytes) {
+        Err(err) => Err(JsValue::from_str(err.to_string().as_str())),
+        Ok(ok) => Ok(ok),
+    }
+}
+
+/// External textures are taken from `wad_bytes` if there is one.
+#[wasm_bindgen]
+pub fn bsp2gltf(
+    bsp_bytes: Vec<u8>,
+    wad_bytes: Option<Vec<u8>>,
+    scale: f64,
+) -> Result<Vec<u8>, JsValue> {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+
+    let (bsp, wad) = read_bsp_and_wad(&bsp_bytes, wad_bytes.as_deref())?;
+    let wads = wad.iter().collect::<Vec<_>>();
+
+    Ok(bsp2gltf_bytes(&bsp, &wads, &Bsp2GltfOptions { scale }))
+}
+
+/// Returns a zip with the OBJ, MTL and textures. `name` is the file stem of the OBJ.
+#[wasm_bindgen]
+pub fn bsp2obj(
+    bsp_bytes: Vec<u8>,
+    wad_bytes: Option<Vec<u8>>,
+    name: String,
+    scale: f64,
+) -> Result<Vec<u8>, JsValue> {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+
+    let (bsp, wad) = read_bsp_and_wad(&bsp_bytes, wad_bytes.as_deref())?;
+    let wads = wad.iter().collect::<Vec<_>>();
+
+    let files = bsp2obj_files(&bsp, &wads, &name, &Bsp2GltfOptions { scale })
+        .into_iter()
+        .map(|(name, bytes)| WasmFile { name, bytes })
+        .collect();
+
+    Ok(zip_files(files))
+}
+
+fn read_bsp_and_wad(
+    bsp_bytes: &[u8],
+    wad_bytes: Option<&[u8]>,
+) -> Result<(Bsp, Vec<Wad>), JsValue> {
+    let bsp =
+        Bsp::from_bytes(bsp_bytes).map_err(|err| JsValue::from_str(err.to_string().as_str()))?;
+    let wad = wad_bytes
+        .map(Wad::from_bytes)
+        .transpose()
+        .map_err(|err| JsValue::from_str(err.to_string().as_str()))?;
+
+    Ok((bsp, wad.into_iter().collect()))
+}
+
+#[wasm_bindgen]
+pub fn bspinfo(bsp_bytes: Vec<u8>) -> Result<String, JsValue> {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+
+    match bspinfo_bytes(&bsp_bytes) {
+        Err(err) => Err(JsValue::from_str(err.to_string().as_str())),
+        Ok(ok) => Ok(ok),
+    }
+}
+
+/// [`LightmapGradeOptions`] from JS, missing fields are the defaults.
+#[derive(Deserialize)]
+#[serde(default)]
+struct JsLightmapGradeOptions {
+    brightness: f64,
+    gamma: f64,
+    tint: [f64; 3],
+    style_scales: Vec<(u8, f64)>,
+    textures: Vec<String>,
+    region: Option<([f32; 3], [f32; 3])>,
+}
+
+impl Default for JsLightmapGradeOptions {
+    fn default() -> Self {
+        let options = LightmapGradeOptions::default();
+
+        Self {
+            brightness: options.brightness,
+            gamma: options.gamma,
+            tint: options.tint,
+            style_scales: options.style_scales,
+            textures: options.textures,
+            region: None,
+        }
+    }
+}
+
+impl From<JsLightmapGradeOptions> for LightmapGradeOptions {
+    fn from(value: JsLightmapGradeOptions) -> Self {
+        Self {
+            brightness: value.brightness,
+            gamma: value.gamma,
+            tint: value.tint,
+            style_scales: value.style_scales,
+            textures: value.textures,
+            region: value
+                .region
+                .map(|(mins, maxs)| (bsp::Vec3::from(mins), bsp::Vec3::from(maxs))),
+        }
+    }
+}
+
+/// `options` is an object with the fields of `LightmapGradeOptions`, such as
+/// `{ brightness: 1.5, style_scales: [[32, 0.5]], textures: ["crate01"], region: [[-64, -64, 0], [64, 64, 128]] }`.
+#[wasm_bindgen]
+pub fn lightmap_grade(bsp_bytes: Vec<u8>, options: JsValue) -> Result<Vec<u8>, JsValue> {
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+
+    let options: LightmapGradeOptions =
+        serde_wasm_bindgen::from_value::<JsLightmapGradeOptions>(options)
+            .map_err(|err| JsValue::from_str(err.to_string().as_str()))?
+            .into();
+
+    match lightmap_grade_bytes(&bsp_bytes, &options) {
         Err(err) => Err(JsValue::from_str(err.to_string().as_str())),
         Ok(ok) => Ok(ok),
     }
@@ -5,6 +5,10 @@ import styles from "./page.module.css";
 import { ResMake } from "@/programs/resmake";
 import { Dem2Cam } from "@/programs/dem2cam";
 import { Bsp2Wad } from "@/programs/bsp2wad";
+import { Bsp2Map } from "@/programs/bsp2map";
+import { Bsp2Gltf } from "@/programs/bsp2gltf";
+import { BspInfo } from "@/programs/bspinfo";
+import { LightmapGrade } from "@/programs/lightmap_grade";
 import { SmdSplit } from "@/programs/smd_split";
 
 export const Main = () => {
@@ -14,6 +18,10 @@ export const Main = () => {
             <ResMake />
             <Dem2Cam />
             <Bsp2Wad />
+            <Bsp2Map />
+            <Bsp2Gltf />
+            <BspInfo />
+            <LightmapGrade />
             <SmdSplit />
         </div>
     </main>
@@ -0,0 +1,104 @@
+import { ChangeEvent, useState } from "react";
+import { GchimpProgram } from "..";
+
+import "./styles.css";
+import { bsp2gltf, bsp2obj } from "gchimp-web";
+import { UploadButton } from "@/components/upload-button";
+
+export const Bsp2Gltf = () => {
+    const [name, setName] = useState<string | undefined>(undefined);
+    const [file, setFile] = useState<File | null>(null);
+    const [wadName, setWadName] = useState<string | undefined>(undefined);
+    const [wadFile, setWadFile] = useState<File | null>(null);
+    const [scale, setScale] = useState<string>("1");
+    const [status, setStatus] = useState<string>("");
+
+    const changeFile = (e: ChangeEvent<HTMLInputElement>) => {
+        const file = (e.target as HTMLInputElement).files?.item(0);
+        // the path will be sandboxed so we only care about the file stem
+        setName(file?.name);
+        setFile(file ? file : null);
+    }
+
+    const changeWadFile = (e: ChangeEvent<HTMLInputElement>) => {
+        const file = (e.target as HTMLInputElement).files?.item(0);
+
+        setWadName(file?.name);
+        setWadFile(file ? file : null);
+    }
+
+    const onDrop = (e: React.DragEvent<HTMLElement>) => {
+        e.preventDefault();
+
+        const file = e.dataTransfer.files.item(0);
+
+        if (file?.name.endsWith(".wad")) {
+            setWadName(file.name);
+            setWadFile(file);
+        } else {
+            setName(file?.name);
+            setFile(file ? file : null);
+        }
+    }
+
+    const runProgram = async (obj: boolean) => {
+        if (!file || !name || !name.endsWith(".bsp")) {
+            setStatus("No BSP selected");
+            return;
+        }
+
+        const bspBytes = new Uint8Array(await file.arrayBuffer());
+        const wadBytes = wadFile ? new Uint8Array(await wadFile.arrayBuffer()) : undefined;
+        const scaleNumber = Number.parseFloat(scale);
+        const stem = extract_file_name(name);
+
+        try {
+            const output = obj
+                ? bsp2obj(bspBytes, wadBytes, stem, Number.isNaN(scaleNumber) ? 1 : scaleNumber)
+                : bsp2gltf(bspBytes, wadBytes, Number.isNaN(scaleNumber) ? 1 : scaleNumber);
+
+            downloadFile(output, obj ? `${stem}.zip` : `${stem}.glb`);
+            setStatus("");
+        } catch (err) {
+            setStatus(`${err}`);
+        }
+    }
+
+    return <GchimpProgram name="Bsp2Gltf" className={`bsp2gltf`} onDrop={onDrop} >
+        <div className="export-option">
+            <label htmlFor="bsp2gltf-scale">Scale:</label>
+            <input type="text" id="bsp2gltf-scale" value={scale} onChange={(e) => setScale(e.target.value)} />
+        </div>
+        <UploadButton label={"Select or Drop BSP"} id={"bsp2gltf-path"} onChange={(e) => changeFile(e)} fileName={name} />
+        <UploadButton label={"Select or Drop WAD (optional)"} id={"bsp2gltf-wad-path"} onChange={(e) => changeWadFile(e)} fileName={wadName} />
+        <div>
+            <button type="button" disabled={file === null} onClick={() => runProgram(false)}><h2>Get glTF</h2></button>
+            <button type="button" disabled={file === null} onClick={() => runProgram(true)}><h2>Get OBJ</h2></button>
+        </div>
+        {status && <p>{status}</p>}
+    </GchimpProgram>
+}
+
+const downloadFile = (bytes: Uint8Array, fileName: string) => {
+    // tried and true method
+    const blob = new Blob([bytes], { type: 'application/octet-stream' });
+    const url = URL.createObjectURL(blob);
+    const link = document.createElement('a');
+
+    link.href = url;
+    link.download = fileName;
+
+    link.click();
+
+    link.remove();
+}
+
+// input is usually `C:\fake_folder\map_name.bsp`
+// remember front slash like windows
+const extract_file_name = (s: string): string => {
+    const splits = s.split("\\");
+    const stem = splits[splits.length - 1];
+    const file_name = stem.split(".")[0];
+
+    return file_name;
+}
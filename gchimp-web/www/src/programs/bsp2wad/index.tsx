@@ -8,7 +8,10 @@ import { UploadButton } from "@/components/upload-button";
 export const Bsp2Wad = () => {
     const [name, setName] = useState<string | undefined>(undefined);
     const [file, setFile] = useState<File | null>(null);
+    const [paletteName, setPaletteName] = useState<string | undefined>(undefined);
+    const [palette, setPalette] = useState<Uint8Array | undefined>(undefined);
     const [output, setOutput] = useState<Uint8Array | null>(null);
+    const [status, setStatus] = useState<string>("");
 
     const submitButton = createRef<HTMLInputElement>();
 
@@ -21,8 +24,14 @@ export const Bsp2Wad = () => {
 
         reader.onload = (e) => {
             if (name) {
-                const res = bsp2wad(new Uint8Array(e.target?.result as ArrayBuffer));
-                setOutput(res);
+                try {
+                    const res = bsp2wad(new Uint8Array(e.target?.result as ArrayBuffer), palette);
+                    setOutput(res);
+                    setStatus("");
+                } catch (err) {
+                    setOutput(null);
+                    setStatus(`${err}`);
+                }
             } else {
                 console.error("no file name set for input demo file");
             }
@@ -43,11 +52,22 @@ export const Bsp2Wad = () => {
         setFile(file ? file : null);
     }
 
+    // Quake maps need palette.lmp for their textures
+    const changePalette = async (file: File | null | undefined) => {
+        setPaletteName(file?.name);
+        setPalette(file ? new Uint8Array(await file.arrayBuffer()) : undefined);
+    }
+
     const onDrop = (e: React.DragEvent<HTMLElement>) => {
         e.preventDefault();
 
         const file = e.dataTransfer.files.item(0);
 
+        if (file?.name.endsWith(".lmp")) {
+            changePalette(file);
+            return;
+        }
+
         setName(file?.name);
 
         setFile(file ? file : null);
@@ -86,17 +106,19 @@ export const Bsp2Wad = () => {
         // equivalent to clicking the run button
         submitButton.current?.click();
     }, [
-        file, submitButton, name
+        file, submitButton, name, palette
     ]);
 
     return <GchimpProgram name="Bsp2Wad" className={`bsp2wad`} onDrop={onDrop} >
         <form onSubmit={async (e) => runProgram(e)}>
             <UploadButton label={"Select or Drop BSP"} id={"bsp2wad-path"} onChange={(e) => changeFile(e)} fileName={name} />
+            <UploadButton label={"Select or Drop palette.lmp (Quake only)"} id={"bsp2wad-palette-path"} onChange={(e) => changePalette(e.target.files?.item(0))} fileName={paletteName} />
             <div>
                 <input type="submit" ref={submitButton} />
                 <button type="button" disabled={output === null} onClick={downloadOutputFile}><h2>Get WAD</h2></button>
             </div>
         </form>
+        {status && <p>{status}</p>}
     </GchimpProgram>
 }
 
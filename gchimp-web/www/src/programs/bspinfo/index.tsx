@@ -0,0 +1,78 @@
+import { ChangeEvent, createRef, FormEvent, useEffect, useState } from "react";
+import { GchimpProgram } from "..";
+
+import "./styles.css";
+import { bspinfo } from "gchimp-web";
+import { UploadButton } from "@/components/upload-button";
+
+export const BspInfo = () => {
+    const [name, setName] = useState<string | undefined>(undefined);
+    const [file, setFile] = useState<File | null>(null);
+    const [output, setOutput] = useState<string>("");
+
+    const submitButton = createRef<HTMLInputElement>();
+
+    const runProgram = async (e: FormEvent<HTMLFormElement>) => {
+        // dont refresh
+        e.preventDefault();
+
+        // reading the file to byte
+        const reader = new FileReader();
+
+        reader.onload = (e) => {
+            try {
+                const res = bspinfo(new Uint8Array(e.target?.result as ArrayBuffer));
+                setOutput(res);
+            } catch (err) {
+                setOutput(`${err}`);
+            }
+        };
+
+        if (!file) {
+            return;
+        }
+
+        reader.readAsArrayBuffer(file as Blob);
+    };
+
+    const changeFile = (e: ChangeEvent<HTMLInputElement>) => {
+        const file = (e.target as HTMLInputElement).files?.item(0);
+        // the path will be sandboxed so we only care about the file stem
+        setName(file?.name);
+        setFile(file ? file : null);
+    }
+
+    const onDrop = (e: React.DragEvent<HTMLElement>) => {
+        e.preventDefault();
+
+        const file = e.dataTransfer.files.item(0);
+
+        setName(file?.name);
+
+        setFile(file ? file : null);
+    }
+
+    // when new file is selected, run the program right away
+    useEffect(() => {
+        // check the files
+        if (!name || (name && !name.endsWith(".bsp")) || !file || !submitButton.current) {
+            setName(undefined);
+            setFile(null);
+            setOutput("");
+            return
+        }
+
+        // equivalent to clicking the run button
+        submitButton.current?.click();
+    }, [
+        file, submitButton, name
+    ]);
+
+    return <GchimpProgram name="BspInfo" className={`bspinfo`} onDrop={onDrop} >
+        <form onSubmit={async (e) => runProgram(e)}>
+            <UploadButton label={"Select or Drop BSP"} id={"bspinfo-path"} onChange={(e) => changeFile(e)} fileName={name} />
+            <input type="submit" ref={submitButton} />
+            <textarea readOnly={true} value={output} />
+        </form>
+    </GchimpProgram>
+}
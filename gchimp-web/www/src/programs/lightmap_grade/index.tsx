@@ -0,0 +1,178 @@
+import { ChangeEvent, createRef, FormEvent, useEffect, useState } from "react";
+import { GchimpProgram } from "..";
+
+import "./styles.css";
+import { lightmap_grade } from "gchimp-web";
+import { UploadButton } from "@/components/upload-button";
+
+export const LightmapGrade = () => {
+    const [name, setName] = useState<string | undefined>(undefined);
+    const [file, setFile] = useState<File | null>(null);
+    const [output, setOutput] = useState<Uint8Array | null>(null);
+
+    const [brightness, setBrightness] = useState<string>("1");
+    const [gamma, setGamma] = useState<string>("1");
+    const [tint, setTint] = useState<string>("1 1 1");
+    const [styleScales, setStyleScales] = useState<string>("");
+    const [textures, setTextures] = useState<string>("");
+    const [region, setRegion] = useState<string>("");
+
+    const submitButton = createRef<HTMLInputElement>();
+
+    const runProgram = async (e: FormEvent<HTMLFormElement>) => {
+        // dont refresh
+        e.preventDefault();
+
+        // reading the file to byte
+        const reader = new FileReader();
+
+        reader.onload = (e) => {
+            if (name) {
+                const regionNumbers = parse_numbers(region, 6, NaN);
+
+                const res = lightmap_grade(
+                    new Uint8Array(e.target?.result as ArrayBuffer),
+                    {
+                        brightness: parse_numbers(brightness, 1, 1)[0],
+                        gamma: parse_numbers(gamma, 1, 1)[0],
+                        tint: parse_numbers(tint, 3, 1),
+                        // "32 0.5, 33 2" scales style 32 by half and style 33 by two
+                        style_scales: styleScales
+                            .split(",")
+                            .filter((pair) => pair.trim().length > 0)
+                            .map((pair) => parse_numbers(pair, 2, 1)),
+                        textures: textures.split(/[\s,]+/).filter((texture) => texture.length > 0),
+                        // mins then maxs, all six numbers or no region
+                        region: regionNumbers.some(Number.isNaN)
+                            ? undefined
+                            : [regionNumbers.slice(0, 3), regionNumbers.slice(3)],
+                    }
+                );
+
+                setOutput(res);
+            } else {
+                console.error("no file name set for input bsp file");
+            }
+        };
+
+        if (!file) {
+            // setStatus("No file selected")
+            return;
+        }
+
+        reader.readAsArrayBuffer(file as Blob);
+    };
+
+    const changeFile = (e: ChangeEvent<HTMLInputElement>) => {
+        const file = (e.target as HTMLInputElement).files?.item(0);
+        // the path will be sandboxed so we only care about the file stem
+        setName(file?.name);
+        setFile(file ? file : null);
+    }
+
+    const onDrop = (e: React.DragEvent<HTMLElement>) => {
+        e.preventDefault();
+
+        const file = e.dataTransfer.files.item(0);
+
+        setName(file?.name);
+
+        setFile(file ? file : null);
+    }
+
+    const downloadOutputFile = () => {
+        if (!output)
+            return;
+
+        // tried and true method
+        const blob = new Blob([output], { type: 'application/octet-stream' });
+        const url = URL.createObjectURL(blob);
+        const link = document.createElement('a');
+
+        link.href = url;
+
+        console.assert(name, "no file name");
+        if (name)
+            link.download = `${extract_file_name(name)}.bsp`;
+
+        link.click();
+
+        link.remove();
+    }
+
+    // when new file is selected, run the program right away
+    useEffect(() => {
+        // check the files
+        if (!name || (name && !name.endsWith(".bsp")) || !file || !submitButton.current) {
+            setName(undefined);
+            setFile(null);
+            setOutput(null);
+            return
+        }
+
+        // equivalent to clicking the run button
+        submitButton.current?.click();
+    }, [
+        file, submitButton, name
+    ]);
+
+    useEffect(() => {
+        setName(undefined);
+        setFile(null);
+        setOutput(null);
+    }, [brightness, gamma, tint, styleScales, textures, region])
+
+    return <GchimpProgram name="LightmapGrade" className={`lightmap-grade`} onDrop={onDrop} >
+        <form onSubmit={async (e) => runProgram(e)}>
+            <div className="grade-option">
+                <label htmlFor="lightmap-grade-brightness">Brightness:</label>
+                <input type="text" id="lightmap-grade-brightness" value={brightness} onChange={(e) => setBrightness(e.target.value)} />
+            </div>
+            <div className="grade-option">
+                <label htmlFor="lightmap-grade-gamma">Gamma:</label>
+                <input type="text" id="lightmap-grade-gamma" value={gamma} onChange={(e) => setGamma(e.target.value)} />
+            </div>
+            <div className="grade-option">
+                <label htmlFor="lightmap-grade-tint">Tint (R G B):</label>
+                <input type="text" id="lightmap-grade-tint" value={tint} onChange={(e) => setTint(e.target.value)} />
+            </div>
+            <div className="grade-option">
+                <label htmlFor="lightmap-grade-style-scales">Style scales (style scale, ...):</label>
+                <input type="text" id="lightmap-grade-style-scales" value={styleScales} onChange={(e) => setStyleScales(e.target.value)} />
+            </div>
+            <div className="grade-option">
+                <label htmlFor="lightmap-grade-textures">Textures:</label>
+                <input type="text" id="lightmap-grade-textures" value={textures} onChange={(e) => setTextures(e.target.value)} />
+            </div>
+            <div className="grade-option">
+                <label htmlFor="lightmap-grade-region">Region (mins maxs):</label>
+                <input type="text" id="lightmap-grade-region" value={region} onChange={(e) => setRegion(e.target.value)} />
+            </div>
+            <UploadButton label={"Select or Drop BSP"} id={"lightmap-grade-path"} onChange={(e) => changeFile(e)} fileName={name} />
+            <div>
+                <input type="submit" ref={submitButton} />
+                <button type="button" disabled={output === null} onClick={downloadOutputFile}><h2>Get BSP</h2></button>
+            </div>
+        </form>
+    </GchimpProgram>
+}
+
+// invalid numbers fall back to the default value
+const parse_numbers = (s: string, count: number, fallback: number): number[] => {
+    const numbers = s.trim().split(/\s+/).map((n) => Number.parseFloat(n));
+
+    return Array.from({ length: count }, (_, i) => {
+        const n = numbers[i];
+        return n === undefined || Number.isNaN(n) ? fallback : n;
+    });
+}
+
+// input is usually `C:\fake_folder\map_name.bsp`
+// remember front slash like windows
+const extract_file_name = (s: string): string => {
+    const splits = s.split("\\");
+    const stem = splits[splits.length - 1];
+    const file_name = stem.split(".")[0];
+
+    return file_name;
+}
@@ -1,8 +1,20 @@
 use std::path::PathBuf;
 
+use fgd::{Choice, Class, ClassType, Fgd, Helper, Property, ValueType};
 use map::{Entity, Map};
 
-use crate::err;
+use crate::{
+    err,
+    modules::{
+        instance::{
+            INSTANCE_ATTR_ANGLES, INSTANCE_ATTR_FILE, INSTANCE_ATTR_SCALE, INSTANCE_ENTITY_NAME,
+        },
+        map2mdl::entity::{
+            MAP2MDL_ATTR_CLIPTYPE, MAP2MDL_ATTR_MODEL_ENTITY, MAP2MDL_ATTR_OPTIONS,
+            MAP2MDL_ATTR_OUTPUT, MAP2MDL_ATTR_TARGET_ORIGIN, MAP2MDL_ENTITY_NAME,
+        },
+    },
+};
 
 pub static GCHIMP_INFO_ENTITY: &str = "gchimp_info";
 
@@ -94,3 +106,163 @@ impl GchimpInfo {
             .unwrap()
     }
 }
+
+fn text(s: &str) -> Option<String> {
+    Some(s.to_string())
+}
+
+fn choices(choices: &[(&str, &str, Option<bool>)]) -> Vec<Choice> {
+    choices
+        .iter()
+        .map(|&(value, name, enabled)| Choice {
+            value: value.to_string(),
+            name: name.to_string(),
+            enabled,
+        })
+        .collect()
+}
+
+/// Entities of gchimp, written to `dist/gchimp.fgd`.
+///
+/// Keys come from the same constants the modules read so the FGD cannot fall behind.
+pub fn gchimp_fgd() -> Fgd {
+    let point_helpers = || {
+        vec![Helper::Size {
+            mins: [-8., -8., -8.],
+            maxs: [8., 8., 8.],
+        }]
+    };
+
+    let gchimp_info = Class {
+        class_type: ClassType::Point,
+        name: GCHIMP_INFO_ENTITY.to_string(),
+        description: text("Info for gchimp"),
+        helpers: [point_helpers(), vec![Helper::Color([255, 0, 255])]].concat(),
+        properties: vec![
+            Property {
+                name: GCHIMP_INFO_HL_PATH.to_string(),
+                display_name: text("Path to hl.exe"),
+                ..Default::default()
+            },
+            Property {
+                name: GCHIMP_INFO_GAMEDIR.to_string(),
+                display_name: text("Game mod directory"),
+                default: text("cstrike"),
+                ..Default::default()
+            },
+            Property {
+                name: GCHIMP_INFO_OPTIONS.to_string(),
+                value_type: ValueType::Flags,
+                choices: choices(&[
+                    ("1", "Enable map2mdl", Some(false)),
+                    (
+                        "2",
+                        "Enable map2mdl resource export (still converts gchimp_map2mdl to cycler_sprite)",
+                        Some(false),
+                    ),
+                ]),
+                ..Default::default()
+            },
+        ],
+    };
+
+    let map2mdl = Class {
+        class_type: ClassType::Solid,
+        name: MAP2MDL_ENTITY_NAME.to_string(),
+        description: text("Converts brush to model"),
+        helpers: vec![Helper::Base(
+            [
+                "Targetname",
+                "Angles",
+                "RenderFields",
+                "ZHLT",
+                "TexLightType",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+        )],
+        properties: vec![
+            Property {
+                name: MAP2MDL_ATTR_OUTPUT.to_string(),
+                display_name: text(
+                    "Path to the model name (eg: models/folder_that_exists/model.mdl)",
+                ),
+                ..Default::default()
+            },
+            Property {
+                name: MAP2MDL_ATTR_MODEL_ENTITY.to_string(),
+                display_name: text("Classname of model displaying entity"),
+                default: text("cycler_sprite"),
+                ..Default::default()
+            },
+            Property {
+                name: MAP2MDL_ATTR_CLIPTYPE.to_string(),
+                value_type: ValueType::Choices,
+                display_name: text("Generates CLIP brush overlaying model"),
+                default: text("0"),
+                choices: choices(&[
+                    ("0", "No clip", None),
+                    ("1", "Precise (matching original brush)", None),
+                    ("2", "Box (biggest bounding box covering brush)", None),
+                ]),
+                ..Default::default()
+            },
+            Property {
+                name: MAP2MDL_ATTR_TARGET_ORIGIN.to_string(),
+                display_name: text("Sets the model origin based on origin of info_target"),
+                ..Default::default()
+            },
+            Property {
+                name: MAP2MDL_ATTR_OPTIONS.to_string(),
+                value_type: ValueType::Flags,
+                choices: choices(&[("1", "Flat shade", Some(true))]),
+                ..Default::default()
+            },
+        ],
+    };
+
+    let instance = Class {
+        class_type: ClassType::Point,
+        name: INSTANCE_ENTITY_NAME.to_string(),
+        description: text("Places another map here, expanded before compiling"),
+        helpers: [point_helpers(), vec![Helper::Color([0, 255, 255])]].concat(),
+        properties: vec![
+            Property {
+                name: INSTANCE_ATTR_FILE.to_string(),
+                display_name: text("Path to the map, relative to this map (eg: prefabs/door.map)"),
+                ..Default::default()
+            },
+            Property {
+                name: INSTANCE_ATTR_ANGLES.to_string(),
+                display_name: text("Pitch Yaw Roll (Y Z X)"),
+                default: text("0 0 0"),
+                ..Default::default()
+            },
+            Property {
+                name: INSTANCE_ATTR_SCALE.to_string(),
+                display_name: text("Scale, one number or X Y Z"),
+                default: text("1"),
+                ..Default::default()
+            },
+        ],
+    };
+
+    Fgd {
+        classes: vec![gchimp_info, map2mdl, instance],
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gchimp_fgd_is_up_to_date() {
+        assert_eq!(
+            gchimp_fgd().write_to_string(),
+            include_str!("../../dist/gchimp.fgd"),
+            "dist/gchimp.fgd needs to be written again from gchimp_fgd()"
+        );
+    }
+}
@@ -0,0 +1,831 @@
+//! Exports the geometry of a BSP into glTF 2.0 or OBJ for other 3D software.
+//!
+//! Every model is a mesh with one primitive per texture. Vertices have the texture UV and a second UV
+//! into an atlas of the lightmaps, which glTF does not have a slot for so the materials point to it
+//! in `extras.lightmapTexture`. Entities with an origin become empty nodes.
+//!
+//! OBJ has only one UV set and no empties so it only gets the textures while the atlas is written next to it.
+//!
+//! The axes are converted from Z up to Y up, which Blender turns back when importing.
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use bsp::{is_missing_texture, Bsp};
+use glam::{DQuat, DVec2, DVec3};
+use image::{ImageFormat, RgbImage, RgbaImage};
+use serde_json::{json, Value};
+use wad::types::Wad;
+
+use crate::{
+    err,
+    modules::{
+        bsp2mdl::find_miptex, embed_texture::find_wads, lightmap_atlas::export_lightmap_atlas,
+    },
+    utils::{
+        bsp_stuffs::{entity_origin, to_dvec3},
+        constants::NoRenderTexture,
+        misc::parse_triplet,
+    },
+};
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLTF_CLAMP_TO_EDGE: u32 = 33071;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bsp2GltfOptions {
+    /// Units are multiplied by this, 0.0254 turns inches into meters.
+    pub scale: f64,
+}
+
+impl Default for Bsp2GltfOptions {
+    fn default() -> Self {
+        Self { scale: 1. }
+    }
+}
+
+/// Faces of one model with the same texture.
+#[derive(Debug, Default)]
+struct Primitive {
+    positions: Vec<DVec3>,
+    normals: Vec<DVec3>,
+    /// In texture sizes, v goes down the image.
+    uvs: Vec<DVec2>,
+    lightmap_uvs: Vec<DVec2>,
+    indices: Vec<u32>,
+}
+
+#[derive(Debug)]
+struct SceneModel {
+    name: String,
+    /// Entity origin, faces of entities with an ORIGIN brush are around it.
+    origin: DVec3,
+    /// By material index.
+    primitives: Vec<(usize, Primitive)>,
+}
+
+#[derive(Debug)]
+struct SceneMaterial {
+    name: String,
+    /// `None` when the texture is not in the BSP or the WADs.
+    image: Option<RgbaImage>,
+    masked: bool,
+}
+
+#[derive(Debug)]
+struct SceneEmpty {
+    name: String,
+    origin: DVec3,
+    /// Degrees around the up axis.
+    yaw: f64,
+}
+
+#[derive(Debug)]
+struct Scene {
+    models: Vec<SceneModel>,
+    materials: Vec<SceneMaterial>,
+    empties: Vec<SceneEmpty>,
+    lightmap: RgbImage,
+}
+
+// Z up to Y up
+fn to_y_up(v: DVec3) -> DVec3 {
+    DVec3::new(v.x, v.z, -v.y)
+}
+
+fn entity_name(entity: &bsp::Entity) -> String {
+    entity
+        .get("targetname")
+        .or(entity.get("classname"))
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn texture_image(miptex: &wad::types::MipTex, masked: bool) -> RgbaImage {
+    let (mut rgba, (width, height)) = miptex.to_rgba();
+
+    // last palette color is transparent
+    if masked {
+        miptex.mip_images[0]
+            .data
+            .get_bytes()
+            .iter()
+            .enumerate()
+            .filter(|(_, &palette_idx)| palette_idx == 255)
+            .for_each(|(idx, _)| rgba[idx * 4 + 3] = 0);
+    }
+
+    RgbaImage::from_raw(width, height, rgba).unwrap()
+}
+
+fn build_scene(bsp: &Bsp, wads: &[&Wad]) -> Scene {
+    let (atlas, layout) = export_lightmap_atlas(bsp);
+
+    // extra white row for faces without lightmap, those are fullbright in game
+    let mut lightmap = RgbImage::from_pixel(
+        atlas.width(),
+        atlas.height() + 1,
+        image::Rgb([255, 255, 255]),
+    );
+    image::imageops::replace(&mut lightmap, &atlas, 0, 0);
+
+    let atlas_size = DVec2::new(lightmap.width() as f64, lightmap.height() as f64);
+    let fullbright_uv = DVec2::new(0.5, lightmap.height() as f64 - 0.5) / atlas_size;
+
+    let atlas_positions = layout
+        .entries
+        .iter()
+        .filter(|entry| entry.style_slot == 0)
+        .map(|entry| (entry.face, DVec2::new(entry.x as f64, entry.y as f64)))
+        .collect::<HashMap<_, _>>();
+
+    let mut materials = vec![];
+    // texture index to material index
+    let mut material_indices = HashMap::new();
+
+    let models = bsp
+        .models
+        .iter()
+        .enumerate()
+        .map(|(model_idx, model)| {
+            let model_key = format!("*{}", model_idx);
+            let entity = if model_idx == 0 {
+                bsp.entities.first()
+            } else {
+                bsp.entities
+                    .iter()
+                    .find(|entity| entity.get("model") == Some(&model_key))
+            };
+
+            let mut primitives: Vec<(usize, Primitive)> = vec![];
+
+            let first_face = model.first_face.max(0) as usize;
+
+            for face_idx in first_face..first_face + model.face_count.max(0) as usize {
+                let vertices = bsp.face_vertices(face_idx);
+
+                if vertices.len() < 3 {
+                    continue;
+                }
+
+                let face = &bsp.faces[face_idx];
+                let (Some(texinfo), Some(normal)) = (
+                    bsp.texinfo.get(face.texinfo as usize),
+                    bsp.face_normal(face_idx),
+                ) else {
+                    continue;
+                };
+                let Some(texture) = bsp
+                    .textures
+                    .get(texinfo.texture_index as usize)
+                    .filter(|texture| !is_missing_texture(texture))
+                else {
+                    continue;
+                };
+
+                let name = texture.texture_name.get_string();
+
+                if NoRenderTexture.contains(&name) {
+                    continue;
+                }
+
+                let material = *material_indices
+                    .entry(texinfo.texture_index)
+                    .or_insert_with(|| {
+                        let masked = name.starts_with('{');
+
+                        materials.push(SceneMaterial {
+                            image: find_miptex(texture, wads)
+                                .filter(|miptex| !miptex.palette.get_bytes().is_empty())
+                                .map(|miptex| texture_image(miptex, masked)),
+                            name,
+                            masked,
+                        });
+
+                        materials.len() - 1
+                    });
+
+                let primitive = match primitives.iter_mut().find(|(idx, _)| *idx == material) {
+                    Some((_, primitive)) => primitive,
+                    None => {
+                        primitives.push((material, Primitive::default()));
+                        &mut primitives.last_mut().unwrap().1
+                    }
+                };
+
+                let normal = to_dvec3(normal);
+
+                let texture_size = DVec2::new(texture.width as f64, texture.height as f64);
+                let lightmap = bsp
+                    .face_lightmap_info(face_idx)
+                    .and_then(|info| Some((info.mins, *atlas_positions.get(&face_idx)?)));
+
+                let first_vertex = primitive.positions.len() as u32;
+
+                vertices.into_iter().for_each(|vertex| {
+                    let pos = to_dvec3(vertex);
+                    let st = DVec2::new(
+                        pos.dot(to_dvec3(texinfo.u)) + texinfo.u_offset as f64,
+                        pos.dot(to_dvec3(texinfo.v)) + texinfo.v_offset as f64,
+                    );
+
+                    let lightmap_uv = match lightmap {
+                        // luxels are at the pixel centers
+                        Some((mins, atlas_pos)) => {
+                            (atlas_pos + st / bsp::LIGHTMAP_SCALE as f64
+                                - DVec2::new(mins[0] as f64, mins[1] as f64)
+                                + 0.5)
+                                / atlas_size
+                        }
+                        None => fullbright_uv,
+                    };
+
+                    primitive.positions.push(pos);
+                    primitive.normals.push(normal);
+                    primitive.uvs.push(st / texture_size);
+                    primitive.lightmap_uvs.push(lightmap_uv);
+                });
+
+                let vertex_count = primitive.positions.len() as u32 - first_vertex;
+
+                // faces wind clockwise from the front while the formats want counter clockwise
+                (1..vertex_count - 1).for_each(|idx| {
+                    primitive.indices.extend([
+                        first_vertex,
+                        first_vertex + idx + 1,
+                        first_vertex + idx,
+                    ]);
+                });
+            }
+
+            SceneModel {
+                name: match entity {
+                    Some(entity) if model_idx != 0 => {
+                        format!("{} {}", entity_name(entity), model_key)
+                    }
+                    _ => model_key,
+                },
+                origin: entity.map(entity_origin).unwrap_or_default(),
+                primitives,
+            }
+        })
+        .collect();
+
+    let empties = bsp
+        .entities
+        .iter()
+        .skip(1)
+        .filter(|entity| entity.contains_key("origin"))
+        .filter(|entity| {
+            !entity
+                .get("model")
+                .is_some_and(|model| model.starts_with('*'))
+        })
+        .map(|entity| {
+            let yaw = entity
+                .get("angles")
+                .and_then(|angles| parse_triplet(angles).ok())
+                .map(|angles| angles[1])
+                .or_else(|| {
+                    // -1 and -2 are up and down
+                    entity
+                        .get("angle")
+                        .and_then(|angle| angle.parse::<f64>().ok())
+                        .filter(|&angle| angle >= 0.)
+                })
+                .unwrap_or(0.);
+
+            SceneEmpty {
+                name: entity_name(entity),
+                origin: entity_origin(entity),
+                yaw,
+            }
+        })
+        .collect();
+
+    Scene {
+        models,
+        materials,
+        empties,
+        lightmap,
+    }
+}
+
+fn encode_png(write: impl FnOnce(&mut Cursor<&mut Vec<u8>>) -> image::ImageResult<()>) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    // writing into memory does not fail
+    write(&mut Cursor::new(&mut bytes)).unwrap();
+
+    bytes
+}
+
+/// Binary buffer with the views and accessors into it.
+#[derive(Default)]
+struct GltfBuffer {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl GltfBuffer {
+    fn push_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        // accessors need aligned offsets
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+
+        self.buffer_views.len() - 1
+    }
+
+    fn push_floats<const N: usize>(&mut self, values: &[[f32; N]], with_bounds: bool) -> usize {
+        let bytes = values
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let view = self.push_view(&bytes, GLTF_ARRAY_BUFFER.into());
+
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": GLTF_FLOAT,
+            "count": values.len(),
+            "type": format!("VEC{}", N),
+        });
+
+        if with_bounds {
+            let bound = |fold: fn(f32, f32) -> f32| {
+                (0..N)
+                    .map(|axis| {
+                        values
+                            .iter()
+                            .map(|value| value[axis])
+                            .reduce(fold)
+                            .unwrap_or(0.)
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            accessor["min"] = json!(bound(f32::min));
+            accessor["max"] = json!(bound(f32::max));
+        }
+
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes = indices
+            .iter()
+            .flat_map(|index| index.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let view = self.push_view(&bytes, GLTF_ELEMENT_ARRAY_BUFFER.into());
+
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": GLTF_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+fn write_glb(scene: &Scene, options: &Bsp2GltfOptions) -> Vec<u8> {
+    let mut buffer = GltfBuffer::default();
+    let mut images = vec![];
+    let mut textures = vec![];
+
+    let mut push_image = |buffer: &mut GltfBuffer, name: &str, png: Vec<u8>, sampler: usize| {
+        let view = buffer.push_view(&png, None);
+
+        images.push(json!({
+            "name": name,
+            "bufferView": view,
+            "mimeType": "image/png",
+        }));
+        textures.push(json!({ "source": images.len() - 1, "sampler": sampler }));
+
+        textures.len() - 1
+    };
+
+    let lightmap_texture = push_image(
+        &mut buffer,
+        "lightmap",
+        encode_png(|cursor| scene.lightmap.write_to(cursor, ImageFormat::Png)),
+        1,
+    );
+
+    let materials = scene
+        .materials
+        .iter()
+        .map(|material| {
+            let mut res = json!({
+                "name": material.name,
+                "pbrMetallicRoughness": {
+                    "metallicFactor": 0.,
+                    "roughnessFactor": 1.,
+                },
+                "extras": {
+                    "lightmapTexture": { "index": lightmap_texture, "texCoord": 1 },
+                },
+            });
+
+            if let Some(image) = &material.image {
+                let texture = push_image(
+                    &mut buffer,
+                    &material.name,
+                    encode_png(|cursor| image.write_to(cursor, ImageFormat::Png)),
+                    0,
+                );
+
+                res["pbrMetallicRoughness"]["baseColorTexture"] = json!({ "index": texture });
+            }
+
+            if material.masked {
+                res["alphaMode"] = json!("MASK");
+            }
+
+            res
+        })
+        .collect::<Vec<_>>();
+
+    let to_f32 = |v: DVec3| to_y_up(v).as_vec3().to_array();
+
+    let mut meshes = vec![];
+    let mut nodes = vec![];
+
+    scene.models.iter().for_each(|model| {
+        let primitives = model
+            .primitives
+            .iter()
+            .map(|(material, primitive)| {
+                let positions = primitive
+                    .positions
+                    .iter()
+                    .map(|&pos| to_f32(pos * options.scale))
+                    .collect::<Vec<_>>();
+                let normals = primitive
+                    .normals
+                    .iter()
+                    .map(|&normal| to_f32(normal))
+                    .collect::<Vec<_>>();
+                let uvs = primitive
+                    .uvs
+                    .iter()
+                    .map(|uv| uv.as_vec2().to_array())
+                    .collect::<Vec<_>>();
+                let lightmap_uvs = primitive
+                    .lightmap_uvs
+                    .iter()
+                    .map(|uv| uv.as_vec2().to_array())
+                    .collect::<Vec<_>>();
+
+                json!({
+                    "attributes": {
+                        "POSITION": buffer.push_floats(&positions, true),
+                        "NORMAL": buffer.push_floats(&normals, false),
+                        "TEXCOORD_0": buffer.push_floats(&uvs, false),
+                        "TEXCOORD_1": buffer.push_floats(&lightmap_uvs, false),
+                    },
+                    "indices": buffer.push_indices(&primitive.indices),
+                    "material": material,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut node = json!({
+            "name": model.name,
+            "translation": to_f32(model.origin * options.scale),
+        });
+
+        if !primitives.is_empty() {
+            meshes.push(json!({ "name": model.name, "primitives": primitives }));
+            node["mesh"] = json!(meshes.len() - 1);
+        }
+
+        nodes.push(node);
+    });
+
+    scene.empties.iter().for_each(|empty| {
+        // up is Y now
+        let rotation = DQuat::from_rotation_y(empty.yaw.to_radians())
+            .as_quat()
+            .to_array();
+
+        nodes.push(json!({
+            "name": empty.name,
+            "translation": to_f32(empty.origin * options.scale),
+            "rotation": rotation,
+        }));
+    });
+
+    let gltf = json!({
+        "asset": { "version": "2.0", "generator": "gchimp" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "textures": textures,
+        "images": images,
+        "samplers": [
+            {},
+            { "wrapS": GLTF_CLAMP_TO_EDGE, "wrapT": GLTF_CLAMP_TO_EDGE },
+        ],
+        "accessors": buffer.accessors,
+        "bufferViews": buffer.buffer_views,
+        "buffers": [{ "byteLength": buffer.bin.len() }],
+    });
+
+    let mut json_chunk = gltf.to_string().into_bytes();
+    json_chunk.resize(json_chunk.len().next_multiple_of(4), b' ');
+
+    let mut bin_chunk = buffer.bin;
+    bin_chunk.resize(bin_chunk.len().next_multiple_of(4), 0);
+
+    let length = 12 + 8 + json_chunk.len() + 8 + bin_chunk.len();
+
+    let mut glb = Vec::with_capacity(length);
+
+    glb.extend(GLB_MAGIC.to_le_bytes());
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((length as u32).to_le_bytes());
+
+    for (chunk_type, chunk) in [(GLB_CHUNK_JSON, json_chunk), (GLB_CHUNK_BIN, bin_chunk)] {
+        glb.extend((chunk.len() as u32).to_le_bytes());
+        glb.extend(chunk_type.to_le_bytes());
+        glb.extend(chunk);
+    }
+
+    glb
+}
+
+/// Exports the BSP into a binary glTF.
+///
+/// External textures are taken from `wads`, materials of textures not found have no image.
+pub fn bsp2gltf_bytes(bsp: &Bsp, wads: &[&Wad], options: &Bsp2GltfOptions) -> Vec<u8> {
+    write_glb(&build_scene(bsp, wads), options)
+}
+
+/// Exports the BSP into OBJ and MTL files along with the textures and the lightmap atlas as PNG.
+///
+/// Returns file names and their bytes. `name` is the file stem of the OBJ.
+pub fn bsp2obj_files(
+    bsp: &Bsp,
+    wads: &[&Wad],
+    name: &str,
+    options: &Bsp2GltfOptions,
+) -> Vec<(String, Vec<u8>)> {
+    let scene = build_scene(bsp, wads);
+
+    let mut obj = format!("mtllib {}.mtl\n", name);
+    let mut mtl = String::new();
+    let mut files = vec![];
+
+    scene.materials.iter().for_each(|material| {
+        mtl += &format!("newmtl {}\nKd 1 1 1\n", material.name);
+
+        if let Some(image) = &material.image {
+            let file_name = format!("{}.png", material.name);
+
+            mtl += &format!("map_Kd {}\n", file_name);
+
+            if material.masked {
+                mtl += &format!("map_d {}\n", file_name);
+            }
+
+            files.push((
+                file_name,
+                encode_png(|cursor| image.write_to(cursor, ImageFormat::Png)),
+            ));
+        }
+
+        mtl += "\n";
+    });
+
+    // indices are 1-based and count from the start of the file
+    let mut vertex_offset = 1;
+
+    scene.models.iter().for_each(|model| {
+        obj += &format!("o {}\n", model.name);
+
+        model.primitives.iter().for_each(|(material, primitive)| {
+            primitive
+                .positions
+                .iter()
+                .zip(&primitive.normals)
+                .zip(&primitive.uvs)
+                .for_each(|((&pos, &normal), uv)| {
+                    let pos = to_y_up((pos + model.origin) * options.scale);
+                    let normal = to_y_up(normal);
+
+                    obj += &format!("v {} {} {}\n", pos.x, pos.y, pos.z);
+                    obj += &format!("vn {} {} {}\n", normal.x, normal.y, normal.z);
+                    // v goes up the image
+                    obj += &format!("vt {} {}\n", uv.x, -uv.y);
+                });
+
+            obj += &format!("usemtl {}\n", scene.materials[*material].name);
+
+            primitive.indices.chunks_exact(3).for_each(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|idx| triangle[idx] as usize + vertex_offset);
+
+                obj += &format!("f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}\n");
+            });
+
+            vertex_offset += primitive.positions.len();
+        });
+    });
+
+    files.push((
+        format!("{}_lightmap.png", name),
+        encode_png(|cursor| scene.lightmap.write_to(cursor, ImageFormat::Png)),
+    ));
+    files.push((format!("{}.obj", name), obj.into_bytes()));
+    files.push((format!("{}.mtl", name), mtl.into_bytes()));
+
+    files
+}
+
+/// Writes `<name>.glb` next to the BSP, or the OBJ files if `obj` is set.
+///
+/// WADs in the `wad` key are looked up like [`find_wads`] and `extra_wads` come after them.
+pub fn bsp2gltf(
+    path: impl AsRef<OsStr> + AsRef<Path>,
+    extra_wads: &[PathBuf],
+    obj: bool,
+    options: &Bsp2GltfOptions,
+) -> eyre::Result<()> {
+    let bsp_path: &Path = path.as_ref();
+    let bsp = Bsp::from_file(bsp_path)?;
+
+    let Some(name) = bsp_path.file_stem().and_then(|stem| stem.to_str()) else {
+        return err!("Invalid BSP path {}", bsp_path.display());
+    };
+
+    let key_wads = find_wads(&bsp, bsp_path);
+    let extra_wads = extra_wads
+        .iter()
+        .map(Wad::from_file)
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    let wads = key_wads
+        .iter()
+        .map(|key_wad| &key_wad.wad)
+        .chain(&extra_wads)
+        .collect::<Vec<_>>();
+
+    if obj {
+        for (file_name, bytes) in bsp2obj_files(&bsp, &wads, name, options) {
+            std::fs::write(bsp_path.with_file_name(file_name), bytes)?;
+        }
+    } else {
+        std::fs::write(
+            bsp_path.with_extension("glb"),
+            bsp2gltf_bytes(&bsp, &wads, options),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_glb_json(glb: &[u8]) -> Value {
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+
+        let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+
+        serde_json::from_slice(&glb[20..20 + json_length]).unwrap()
+    }
+
+    #[test]
+    fn gltf() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let glb = bsp2gltf_bytes(&bsp, &[], &Bsp2GltfOptions::default());
+        let gltf = read_glb_json(&glb);
+
+        let nodes = gltf["nodes"].as_array().unwrap();
+
+        // a node for each model then the empties
+        assert!(nodes.len() > bsp.models.len());
+        assert_eq!(nodes[0]["name"], "*0");
+        assert!(nodes[0]["mesh"].is_u64());
+
+        gltf["meshes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|mesh| mesh["primitives"].as_array().unwrap())
+            .for_each(|primitive| {
+                let attributes = &primitive["attributes"];
+                let count = |accessor: &Value| {
+                    gltf["accessors"][accessor.as_u64().unwrap() as usize]["count"]
+                        .as_u64()
+                        .unwrap()
+                };
+
+                let vertex_count = count(&attributes["POSITION"]);
+
+                assert_eq!(count(&attributes["TEXCOORD_1"]), vertex_count);
+                assert_eq!(count(&primitive["indices"]) % 3, 0);
+            });
+
+        // every view is inside the buffer
+        let buffer_length = gltf["buffers"][0]["byteLength"].as_u64().unwrap();
+
+        gltf["bufferViews"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .for_each(|view| {
+                assert!(
+                    view["byteOffset"].as_u64().unwrap() + view["byteLength"].as_u64().unwrap()
+                        <= buffer_length
+                );
+            });
+    }
+
+    #[test]
+    fn lightmap_uv_in_atlas() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let scene = build_scene(&bsp, &[]);
+
+        scene
+            .models
+            .iter()
+            .flat_map(|model| &model.primitives)
+            .flat_map(|(_, primitive)| &primitive.lightmap_uvs)
+            .for_each(|uv| {
+                assert!((0. ..=1.).contains(&uv.x));
+                assert!((0. ..=1.).contains(&uv.y));
+            });
+    }
+
+    #[test]
+    fn obj() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        let files = bsp2obj_files(&bsp, &[], "normal", &Bsp2GltfOptions::default());
+
+        let names = files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+
+        assert!(names.contains(&"normal.obj"));
+        assert!(names.contains(&"normal.mtl"));
+        assert!(names.contains(&"normal_lightmap.png"));
+        assert!(names.contains(&"dev_gray_10_128.png"));
+
+        let obj = String::from_utf8(
+            files
+                .iter()
+                .find(|(name, _)| name == "normal.obj")
+                .unwrap()
+                .1
+                .clone(),
+        )
+        .unwrap();
+        let vertex_count = obj.lines().filter(|line| line.starts_with("v ")).count();
+
+        // every face refers to a vertex that exists
+        obj.lines()
+            .filter(|line| line.starts_with("f "))
+            .flat_map(|line| line.split_whitespace().skip(1))
+            .for_each(|corner| {
+                let vertex = corner.split('/').next().unwrap().parse::<usize>().unwrap();
+
+                assert!((1..=vertex_count).contains(&vertex));
+            });
+    }
+}
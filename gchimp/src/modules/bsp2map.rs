@@ -0,0 +1,608 @@
+//! Decompiles a BSP back into a MAP.
+//!
+//! Every solid leaf of a model hull 0 is a convex volume bounded by the planes of its parent nodes so it becomes a brush.
+//! Texture of each brush side is taken from the face on the same plane.
+//!
+//! The result is not the original source. Brushes are split along the BSP tree and tool textures
+//! like CLIP and SKIP are lost because they do not exist in hull 0.
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::OpenOptions,
+    io::{Read, Write},
+    path::Path,
+};
+
+use bsp::{is_missing_texture, Bsp, LeafContent, TexInfo};
+use glam::{DVec3, DVec4};
+use map::{base_winding, clip_winding, Attributes, Brush, BrushPlane, Entity, Map, MapFormat};
+
+use crate::utils::{bsp_stuffs::to_dvec3, map_stuffs::brush_from_mins_maxs};
+
+const ON_EPSILON: f64 = 0.01;
+/// Brushes are clipped by the model bounds expanded by this much.
+const BOUNDS_PADDING: f64 = 16.;
+const NULL_TEXTURE: &str = "NULL";
+const ORIGIN_TEXTURE: &str = "ORIGIN";
+
+/// One side of a brush with the normal pointing outward.
+#[derive(Debug, Clone, Copy)]
+struct Side {
+    normal: DVec3,
+    distance: f64,
+    /// Plane index in the BSP. `None` for the sides from the model bounds.
+    plane: Option<usize>,
+}
+
+impl Side {
+    fn flip(self) -> Self {
+        Self {
+            normal: -self.normal,
+            distance: -self.distance,
+            plane: self.plane,
+        }
+    }
+}
+
+fn bounds_sides(mins: DVec3, maxs: DVec3) -> Vec<Side> {
+    (0..3)
+        .flat_map(|axis| {
+            let mut normal = DVec3::ZERO;
+            normal[axis] = 1.;
+
+            [
+                Side {
+                    normal,
+                    distance: maxs[axis] + BOUNDS_PADDING,
+                    plane: None,
+                },
+                Side {
+                    normal: -normal,
+                    distance: -mins[axis] + BOUNDS_PADDING,
+                    plane: None,
+                },
+            ]
+        })
+        .collect()
+}
+
+/// Polygons of every side that is not redundant.
+fn brush_polygons(sides: &[Side]) -> Vec<(Side, Vec<DVec3>)> {
+    sides
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, side)| {
+            let mut winding = base_winding(side.normal, side.distance);
+
+            for (other_idx, other) in sides.iter().enumerate() {
+                if other_idx == idx || winding.len() < 3 {
+                    continue;
+                }
+
+                // same plane appearing twice from the tree
+                if other.normal.dot(side.normal) > 1. - 1e-9
+                    && (other.distance - side.distance).abs() < ON_EPSILON
+                {
+                    if other_idx < idx {
+                        winding.clear();
+                    }
+
+                    continue;
+                }
+
+                winding = clip_winding(&winding, other.normal, other.distance, ON_EPSILON);
+            }
+
+            if winding.len() < 3 {
+                return None;
+            }
+
+            Some((*side, winding))
+        })
+        .collect()
+}
+
+// gets rid of numbers like 63.99999999999999
+fn snap(v: DVec3) -> DVec3 {
+    let rounded = v.round();
+
+    if (v - rounded).abs().max_element() < 0.001 {
+        rounded
+    } else {
+        v
+    }
+}
+
+/// Three points on the polygon where the normal of the points is pointing inward like how it is in a MAP.
+fn plane_points(winding: &[DVec3], outward: DVec3) -> [DVec3; 3] {
+    let p1 = snap(winding[0]);
+
+    // the biggest triangle is the most accurate
+    let mut best = (snap(winding[1]), snap(winding[2]));
+    let mut best_area = 0.;
+
+    for i in 1..winding.len() {
+        for j in (i + 1)..winding.len() {
+            let p2 = snap(winding[i]);
+            let p3 = snap(winding[j]);
+            let area = (p2 - p1).cross(p3 - p1).length();
+
+            if area > best_area {
+                best_area = area;
+                best = (p2, p3);
+            }
+        }
+    }
+
+    let (p2, p3) = best;
+
+    if (p2 - p1).cross(p3 - p1).dot(outward) > 0. {
+        [p1, p3, p2]
+    } else {
+        [p1, p2, p3]
+    }
+}
+
+/// Same as `TextureAxisFromPlane`, used when there is no face to take texture from.
+fn default_texture_axes(normal: DVec3) -> (DVec3, DVec3) {
+    const BASE_AXES: [[DVec3; 3]; 6] = [
+        [DVec3::Z, DVec3::X, DVec3::NEG_Y],
+        [DVec3::NEG_Z, DVec3::X, DVec3::NEG_Y],
+        [DVec3::X, DVec3::Y, DVec3::NEG_Z],
+        [DVec3::NEG_X, DVec3::Y, DVec3::NEG_Z],
+        [DVec3::Y, DVec3::X, DVec3::NEG_Z],
+        [DVec3::NEG_Y, DVec3::X, DVec3::NEG_Z],
+    ];
+
+    let mut best = 0;
+    let mut best_dot = 0.;
+
+    for (idx, axes) in BASE_AXES.iter().enumerate() {
+        let dot = normal.dot(axes[0]);
+
+        if dot > best_dot {
+            best_dot = dot;
+            best = idx;
+        }
+    }
+
+    (BASE_AXES[best][1], BASE_AXES[best][2])
+}
+
+/// Converts the texture vectors into Valve220 axes, offsets and scales.
+fn texinfo_to_axes(texinfo: &TexInfo) -> (DVec4, DVec4, f64, f64) {
+    let u = to_dvec3(texinfo.u);
+    let v = to_dvec3(texinfo.v);
+
+    let u_length = u.length().max(f64::EPSILON);
+    let v_length = v.length().max(f64::EPSILON);
+
+    (
+        (u / u_length).extend(texinfo.u_offset as f64),
+        (v / v_length).extend(texinfo.v_offset as f64),
+        1. / u_length,
+        1. / v_length,
+    )
+}
+
+/// Brushes of one model.
+struct Decompiler<'a> {
+    bsp: &'a Bsp,
+    model_idx: usize,
+    /// Faces of the model on each plane index.
+    plane_faces: HashMap<usize, Vec<usize>>,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(bsp: &'a Bsp, model_idx: usize) -> Self {
+        let mut plane_faces: HashMap<usize, Vec<usize>> = HashMap::new();
+
+        if let Some(model) = bsp.models.get(model_idx) {
+            let first_face = model.first_face.max(0) as usize;
+            let face_count = model.face_count.max(0) as usize;
+
+            (first_face..(first_face + face_count).min(bsp.faces.len())).for_each(|idx| {
+                plane_faces
+                    .entry(bsp.faces[idx].plane as usize)
+                    .or_default()
+                    .push(idx);
+            });
+        }
+
+        Self {
+            bsp,
+            model_idx,
+            plane_faces,
+        }
+    }
+
+    fn face_vertices(&self, face_idx: usize) -> Vec<DVec3> {
+        let face = &self.bsp.faces[face_idx];
+
+        (0..face.edge_count as usize)
+            .filter_map(|idx| {
+                let surf_edge = *self.bsp.surf_edges.get(face.first_edge as usize + idx)?;
+
+                let vertex = if surf_edge >= 0 {
+                    self.bsp.edges.get(surf_edge as usize)?[0]
+                } else {
+                    self.bsp.edges.get(surf_edge.unsigned_abs() as usize)?[1]
+                };
+
+                self.bsp
+                    .vertices
+                    .get(vertex as usize)
+                    .copied()
+                    .map(to_dvec3)
+            })
+            .collect()
+    }
+
+    /// Face facing the same way as the side, preferring the one inside the side polygon.
+    fn face_for_side(&self, side: &Side, winding: &[DVec3]) -> Option<usize> {
+        let plane_idx = side.plane?;
+        let plane = self.bsp.planes.get(plane_idx)?;
+
+        // side is on the back of the plane if its normal is the same as the plane
+        let side_is_front = to_dvec3(plane.normal).dot(side.normal) < 0.;
+
+        let winding_center = winding.iter().sum::<DVec3>() / winding.len() as f64;
+
+        self.plane_faces
+            .get(&plane_idx)?
+            .iter()
+            .filter(|&&face_idx| (self.bsp.faces[face_idx].side != 0) == side_is_front)
+            .filter_map(|&face_idx| {
+                let vertices = self.face_vertices(face_idx);
+
+                if vertices.is_empty() {
+                    return None;
+                }
+
+                let center = vertices.iter().sum::<DVec3>() / vertices.len() as f64;
+
+                let inside = winding.iter().enumerate().all(|(idx, &a)| {
+                    let b = winding[(idx + 1) % winding.len()];
+
+                    // edge normal pointing out of the polygon, whichever the winding order is
+                    let edge_normal = (b - a).cross(side.normal);
+                    let edge_normal = if edge_normal.dot(winding_center - a) > 0. {
+                        -edge_normal
+                    } else {
+                        edge_normal
+                    };
+
+                    edge_normal.dot(center - a) <= ON_EPSILON
+                });
+
+                let distance = center.distance(winding_center);
+
+                Some((face_idx, !inside, distance))
+            })
+            .min_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)))
+            .map(|(face_idx, _, _)| face_idx)
+    }
+
+    fn brush_plane(&self, side: &Side, winding: &[DVec3]) -> BrushPlane {
+        let [p1, p2, p3] = plane_points(winding, side.normal);
+
+        let texinfo = self.face_for_side(side, winding).and_then(|face_idx| {
+            self.bsp
+                .texinfo
+                .get(self.bsp.faces[face_idx].texinfo as usize)
+        });
+
+        let (texture_name, u, v, u_scale, v_scale) = if let Some(texinfo) = texinfo {
+            let texture_name = self
+                .bsp
+                .textures
+                .get(texinfo.texture_index as usize)
+                .filter(|texture| !is_missing_texture(texture))
+                .map(|texture| texture.texture_name.get_string())
+                .unwrap_or(NULL_TEXTURE.to_string());
+
+            let (u, v, u_scale, v_scale) = texinfo_to_axes(texinfo);
+
+            (texture_name, u, v, u_scale, v_scale)
+        } else {
+            let (u, v) = default_texture_axes(side.normal);
+
+            (NULL_TEXTURE.to_string(), u.extend(0.), v.extend(0.), 1., 1.)
+        };
+
+        BrushPlane {
+            p1,
+            p2,
+            p3,
+            texture_name,
+            u,
+            v,
+            rotation: 0.,
+            u_scale,
+            v_scale,
+        }
+    }
+
+    fn brush(&self, sides: &[Side]) -> Option<Brush> {
+        let polygons = brush_polygons(sides);
+
+        if polygons.len() < 4 {
+            return None;
+        }
+
+        Some(Brush {
+            planes: polygons
+                .iter()
+                .map(|(side, winding)| self.brush_plane(side, winding))
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    fn walk(&self, node: i32, sides: &mut Vec<Side>, brushes: &mut Vec<Brush>, depth: usize) {
+        // malformed trees could loop forever
+        if depth > self.bsp.nodes.len() {
+            return;
+        }
+
+        if node < 0 {
+            let is_solid = self
+                .bsp
+                .leaves
+                .get((-1 - node) as usize)
+                .map(|leaf| !matches!(leaf.contents, LeafContent::ContentsEmpty))
+                .unwrap_or(true);
+
+            if is_solid {
+                brushes.extend(self.brush(sides));
+            }
+
+            return;
+        }
+
+        let Some(node) = self.bsp.nodes.get(node as usize) else {
+            return;
+        };
+        let Some(plane) = self.bsp.planes.get(node.plane as usize) else {
+            return;
+        };
+
+        let side = Side {
+            normal: to_dvec3(plane.normal),
+            distance: plane.distance as f64,
+            plane: Some(node.plane as usize),
+        };
+
+        // front child is in front of the plane so the side bounding it is facing the other way
+        sides.push(side.flip());
+        self.walk(node.children[0] as i32, sides, brushes, depth + 1);
+        sides.pop();
+
+        sides.push(side);
+        self.walk(node.children[1] as i32, sides, brushes, depth + 1);
+        sides.pop();
+    }
+
+    fn brushes(&self) -> Vec<Brush> {
+        let Some(model) = self.bsp.models.get(self.model_idx) else {
+            return vec![];
+        };
+
+        let mut sides = bounds_sides(to_dvec3(model.mins), to_dvec3(model.maxs));
+        let mut brushes = vec![];
+
+        let head_node = model.head_nodes[0];
+
+        // models with only one leaf
+        if head_node < 0 {
+            return brushes;
+        }
+
+        self.walk(head_node, &mut sides, &mut brushes, 0);
+
+        brushes
+    }
+}
+
+/// Moves the brush while keeping the texture at the same place.
+fn translate_brush(brush: &mut Brush, offset: DVec3) {
+    brush.planes.iter_mut().for_each(|plane| {
+        plane.p1 += offset;
+        plane.p2 += offset;
+        plane.p3 += offset;
+
+        plane.u.w -= plane.u.truncate().dot(offset) / plane.u_scale;
+        plane.v.w -= plane.v.truncate().dot(offset) / plane.v_scale;
+    });
+}
+
+fn parse_origin(s: &str) -> Option<DVec3> {
+    let res = s
+        .split_whitespace()
+        .map(|n| n.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .ok()?;
+
+    if res.len() != 3 {
+        return None;
+    }
+
+    Some(DVec3::from_slice(&res))
+}
+
+/// Decompiles the BSP into a MAP.
+///
+/// Brush entities get back their brushes from the `*N` model and lose the `model` key.
+/// Brush entities with an origin get an ORIGIN brush.
+pub fn bsp_to_map(bsp: &Bsp) -> Map {
+    let entities = bsp
+        .entities
+        .iter()
+        .map(|bsp_entity| {
+            let mut attributes: Attributes = bsp_entity.clone().into_iter().collect();
+
+            let is_worldspawn = attributes
+                .get("classname")
+                .map(|classname| classname == "worldspawn")
+                .unwrap_or(false);
+
+            let model_idx = if is_worldspawn {
+                if !attributes.contains_key("mapversion") {
+                    attributes.insert("mapversion", "220");
+                }
+
+                Some(0)
+            } else {
+                attributes
+                    .get("model")
+                    .and_then(|model| model.strip_prefix('*'))
+                    .and_then(|model| model.parse::<usize>().ok())
+            };
+
+            let Some(model_idx) = model_idx else {
+                return Entity {
+                    attributes,
+                    brushes: None,
+                    ..Default::default()
+                };
+            };
+
+            if !is_worldspawn {
+                attributes.remove("model");
+            }
+
+            let mut brushes = Decompiler::new(bsp, model_idx).brushes();
+
+            let origin = (!is_worldspawn)
+                .then(|| attributes.get("origin").and_then(|s| parse_origin(s)))
+                .flatten();
+
+            if let Some(origin) = origin {
+                brushes
+                    .iter_mut()
+                    .for_each(|brush| translate_brush(brush, origin));
+
+                brushes.push(brush_from_mins_maxs(
+                    &(origin - 8.).to_array(),
+                    &(origin + 8.).to_array(),
+                    ORIGIN_TEXTURE,
+                ));
+
+                attributes.remove("origin");
+            }
+
+            Entity {
+                attributes,
+                brushes: Some(brushes),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    Map {
+        format: MapFormat::Valve220,
+        tb_header: None,
+        entities,
+        ..Default::default()
+    }
+}
+
+pub fn bsp2map_bytes(bsp_bytes: &[u8]) -> eyre::Result<Vec<u8>> {
+    let bsp = Bsp::from_bytes(bsp_bytes)?;
+
+    Ok(bsp_to_map(&bsp).write_to_string().into_bytes())
+}
+
+/// Writes `<name>.decompiled.map` next to the BSP so it does not overwrite the original source.
+pub fn bsp2map(path: impl AsRef<OsStr> + AsRef<Path>) -> eyre::Result<()> {
+    let bsp_path: &Path = path.as_ref();
+
+    let mut bsp_file = OpenOptions::new().read(true).open(bsp_path)?;
+    let mut bsp_bytes: Vec<u8> = vec![];
+
+    bsp_file.read_to_end(&mut bsp_bytes)?;
+
+    let res_bytes = bsp2map_bytes(&bsp_bytes)?;
+
+    let mut out_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(bsp_path.with_extension("decompiled.map"))?;
+
+    out_file.write_all(&res_bytes)?;
+    out_file.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clip() {
+        let winding = base_winding(DVec3::Z, 0.);
+        let winding = clip_winding(&winding, DVec3::X, 32., ON_EPSILON);
+        let winding = clip_winding(&winding, DVec3::NEG_X, 32., ON_EPSILON);
+
+        assert_eq!(winding.len(), 4);
+        assert!(winding.iter().all(|p| p.x.abs() <= 32. + ON_EPSILON));
+    }
+
+    #[test]
+    fn cube_brush() {
+        let sides = bounds_sides(DVec3::splat(-16.), DVec3::splat(16.));
+        let polygons = brush_polygons(&sides);
+
+        assert_eq!(polygons.len(), 6);
+
+        polygons.iter().for_each(|(side, winding)| {
+            assert_eq!(winding.len(), 4);
+
+            let [p1, p2, p3] = plane_points(winding, side.normal);
+
+            // points inward
+            assert!((p2 - p1).cross(p3 - p1).dot(side.normal) < 0.);
+        });
+    }
+
+    #[test]
+    fn decompile_c1a3d() {
+        let bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let map = bsp_to_map(&bsp);
+
+        assert_eq!(map.entities.len(), bsp.entities.len());
+
+        let worldspawn = &map.entities[0];
+        assert!(!worldspawn.brushes.as_ref().unwrap().is_empty());
+
+        // every brush entity gets its brushes back
+        map.entities
+            .iter()
+            .zip(&bsp.entities)
+            .for_each(|(entity, bsp_entity)| {
+                let is_brush_model =
+                    |model: Option<&String>| model.is_some_and(|model| model.starts_with('*'));
+
+                assert!(!is_brush_model(entity.attributes.get("model")));
+
+                if is_brush_model(bsp_entity.get("model")) {
+                    assert!(
+                        entity
+                            .brushes
+                            .as_ref()
+                            .is_some_and(|brushes| !brushes.is_empty()),
+                        "{:?}",
+                        bsp_entity
+                    );
+                }
+            });
+
+        // and the output is still a valid map
+        let text = map.write_to_string();
+        let map2 = Map::from_text(&text).unwrap();
+
+        assert_eq!(map2.entities.len(), map.entities.len());
+    }
+}
@@ -0,0 +1,599 @@
+//! Converts a brush model of a compiled BSP into a GoldSrc model.
+//!
+//! Faces are triangulated from their edges and mapped with their texinfo, then they go through
+//! the same SMD, QC and studiomdl steps as map2mdl.
+//!
+//! With lightmap baking, every lit face is baked with the lightmap multiplied in so the model keeps
+//! the lighting it has in the map. Only the first light style is baked. The baked faces are packed
+//! into atlases instead of one texture per face, studiomdl only takes 100 textures per model.
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use bsp::{is_missing_texture, sample_luxel, Bsp, FaceLightmapInfo, LIGHTMAP_SCALE};
+use glam::{DVec2, DVec3};
+use image::{imageops, RgbaImage};
+use smd::{Triangle, Vertex};
+use wad::types::{FileEntry, MipTex, Wad};
+
+use crate::{
+    err,
+    modules::{
+        embed_texture::find_wads,
+        map2mdl::{ConvertFromTrianglesOptions, Map2Mdl},
+    },
+    utils::{
+        bsp_stuffs::to_dvec3,
+        constants::{NoRenderTexture, MAX_GOLDSRC_TEXTURE_SIZE},
+        img_stuffs::{rgba8_to_8bpp, write_8bpp_to_file, GoldSrcBmp},
+        mdl_stuffs::handle_studiomdl_output,
+        smd_stuffs::textures_used_in_triangles,
+    },
+};
+
+#[derive(Debug)]
+pub struct Bsp2MdlOptions {
+    /// Bakes the face lightmaps into the textures.
+    pub bake_lightmap: bool,
+    /// The model is moved so its centroid is at the origin.
+    ///
+    /// Models of entities with an ORIGIN brush are already around the entity origin and stay there.
+    pub move_to_origin: bool,
+    /// Model is flatshade, which is what baked lighting wants.
+    pub flatshade: bool,
+    pub studiomdl: Option<PathBuf>,
+    #[cfg(target_os = "linux")]
+    pub wineprefix: Option<String>,
+}
+
+impl Default for Bsp2MdlOptions {
+    fn default() -> Self {
+        Self {
+            bake_lightmap: false,
+            move_to_origin: true,
+            flatshade: true,
+            studiomdl: None,
+            #[cfg(target_os = "linux")]
+            wineprefix: None,
+        }
+    }
+}
+
+/// Triangles of a brush model and the images of the textures they use.
+#[derive(Debug)]
+pub struct ModelMesh {
+    pub triangles: Vec<Triangle>,
+    /// Keyed by the material name of the triangles.
+    pub textures: HashMap<String, GoldSrcBmp>,
+}
+
+/// Finds the model from its index, written as `3` or `*3`, or from the targetname of its entity.
+pub fn find_model(bsp: &Bsp, model: &str) -> eyre::Result<usize> {
+    let index = if let Ok(index) = model.trim_start_matches('*').parse::<usize>() {
+        index
+    } else {
+        let Some(index) = bsp
+            .entities
+            .iter()
+            .filter(|entity| entity.get("targetname").is_some_and(|name| name == model))
+            .find_map(|entity| {
+                entity
+                    .get("model")?
+                    .strip_prefix('*')?
+                    .parse::<usize>()
+                    .ok()
+            })
+        else {
+            return err!("Cannot find brush entity with targetname {}", model);
+        };
+
+        index
+    };
+
+    if index >= bsp.models.len() {
+        return err!(
+            "Model {} does not exist, there are only {} models",
+            index,
+            bsp.models.len()
+        );
+    }
+
+    Ok(index)
+}
+
+/// Embedded texture or the texture with the same name and dimensions from `wads`.
+pub(crate) fn find_miptex<'a>(texture: &'a MipTex, wads: &[&'a Wad]) -> Option<&'a MipTex> {
+    if !texture.is_external() {
+        return Some(texture);
+    }
+
+    let name = texture.texture_name.get_string_standard();
+
+    wads.iter()
+        .flat_map(|wad| wad.entries.iter())
+        .find_map(|entry| match &entry.file_entry {
+            FileEntry::MipTex(miptex)
+                if entry.texture_name_standard() == name
+                    && miptex.width == texture.width
+                    && miptex.height == texture.height
+                    && !miptex.is_external() =>
+            {
+                Some(miptex)
+            }
+            _ => None,
+        })
+}
+
+/// Border around every baked face so filtering does not pick up the face next to it in the atlas.
+const BAKE_PADDING: u32 = 1;
+
+/// Area of the texture plane a baked texture covers.
+struct BakeRect {
+    /// Texel position of the top left corner.
+    origin: DVec2,
+    /// Pixels per texel, less than 1 when the face is too big for one texture.
+    density: f64,
+    /// Without the padding.
+    dimensions: (u32, u32),
+}
+
+impl BakeRect {
+    fn new(info: &FaceLightmapInfo) -> Self {
+        // luxels are on the corners so the last luxel is where the face ends
+        let span = [info.width, info.height]
+            .map(|luxels| (luxels.saturating_sub(1).max(1) as i32 * LIGHTMAP_SCALE) as f64);
+
+        let max_size = (MAX_GOLDSRC_TEXTURE_SIZE - BAKE_PADDING * 2) as f64;
+        let density = span
+            .iter()
+            .fold(1f64, |acc, &span| acc.min(max_size / span));
+
+        let [width, height] = span.map(|span| (span * density).ceil() as u32);
+
+        Self {
+            origin: DVec2::new(
+                (info.mins[0] * LIGHTMAP_SCALE) as f64,
+                (info.mins[1] * LIGHTMAP_SCALE) as f64,
+            ),
+            density,
+            dimensions: (width, height),
+        }
+    }
+
+    /// Dimensions of the baked image with the padding.
+    fn padded_dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.dimensions;
+
+        (width + BAKE_PADDING * 2, height + BAKE_PADDING * 2)
+    }
+
+    /// Pixel position in the baked image.
+    fn pixel(&self, st: DVec2) -> DVec2 {
+        (st - self.origin) * self.density + BAKE_PADDING as f64
+    }
+}
+
+/// Atlas page and the top left corner in it.
+type AtlasSlot = (usize, u32, u32);
+
+/// Packs the images into rows on as many pages as needed, tallest first.
+///
+/// Returns the slot of every image in order and the dimensions of every page.
+fn pack_atlases(dimensions: &[(u32, u32)]) -> (Vec<AtlasSlot>, Vec<(u32, u32)>) {
+    let mut order = (0..dimensions.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| std::cmp::Reverse(dimensions[idx].1));
+
+    let mut slots = vec![(0, 0, 0); dimensions.len()];
+    let mut pages: Vec<(u32, u32)> = vec![];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+
+    for idx in order {
+        let (width, height) = dimensions[idx];
+
+        if x + width > MAX_GOLDSRC_TEXTURE_SIZE {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+
+        if pages.is_empty() || y + height > MAX_GOLDSRC_TEXTURE_SIZE {
+            pages.push((0, 0));
+            (x, y, row_height) = (0, 0, 0);
+        }
+
+        slots[idx] = (pages.len() - 1, x, y);
+
+        let page = pages.last_mut().unwrap();
+        page.0 = page.0.max(x + width);
+        page.1 = page.1.max(y + height);
+
+        x += width;
+        row_height = row_height.max(height);
+    }
+
+    (slots, pages)
+}
+
+/// Texture tiled over the face with the lightmap multiplied in.
+///
+/// The padding keeps going past the face edges.
+fn bake_face(
+    miptex: &MipTex,
+    luxels: &[[u8; 3]],
+    info: &FaceLightmapInfo,
+    rect: &BakeRect,
+) -> RgbaImage {
+    let (rgb, (texture_width, texture_height)) = miptex.to_rgb();
+    let (width, height) = rect.padded_dimensions();
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        // texel at the pixel center
+        let pixel = DVec2::new(x as f64, y as f64) + 0.5 - BAKE_PADDING as f64;
+        let st = rect.origin + pixel / rect.density;
+
+        let texel_x = (st.x.floor() as i64).rem_euclid(texture_width as i64) as usize;
+        let texel_y = (st.y.floor() as i64).rem_euclid(texture_height as i64) as usize;
+        let texel = (texel_y * texture_width as usize + texel_x) * 3;
+
+        let light = sample_luxel(
+            luxels,
+            info.width,
+            st.x / LIGHTMAP_SCALE as f64 - info.mins[0] as f64,
+            st.y / LIGHTMAP_SCALE as f64 - info.mins[1] as f64,
+        );
+
+        let [r, g, b] = std::array::from_fn(|channel| {
+            (rgb[texel + channel] as f64 * light[channel] / 255.).round() as u8
+        });
+
+        image::Rgba([r, g, b, 255])
+    })
+}
+
+/// Triangulates the faces of the model, skipping the ones with tool textures.
+///
+/// External textures are looked up in `wads`.
+/// Masked textures are never baked because baking would lose the transparent color.
+pub fn model_to_mesh(
+    bsp: &Bsp,
+    model_index: usize,
+    wads: &[&Wad],
+    bake_lightmap: bool,
+) -> eyre::Result<ModelMesh> {
+    let Some(model) = bsp.models.get(model_index) else {
+        return err!("Model {} does not exist", model_index);
+    };
+
+    let mut triangles = vec![];
+    let mut textures = HashMap::new();
+    let mut missing = HashSet::new();
+    // baked face images and the triangles of the face, those have pixels as uv until packed
+    let mut baked_images = vec![];
+    let mut baked_triangles = vec![];
+
+    let first_face = model.first_face.max(0) as usize;
+
+    for face_index in first_face..first_face + model.face_count.max(0) as usize {
+        let (Some(face), Some(vertices)) = (
+            bsp.faces.get(face_index),
+            Some(bsp.face_vertices(face_index)).filter(|vertices| vertices.len() >= 3),
+        ) else {
+            continue;
+        };
+
+        let Some(texinfo) = bsp.texinfo.get(face.texinfo as usize) else {
+            continue;
+        };
+        let Some(texture) = bsp
+            .textures
+            .get(texinfo.texture_index as usize)
+            .filter(|texture| !is_missing_texture(texture))
+        else {
+            continue;
+        };
+        let Some(norm) = bsp.face_normal(face_index) else {
+            continue;
+        };
+
+        let name = texture.texture_name.get_string();
+
+        if NoRenderTexture.contains(&name) {
+            continue;
+        }
+
+        let Some(miptex) = find_miptex(texture, wads) else {
+            missing.insert(name);
+            continue;
+        };
+
+        let norm = to_dvec3(norm);
+
+        let u = (to_dvec3(texinfo.u), texinfo.u_offset as f64);
+        let v = (to_dvec3(texinfo.v), texinfo.v_offset as f64);
+
+        let vertices = vertices
+            .into_iter()
+            .map(|vertex| {
+                let pos = to_dvec3(vertex);
+                let st = DVec2::new(pos.dot(u.0) + u.1, pos.dot(v.0) + v.1);
+
+                (pos, st)
+            })
+            .collect::<Vec<_>>();
+
+        let baked = (bake_lightmap && !name.starts_with('{'))
+            .then(|| {
+                let info = bsp.face_lightmap_info(face_index)?;
+                let luxels = bsp.face_lightmap(face_index, 0)?;
+
+                Some((info, luxels))
+            })
+            .flatten();
+
+        let (material, uv): (String, Box<dyn Fn(DVec2) -> DVec2>) =
+            if let Some((info, luxels)) = baked {
+                let rect = BakeRect::new(&info);
+                baked_images.push(bake_face(miptex, luxels, &info, &rect));
+                baked_triangles.push(triangles.len()..triangles.len() + vertices.len() - 2);
+
+                (String::new(), Box::new(move |st| rect.pixel(st)))
+            } else {
+                if !textures.contains_key(&name) {
+                    if miptex.palette.get_bytes().is_empty() {
+                        return err!("Texture {} does not have palette", name);
+                    }
+
+                    textures.insert(
+                        name.clone(),
+                        GoldSrcBmp {
+                            image: miptex.mip_images[0].data.get_bytes().to_owned(),
+                            palette: miptex.palette.get_bytes().to_owned(),
+                            dimensions: (miptex.width, miptex.height),
+                        },
+                    );
+                }
+
+                let dimensions = DVec2::new(miptex.width as f64, miptex.height as f64);
+
+                // flip the v coordinate because the texture is upside down in the model
+                (
+                    name,
+                    Box::new(move |st| st / dimensions * DVec2::new(1., -1.)),
+                )
+            };
+
+        let vertex = |(pos, st): (DVec3, DVec2)| Vertex {
+            parent: 0,
+            pos,
+            norm,
+            uv: uv(st),
+            source: None,
+        };
+
+        // faces wind clockwise from the front while the model wants counter clockwise
+        (1..vertices.len() - 1).for_each(|idx| {
+            triangles.push(Triangle {
+                material: material.clone(),
+                vertices: vec![
+                    vertex(vertices[0]),
+                    vertex(vertices[idx + 1]),
+                    vertex(vertices[idx]),
+                ],
+            });
+        });
+    }
+
+    if !missing.is_empty() {
+        return err!("Missing textures: {:?}", missing);
+    }
+
+    let (slots, pages) = pack_atlases(
+        &baked_images
+            .iter()
+            .map(|img| img.dimensions())
+            .collect::<Vec<_>>(),
+    );
+
+    let mut atlases = pages
+        .iter()
+        .map(|&(width, height)| RgbaImage::new(width, height))
+        .collect::<Vec<_>>();
+
+    for ((img, range), (page, x, y)) in baked_images.iter().zip(baked_triangles).zip(slots) {
+        imageops::replace(&mut atlases[page], img, x as i64, y as i64);
+
+        let size = DVec2::new(pages[page].0 as f64, pages[page].1 as f64);
+        let offset = DVec2::new(x as f64, y as f64);
+
+        triangles[range].iter_mut().for_each(|triangle| {
+            triangle.material = format!("baked{}_{}", model_index, page);
+
+            // flip the v coordinate because the texture is upside down in the model
+            triangle.vertices.iter_mut().for_each(|vertex| {
+                let uv = (vertex.uv + offset) / size;
+                vertex.uv = DVec2::new(uv.x, 1. - uv.y);
+            });
+        });
+    }
+
+    for (page, atlas) in atlases.into_iter().enumerate() {
+        textures.insert(
+            format!("baked{}_{}", model_index, page),
+            rgba8_to_8bpp(atlas)?,
+        );
+    }
+
+    Ok(ModelMesh {
+        triangles,
+        textures,
+    })
+}
+
+/// Converts the brush model into `output_path`, which is a .mdl file.
+///
+/// QC, SMD and textures are written next to the output.
+pub fn bsp2mdl(
+    bsp_path: &Path,
+    model: &str,
+    output_path: &Path,
+    options: &Bsp2MdlOptions,
+) -> eyre::Result<()> {
+    let Some(studiomdl) = &options.studiomdl else {
+        return err!("No studiomdl.exe supplied.");
+    };
+
+    #[cfg(target_os = "linux")]
+    let Some(wineprefix) = &options.wineprefix
+    else {
+        return err!("No WINEPREFIX supplied.");
+    };
+
+    let bsp = Bsp::from_file(bsp_path)?;
+    let model_index = find_model(&bsp, model)?;
+
+    let key_wads = find_wads(&bsp, bsp_path);
+    let wads = key_wads
+        .iter()
+        .map(|key_wad| &key_wad.wad)
+        .collect::<Vec<_>>();
+
+    let mesh = model_to_mesh(&bsp, model_index, &wads, options.bake_lightmap)?;
+
+    if mesh.triangles.is_empty() {
+        return err!("Model {} does not have any visible face", model_index);
+    }
+
+    for (name, bmp) in &mesh.textures {
+        write_8bpp_to_file(
+            &bmp.image,
+            &bmp.palette,
+            bmp.dimensions,
+            output_path.with_file_name(format!("{}.bmp", name)),
+        )?;
+    }
+
+    // with an ORIGIN brush, the faces are already around the entity origin
+    let has_origin = bsp.entities.iter().any(|entity| {
+        entity
+            .get("model")
+            .is_some_and(|key| key == &format!("*{}", model_index))
+            && entity.contains_key("origin")
+    });
+
+    let mut map2mdl = Map2Mdl::default();
+    map2mdl.studiomdl(studiomdl);
+
+    #[cfg(target_os = "linux")]
+    map2mdl.wineprefix(wineprefix);
+
+    let handles = map2mdl.convert_from_triangles(
+        &mesh.triangles,
+        &textures_used_in_triangles(&mesh.triangles),
+        ConvertFromTrianglesOptions {
+            output_path,
+            resource_path: output_path,
+            move_to_origin: options.move_to_origin,
+            export_resource: true,
+            use_special_texture: false,
+            maybe_target_origin: has_origin.then_some([0.; 3]),
+            flatshade: options.flatshade,
+        },
+    )?;
+
+    if let Some(handles) = handles {
+        let errs = handles
+            .into_iter()
+            .map(|handle| handle_studiomdl_output(handle.join(), None))
+            .filter_map(|res| res.err())
+            .collect::<Vec<_>>();
+
+        if !errs.is_empty() {
+            return err!(
+                "{}",
+                errs.iter()
+                    .fold(String::new(), |acc, e| acc + e.to_string().as_str() + "\n")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn worldspawn_mesh() {
+        let file = include_bytes!("../../../bsp/src/tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        assert_eq!(find_model(&bsp, "*0").unwrap(), 0);
+        assert!(find_model(&bsp, "nothing").is_err());
+
+        let mesh = model_to_mesh(&bsp, 0, &[], false).unwrap();
+
+        assert!(!mesh.triangles.is_empty());
+        assert!(mesh
+            .triangles
+            .iter()
+            .all(|triangle| !NoRenderTexture.contains(&triangle.material)));
+        assert!(mesh
+            .triangles
+            .iter()
+            .all(|triangle| mesh.textures.contains_key(&triangle.material)));
+
+        // counter clockwise around the normal
+        mesh.triangles.iter().for_each(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|idx| triangle.vertices[idx].pos);
+
+            assert!((b - a).cross(c - a).dot(triangle.vertices[0].norm) > 0.);
+        });
+    }
+
+    #[test]
+    fn baked_mesh() {
+        let file = include_bytes!("../../../bsp/src/tests/normal.bsp");
+        let bsp = Bsp::from_bytes(file).unwrap();
+
+        let plain = model_to_mesh(&bsp, 0, &[], false).unwrap();
+        let baked = model_to_mesh(&bsp, 0, &[], true).unwrap();
+
+        assert_eq!(plain.triangles.len(), baked.triangles.len());
+
+        // every lit face shares one atlas in a small map
+        let atlases = baked
+            .textures
+            .keys()
+            .filter(|name| !plain.textures.contains_key(*name))
+            .collect::<Vec<_>>();
+        assert_eq!(atlases, ["baked0_0"]);
+
+        // baked textures cover the face so the uv stays in one tile
+        baked
+            .triangles
+            .iter()
+            .filter(|triangle| !plain.textures.contains_key(&triangle.material))
+            .for_each(|triangle| {
+                triangle.vertices.iter().for_each(|vertex| {
+                    assert!((-0.01..=1.01).contains(&vertex.uv.x));
+                    assert!((-0.01..=1.01).contains(&vertex.uv.y));
+                });
+            });
+
+        baked.textures.values().for_each(|bmp| {
+            assert!(bmp.dimensions.0 <= MAX_GOLDSRC_TEXTURE_SIZE);
+            assert!(bmp.dimensions.1 <= MAX_GOLDSRC_TEXTURE_SIZE);
+        });
+    }
+
+    #[test]
+    fn atlas_pages() {
+        let (slots, pages) = pack_atlases(&[(300, 200), (300, 400), (200, 100), (512, 512)]);
+
+        // tallest first, the last one fits next to the third
+        assert_eq!(slots, [(2, 0, 0), (1, 0, 0), (2, 300, 0), (0, 0, 0)]);
+        assert_eq!(pages, [(512, 512), (300, 400), (500, 200)]);
+    }
+}
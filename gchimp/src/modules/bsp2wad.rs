@@ -8,17 +8,40 @@ use std::{
 use bsp::Bsp;
 use wad::types::{Entry, Wad};
 
+use crate::err;
+
 pub fn bsp2wad_bytes(bsp_bytes: &[u8]) -> eyre::Result<Vec<u8>> {
+    bsp2wad_bytes_with_palette(bsp_bytes, None)
+}
+
+/// Quake textures do not come with palette so they use `palette`, which is `gfx/palette.lmp` of the game.
+pub fn bsp2wad_bytes_with_palette(
+    bsp_bytes: &[u8],
+    palette: Option<&[[u8; 3]]>,
+) -> eyre::Result<Vec<u8>> {
     let bsp = Bsp::from_bytes(bsp_bytes)?;
     let textures = bsp.textures;
 
     let mut out_wad = Wad::new();
 
-    textures.iter().for_each(|texture| {
+    for texture in &textures {
         if texture.mip_offsets[0] == 0 {
-            return;
+            continue;
         }
 
+        let texture_palette = if texture.palette.get_bytes().is_empty() {
+            let Some(palette) = palette else {
+                return err!(
+                    "Texture {} does not have palette, palette.lmp is needed",
+                    texture.texture_name.get_string()
+                );
+            };
+
+            palette
+        } else {
+            texture.palette.get_bytes().as_slice()
+        };
+
         let mip_maps = texture
             .mip_images
             .iter()
@@ -29,16 +52,44 @@ pub fn bsp2wad_bytes(bsp_bytes: &[u8]) -> eyre::Result<Vec<u8>> {
             texture.texture_name.get_string_standard(),
             (texture.width, texture.height),
             &mip_maps,
-            texture.palette.get_bytes().as_slice(),
+            texture_palette,
         );
 
         out_wad.header.num_dirs += 1;
         out_wad.entries.push(new_entry);
-    });
+    }
 
     Ok(out_wad.write_to_bytes())
 }
 
+// next to the map or where Quake has it
+fn find_palette_lmp(bsp_path: &Path) -> Option<Vec<[u8; 3]>> {
+    let candidates = [
+        bsp_path.with_file_name("palette.lmp"),
+        bsp_path.parent()?.parent()?.join("gfx/palette.lmp"),
+    ];
+
+    let bytes = candidates
+        .iter()
+        .find_map(|path| std::fs::read(path).ok())?;
+
+    palette_from_lmp(&bytes)
+}
+
+/// Colors of `palette.lmp`, `None` if it is not 256 colors.
+pub fn palette_from_lmp(bytes: &[u8]) -> Option<Vec<[u8; 3]>> {
+    if bytes.len() != 256 * 3 {
+        return None;
+    }
+
+    Some(
+        bytes
+            .chunks_exact(3)
+            .map(|color| [color[0], color[1], color[2]])
+            .collect(),
+    )
+}
+
 pub fn bsp2wad(path: impl AsRef<OsStr> + AsRef<Path>) -> eyre::Result<()> {
     let bsp_path: &Path = path.as_ref();
 
@@ -47,7 +98,8 @@ pub fn bsp2wad(path: impl AsRef<OsStr> + AsRef<Path>) -> eyre::Result<()> {
 
     bsp_file.read_to_end(&mut bsp_bytes)?;
 
-    let res_bytes = bsp2wad_bytes(&bsp_bytes)?;
+    let palette = find_palette_lmp(bsp_path);
+    let res_bytes = bsp2wad_bytes_with_palette(&bsp_bytes, palette.as_deref())?;
 
     let mut out_file = OpenOptions::new()
         .create(true)
@@ -63,7 +115,9 @@ pub fn bsp2wad(path: impl AsRef<OsStr> + AsRef<Path>) -> eyre::Result<()> {
 
 #[cfg(test)]
 mod test {
-    use super::bsp2wad;
+    use bsp::BspVariant;
+
+    use super::*;
 
     #[test]
     fn run() {
@@ -74,4 +128,19 @@ mod test {
     fn run_no_embedded() {
         bsp2wad("/home/khang/bxt/game_isolated/valve/maps/c4a2b.bsp").unwrap();
     }
+
+    #[test]
+    fn quake_palette() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        bsp.variant = BspVariant::Quake;
+        let bsp_bytes = bsp.write_to_bytes();
+
+        assert!(bsp2wad_bytes(&bsp_bytes).is_err());
+
+        let palette = vec![[1, 2, 3]; 256];
+        let wad = Wad::from_bytes(&bsp2wad_bytes_with_palette(&bsp_bytes, Some(&palette)).unwrap())
+            .unwrap();
+
+        assert_eq!(wad.entries.len(), 2);
+    }
 }
@@ -0,0 +1,51 @@
+//! Same as `bspinfo` from the compile tools with checks for broken references.
+use std::path::Path;
+
+use bsp::Bsp;
+
+/// Lump usage table followed by every problem found.
+pub fn bspinfo_report(bsp: &Bsp) -> String {
+    let mut res = bsp.stats().to_string();
+
+    let issues = bsp.validate();
+
+    res += "\n\n";
+
+    if issues.is_empty() {
+        res += "No problem found\n";
+    } else {
+        res += format!("{} problem(s) found:\n", issues.len()).as_str();
+
+        issues.iter().for_each(|issue| {
+            res += format!("- {}\n", issue).as_str();
+        });
+    }
+
+    res
+}
+
+pub fn bspinfo_bytes(bsp_bytes: &[u8]) -> eyre::Result<String> {
+    let bsp = Bsp::from_bytes(bsp_bytes)?;
+
+    Ok(bspinfo_report(&bsp))
+}
+
+/// Returns the report and whether the BSP has no problem.
+pub fn bspinfo(bsp_path: impl AsRef<Path>) -> eyre::Result<(String, bool)> {
+    let bsp = Bsp::from_file(bsp_path.as_ref())?;
+
+    Ok((bspinfo_report(&bsp), bsp.validate().is_empty()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let report = bspinfo_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+
+        assert!(report.starts_with("Object names"));
+        assert!(report.ends_with("No problem found\n"));
+    }
+}
@@ -0,0 +1,212 @@
+use std::path::Path;
+
+use fgd::{EntityIssue, Fgd};
+use map::Map;
+
+use super::instance::{INSTANCE_ENTITY_NAME, INSTANCE_REPLACE_PREFIX};
+
+/// hlrad gives targeted lights their own `style` starting from here.
+const FIRST_COMPILED_STYLE: i64 = 32;
+
+/// Reads the FGDs in order with their includes, later ones win like in the editors.
+pub fn load_fgds(paths: &[impl AsRef<Path>]) -> eyre::Result<Fgd> {
+    let mut res = Fgd::new();
+
+    for path in paths {
+        let path = path.as_ref();
+        let mut fgd = Fgd::from_file(path)?;
+
+        fgd.load_includes(path.parent().unwrap_or(Path::new("")))?;
+
+        res.extend(fgd);
+    }
+
+    Ok(res)
+}
+
+// `is_bsp` skips the values written by the compilers
+fn check_pairs<'a>(
+    entities: impl Iterator<Item = &'a [(String, String)]>,
+    fgd: &Fgd,
+    is_bsp: bool,
+) -> Vec<(usize, EntityIssue)> {
+    entities
+        .enumerate()
+        .flat_map(|(index, pairs)| {
+            let is_instance = pairs
+                .iter()
+                .any(|(key, value)| key == "classname" && value == INSTANCE_ENTITY_NAME);
+
+            fgd.check_entity(pairs)
+                .into_iter()
+                .filter(move |issue| match issue {
+                    // replacements are made up by the mapper
+                    EntityIssue::UnknownKey(key) => {
+                        !(is_instance && key.starts_with(INSTANCE_REPLACE_PREFIX))
+                    }
+                    EntityIssue::InvalidChoice { key, value } if is_bsp && key == "style" => value
+                        .trim()
+                        .parse::<i64>()
+                        .map_or(true, |style| style < FIRST_COMPILED_STYLE),
+                    _ => true,
+                })
+                .map(move |issue| (index, issue))
+        })
+        .collect()
+}
+
+/// Issues of every entity with the entity index.
+pub fn check_map_entities(map: &Map, fgd: &Fgd) -> Vec<(usize, EntityIssue)> {
+    check_pairs(
+        map.entities.iter().map(|entity| entity.attributes.pairs()),
+        fgd,
+        false,
+    )
+}
+
+/// Issues of every entity in the entity lump with the entity index.
+///
+/// Light styles given by the compiler to targeted lights are not reported.
+pub fn check_bsp_entities(bsp: &bsp::Bsp, fgd: &Fgd) -> Vec<(usize, EntityIssue)> {
+    check_pairs(bsp.entities.iter().map(|entity| entity.pairs()), fgd, true)
+}
+
+/// Checks a .map, .rmf, .jmf or .bsp against the FGDs.
+pub fn check_entity_file(
+    path: impl AsRef<Path>,
+    fgd_paths: &[impl AsRef<Path>],
+) -> eyre::Result<Vec<(usize, EntityIssue)>> {
+    let path = path.as_ref();
+    let fgd = load_fgds(fgd_paths)?;
+
+    let is_bsp = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("bsp"));
+
+    if is_bsp {
+        let bsp = bsp::Bsp::from_file(path)?;
+
+        Ok(check_bsp_entities(&bsp, &fgd))
+    } else {
+        let map = Map::from_file(path)?;
+
+        Ok(check_map_entities(&map, &fgd))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::entity::gchimp_fgd;
+
+    #[test]
+    fn gchimp_entities() {
+        let map = Map::from_text(
+            "\
+{
+\"classname\" \"gchimp_instance\"
+\"file\" \"door.map\"
+\"$name\" \"door1\"
+\"origin\" \"0 0 0\"
+}
+{
+\"classname\" \"gchimp_map2mdl\"
+\"cliptype\" \"3\"
+\"options\" \"flat\"
+\"wrong\" \"1\"
+}
+{
+\"classname\" \"gchimp_nothing\"
+}
+",
+        )
+        .unwrap();
+
+        let issues = check_map_entities(&map, &gchimp_fgd());
+
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    1,
+                    EntityIssue::InvalidChoice {
+                        key: "cliptype".to_string(),
+                        value: "3".to_string()
+                    }
+                ),
+                (
+                    1,
+                    EntityIssue::WrongType {
+                        key: "options".to_string(),
+                        value: "flat".to_string(),
+                        value_type: fgd::ValueType::Flags
+                    }
+                ),
+                (1, EntityIssue::UnknownKey("wrong".to_string())),
+                (2, EntityIssue::UnknownClass("gchimp_nothing".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn compiled_light_style() {
+        let fgd = Fgd::from_text(
+            "\
+@PointClass = light : \"Light\"
+[
+    targetname(target_source) : \"Name\"
+    style(choices) : \"Appearance\" : 0 =
+    [
+        0 : \"Normal\"
+        10 : \"Fluorescent flicker\"
+    ]
+]
+",
+        )
+        .unwrap();
+
+        let mut bsp =
+            bsp::Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+
+        // hlrad gives the targeted light style 32
+        bsp.set_ent_string(
+            "\
+{
+\"classname\" \"light\"
+\"targetname\" \"lamp\"
+\"style\" \"32\"
+}
+{
+\"classname\" \"light\"
+\"style\" \"5\"
+}
+",
+        )
+        .unwrap();
+
+        let style_5 = EntityIssue::InvalidChoice {
+            key: "style".to_string(),
+            value: "5".to_string(),
+        };
+
+        assert_eq!(check_bsp_entities(&bsp, &fgd), vec![(1, style_5.clone())]);
+
+        // still reported in the map
+        let map = Map::from_text(&bsp.ent_string()).unwrap();
+
+        assert_eq!(
+            check_map_entities(&map, &fgd),
+            vec![
+                (
+                    0,
+                    EntityIssue::InvalidChoice {
+                        key: "style".to_string(),
+                        value: "32".to_string(),
+                    }
+                ),
+                (1, style_5),
+            ]
+        );
+    }
+}
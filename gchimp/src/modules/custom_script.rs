@@ -1,8 +1,13 @@
 use std::{fs::OpenOptions, io::Read, path::Path};
 
+use glam::DVec3;
+use map::Transform;
 use rhai::Engine;
 
-use super::{duplicate_triangle, light_scale, rotate_prop_static, texture_scale};
+use super::{
+    duplicate_triangle, light_scale, lightmap_grade::LightmapGradeOptions, rotate_prop_static,
+    texture_scale,
+};
 
 fn rotate_prop_static_single(map: &mut map::Map) {
     rotate_prop_static::rotate_prop_static(map, None);
@@ -36,9 +41,174 @@ fn texture_scale_int(map: &mut map::Map, scalar: i64) {
     texture_scale(map, scalar as f64);
 }
 
-// TODO propagate results
-pub fn custom_script(rhai_file: &Path) {
-    // Rhai engine part
+fn translate(map: &mut map::Map, x: f64, y: f64, z: f64) {
+    map.translate(DVec3::new(x, y, z));
+}
+
+fn translate_int(map: &mut map::Map, x: i64, y: i64, z: i64) {
+    translate(map, x as f64, y as f64, z as f64);
+}
+
+fn rotate(map: &mut map::Map, pitch: f64, yaw: f64, roll: f64) {
+    map.rotate(DVec3::new(pitch, yaw, roll), DVec3::ZERO);
+}
+
+fn rotate_int(map: &mut map::Map, pitch: i64, yaw: i64, roll: i64) {
+    rotate(map, pitch as f64, yaw as f64, roll as f64);
+}
+
+fn rotate_around(map: &mut map::Map, pitch: f64, yaw: f64, roll: f64, x: f64, y: f64, z: f64) {
+    map.rotate(DVec3::new(pitch, yaw, roll), DVec3::new(x, y, z));
+}
+
+fn scale(map: &mut map::Map, scalar: f64) {
+    map.scale(DVec3::splat(scalar), DVec3::ZERO);
+}
+
+fn scale_int(map: &mut map::Map, scalar: i64) {
+    scale(map, scalar as f64);
+}
+
+fn scale_axes(map: &mut map::Map, x: f64, y: f64, z: f64) {
+    map.scale(DVec3::new(x, y, z), DVec3::ZERO);
+}
+
+fn mirror(map: &mut map::Map, x: f64, y: f64, z: f64) {
+    map.mirror(DVec3::new(x, y, z), DVec3::ZERO);
+}
+
+fn mirror_int(map: &mut map::Map, x: i64, y: i64, z: i64) {
+    mirror(map, x as f64, y as f64, z as f64);
+}
+
+fn is_textured(brush: &map::Brush, texture: &str) -> bool {
+    brush
+        .planes
+        .iter()
+        .all(|plane| plane.texture_name.eq_ignore_ascii_case(texture))
+}
+
+/// Brushes with only this texture cut out the other brushes and then go away.
+fn carve(map: &mut map::Map, texture: &str) {
+    let mut cutters = vec![];
+
+    for brushes in map.entities.iter_mut().filter_map(|e| e.brushes.as_mut()) {
+        let (cut, rest) = std::mem::take(brushes)
+            .into_iter()
+            .partition(|brush| is_textured(brush, texture));
+
+        cutters.extend::<Vec<_>>(cut);
+        *brushes = rest;
+    }
+
+    for brushes in map.entities.iter_mut().filter_map(|e| e.brushes.as_mut()) {
+        for cutter in &cutters {
+            *brushes = brushes
+                .iter()
+                .flat_map(|brush| brush.subtract(cutter))
+                .collect();
+        }
+    }
+}
+
+/// Brushes with only this texture are turned into walls.
+fn hollow(map: &mut map::Map, texture: &str, thickness: f64) {
+    for brushes in map.entities.iter_mut().filter_map(|e| e.brushes.as_mut()) {
+        *brushes = brushes
+            .iter()
+            .flat_map(|brush| {
+                if !is_textured(brush, texture) {
+                    return vec![brush.clone()];
+                }
+
+                brush
+                    .hollow(thickness)
+                    .unwrap_or_else(|| vec![brush.clone()])
+            })
+            .collect();
+    }
+}
+
+fn hollow_int(map: &mut map::Map, texture: &str, thickness: i64) {
+    hollow(map, texture, thickness as f64);
+}
+
+fn lightmap_grade(bsp: &mut bsp::Bsp, brightness: f64, gamma: f64) {
+    lightmap_grade_tint(bsp, brightness, gamma, 1., 1., 1.);
+}
+
+fn lightmap_grade_int(bsp: &mut bsp::Bsp, brightness: i64, gamma: i64) {
+    lightmap_grade(bsp, brightness as f64, gamma as f64);
+}
+
+fn lightmap_grade_brightness(bsp: &mut bsp::Bsp, brightness: f64) {
+    lightmap_grade(bsp, brightness, 1.);
+}
+
+fn lightmap_grade_brightness_int(bsp: &mut bsp::Bsp, brightness: i64) {
+    lightmap_grade(bsp, brightness as f64, 1.);
+}
+
+fn lightmap_grade_tint(bsp: &mut bsp::Bsp, brightness: f64, gamma: f64, r: f64, g: f64, b: f64) {
+    super::lightmap_grade::lightmap_grade(
+        bsp,
+        &LightmapGradeOptions {
+            brightness,
+            gamma,
+            tint: [r, g, b],
+            ..Default::default()
+        },
+    );
+}
+
+fn lightmap_grade_tint_int(
+    bsp: &mut bsp::Bsp,
+    brightness: i64,
+    gamma: i64,
+    r: i64,
+    g: i64,
+    b: i64,
+) {
+    lightmap_grade_tint(
+        bsp,
+        brightness as f64,
+        gamma as f64,
+        r as f64,
+        g as f64,
+        b as f64,
+    );
+}
+
+fn lightmap_grade_texture(bsp: &mut bsp::Bsp, texture: &str, brightness: f64) {
+    super::lightmap_grade::lightmap_grade(
+        bsp,
+        &LightmapGradeOptions {
+            brightness,
+            textures: vec![texture.to_string()],
+            ..Default::default()
+        },
+    );
+}
+
+fn lightmap_grade_texture_int(bsp: &mut bsp::Bsp, texture: &str, brightness: i64) {
+    lightmap_grade_texture(bsp, texture, brightness as f64);
+}
+
+fn lightmap_style_scale(bsp: &mut bsp::Bsp, style: i64, scale: f64) {
+    super::lightmap_grade::lightmap_grade(
+        bsp,
+        &LightmapGradeOptions {
+            style_scales: vec![(style.clamp(0, 255) as u8, scale)],
+            ..Default::default()
+        },
+    );
+}
+
+fn lightmap_style_scale_int(bsp: &mut bsp::Bsp, style: i64, scale: i64) {
+    lightmap_style_scale(bsp, style, scale as f64);
+}
+
+fn script_engine() -> Engine {
     let mut engine = Engine::new();
 
     engine
@@ -71,7 +241,41 @@ pub fn custom_script(rhai_file: &Path) {
         })
         .register_fn("light_scale", light_scale::light_scale)
         .register_fn("sexture_scale", texture_scale::texture_scale)
-        .register_fn("rotate_prop_static", rotate_prop_static::rotate_prop_static);
+        .register_fn("rotate_prop_static", rotate_prop_static::rotate_prop_static)
+        // transforms, with texture lock
+        .register_fn("translate", translate)
+        .register_fn("translate", translate_int)
+        .register_fn("rotate", rotate)
+        .register_fn("rotate", rotate_int)
+        .register_fn("rotate", rotate_around)
+        .register_fn("scale", scale)
+        .register_fn("scale", scale_int)
+        .register_fn("scale", scale_axes)
+        .register_fn("mirror", mirror)
+        .register_fn("mirror", mirror_int)
+        // brush csg, by texture
+        .register_fn("carve", carve)
+        .register_fn("hollow", hollow)
+        .register_fn("hollow", hollow_int);
+
+    engine
+        .register_type_with_name::<bsp::Bsp>("Bsp")
+        .register_fn("new_bsp", |file_name: String| {
+            bsp::Bsp::from_file(file_name).unwrap()
+        })
+        .register_fn("write", |bsp: &mut bsp::Bsp, out: String| {
+            let _ = bsp.write_to_file(out);
+        })
+        .register_fn("lightmap_grade", lightmap_grade)
+        .register_fn("lightmap_grade", lightmap_grade_int)
+        .register_fn("lightmap_grade", lightmap_grade_brightness)
+        .register_fn("lightmap_grade", lightmap_grade_brightness_int)
+        .register_fn("lightmap_grade", lightmap_grade_tint)
+        .register_fn("lightmap_grade", lightmap_grade_tint_int)
+        .register_fn("lightmap_grade_texture", lightmap_grade_texture)
+        .register_fn("lightmap_grade_texture", lightmap_grade_texture_int)
+        .register_fn("lightmap_style_scale", lightmap_style_scale)
+        .register_fn("lightmap_style_scale", lightmap_style_scale_int);
 
     engine
         .register_type_with_name::<qc::Qc>("Qc")
@@ -92,6 +296,14 @@ pub fn custom_script(rhai_file: &Path) {
         })
         .register_fn("duplicate_triangle", duplicate_triangle::duplicate_triangle);
 
+    engine
+}
+
+// TODO propagate results
+pub fn custom_script(rhai_file: &Path) {
+    // Rhai engine part
+    let engine = script_engine();
+
     let file = OpenOptions::new().read(true).open(rhai_file);
 
     if let Err(err) = file {
@@ -110,3 +322,37 @@ pub fn custom_script(rhai_file: &Path) {
         println!("Problem with running the script. {}", err);
     };
 }
+
+#[cfg(test)]
+mod test {
+    use rhai::Scope;
+
+    use super::*;
+
+    #[test]
+    fn lightmap_grade_int_arguments() {
+        let bsp = bsp::Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let original = bsp.lightmap.clone();
+
+        let mut scope = Scope::new();
+        scope.push("bsp", bsp);
+
+        script_engine()
+            .run_with_scope(
+                &mut scope,
+                "\
+bsp.lightmap_style_scale(0, 1);
+bsp.lightmap_grade_texture(\"texture that does not exist\", 0);
+bsp.lightmap_grade(1, 1, 1, 1, 1);
+bsp.lightmap_grade(1, 1);
+bsp.lightmap_grade(0);
+",
+            )
+            .unwrap();
+
+        let bsp = scope.get_value::<bsp::Bsp>("bsp").unwrap();
+
+        assert_ne!(bsp.lightmap, original);
+        assert!(bsp.lightmap.iter().all(|luxel| *luxel == [0; 3]));
+    }
+}
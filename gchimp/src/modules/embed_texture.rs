@@ -0,0 +1,164 @@
+//! Embeds WAD textures into a compiled BSP or takes them out into a WAD without recompiling.
+use std::path::{Path, PathBuf};
+
+use bsp::{Bsp, BspVariant};
+use wad::types::{DirectoryEntry, Entry, FileEntry, Wad};
+
+use crate::err;
+
+/// WAD file with the path written in the `wad` key.
+pub struct KeyWad {
+    pub key_path: String,
+    pub wad: Wad,
+}
+
+/// Reads the WADs in the `wad` key.
+///
+/// Paths in the key are from the mapper computer so the WADs are looked up by file name
+/// next to the map and in the game folder.
+pub fn find_wads(bsp: &Bsp, bsp_path: &Path) -> Vec<KeyWad> {
+    bsp.wad_paths()
+        .into_iter()
+        .filter_map(|key_path| {
+            let file_name = key_path.rsplit(['\\', '/']).next()?.to_string();
+
+            let candidates = [
+                Some(PathBuf::from(&key_path)),
+                Some(bsp_path.with_file_name(&file_name)),
+                bsp_path
+                    .parent()
+                    .and_then(|maps| maps.parent())
+                    .map(|game| game.join(&file_name)),
+            ];
+
+            let wad = candidates
+                .into_iter()
+                .flatten()
+                .find_map(|path| Wad::from_file(path).ok())?;
+
+            Some(KeyWad { key_path, wad })
+        })
+        .collect()
+}
+
+/// Embeds external textures from `wads` and drops the WADs no longer needed from the `wad` key.
+///
+/// Returns names of the textures which are still external.
+pub fn embed_texture(bsp: &mut Bsp, wads: &[KeyWad]) -> Vec<String> {
+    let key_paths = bsp.wad_paths();
+
+    let wad_list = wads.iter().map(|key_wad| &key_wad.wad).collect::<Vec<_>>();
+
+    let missing = bsp.embed_textures(&wad_list);
+
+    if missing.is_empty() {
+        return missing;
+    }
+
+    // keeps the WADs we could not read because they might have the missing textures
+    let key_paths = key_paths
+        .into_iter()
+        .filter(|key_path| {
+            let Some(key_wad) = wads.iter().find(|key_wad| &key_wad.key_path == key_path) else {
+                return true;
+            };
+
+            key_wad.wad.entries.iter().any(|entry| {
+                missing
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&entry.texture_name()))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    bsp.set_wad_paths(&key_paths);
+
+    missing
+}
+
+/// Makes the textures external and returns them in a WAD, which is added to the `wad` key by its file name.
+///
+/// Every embedded texture is taken out if `texture_names` is empty.
+pub fn unembed_texture(
+    bsp: &mut Bsp,
+    texture_names: &[impl AsRef<str>],
+    wad_path: &Path,
+) -> eyre::Result<Wad> {
+    if bsp.variant == BspVariant::Quake {
+        return err!("Quake maps cannot use external textures");
+    }
+
+    let Some(wad_file_name) = wad_path.file_name().and_then(|name| name.to_str()) else {
+        return err!("Invalid WAD path {}", wad_path.display());
+    };
+
+    let mut wad = Wad::new();
+
+    bsp.unembed_textures(texture_names, wad_file_name)
+        .into_iter()
+        .for_each(|miptex| {
+            wad.header.num_dirs += 1;
+            wad.entries.push(Entry {
+                directory_entry: DirectoryEntry::new(miptex.texture_name.get_string_standard()),
+                file_entry: FileEntry::MipTex(miptex),
+            });
+        });
+
+    Ok(wad)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unembed_then_embed() {
+        let file = include_bytes!("../../../bsp/src/tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let wad = unembed_texture(&mut bsp, &[] as &[&str], Path::new("/maps/normal.wad")).unwrap();
+
+        assert_eq!(wad.entries.len(), 2);
+        assert!(bsp.textures.iter().all(|texture| texture.is_external()));
+        assert_eq!(bsp.wad_paths(), vec!["normal.wad"]);
+
+        // going through bytes like it is read from the disk
+        let wad = Wad::from_bytes(&wad.write_to_bytes()).unwrap();
+        let missing = embed_texture(
+            &mut bsp,
+            &[KeyWad {
+                key_path: "normal.wad".to_string(),
+                wad,
+            }],
+        );
+
+        assert!(missing.is_empty());
+        assert!(bsp.wad_paths().is_empty());
+        assert!(bsp.textures.iter().all(|texture| !texture.is_external()));
+    }
+
+    #[test]
+    fn keep_needed_wads() {
+        let file = include_bytes!("../../../bsp/src/tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let sky_wad = unembed_texture(&mut bsp, &["sky"], Path::new("sky.wad")).unwrap();
+        let gray_wad =
+            unembed_texture(&mut bsp, &["dev_gray_10_128"], Path::new("gray.wad")).unwrap();
+
+        assert_eq!(bsp.wad_paths(), vec!["sky.wad", "gray.wad"]);
+        assert_eq!(sky_wad.entries.len(), 1);
+        assert_eq!(gray_wad.entries.len(), 1);
+
+        let missing = embed_texture(
+            &mut bsp,
+            &[KeyWad {
+                key_path: "sky.wad".to_string(),
+                wad: sky_wad,
+            }],
+        );
+
+        assert_eq!(missing, vec!["dev_gray_10_128"]);
+        assert_eq!(bsp.wad_paths(), vec!["gray.wad"]);
+    }
+}
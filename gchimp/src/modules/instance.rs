@@ -0,0 +1,366 @@
+use std::path::{Path, PathBuf};
+
+use glam::DVec3;
+use map::{Entity, Map, Transform};
+
+use crate::err;
+
+// dist/gchimp.fgd is written from these in `crate::entity::gchimp_fgd`
+
+pub static INSTANCE_ENTITY_NAME: &str = "gchimp_instance";
+
+pub static INSTANCE_ATTR_FILE: &str = "file";
+
+pub static INSTANCE_ATTR_ORIGIN: &str = "origin";
+
+pub static INSTANCE_ATTR_ANGLES: &str = "angles";
+
+pub static INSTANCE_ATTR_SCALE: &str = "scale";
+
+/// Keys starting with this are replaced in the values of the instanced map.
+pub static INSTANCE_REPLACE_PREFIX: char = '$';
+
+// instances including themselves would never end
+const MAX_DEPTH: usize = 32;
+
+fn is_instance(entity: &Entity) -> bool {
+    entity
+        .attributes
+        .get("classname")
+        .is_some_and(|classname| classname == INSTANCE_ENTITY_NAME)
+}
+
+fn parse_vector(entity: &Entity, key: &str) -> eyre::Result<DVec3> {
+    let Some(value) = entity.attributes.get(key) else {
+        return Ok(DVec3::ZERO);
+    };
+
+    let values = value
+        .split_whitespace()
+        .map(|n| n.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>();
+
+    match values {
+        Ok(values) if values.len() == 3 => Ok(DVec3::from_slice(&values)),
+        _ => err!(
+            "{}: Value for \"{}\" is not 3 numbers: {}",
+            INSTANCE_ENTITY_NAME,
+            key,
+            value
+        ),
+    }
+}
+
+/// One number scales evenly, three numbers scale each axis.
+fn parse_scale(entity: &Entity) -> eyre::Result<DVec3> {
+    let Some(value) = entity.attributes.get(INSTANCE_ATTR_SCALE) else {
+        return Ok(DVec3::ONE);
+    };
+
+    if let Ok(scale) = value.trim().parse::<f64>() {
+        return Ok(DVec3::splat(scale));
+    }
+
+    parse_vector(entity, INSTANCE_ATTR_SCALE)
+}
+
+/// Replaces `$name` in every value with the value of the `$name` key of the instance.
+///
+/// Longer names go first so `$door` does not eat into `$door2`.
+fn replace_values(map: &mut Map, instance: &Entity) {
+    let mut replacements = instance
+        .attributes
+        .iter()
+        .filter(|(key, _)| key.starts_with(INSTANCE_REPLACE_PREFIX))
+        .collect::<Vec<_>>();
+
+    if replacements.is_empty() {
+        return;
+    }
+
+    replacements.sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
+
+    map.entities
+        .iter_mut()
+        .flat_map(|entity| entity.attributes.iter_mut())
+        .for_each(|(_, value)| {
+            if !value.contains(INSTANCE_REPLACE_PREFIX) {
+                return;
+            }
+
+            for (key, replacement) in &replacements {
+                *value = value.replace(key.as_str(), replacement);
+            }
+        });
+}
+
+/// Adds WADs of the instanced map that the map does not have yet.
+fn merge_wads(world: &mut Entity, other: &Entity) {
+    let Some(other_wads) = other.attributes.get("wad") else {
+        return;
+    };
+
+    let mut wads = world
+        .attributes
+        .get("wad")
+        .map(|wads| {
+            wads.split(';')
+                .filter(|wad| !wad.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    for wad in other_wads.split(';').filter(|wad| !wad.is_empty()) {
+        if !wads.iter().any(|existing| existing == wad) {
+            wads.push(wad.to_string());
+        }
+    }
+
+    world.attributes.insert("wad", wads.join(";"));
+}
+
+/// Reads the map of the instance with its own instances expanded, in the space of the instance.
+fn load_instance(instance: &Entity, map_dir: &Path, stack: &mut Vec<PathBuf>) -> eyre::Result<Map> {
+    let Some(file) = instance.attributes.get(INSTANCE_ATTR_FILE) else {
+        return err!(
+            "{}: No \"{}\" provided",
+            INSTANCE_ENTITY_NAME,
+            INSTANCE_ATTR_FILE
+        );
+    };
+
+    let path = map_dir.join(file);
+
+    if !path.exists() {
+        return err!(
+            "{}: Cannot find file {}",
+            INSTANCE_ENTITY_NAME,
+            path.display()
+        );
+    }
+
+    let canonical = path.canonicalize()?;
+
+    if stack.contains(&canonical) || stack.len() >= MAX_DEPTH {
+        return err!(
+            "{}: {} ends up including itself",
+            INSTANCE_ENTITY_NAME,
+            path.display()
+        );
+    }
+
+    let mut map = Map::from_file(&path)?;
+
+    // before going deeper so nested instances can pass the values on
+    replace_values(&mut map, instance);
+
+    stack.push(canonical);
+
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    expand_instances_inner(&mut map, &dir, stack)?;
+
+    stack.pop();
+
+    let scale = parse_scale(instance)?;
+    let angles = parse_vector(instance, INSTANCE_ATTR_ANGLES)?;
+    let origin = parse_vector(instance, INSTANCE_ATTR_ORIGIN)?;
+
+    map.scale(scale, DVec3::ZERO)
+        .rotate(angles, DVec3::ZERO)
+        .translate(origin);
+
+    Ok(map)
+}
+
+fn expand_instances_inner(
+    map: &mut Map,
+    map_dir: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> eyre::Result<usize> {
+    let (instances, entities): (Vec<_>, Vec<_>) = std::mem::take(&mut map.entities)
+        .into_iter()
+        .partition(is_instance);
+
+    map.entities = entities;
+
+    let mut count = 0;
+
+    for instance in instances {
+        let instance_map = load_instance(&instance, map_dir, stack)?;
+
+        count += 1;
+
+        for entity in instance_map.entities {
+            let is_world = entity
+                .attributes
+                .get("classname")
+                .is_some_and(|classname| classname == "worldspawn");
+
+            if !is_world {
+                map.entities.push(entity);
+                continue;
+            }
+
+            let Some(world) = map.entities.first_mut() else {
+                map.entities.push(entity);
+                continue;
+            };
+
+            merge_wads(world, &entity);
+
+            if let Some(brushes) = entity.brushes {
+                world.brushes.get_or_insert_with(Vec::new).extend(brushes);
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Replaces every `gchimp_instance` with the content of its map, going into instances inside instances.
+///
+/// Files are relative to the directory of the map containing the instance. Instanced maps are scaled,
+/// then rotated by `angles`, then moved to `origin`. Worldspawn brushes go into the worldspawn of the map.
+///
+/// Returns the number of instances expanded.
+pub fn expand_instances(map: &mut Map, map_dir: impl AsRef<Path>) -> eyre::Result<usize> {
+    expand_instances_inner(map, map_dir.as_ref(), &mut vec![])
+}
+
+pub fn expand_instances_file(
+    map_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+) -> eyre::Result<usize> {
+    let map_path = map_path.as_ref();
+    let mut map = Map::from_file(map_path)?;
+
+    let mut stack = vec![map_path.canonicalize()?];
+    let map_dir = map_path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let count = expand_instances_inner(&mut map, &map_dir, &mut stack)?;
+
+    map.write(out_path.as_ref())?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::utils::map_stuffs::brush_from_mins_maxs;
+
+    const ROOM: &str = "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"room.wad\"
+}
+{
+\"classname\" \"light\"
+\"targetname\" \"$name_light\"
+\"_light\" \"$color 200\"
+\"origin\" \"16 0 0\"
+}
+";
+
+    fn room() -> String {
+        let mut map = Map::from_text(ROOM).unwrap();
+        map.entities[0].brushes = Some(vec![brush_from_mins_maxs(&[0.; 3], &[32.; 3], "NULL")]);
+        map.write_to_string()
+    }
+
+    fn write_files(dir: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", dir, std::process::id()));
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for (name, text) in files {
+            std::fs::write(dir.join(name), text).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn nested() {
+        let room = room();
+        let dir = write_files(
+            "gchimp_instance_nested",
+            &[
+                ("room.map", &room),
+                (
+                    "hall.map",
+                    "\
+{
+\"classname\" \"worldspawn\"
+}
+{
+\"classname\" \"gchimp_instance\"
+\"file\" \"room.map\"
+\"$name\" \"$hall\"
+\"$color\" \"255 0 0\"
+\"origin\" \"0 64 0\"
+}
+",
+                ),
+            ],
+        );
+
+        let mut map = Map::from_text(
+            "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"main.wad\"
+}
+{
+\"classname\" \"gchimp_instance\"
+\"file\" \"hall.map\"
+\"$hall\" \"west\"
+\"angles\" \"0 90 0\"
+\"scale\" \"2\"
+\"origin\" \"1000 0 0\"
+}
+",
+        )
+        .unwrap();
+
+        assert_eq!(expand_instances(&mut map, &dir).unwrap(), 1);
+        assert_eq!(map.entities.len(), 2);
+
+        let world = &map.entities[0];
+        assert_eq!(world.attributes.get("wad").unwrap(), "main.wad;room.wad");
+        assert_eq!(
+            world.brushes.as_ref().unwrap()[0].bounds(),
+            Some((DVec3::new(808., 0., 0.), DVec3::new(872., 64., 64.)))
+        );
+
+        let light = &map.entities[1].attributes;
+        assert_eq!(light.get("targetname").unwrap(), "west_light");
+        assert_eq!(light.get("_light").unwrap(), "255 0 0 200");
+        assert_eq!(light.get("origin").unwrap(), "872 32 0");
+    }
+
+    #[test]
+    fn recursive() {
+        let dir = write_files(
+            "gchimp_instance_recursive",
+            &[(
+                "self.map",
+                "\
+{
+\"classname\" \"worldspawn\"
+}
+{
+\"classname\" \"gchimp_instance\"
+\"file\" \"self.map\"
+}
+",
+            )],
+        );
+
+        let mut map = Map::from_file(dir.join("self.map")).unwrap();
+
+        assert!(expand_instances(&mut map, &dir).is_err());
+    }
+}
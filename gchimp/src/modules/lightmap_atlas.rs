@@ -0,0 +1,273 @@
+//! Puts every face lightmap into one image so lighting can be touched up after compiling.
+//!
+//! The layout JSON remembers where each lightmap is in the atlas. Editing the image is fine but the layout must stay the same
+//! and the BSP must be the same one the atlas is exported from.
+use std::path::{Path, PathBuf};
+
+use bsp::Bsp;
+use image::{ImageFormat, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::err;
+
+/// Gap between lightmaps so painting does not bleed into the next one.
+const ATLAS_PADDING: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightmapAtlasEntry {
+    pub face: usize,
+    /// Index into the face styles.
+    pub style_slot: usize,
+    /// Light style of the slot, only for checking the BSP.
+    pub style: u8,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightmapAtlasLayout {
+    pub width: u32,
+    pub height: u32,
+    pub entries: Vec<LightmapAtlasEntry>,
+}
+
+/// Packs every lightmap of every face into rows.
+pub fn lightmap_atlas_layout(bsp: &Bsp) -> LightmapAtlasLayout {
+    let mut entries = (0..bsp.faces.len())
+        .filter_map(|face_idx| Some((face_idx, bsp.face_lightmap_info(face_idx)?)))
+        .flat_map(|(face_idx, info)| {
+            (0..info.style_count).map(move |style_slot| LightmapAtlasEntry {
+                face: face_idx,
+                style_slot,
+                style: 0,
+                x: 0,
+                y: 0,
+                width: info.width as u32,
+                height: info.height as u32,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    entries
+        .iter_mut()
+        .for_each(|entry| entry.style = bsp.faces[entry.face].styles[entry.style_slot]);
+
+    // tallest first so each row wastes less space
+    entries.sort_by(|a, b| {
+        b.height
+            .cmp(&a.height)
+            .then(a.face.cmp(&b.face))
+            .then(a.style_slot.cmp(&b.style_slot))
+    });
+
+    let area = entries
+        .iter()
+        .map(|entry| (entry.width + ATLAS_PADDING) * (entry.height + ATLAS_PADDING))
+        .sum::<u32>();
+    let widest = entries
+        .iter()
+        .map(|entry| entry.width + ATLAS_PADDING)
+        .max()
+        .unwrap_or(0);
+
+    let width = ((area as f64).sqrt().ceil() as u32)
+        .next_power_of_two()
+        .max(widest);
+
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+
+    entries.iter_mut().for_each(|entry| {
+        if x + entry.width > width {
+            x = 0;
+            y += row_height + ATLAS_PADDING;
+            row_height = 0;
+        }
+
+        entry.x = x;
+        entry.y = y;
+
+        x += entry.width + ATLAS_PADDING;
+        row_height = row_height.max(entry.height);
+    });
+
+    LightmapAtlasLayout {
+        width: width.max(1),
+        height: (y + row_height).max(1),
+        entries,
+    }
+}
+
+/// Copies every face lightmap into an image.
+pub fn export_lightmap_atlas(bsp: &Bsp) -> (RgbImage, LightmapAtlasLayout) {
+    let layout = lightmap_atlas_layout(bsp);
+    let mut img = RgbImage::new(layout.width, layout.height);
+
+    layout.entries.iter().for_each(|entry| {
+        // layout only has faces with valid lightmap
+        let luxels = bsp.face_lightmap(entry.face, entry.style_slot).unwrap();
+
+        luxels.iter().enumerate().for_each(|(idx, luxel)| {
+            let x = entry.x + idx as u32 % entry.width;
+            let y = entry.y + idx as u32 / entry.width;
+
+            img.put_pixel(x, y, image::Rgb(*luxel));
+        });
+    });
+
+    (img, layout)
+}
+
+/// Writes the lightmaps from the atlas back into the BSP.
+///
+/// Fails if the layout does not match the BSP.
+pub fn import_lightmap_atlas(
+    bsp: &mut Bsp,
+    img: &RgbImage,
+    layout: &LightmapAtlasLayout,
+) -> eyre::Result<()> {
+    if img.dimensions() != (layout.width, layout.height) {
+        return err!(
+            "Atlas is {}x{} but layout is {}x{}",
+            img.width(),
+            img.height(),
+            layout.width,
+            layout.height
+        );
+    }
+
+    // check everything first so nothing is half written
+    for entry in &layout.entries {
+        let Some(info) = bsp.face_lightmap_info(entry.face) else {
+            return err!("Face {} does not have lightmap", entry.face);
+        };
+
+        if entry.style_slot >= info.style_count
+            || bsp.faces[entry.face].styles[entry.style_slot] != entry.style
+        {
+            return err!(
+                "Face {} does not have style {} in slot {}",
+                entry.face,
+                entry.style,
+                entry.style_slot
+            );
+        }
+
+        if (entry.width, entry.height) != (info.width as u32, info.height as u32) {
+            return err!(
+                "Face {} lightmap is {}x{} but layout has {}x{}",
+                entry.face,
+                info.width,
+                info.height,
+                entry.width,
+                entry.height
+            );
+        }
+
+        if entry.x + entry.width > layout.width || entry.y + entry.height > layout.height {
+            return err!("Face {} lightmap is outside of the atlas", entry.face);
+        }
+    }
+
+    layout.entries.iter().for_each(|entry| {
+        let luxels = bsp.face_lightmap_mut(entry.face, entry.style_slot).unwrap();
+
+        luxels.iter_mut().enumerate().for_each(|(idx, luxel)| {
+            let x = entry.x + idx as u32 % entry.width;
+            let y = entry.y + idx as u32 / entry.width;
+
+            *luxel = img.get_pixel(x, y).0;
+        });
+    });
+
+    Ok(())
+}
+
+fn atlas_paths(bsp_path: &Path) -> (PathBuf, PathBuf) {
+    (
+        bsp_path.with_extension("lightmap.png"),
+        bsp_path.with_extension("lightmap.json"),
+    )
+}
+
+/// Writes `<name>.lightmap.png` and `<name>.lightmap.json` next to the BSP.
+pub fn lightmap_export(bsp_path: impl AsRef<Path>) -> eyre::Result<()> {
+    let bsp_path = bsp_path.as_ref();
+    let bsp = Bsp::from_file(bsp_path)?;
+
+    let (img, layout) = export_lightmap_atlas(&bsp);
+    let (img_path, layout_path) = atlas_paths(bsp_path);
+
+    img.save_with_format(img_path, ImageFormat::Png)?;
+    std::fs::write(layout_path, serde_json::to_string_pretty(&layout)?)?;
+
+    Ok(())
+}
+
+/// Reads `<name>.lightmap.png` and `<name>.lightmap.json` next to the BSP and writes the new BSP to `out_path`.
+pub fn lightmap_import(
+    bsp_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path> + Into<PathBuf>,
+) -> eyre::Result<()> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    let (img_path, layout_path) = atlas_paths(bsp_path);
+
+    let img = image::open(img_path)?.to_rgb8();
+    let layout: LightmapAtlasLayout = serde_json::from_str(&std::fs::read_to_string(layout_path)?)?;
+
+    import_lightmap_atlas(&mut bsp, &img, &layout)?;
+
+    bsp.write_to_file(out_path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let (img, layout) = export_lightmap_atlas(&bsp);
+
+        // every luxel is in the atlas once
+        let luxels = layout
+            .entries
+            .iter()
+            .map(|entry| entry.width * entry.height)
+            .sum::<u32>();
+        assert_eq!(luxels as usize, bsp.lightmap.len());
+
+        let layout: LightmapAtlasLayout =
+            serde_json::from_str(&serde_json::to_string(&layout).unwrap()).unwrap();
+
+        let original = bsp.lightmap.clone();
+        import_lightmap_atlas(&mut bsp, &img, &layout).unwrap();
+        assert_eq!(original, bsp.lightmap);
+
+        // edits go back to the face
+        let mut img = img;
+        let entry = &layout.entries[0];
+        img.put_pixel(entry.x, entry.y, image::Rgb([1, 2, 3]));
+
+        import_lightmap_atlas(&mut bsp, &img, &layout).unwrap();
+        assert_eq!(
+            bsp.face_lightmap(entry.face, entry.style_slot).unwrap()[0],
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn mismatched_layout() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap();
+        let (img, mut layout) = export_lightmap_atlas(&bsp);
+
+        layout.entries[0].width += 1;
+
+        assert!(import_lightmap_atlas(&mut bsp, &img, &layout).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Color grading of the compiled lighting.
+//!
+//! Same idea as `light_scale` but it works on the BSP lightmap so there is no need to recompile.
+use std::collections::HashSet;
+
+use bsp::{Bsp, Vec3};
+
+#[derive(Debug, Clone)]
+pub struct LightmapGradeOptions {
+    /// Multiplies every luxel.
+    pub brightness: f64,
+    /// Applied after everything else, values above 1 brighten the dark parts.
+    pub gamma: f64,
+    /// Multiplies R G B of every luxel.
+    pub tint: [f64; 3],
+    /// Multiplies lightmaps of a light style. Styles not listed are not scaled.
+    pub style_scales: Vec<(u8, f64)>,
+    /// Only grades faces with these textures. Case insensitive. Empty means every texture.
+    pub textures: Vec<String>,
+    /// Only grades faces with the center inside the box, in model space.
+    pub region: Option<(Vec3, Vec3)>,
+}
+
+impl Default for LightmapGradeOptions {
+    fn default() -> Self {
+        Self {
+            brightness: 1.,
+            gamma: 1.,
+            tint: [1.; 3],
+            style_scales: vec![],
+            textures: vec![],
+            region: None,
+        }
+    }
+}
+
+impl LightmapGradeOptions {
+    fn style_scale(&self, style: u8) -> f64 {
+        self.style_scales
+            .iter()
+            .find(|(s, _)| *s == style)
+            .map(|(_, scale)| *scale)
+            .unwrap_or(1.)
+    }
+
+    // every luxel value for each channel is mapped to the new value
+    fn lookup_table(&self, style: u8) -> [[u8; 256]; 3] {
+        let mut res = [[0u8; 256]; 3];
+        let scale = self.brightness * self.style_scale(style);
+        let gamma = self.gamma.max(f64::EPSILON);
+
+        for (channel, table) in res.iter_mut().enumerate() {
+            for (value, new_value) in table.iter_mut().enumerate() {
+                let v = (value as f64 / 255. * scale * self.tint[channel]).clamp(0., 1.);
+
+                *new_value = (v.powf(1. / gamma) * 255.).round() as u8;
+            }
+        }
+
+        res
+    }
+
+    fn includes_face(&self, bsp: &Bsp, face_idx: usize) -> bool {
+        if !self.textures.is_empty() {
+            let texture_name = bsp
+                .texinfo
+                .get(bsp.faces[face_idx].texinfo as usize)
+                .and_then(|texinfo| bsp.textures.get(texinfo.texture_index as usize))
+                .map(|texture| texture.texture_name.get_string());
+
+            let Some(texture_name) = texture_name else {
+                return false;
+            };
+
+            if !self
+                .textures
+                .iter()
+                .any(|texture| texture.eq_ignore_ascii_case(&texture_name))
+            {
+                return false;
+            }
+        }
+
+        if let Some((mins, maxs)) = self.region {
+            let vertices = bsp.face_vertices(face_idx);
+
+            if vertices.is_empty() {
+                return false;
+            }
+
+            let center = vertices.iter().sum::<Vec3>() / vertices.len() as f32;
+
+            if center.cmplt(mins).any() || center.cmpgt(maxs).any() {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Grades the lightmap of every face matching the options.
+///
+/// Mutate the input bsp data.
+pub fn lightmap_grade(bsp: &mut Bsp, options: &LightmapGradeOptions) {
+    let tables = (0..=u8::MAX)
+        .map(|style| options.lookup_table(style))
+        .collect::<Vec<_>>();
+
+    // faces could share the same lightmap, it should not be graded twice
+    let mut graded: HashSet<usize> = HashSet::new();
+
+    for face_idx in 0..bsp.faces.len() {
+        if !options.includes_face(bsp, face_idx) {
+            continue;
+        }
+
+        let Some(info) = bsp.face_lightmap_info(face_idx) else {
+            continue;
+        };
+
+        if !graded.insert(info.offset) {
+            continue;
+        }
+
+        for style_slot in 0..info.style_count {
+            let table = &tables[bsp.faces[face_idx].styles[style_slot] as usize];
+
+            bsp.lightmap[info.style_range(style_slot)]
+                .iter_mut()
+                .for_each(|luxel| {
+                    luxel
+                        .iter_mut()
+                        .enumerate()
+                        .for_each(|(channel, value)| *value = table[channel][*value as usize]);
+                });
+        }
+    }
+}
+
+pub fn lightmap_grade_bytes(
+    bsp_bytes: &[u8],
+    options: &LightmapGradeOptions,
+) -> eyre::Result<Vec<u8>> {
+    let mut bsp = Bsp::from_bytes(bsp_bytes)?;
+
+    lightmap_grade(&mut bsp, options);
+
+    Ok(bsp.write_to_bytes())
+}
+
+pub trait LightmapGradeImpl {
+    fn lightmap_grade(&mut self, options: &LightmapGradeOptions) -> &mut Self;
+}
+
+impl LightmapGradeImpl for Bsp {
+    fn lightmap_grade(&mut self, options: &LightmapGradeOptions) -> &mut Self {
+        lightmap_grade(self, options);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn c1a3d() -> Bsp {
+        Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap()
+    }
+
+    #[test]
+    fn identity() {
+        let mut bsp = c1a3d();
+        let original = bsp.lightmap.clone();
+
+        lightmap_grade(&mut bsp, &LightmapGradeOptions::default());
+
+        assert_eq!(original, bsp.lightmap);
+    }
+
+    #[test]
+    fn darken() {
+        let mut bsp = c1a3d();
+        let original = bsp.lightmap.clone();
+
+        lightmap_grade(
+            &mut bsp,
+            &LightmapGradeOptions {
+                brightness: 0.5,
+                tint: [1., 1., 0.],
+                ..Default::default()
+            },
+        );
+
+        original.iter().zip(bsp.lightmap.iter()).for_each(|(a, b)| {
+            assert_eq!(b[0], (a[0] as f64 * 0.5).round() as u8);
+            assert_eq!(b[2], 0);
+        });
+    }
+
+    #[test]
+    fn texture_filter() {
+        let mut bsp = c1a3d();
+        let original = bsp.lightmap.clone();
+
+        lightmap_grade(
+            &mut bsp,
+            &LightmapGradeOptions {
+                brightness: 0.,
+                textures: vec!["texture that does not exist".to_string()],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(original, bsp.lightmap);
+    }
+}
@@ -1,4 +1,4 @@
-// make sure data here match gchimp.fgd and vice versa
+// dist/gchimp.fgd is written from these in `crate::entity::gchimp_fgd`
 
 pub static MAP2MDL_ENTITY_NAME: &str = "gchimp_map2mdl";
 
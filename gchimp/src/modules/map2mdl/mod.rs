@@ -46,27 +46,27 @@ use crate::utils::run_bin::run_studiomdl;
 
 pub mod entity;
 
-struct ConvertFromTrianglesOptions<'a> {
+pub(crate) struct ConvertFromTrianglesOptions<'a> {
     // output path would be where the model ends up with
     // output path should be the .mdl file
-    output_path: &'a Path,
+    pub output_path: &'a Path,
     // resource path is where qc smd and textures file are stored
     // usually it should be the .map file
-    resource_path: &'a Path,
-    move_to_origin: bool,
-    export_resource: bool,
+    pub resource_path: &'a Path,
+    pub move_to_origin: bool,
+    pub export_resource: bool,
     // contentwater and such
     // when converting a whole map, maybe don't enable it but smaller one should
     // the reason why is that a whole map would have all triangles included in ONE smd
     // then that one smd is split. One CONTENTWATER would make the entire smd contentwater
     // could be something fixed with planning the steps going differently
     // TODO: maybe add triangles to smd one brush by one brush
-    use_special_texture: bool,
+    pub use_special_texture: bool,
     // this will be the origin of the model relatively from where it is
     // it means that this will be the centroid of the model
-    maybe_target_origin: Option<[f64; 3]>,
+    pub maybe_target_origin: Option<[f64; 3]>,
     // nested flatshade again because this is per model
-    flatshade: bool,
+    pub flatshade: bool,
 }
 
 #[derive(Debug)]
@@ -234,7 +234,7 @@ impl Map2Mdl {
         }
     }
 
-    fn convert_from_triangles(
+    pub(crate) fn convert_from_triangles(
         &self,
         smd_triangles: &[Triangle],
         textures_used: &HashSet<String>,
@@ -1057,6 +1057,7 @@ However, it will still turn {} into model displaying entities such as cycler_spr
                                         ("model".to_owned(), curr_model_name),
                                     ]),
                                     brushes: None,
+                                    ..Default::default()
                                 };
 
                                 entities_to_insert.push(new_entity);
@@ -1074,6 +1075,7 @@ However, it will still turn {} into model displaying entities such as cycler_spr
                                 }
 
                                 clip_brush_entity.attributes.clear();
+                                clip_brush_entity.comments.clear();
 
                                 clip_brush_entity
                                     .attributes
@@ -1110,6 +1112,7 @@ However, it will still turn {} into model displaying entities such as cycler_spr
                                         "func_detail".to_owned(),
                                     )]),
                                     brushes: vec![new_brush].into(),
+                                    ..Default::default()
                                 };
 
                                 entities_to_insert.push(new_brush_entity);
@@ -0,0 +1,590 @@
+use std::{collections::HashMap, fmt, path::Path};
+
+use map::{Brush, BrushPlane, Entity, Map};
+use serde::Serialize;
+
+/// Entity that is only in one of the maps.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntitySummary {
+    /// Index in the map the entity is from.
+    pub index: usize,
+    pub classname: String,
+    pub targetname: Option<String>,
+    pub origin: Option<String>,
+    pub brush_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyChange {
+    pub key: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Brush with the same planes but different textures.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BrushChange {
+    pub old_index: usize,
+    pub new_index: usize,
+}
+
+/// Entity that is in both maps but is not the same.
+///
+/// Brush indices are the indices in the brushes of the entity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntityDiff {
+    pub old_index: usize,
+    pub new_index: usize,
+    pub classname: String,
+    pub targetname: Option<String>,
+    pub added_keys: Vec<(String, String)>,
+    pub removed_keys: Vec<(String, String)>,
+    pub changed_keys: Vec<KeyChange>,
+    pub added_brushes: Vec<usize>,
+    pub removed_brushes: Vec<usize>,
+    pub changed_brushes: Vec<BrushChange>,
+}
+
+impl EntityDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_keys.is_empty()
+            && self.removed_keys.is_empty()
+            && self.changed_keys.is_empty()
+            && self.added_brushes.is_empty()
+            && self.removed_brushes.is_empty()
+            && self.changed_brushes.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MapDiff {
+    pub added_entities: Vec<EntitySummary>,
+    pub removed_entities: Vec<EntitySummary>,
+    pub changed_entities: Vec<EntityDiff>,
+}
+
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_entities.is_empty()
+            && self.removed_entities.is_empty()
+            && self.changed_entities.is_empty()
+    }
+
+    pub fn to_json(&self) -> eyre::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for EntitySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "entity {} {}", self.index, self.classname)?;
+
+        if let Some(targetname) = &self.targetname {
+            write!(f, " \"{}\"", targetname)?;
+        }
+
+        if let Some(origin) = &self.origin {
+            write!(f, " at {}", origin)?;
+        }
+
+        if self.brush_count > 0 {
+            write!(f, " with {} brushes", self.brush_count)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entity in &self.removed_entities {
+            writeln!(f, "- {}", entity)?;
+        }
+
+        for entity in &self.added_entities {
+            writeln!(f, "+ {}", entity)?;
+        }
+
+        for entity in &self.changed_entities {
+            write!(
+                f,
+                "~ entity {} -> {} {}",
+                entity.old_index, entity.new_index, entity.classname
+            )?;
+
+            if let Some(targetname) = &entity.targetname {
+                write!(f, " \"{}\"", targetname)?;
+            }
+
+            writeln!(f)?;
+
+            for (key, value) in &entity.removed_keys {
+                writeln!(f, "    - \"{}\" \"{}\"", key, value)?;
+            }
+
+            for (key, value) in &entity.added_keys {
+                writeln!(f, "    + \"{}\" \"{}\"", key, value)?;
+            }
+
+            for change in &entity.changed_keys {
+                writeln!(
+                    f,
+                    "    ~ \"{}\" \"{}\" -> \"{}\"",
+                    change.key, change.old, change.new
+                )?;
+            }
+
+            for index in &entity.removed_brushes {
+                writeln!(f, "    - brush {}", index)?;
+            }
+
+            for index in &entity.added_brushes {
+                writeln!(f, "    + brush {}", index)?;
+            }
+
+            for change in &entity.changed_brushes {
+                writeln!(
+                    f,
+                    "    ~ brush {} -> {} textures",
+                    change.old_index, change.new_index
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// planes written with different points are still the same plane
+type PlaneKey = [i64; 4];
+type BrushKey = Vec<PlaneKey>;
+
+fn plane_key(plane: &BrushPlane) -> PlaneKey {
+    let normal = (plane.normal() * 1e4).round();
+
+    [
+        normal.x as i64,
+        normal.y as i64,
+        normal.z as i64,
+        (plane.distance() * 1e2).round() as i64,
+    ]
+}
+
+fn brush_key(brush: &Brush) -> BrushKey {
+    let mut res = brush.planes.iter().map(plane_key).collect::<Vec<_>>();
+
+    res.sort();
+    res.dedup();
+
+    res
+}
+
+fn sorted_planes(brush: &Brush) -> Vec<&BrushPlane> {
+    let mut res = brush.planes.iter().collect::<Vec<_>>();
+
+    res.sort_by_key(|plane| plane_key(plane));
+
+    res
+}
+
+fn same_textures(a: &Brush, b: &Brush) -> bool {
+    sorted_planes(a)
+        .into_iter()
+        .zip(sorted_planes(b))
+        .all(|(a, b)| {
+            a.texture_name == b.texture_name
+                && a.u == b.u
+                && a.v == b.v
+                && a.u_scale == b.u_scale
+                && a.v_scale == b.v_scale
+        })
+}
+
+fn brushes(entity: &Entity) -> &[Brush] {
+    entity.brushes.as_deref().unwrap_or_default()
+}
+
+fn brush_keys(entity: &Entity) -> HashMap<BrushKey, Vec<usize>> {
+    let mut res: HashMap<BrushKey, Vec<usize>> = HashMap::new();
+
+    for (index, brush) in brushes(entity).iter().enumerate() {
+        res.entry(brush_key(brush)).or_default().push(index);
+    }
+
+    res
+}
+
+fn classname(entity: &Entity) -> &str {
+    entity
+        .attributes
+        .get("classname")
+        .map(String::as_str)
+        .unwrap_or_default()
+}
+
+fn targetname(entity: &Entity) -> Option<&str> {
+    entity
+        .attributes
+        .get("targetname")
+        .map(String::as_str)
+        .filter(|name| !name.is_empty())
+}
+
+fn summary(index: usize, entity: &Entity) -> EntitySummary {
+    EntitySummary {
+        index,
+        classname: classname(entity).to_string(),
+        targetname: targetname(entity).map(str::to_string),
+        origin: entity.attributes.get("origin").cloned(),
+        brush_count: brushes(entity).len(),
+    }
+}
+
+fn diff_keys(old: &Entity, new: &Entity, res: &mut EntityDiff) {
+    let mut keys = old.attributes.keys().collect::<Vec<_>>();
+
+    keys.extend(new.attributes.keys());
+    keys.sort();
+    keys.dedup();
+
+    for key in keys {
+        let old_values = old.attributes.get_all(key).collect::<Vec<_>>();
+        let new_values = new.attributes.get_all(key).collect::<Vec<_>>();
+
+        // duplicate keys are compared in order
+        for (old_value, new_value) in old_values.iter().zip(new_values.iter()) {
+            if old_value != new_value {
+                res.changed_keys.push(KeyChange {
+                    key: key.clone(),
+                    old: old_value.to_string(),
+                    new: new_value.to_string(),
+                });
+            }
+        }
+
+        for value in old_values.iter().skip(new_values.len()) {
+            res.removed_keys.push((key.clone(), value.to_string()));
+        }
+
+        for value in new_values.iter().skip(old_values.len()) {
+            res.added_keys.push((key.clone(), value.to_string()));
+        }
+    }
+}
+
+fn diff_brushes(old: &Entity, new: &Entity, res: &mut EntityDiff) {
+    let mut new_keys = brush_keys(new);
+
+    for (old_index, brush) in brushes(old).iter().enumerate() {
+        let Some(new_index) = new_keys
+            .get_mut(&brush_key(brush))
+            .filter(|indices| !indices.is_empty())
+            .map(|indices| indices.remove(0))
+        else {
+            res.removed_brushes.push(old_index);
+            continue;
+        };
+
+        if !same_textures(brush, &brushes(new)[new_index]) {
+            res.changed_brushes.push(BrushChange {
+                old_index,
+                new_index,
+            });
+        }
+    }
+
+    res.added_brushes = new_keys.into_values().flatten().collect();
+    res.added_brushes.sort();
+}
+
+fn diff_entity(old_index: usize, old: &Entity, new_index: usize, new: &Entity) -> EntityDiff {
+    let mut res = EntityDiff {
+        old_index,
+        new_index,
+        classname: classname(new).to_string(),
+        targetname: targetname(new).map(str::to_string),
+        added_keys: vec![],
+        removed_keys: vec![],
+        changed_keys: vec![],
+        added_brushes: vec![],
+        removed_brushes: vec![],
+        changed_brushes: vec![],
+    };
+
+    diff_keys(old, new, &mut res);
+    diff_brushes(old, new, &mut res);
+
+    res
+}
+
+/// Pairs entities of two maps, from the surest match to the least sure.
+///
+/// 1. Same classname and targetname, when the targetname is only used once in each map.
+/// 2. Same classname and origin.
+/// 3. Same classname and sharing the most brushes.
+/// 4. Same classname when it is the only one left on both sides.
+fn match_entities(old: &Map, new: &Map) -> Vec<(usize, usize)> {
+    let mut old_left = (0..old.entities.len()).collect::<Vec<_>>();
+    let mut new_left = (0..new.entities.len()).collect::<Vec<_>>();
+    let mut res = vec![];
+
+    let mut pair_by = |old_left: &mut Vec<usize>,
+                       new_left: &mut Vec<usize>,
+                       score: &dyn Fn(&Entity, &Entity) -> usize| {
+        let mut old_idx = 0;
+
+        while old_idx < old_left.len() {
+            let old_entity = &old.entities[old_left[old_idx]];
+
+            let best = new_left
+                .iter()
+                .enumerate()
+                .map(|(idx, &new_index)| (idx, score(old_entity, &new.entities[new_index])))
+                .filter(|(_, score)| *score > 0)
+                // first one wins ties
+                .fold(None, |best: Option<(usize, usize)>, curr| match best {
+                    Some(best) if best.1 >= curr.1 => Some(best),
+                    _ => Some(curr),
+                });
+
+            match best {
+                Some((new_idx, _)) => {
+                    res.push((old_left.remove(old_idx), new_left.remove(new_idx)));
+                }
+                None => old_idx += 1,
+            }
+        }
+    };
+
+    let count_targetname = |map: &Map, name: &str| {
+        map.entities
+            .iter()
+            .filter(|entity| targetname(entity) == Some(name))
+            .count()
+    };
+
+    pair_by(&mut old_left, &mut new_left, &|a, b| {
+        let same = classname(a) == classname(b)
+            && targetname(a).is_some_and(|name| {
+                targetname(b) == Some(name)
+                    && count_targetname(old, name) == 1
+                    && count_targetname(new, name) == 1
+            });
+
+        same as usize
+    });
+
+    pair_by(&mut old_left, &mut new_left, &|a, b| {
+        let same = classname(a) == classname(b)
+            && a.attributes
+                .get("origin")
+                .is_some_and(|origin| b.attributes.get("origin") == Some(origin));
+
+        same as usize
+    });
+
+    pair_by(&mut old_left, &mut new_left, &|a, b| {
+        if classname(a) != classname(b) {
+            return 0;
+        }
+
+        let b_keys = brush_keys(b);
+
+        brushes(a)
+            .iter()
+            .filter(|brush| b_keys.contains_key(&brush_key(brush)))
+            .count()
+    });
+
+    let only_one = |map: &Map, left: &[usize], name: &str| {
+        left.iter()
+            .filter(|&&index| classname(&map.entities[index]) == name)
+            .count()
+            == 1
+    };
+
+    for old_index in old_left.clone() {
+        let name = classname(&old.entities[old_index]);
+
+        if !only_one(old, &old_left, name) || !only_one(new, &new_left, name) {
+            continue;
+        }
+
+        let new_idx = new_left
+            .iter()
+            .position(|&index| classname(&new.entities[index]) == name)
+            .unwrap();
+
+        res.push((old_index, new_left.remove(new_idx)));
+    }
+
+    res.sort();
+
+    res
+}
+
+/// Compares two maps by what is in them rather than by text.
+///
+/// Entities are paired by targetname, then origin, then shared brushes. Brushes are paired by their planes
+/// so brushes that are only reordered or written with different plane points are the same brush.
+pub fn map_diff(old: &Map, new: &Map) -> MapDiff {
+    let pairs = match_entities(old, new);
+    let mut res = MapDiff::default();
+
+    for &(old_index, new_index) in &pairs {
+        let diff = diff_entity(
+            old_index,
+            &old.entities[old_index],
+            new_index,
+            &new.entities[new_index],
+        );
+
+        if !diff.is_empty() {
+            res.changed_entities.push(diff);
+        }
+    }
+
+    res.removed_entities = old
+        .entities
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !pairs.iter().any(|pair| pair.0 == *index))
+        .map(|(index, entity)| summary(index, entity))
+        .collect();
+
+    res.added_entities = new
+        .entities
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !pairs.iter().any(|pair| pair.1 == *index))
+        .map(|(index, entity)| summary(index, entity))
+        .collect();
+
+    res
+}
+
+pub fn map_diff_file(
+    old_path: impl AsRef<Path> + Into<std::path::PathBuf>,
+    new_path: impl AsRef<Path> + Into<std::path::PathBuf>,
+) -> eyre::Result<MapDiff> {
+    let old = Map::from_file(old_path)?;
+    let new = Map::from_file(new_path)?;
+
+    Ok(map_diff(&old, &new))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::utils::map_stuffs::brush_from_mins_maxs;
+
+    const OLD: &str = "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"a.wad\"
+}
+{
+\"classname\" \"light\"
+\"origin\" \"16 16 16\"
+}
+{
+\"classname\" \"info_target\"
+\"targetname\" \"spot\"
+\"origin\" \"0 0 0\"
+}
+";
+
+    // target moved, light gone
+    const NEW: &str = "\
+{
+\"wad\" \"a.wad;b.wad\"
+\"classname\" \"worldspawn\"
+}
+{
+\"classname\" \"info_target\"
+\"targetname\" \"spot\"
+\"origin\" \"8 0 0\"
+}
+{
+\"classname\" \"info_player_start\"
+\"origin\" \"0 0 64\"
+}
+";
+
+    fn with_world_brushes(text: &str, brushes: Vec<Brush>) -> Map {
+        let mut map = Map::from_text(text).unwrap();
+        map.entities[0].brushes = Some(brushes);
+        map
+    }
+
+    fn old_map() -> Map {
+        with_world_brushes(
+            OLD,
+            vec![
+                brush_from_mins_maxs(&[0.; 3], &[32.; 3], "NULL"),
+                brush_from_mins_maxs(&[64., 0., 0.], &[96., 32., 32.], "NULL"),
+            ],
+        )
+    }
+
+    // brushes swapped and written with other points, one retextured
+    fn new_map() -> Map {
+        let mut first = brush_from_mins_maxs(&[0.; 3], &[32.; 3], "NULL");
+        let plane = &mut first.planes[0];
+        plane.p2 = plane.p1 + (plane.p2 - plane.p1) * 2.;
+        plane.p3 = plane.p1 + (plane.p3 - plane.p1) * 2.;
+
+        let mut second = brush_from_mins_maxs(&[64., 0., 0.], &[96., 32., 32.], "NULL");
+        // up
+        second.planes[3].texture_name = "SKY".to_owned();
+
+        with_world_brushes(NEW, vec![second, first])
+    }
+
+    #[test]
+    fn same_map() {
+        let map = old_map();
+
+        assert!(map_diff(&map, &map).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let old = old_map();
+        let new = new_map();
+
+        let diff = map_diff(&old, &new);
+
+        assert_eq!(diff.removed_entities.len(), 1);
+        assert_eq!(diff.removed_entities[0].classname, "light");
+        assert_eq!(diff.added_entities.len(), 1);
+        assert_eq!(diff.added_entities[0].classname, "info_player_start");
+
+        assert_eq!(diff.changed_entities.len(), 2);
+
+        let world = &diff.changed_entities[0];
+        assert_eq!(world.changed_keys.len(), 1);
+        assert_eq!(world.changed_keys[0].new, "a.wad;b.wad");
+        assert!(world.added_brushes.is_empty());
+        assert!(world.removed_brushes.is_empty());
+        assert_eq!(
+            world.changed_brushes,
+            vec![BrushChange {
+                old_index: 1,
+                new_index: 0
+            }]
+        );
+
+        let target = &diff.changed_entities[1];
+        assert_eq!((target.old_index, target.new_index), (2, 1));
+        assert_eq!(target.changed_keys[0].key, "origin");
+
+        let text = diff.to_string();
+        assert!(text.contains("- entity 1 light at 16 16 16"));
+        assert!(text.contains("~ \"origin\" \"0 0 0\" -> \"8 0 0\""));
+
+        let json: serde_json::Value = serde_json::from_str(&diff.to_json().unwrap()).unwrap();
+        assert_eq!(json["added_entities"][0]["origin"], "0 0 64");
+    }
+}
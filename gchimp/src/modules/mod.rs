@@ -1,15 +1,27 @@
 pub mod blender_lightmap_baker_helper;
+pub mod check_entity;
 pub mod check_illegal_brush;
 pub mod check_missing_texture;
 pub mod custom_script;
 pub mod dem2cam;
 // pub mod demdoc;
+pub mod bsp2gltf;
+pub mod bsp2map;
+pub mod bsp2mdl;
 pub mod bsp2wad;
+pub mod bspinfo;
 pub mod duplicate_triangle;
+pub mod embed_texture;
 pub mod find_low_scaling;
+pub mod instance;
 pub mod light_scale;
+pub mod lightmap_atlas;
+pub mod lightmap_grade;
 pub mod loop_wave;
 pub mod map2mdl;
+pub mod map_diff;
+pub mod relight;
+pub mod replace_texture;
 pub mod resmake;
 pub mod rotate_prop_static;
 pub mod s2g;
@@ -0,0 +1,816 @@
+//! Direct lighting of an already compiled BSP.
+//!
+//! Lights are read from the `light`, `light_spot` and `light_environment` entities still in the BSP
+//! along with texture lights. Every luxel is lit by whatever light it can see through the BSP tree
+//! and written back into the existing lightmaps so the layout does not change. There is no bounce light.
+//!
+//! A face only gets the styles it was compiled with. Moving a switchable light onto a face that
+//! did not have its style before will not light that face.
+use std::{collections::HashMap, f64::consts::PI, path::Path};
+
+use bsp::{Bsp, LeafContent, Pvs, HULL_POINT, LIGHTMAP_SCALE};
+use glam::{DMat3, DVec3};
+use rayon::prelude::*;
+
+use crate::utils::{
+    bsp_stuffs::{entity_origin, to_dvec3, to_vec3},
+    misc::parse_triplet,
+};
+
+/// Texture lights are split into pieces no longer than this, same as the default `-chop` in hlrad.
+const TEXLIGHT_CHOP: f64 = 64.;
+/// How far a sun ray goes to find the sky.
+const SUN_DISTANCE: f64 = 65536.;
+/// How many directions are checked for the sky light, half of them face the luxel.
+const SKY_DIRECTIONS: usize = 128;
+/// Luxels are lifted off the face so the face does not shadow itself.
+const SAMPLE_OFFSET: f64 = 1.;
+/// Point lights adding less than this to a luxel are skipped.
+const POINT_CUTOFF: f64 = 1. / 255.;
+
+#[derive(Debug, Clone)]
+pub struct RelightOptions {
+    /// Multiplies direct lighting except from `light_environment`, same as `-dscale` in hlrad.
+    pub direct_scale: f64,
+    /// Applied to the final light, same as `-gamma` in hlrad.
+    pub gamma: f64,
+    /// Texture name to RGB intensity, same as `lights.rad`. Case insensitive.
+    ///
+    /// `info_texlights` in the map is added on top.
+    pub texlights: HashMap<String, [f64; 3]>,
+}
+
+impl Default for RelightOptions {
+    fn default() -> Self {
+        Self {
+            direct_scale: 2.,
+            gamma: 0.55,
+            texlights: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LightKind {
+    Point,
+    Spot {
+        direction: DVec3,
+        stop_dot: f64,
+        stop_dot2: f64,
+    },
+    /// `light_environment`, the direction is where the light travels.
+    Sun {
+        direction: DVec3,
+    },
+    /// Diffuse light from every bit of sky the luxel can see, also from `light_environment`.
+    Sky,
+    /// A piece of a texture light.
+    Surface {
+        normal: DVec3,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Light {
+    origin: DVec3,
+    intensity: DVec3,
+    fade: f64,
+    style: u8,
+    kind: LightKind,
+    /// Leaf containing the light, used to skip lights the luxel cannot see.
+    leaf: usize,
+}
+
+impl Light {
+    /// Whether the light could reach a luxel in the leaf, same as the PVS check in hlrad.
+    ///
+    /// Sun and sky come from the sky faces so they are never culled. Leaves without visibility
+    /// information are not culled either.
+    fn in_pvs(&self, pvs: &Pvs, leaf: usize) -> bool {
+        if matches!(self.kind, LightKind::Sun { .. } | LightKind::Sky) || leaf == self.leaf {
+            return true;
+        }
+
+        if pvs.row(leaf).is_none() || pvs.row(self.leaf).is_none() {
+            return true;
+        }
+
+        pvs.can_see(leaf, self.leaf)
+    }
+
+    fn gather(&self, bsp: &Bsp, pos: DVec3, normal: DVec3) -> DVec3 {
+        if let LightKind::Sun { direction } = self.kind {
+            let dot = -direction.dot(normal);
+
+            if dot <= 0. {
+                return DVec3::ZERO;
+            }
+
+            // the sun only reaches what can see the sky
+            return match bsp.test_line(to_vec3(pos), to_vec3(pos - direction * SUN_DISTANCE)) {
+                Some((LeafContent::ContentsSky, _)) => self.intensity * dot,
+                _ => DVec3::ZERO,
+            };
+        }
+
+        if let LightKind::Sky = self.kind {
+            // every direction has the same weight over the whole sphere so open ground gets a quarter
+            let visible = sky_directions()
+                .map(|direction| (direction, direction.dot(normal)))
+                .filter(|(_, dot)| *dot > 0.)
+                .filter(|(direction, _)| {
+                    matches!(
+                        bsp.test_line(to_vec3(pos), to_vec3(pos + *direction * SUN_DISTANCE)),
+                        Some((LeafContent::ContentsSky, _))
+                    )
+                })
+                .map(|(_, dot)| dot)
+                .sum::<f64>();
+
+            return self.intensity * visible / SKY_DIRECTIONS as f64;
+        }
+
+        let delta = self.origin - pos;
+        let dist = delta.length().max(1.);
+        let delta = delta / dist;
+
+        // the falloff is already too small to matter, skips the trace
+        if matches!(self.kind, LightKind::Point | LightKind::Spot { .. })
+            && self.intensity.max_element() / (dist * dist * self.fade) < POINT_CUTOFF
+        {
+            return DVec3::ZERO;
+        }
+        let dot = delta.dot(normal);
+
+        if dot <= 0. {
+            return DVec3::ZERO;
+        }
+
+        let ratio = match self.kind {
+            LightKind::Point => dot / (dist * dist * self.fade),
+            LightKind::Spot {
+                direction,
+                stop_dot,
+                stop_dot2,
+            } => {
+                let dot2 = -delta.dot(direction);
+
+                if dot2 <= stop_dot2 {
+                    return DVec3::ZERO;
+                }
+
+                let ratio = dot * dot2 / (dist * dist * self.fade);
+
+                // soft edge between the two cones
+                if dot2 <= stop_dot {
+                    ratio * (dot2 - stop_dot2) / (stop_dot - stop_dot2)
+                } else {
+                    ratio
+                }
+            }
+            LightKind::Surface {
+                normal: emit_normal,
+            } => {
+                let dot2 = -delta.dot(emit_normal);
+
+                if dot2 <= 0. {
+                    return DVec3::ZERO;
+                }
+
+                dot * dot2 / (dist * dist)
+            }
+            LightKind::Sun { .. } | LightKind::Sky => unreachable!(),
+        };
+
+        if bsp.test_line(to_vec3(pos), to_vec3(self.origin)).is_some() {
+            return DVec3::ZERO;
+        }
+
+        self.intensity * ratio
+    }
+}
+
+/// Directions spread evenly over the sphere for the sky light.
+fn sky_directions() -> impl Iterator<Item = DVec3> {
+    // golden angle spiral
+    let golden_angle = PI * (3. - 5f64.sqrt());
+
+    (0..SKY_DIRECTIONS).map(move |idx| {
+        let z = 1. - (idx as f64 + 0.5) / SKY_DIRECTIONS as f64 * 2.;
+        let radius = (1. - z * z).sqrt();
+        let angle = golden_angle * idx as f64;
+
+        DVec3::new(angle.cos() * radius, angle.sin() * radius, z)
+    })
+}
+
+/// Parses `_light` and the values in `lights.rad`.
+///
+/// Could be one value for all channels, RGB, or RGB with brightness.
+fn parse_intensity(i: &str) -> Option<DVec3> {
+    let values = i
+        .split_ascii_whitespace()
+        .filter_map(|i| i.parse::<f64>().ok())
+        .collect::<Vec<f64>>();
+
+    match values.len() {
+        0 => None,
+        1 | 2 => Some(DVec3::splat(values[0])),
+        3 => Some(DVec3::from_slice(&values)),
+        _ => Some(DVec3::from_slice(&values) * values[3] / 255.),
+    }
+}
+
+/// Parses `lights.rad`, every line is a texture name followed by the intensity.
+pub fn parse_lights_rad(i: &str) -> HashMap<String, [f64; 3]> {
+    i.lines()
+        .filter_map(|line| {
+            let line = line.split("//").next().unwrap_or_default().trim();
+            let (texture, intensity) = line.split_once(char::is_whitespace)?;
+
+            Some((
+                texture.to_lowercase(),
+                parse_intensity(intensity)?.to_array(),
+            ))
+        })
+        .collect()
+}
+
+// same as how hlrad reads the light direction
+fn light_direction(bsp: &Bsp, entity: &bsp::Entity) -> DVec3 {
+    if let Some(target) = entity.get("target") {
+        let target = bsp
+            .entities
+            .iter()
+            .find(|other| other.get("targetname") == Some(target));
+
+        if let Some(target) = target {
+            let direction = entity_origin(target) - entity_origin(entity);
+
+            if direction.length_squared() > 0. {
+                return direction.normalize();
+            }
+        }
+    }
+
+    let angles = entity
+        .get("angles")
+        .and_then(|angles| parse_triplet(angles).ok())
+        .unwrap_or_default();
+    let key = |key: &str| entity.get(key).and_then(|value| value.parse::<f64>().ok());
+
+    let yaw = key("angle").unwrap_or(angles[1]);
+    let pitch = key("pitch").unwrap_or(angles[0]);
+
+    if yaw == -1. {
+        return DVec3::Z;
+    }
+
+    if yaw == -2. {
+        return DVec3::NEG_Z;
+    }
+
+    let (yaw, pitch) = (yaw.to_radians(), pitch.to_radians());
+
+    DVec3::new(
+        yaw.cos() * pitch.cos(),
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+    )
+}
+
+fn entity_lights(bsp: &Bsp) -> Vec<Light> {
+    let mut lights = vec![];
+
+    for entity in &bsp.entities {
+        let Some(classname) = entity.get("classname") else {
+            continue;
+        };
+
+        let key = |key: &str| entity.get(key).and_then(|value| value.parse::<f64>().ok());
+
+        let intensity = entity
+            .get("_light")
+            .and_then(|light| parse_intensity(light))
+            .unwrap_or(DVec3::splat(300.));
+        let light = Light {
+            origin: entity_origin(entity),
+            // same as `CreateDirectLights` in hlrad, every entity light but the sky is scaled by
+            // its brightest channel squared over 10
+            intensity: intensity * intensity.max_element().powi(2) / 10.,
+            fade: key("_fade").filter(|fade| *fade > 0.).unwrap_or(1.),
+            style: entity
+                .get("style")
+                .and_then(|style| style.parse::<u8>().ok())
+                .unwrap_or(0),
+            kind: LightKind::Point,
+            leaf: 0,
+        };
+
+        match classname.as_str() {
+            "light" => lights.push(light),
+            "light_spot" => {
+                let stop_dot = key("_cone").unwrap_or(10.).to_radians().cos();
+                let stop_dot2 = key("_cone2").unwrap_or(0.).to_radians().cos().min(stop_dot);
+
+                lights.push(Light {
+                    kind: LightKind::Spot {
+                        direction: light_direction(bsp, entity),
+                        stop_dot,
+                        stop_dot2,
+                    },
+                    ..light
+                });
+            }
+            "light_environment" => {
+                lights.push(Light {
+                    intensity,
+                    kind: LightKind::Sun {
+                        direction: light_direction(bsp, entity),
+                    },
+                    ..light
+                });
+
+                lights.push(Light {
+                    intensity: entity
+                        .get("_diffuse_light")
+                        .and_then(|light| parse_intensity(light))
+                        .unwrap_or(intensity),
+                    kind: LightKind::Sky,
+                    ..light
+                });
+            }
+            _ => (),
+        }
+    }
+
+    lights
+}
+
+// texture of the face in lowercase
+fn face_texture(bsp: &Bsp, face_idx: usize) -> Option<String> {
+    bsp.texinfo
+        .get(bsp.faces[face_idx].texinfo as usize)
+        .and_then(|texinfo| bsp.textures.get(texinfo.texture_index as usize))
+        .map(|texture| texture.texture_name.get_string().to_lowercase())
+}
+
+/// Offset of every face from the entity owning it.
+fn face_origins(bsp: &Bsp) -> Vec<DVec3> {
+    let mut res = vec![DVec3::ZERO; bsp.faces.len()];
+
+    bsp.entities
+        .iter()
+        .filter_map(|entity| {
+            let model = entity
+                .get("model")?
+                .strip_prefix('*')?
+                .parse::<usize>()
+                .ok()?;
+
+            Some((bsp.models.get(model)?, entity_origin(entity)))
+        })
+        .for_each(|(model, origin)| {
+            let first_face = model.first_face as usize;
+
+            res.iter_mut()
+                .skip(first_face)
+                .take(model.face_count as usize)
+                .for_each(|face_origin| *face_origin = origin);
+        });
+
+    res
+}
+
+// splits the triangle until it is small enough and emits a light at the center of every piece
+fn chop_texlight(triangle: [DVec3; 3], template: Light, lights: &mut Vec<Light>) {
+    let edges = [0, 1, 2].map(|idx| triangle[(idx + 1) % 3] - triangle[idx]);
+    let (longest, edge) = edges
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.length_squared().total_cmp(&b.1.length_squared()))
+        .unwrap();
+
+    if edge.length() > TEXLIGHT_CHOP {
+        let mid = triangle[longest] + *edge / 2.;
+        let (a, b, c) = (
+            triangle[longest],
+            triangle[(longest + 1) % 3],
+            triangle[(longest + 2) % 3],
+        );
+
+        chop_texlight([a, mid, c], template, lights);
+        chop_texlight([mid, b, c], template, lights);
+
+        return;
+    }
+
+    let area = edges[0].cross(edges[1]).length() / 2.;
+
+    if area <= 0. {
+        return;
+    }
+
+    let LightKind::Surface { normal } = template.kind else {
+        unreachable!()
+    };
+
+    lights.push(Light {
+        origin: (triangle[0] + triangle[1] + triangle[2]) / 3. + normal * SAMPLE_OFFSET,
+        intensity: template.intensity * area / PI,
+        ..template
+    });
+}
+
+fn texlight_lights(
+    bsp: &Bsp,
+    texlights: &HashMap<String, [f64; 3]>,
+    origins: &[DVec3],
+) -> Vec<Light> {
+    let mut lights = vec![];
+
+    for (face_idx, origin) in origins.iter().enumerate() {
+        let Some(intensity) = face_texture(bsp, face_idx).and_then(|name| texlights.get(&name))
+        else {
+            continue;
+        };
+
+        let vertices = bsp
+            .face_vertices(face_idx)
+            .into_iter()
+            .map(|vertex| to_dvec3(vertex) + *origin)
+            .collect::<Vec<_>>();
+
+        if vertices.len() < 3 {
+            continue;
+        }
+
+        let template = Light {
+            origin: DVec3::ZERO,
+            intensity: DVec3::from_array(*intensity),
+            fade: 1.,
+            style: 0,
+            kind: LightKind::Surface {
+                normal: to_dvec3(bsp.face_normal(face_idx).unwrap_or_default()),
+            },
+            leaf: 0,
+        };
+
+        (1..vertices.len() - 1).for_each(|idx| {
+            chop_texlight(
+                [vertices[0], vertices[idx], vertices[idx + 1]],
+                template,
+                &mut lights,
+            );
+        });
+    }
+
+    lights
+}
+
+/// Every luxel of the face in the world, lifted off the face.
+///
+/// Luxels outside of the face could end up in solid, those are pulled toward the face center.
+fn face_samples(bsp: &Bsp, face_idx: usize, origin: DVec3) -> Option<Vec<DVec3>> {
+    let info = bsp.face_lightmap_info(face_idx)?;
+    let face = &bsp.faces[face_idx];
+    let texinfo = bsp.texinfo.get(face.texinfo as usize)?;
+    let plane = bsp.planes.get(face.plane as usize)?;
+
+    let plane_normal = to_dvec3(plane.normal);
+    let normal = to_dvec3(bsp.face_normal(face_idx).unwrap_or_default());
+
+    // solves for the point on the plane with the given texture coordinates
+    let to_world =
+        DMat3::from_cols(to_dvec3(texinfo.u), to_dvec3(texinfo.v), plane_normal).transpose();
+
+    if to_world.determinant().abs() < f64::EPSILON {
+        return None;
+    }
+
+    let to_world = to_world.inverse();
+
+    let vertices = bsp.face_vertices(face_idx);
+    let center = vertices.iter().map(|v| to_dvec3(*v)).sum::<DVec3>() / vertices.len() as f64
+        + origin
+        + normal * SAMPLE_OFFSET;
+
+    let samples = (0..info.height)
+        .flat_map(|y| (0..info.width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let s = ((info.mins[0] + x as i32) * LIGHTMAP_SCALE) as f64;
+            let t = ((info.mins[1] + y as i32) * LIGHTMAP_SCALE) as f64;
+
+            let pos = to_world
+                * DVec3::new(
+                    s - texinfo.u_offset as f64,
+                    t - texinfo.v_offset as f64,
+                    plane.distance as f64,
+                )
+                + origin
+                + normal * SAMPLE_OFFSET;
+
+            (1..=4)
+                .map(|step| pos.lerp(center, step as f64 / 4.))
+                .fold(pos, |found, next| {
+                    if matches!(
+                        bsp.point_contents(HULL_POINT, to_vec3(found)),
+                        LeafContent::ContentsSolid
+                    ) {
+                        next
+                    } else {
+                        found
+                    }
+                })
+        })
+        .collect();
+
+    Some(samples)
+}
+
+fn finalize(light: DVec3, gamma: f64) -> [u8; 3] {
+    light.to_array().map(|v| {
+        ((v.max(0.) / 256.).powf(gamma) * 256.)
+            .clamp(0., 255.)
+            .round() as u8
+    })
+}
+
+/// Lights the lightmap of every face from the lights in the map.
+///
+/// Mutate the input bsp data. Returns how many lights are used. Texture lights count once per piece
+/// and `light_environment` counts as both sun and sky.
+pub fn relight(bsp: &mut Bsp, options: &RelightOptions) -> usize {
+    let mut texlights = options.texlights.clone();
+
+    bsp.entities
+        .iter()
+        .filter(|entity| {
+            entity
+                .get("classname")
+                .is_some_and(|classname| classname == "info_texlights")
+        })
+        .flat_map(|entity| entity.pairs())
+        .filter(|(key, _)| key != "classname" && key != "origin")
+        .for_each(|(key, value)| {
+            if let Some(intensity) = parse_intensity(value) {
+                texlights.insert(key.to_lowercase(), intensity.to_array());
+            }
+        });
+
+    let origins = face_origins(bsp);
+
+    let mut lights = entity_lights(bsp);
+    lights.extend(texlight_lights(bsp, &texlights, &origins));
+
+    // environment light is not scaled
+    lights
+        .iter_mut()
+        .filter(|light| !matches!(light.kind, LightKind::Sun { .. } | LightKind::Sky))
+        .for_each(|light| light.intensity *= options.direct_scale);
+
+    lights
+        .iter_mut()
+        .for_each(|light| light.leaf = bsp.leaf_at(to_vec3(light.origin)));
+
+    let pvs = bsp.pvs();
+
+    let mut lights_by_style: HashMap<u8, Vec<Light>> = HashMap::new();
+
+    lights
+        .iter()
+        .for_each(|light| lights_by_style.entry(light.style).or_default().push(*light));
+
+    let lit_faces = (0..bsp.faces.len())
+        .into_par_iter()
+        .filter_map(|face_idx| {
+            let info = bsp.face_lightmap_info(face_idx)?;
+            let samples = face_samples(bsp, face_idx, origins[face_idx])?;
+            let sample_leaves = samples
+                .iter()
+                .map(|pos| bsp.leaf_at(to_vec3(*pos)))
+                .collect::<Vec<_>>();
+            let normal = to_dvec3(bsp.face_normal(face_idx).unwrap_or_default());
+
+            // texture lights light themselves
+            let self_light = face_texture(bsp, face_idx)
+                .and_then(|name| texlights.get(&name))
+                .map(|intensity| DVec3::from_array(*intensity))
+                .unwrap_or_default();
+
+            let slots = (0..info.style_count)
+                .map(|style_slot| {
+                    let style = bsp.faces[face_idx].styles[style_slot];
+                    let lights = lights_by_style
+                        .get(&style)
+                        .map(|lights| lights.as_slice())
+                        .unwrap_or_default();
+
+                    samples
+                        .iter()
+                        .zip(&sample_leaves)
+                        .map(|(&pos, &leaf)| {
+                            let light = lights
+                                .iter()
+                                .filter(|light| light.in_pvs(&pvs, leaf))
+                                .map(|light| light.gather(bsp, pos, normal))
+                                .sum::<DVec3>();
+
+                            if style == 0 {
+                                light + self_light
+                            } else {
+                                light
+                            }
+                        })
+                        .map(|light| finalize(light, options.gamma))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+
+            Some((face_idx, slots))
+        })
+        .collect::<Vec<_>>();
+
+    lit_faces.into_iter().for_each(|(face_idx, slots)| {
+        slots
+            .into_iter()
+            .enumerate()
+            .for_each(|(style_slot, luxels)| {
+                if let Some(lightmap) = bsp.face_lightmap_mut(face_idx, style_slot) {
+                    lightmap.copy_from_slice(&luxels);
+                }
+            });
+    });
+
+    lights.len()
+}
+
+pub fn relight_bytes(bsp_bytes: &[u8], options: &RelightOptions) -> eyre::Result<Vec<u8>> {
+    let mut bsp = Bsp::from_bytes(bsp_bytes)?;
+
+    relight(&mut bsp, options);
+
+    Ok(bsp.write_to_bytes())
+}
+
+/// Relights the BSP and writes it to `out_path`.
+///
+/// `lights.rad` next to the BSP is picked up when `options.texlights` is empty.
+pub fn relight_file(
+    bsp_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    options: &RelightOptions,
+) -> eyre::Result<usize> {
+    let bsp_path = bsp_path.as_ref();
+    let mut bsp = Bsp::from_file(bsp_path)?;
+
+    let mut options = options.clone();
+
+    if options.texlights.is_empty() {
+        let rad_path = bsp_path.with_file_name("lights.rad");
+
+        if let Ok(rad) = std::fs::read_to_string(rad_path) {
+            options.texlights = parse_lights_rad(&rad);
+        }
+    }
+
+    let count = relight(&mut bsp, &options);
+
+    bsp.write_to_file(out_path.as_ref())?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn c1a3d() -> Bsp {
+        Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/c1a3d.bsp")).unwrap()
+    }
+
+    #[test]
+    fn no_lights_is_dark() {
+        let mut bsp = c1a3d();
+        bsp.entities.retain(|entity| {
+            !entity
+                .get("classname")
+                .is_some_and(|classname| classname.starts_with("light"))
+        });
+
+        let layout = bsp
+            .faces
+            .iter()
+            .map(|face| face.lightmap_offset)
+            .collect::<Vec<_>>();
+        let size = bsp.lightmap.len();
+
+        assert_eq!(relight(&mut bsp, &RelightOptions::default()), 0);
+
+        assert!(bsp.lightmap.iter().all(|luxel| *luxel == [0; 3]));
+        assert_eq!(size, bsp.lightmap.len());
+        assert_eq!(
+            layout,
+            bsp.faces
+                .iter()
+                .map(|face| face.lightmap_offset)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn spot_lights_the_floor_below() {
+        let mut bsp = c1a3d();
+
+        assert_eq!(relight(&mut bsp, &RelightOptions::default()), 4);
+
+        // the spot points down at 800 -280 776
+        let spot = DVec3::new(800., -280., 776.);
+        let hit = bsp
+            .test_line(to_vec3(spot), to_vec3(spot - DVec3::Z * 4096.))
+            .unwrap()
+            .1;
+        let floor = to_dvec3(hit);
+
+        let brightest_near_floor = (0..bsp.faces.len())
+            .filter(|&face_idx| to_dvec3(bsp.face_normal(face_idx).unwrap_or_default()).z > 0.7)
+            .filter_map(|face_idx| {
+                let samples = face_samples(&bsp, face_idx, DVec3::ZERO)?;
+                let lightmap = bsp.face_lightmap(face_idx, 0)?;
+
+                samples
+                    .iter()
+                    .zip(lightmap)
+                    .filter(|(pos, _)| pos.distance(floor) < 32.)
+                    .map(|(_, luxel)| luxel[0])
+                    .max()
+            })
+            .max()
+            .unwrap();
+
+        assert!(brightest_near_floor > 0);
+    }
+
+    #[test]
+    fn environment_close_to_hlrad() {
+        let mut bsp = Bsp::from_bytes(include_bytes!("../../../bsp/src/tests/normal.bsp")).unwrap();
+        let original = bsp.lightmap.clone();
+
+        // sun and sky
+        assert_eq!(relight(&mut bsp, &RelightOptions::default()), 2);
+
+        let mean = |lightmap: &[[u8; 3]]| {
+            lightmap
+                .iter()
+                .flatten()
+                .map(|value| *value as f64)
+                .sum::<f64>()
+                / (lightmap.len() * 3) as f64
+        };
+
+        let ratio = mean(&bsp.lightmap) / mean(&original);
+
+        assert!((0.8..1.2).contains(&ratio), "{ratio}");
+    }
+
+    #[test]
+    fn pvs_culling() {
+        let mut pvs = Pvs::new(3);
+        pvs.set(1, 2, true);
+
+        let light = Light {
+            origin: DVec3::ZERO,
+            intensity: DVec3::ONE,
+            fade: 1.,
+            style: 0,
+            kind: LightKind::Point,
+            leaf: 2,
+        };
+
+        assert!(light.in_pvs(&pvs, 1));
+        assert!(light.in_pvs(&pvs, 2));
+        assert!(!light.in_pvs(&pvs, 3));
+        // solid leaf has no visibility information
+        assert!(light.in_pvs(&pvs, 0));
+
+        let sun = Light {
+            kind: LightKind::Sky,
+            ..light
+        };
+
+        assert!(sun.in_pvs(&pvs, 3));
+
+        // falls off before reaching the luxel
+        let far = DVec3::new(0., 0., 4096.);
+        assert_eq!(light.gather(&c1a3d(), far, DVec3::NEG_Z), DVec3::ZERO);
+    }
+
+    #[test]
+    fn lights_rad() {
+        let texlights = parse_lights_rad("// comment\n~LIGHT3A 255 128 0 510\nLAB1_W1 100\n\n");
+
+        assert_eq!(texlights.get("~light3a"), Some(&[510., 256., 0.]));
+        assert_eq!(texlights.get("lab1_w1"), Some(&[100.; 3]));
+        assert_eq!(texlights.len(), 2);
+    }
+}
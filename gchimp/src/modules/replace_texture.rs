@@ -0,0 +1,94 @@
+//! Swaps textures of a compiled BSP with ones from WADs or images.
+use std::path::Path;
+
+use bsp::Bsp;
+use wad::types::{FileEntry, MipTex, Wad};
+
+use crate::{err, utils::img_stuffs::generate_mipmaps_from_path};
+
+/// Where the new texture comes from.
+pub enum TextureSource<'a> {
+    /// Texture with the same name as the one to replace in the first WAD having it.
+    Wads(&'a [Wad]),
+    /// Any image format, converted to 8bpp.
+    Image(&'a Path),
+}
+
+/// Returns the texture named `name` from the first WAD having it.
+pub fn texture_from_wads(name: &str, wads: &[Wad]) -> Option<MipTex> {
+    wads.iter()
+        .flat_map(|wad| wad.entries.iter())
+        .find_map(|entry| match &entry.file_entry {
+            FileEntry::MipTex(miptex)
+                if entry.texture_name().eq_ignore_ascii_case(name) && !miptex.is_external() =>
+            {
+                Some(miptex.clone())
+            }
+            _ => None,
+        })
+}
+
+pub fn texture_from_image(name: &str, image_path: &Path) -> eyre::Result<MipTex> {
+    let res = generate_mipmaps_from_path(image_path)?;
+    let mips = res.mips.each_ref().map(|mip| mip.as_slice());
+
+    Ok(MipTex::new(name, res.dimensions, &mips, res.palette))
+}
+
+/// Replaces the texture and keeps the same name so the map looks the same except for the texture itself.
+pub fn replace_texture(bsp: &mut Bsp, name: &str, source: TextureSource) -> eyre::Result<()> {
+    let Some(old_texture) = bsp
+        .textures
+        .iter()
+        .find(|texture| texture.texture_name.get_string().eq_ignore_ascii_case(name))
+    else {
+        return err!("Cannot find texture {} in the BSP", name);
+    };
+
+    // keeps the casing of the BSP
+    let name = old_texture.texture_name.get_string();
+
+    let texture = match source {
+        TextureSource::Wads(wads) => {
+            let Some(texture) = texture_from_wads(&name, wads) else {
+                return err!("Cannot find texture {} in the WADs", name);
+            };
+
+            texture
+        }
+        TextureSource::Image(image_path) => texture_from_image(&name, image_path)?,
+    };
+
+    let texture = MipTex {
+        texture_name: old_texture.texture_name.clone(),
+        ..texture
+    };
+
+    bsp.replace_texture(&name, texture)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replace_from_wad() {
+        let file = include_bytes!("../../../bsp/src/tests/normal.bsp");
+        let mut bsp = Bsp::from_bytes(file).unwrap();
+
+        let wads = [Wad::from_bytes(include_bytes!("../../../wad/test/wad_test2.wad")).unwrap()];
+        let white = texture_from_wads("WHITE", &wads).unwrap();
+
+        bsp.rename_texture("sky", "white").unwrap();
+        replace_texture(&mut bsp, "WHITE", TextureSource::Wads(&wads)).unwrap();
+
+        assert_eq!(bsp.textures[0].texture_name.get_string(), "white");
+        assert_eq!(bsp.textures[0].width, white.width);
+        assert!(!bsp.textures[0].is_external());
+        assert!(bsp.validate().is_empty());
+
+        assert!(replace_texture(&mut bsp, "missing", TextureSource::Wads(&[])).is_err());
+    }
+}
@@ -6,7 +6,7 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use bsp::Bsp;
+use bsp::{is_missing_texture, Bsp};
 use chrono::Local;
 use eyre::OptionExt;
 use wad::types::Wad;
@@ -440,7 +440,7 @@ fn need_external_wad(bsp: &Bsp) -> HashSet<String> {
         // 0 offset means external wad
         let texture = &bsp.textures[texindex as usize];
 
-        if texture.mip_offsets[0] == 0 {
+        if texture.mip_offsets[0] == 0 && !is_missing_texture(texture) {
             external_textures.insert(texture.texture_name.get_string_standard());
         }
     }
@@ -4,7 +4,7 @@ use std::{
     str::from_utf8,
 };
 
-use bsp::Bsp;
+use bsp::{is_missing_texture, Bsp};
 use image::RgbaImage;
 use rayon::prelude::*;
 
@@ -51,7 +51,11 @@ impl Waddy {
         let mut res = Self::new();
 
         let bsp = Bsp::from_file(path)?;
-        let textures = bsp.textures;
+        let textures = bsp
+            .textures
+            .into_iter()
+            .filter(|texture| !is_missing_texture(texture))
+            .collect::<Vec<_>>();
 
         // TODO maybe one day I will change this at wad write level
         res.wad.header.num_dirs = textures.len() as i32;
@@ -0,0 +1,20 @@
+use glam::DVec3;
+
+use super::misc::parse_triplet;
+
+pub fn to_dvec3(v: bsp::Vec3) -> DVec3 {
+    DVec3::from_array(v.to_array().map(|e| e as f64))
+}
+
+pub fn to_vec3(v: DVec3) -> bsp::Vec3 {
+    bsp::Vec3::from_array(v.to_array().map(|e| e as f32))
+}
+
+/// The `origin` key of the entity, zero if it is missing or broken.
+pub fn entity_origin(entity: &bsp::Entity) -> DVec3 {
+    entity
+        .get("origin")
+        .and_then(|origin| parse_triplet(origin).ok())
+        .map(DVec3::from_array)
+        .unwrap_or_default()
+}
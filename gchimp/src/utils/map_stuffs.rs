@@ -439,6 +439,7 @@ pub fn brush_from_mins_maxs(mins: &[f64], maxs: &[f64], texture: &str) -> Brush
 
     Brush {
         planes: vec![left, back, down, up, front, right],
+        ..Default::default()
     }
 }
 
@@ -787,6 +788,7 @@ mod test {
         map.entities.push(Entity {
             attributes: Attributes::new(),
             brushes: vec![brush].into(),
+            ..Default::default()
         });
 
         map.write(path.replace("fuck.map", "fuck2.map")).unwrap();
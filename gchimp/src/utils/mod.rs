@@ -1,3 +1,4 @@
+pub mod bsp_stuffs;
 pub mod constants;
 pub mod dem_stuffs;
 pub mod img_stuffs;
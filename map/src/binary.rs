@@ -0,0 +1,237 @@
+//! Pieces shared by the RMF and JMF parsers and writers.
+use byte_writer::ByteWriter;
+use glam::DVec3;
+use nom::{
+    bytes::complete::take,
+    combinator::map,
+    number::complete::{le_f32, le_u8},
+    sequence::tuple,
+    IResult as _IResult,
+};
+
+use crate::{Attributes, Brush, Entity};
+
+pub(crate) type IResult<'a, T> = _IResult<&'a [u8], T>;
+
+// Text in these files is in the code page of the machine that saved it.
+// Every byte becomes the char of the same value so writing it back gives the same bytes.
+pub(crate) fn bytes_to_string(i: &[u8]) -> String {
+    i.iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as char)
+        .collect()
+}
+
+pub(crate) fn string_to_bytes(s: &str) -> Vec<u8> {
+    s.chars()
+        .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+        .collect()
+}
+
+/// `char[len]` padded with nulls.
+pub(crate) fn fixed_string(len: usize) -> impl FnMut(&[u8]) -> IResult<'_, String> {
+    move |i| map(take(len), bytes_to_string)(i)
+}
+
+pub(crate) fn vector(i: &[u8]) -> IResult<'_, DVec3> {
+    map(tuple((le_f32, le_f32, le_f32)), |(x, y, z)| {
+        DVec3::new(x as f64, y as f64, z as f64)
+    })(i)
+}
+
+pub(crate) fn color(i: &[u8]) -> IResult<'_, [u8; 3]> {
+    map(tuple((le_u8, le_u8, le_u8)), |(r, g, b)| [r, g, b])(i)
+}
+
+pub(crate) fn append_fixed_string(
+    writer: &mut ByteWriter,
+    s: &str,
+    len: usize,
+) -> eyre::Result<()> {
+    let bytes = string_to_bytes(s);
+
+    // one more for the null
+    if bytes.len() >= len {
+        return Err(eyre::eyre!(
+            "\"{}\" is longer than {} characters",
+            s,
+            len - 1
+        ));
+    }
+
+    writer.append_u8_slice(&bytes);
+    writer.append_u8_slice(&vec![0; len - bytes.len()]);
+
+    Ok(())
+}
+
+pub(crate) fn append_vector(writer: &mut ByteWriter, v: DVec3) {
+    writer.append_f32(v.x as f32);
+    writer.append_f32(v.y as f32);
+    writer.append_f32(v.z as f32);
+}
+
+/// Value of the `origin` key like the editors write it.
+pub(crate) fn origin_string(origin: DVec3) -> String {
+    format!(
+        "{} {} {}",
+        origin.x as f32, origin.y as f32, origin.z as f32
+    )
+}
+
+pub(crate) fn parse_vector_string(s: &str) -> Option<DVec3> {
+    let values = s
+        .split_whitespace()
+        .map(|n| n.parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    (values.len() == 3).then(|| DVec3::from_slice(&values))
+}
+
+/// Spawnflags are not a key-value pair in the binary formats.
+pub(crate) fn spawnflags(entity: &Entity) -> i32 {
+    entity
+        .attributes
+        .get("spawnflags")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Keys that have their own field in the binary formats.
+pub(crate) fn is_special_key(key: &str, is_point_entity: bool) -> bool {
+    key == "classname" || key == "spawnflags" || (is_point_entity && key == "origin")
+}
+
+/// Makes every plane face away from the middle of the brush.
+///
+/// The middle is from the face vertices, or the plane points when there are no vertices.
+/// The editors do not care about the winding of the plane points but the compilers do.
+pub(crate) fn orient_planes(brush: &mut Brush, vertices: &[DVec3]) {
+    let center = if vertices.is_empty() {
+        brush
+            .planes
+            .iter()
+            .flat_map(|plane| [plane.p1, plane.p2, plane.p3])
+            .sum::<DVec3>()
+            / (brush.planes.len() * 3).max(1) as f64
+    } else {
+        vertices.iter().sum::<DVec3>() / vertices.len() as f64
+    };
+
+    for plane in &mut brush.planes {
+        if plane.normal().dot(plane.p1 - center) < 0. {
+            std::mem::swap(&mut plane.p1, &mut plane.p3);
+        }
+    }
+}
+
+/// Three points on the plane from the polygon of the face.
+///
+/// Falls back to the plane equation when the polygon is degenerate.
+pub(crate) fn plane_points(vertices: &[DVec3], normal: DVec3, dist: f64) -> [DVec3; 3] {
+    let mut best = None;
+    let mut best_area = 1e-3;
+
+    for (idx, &p2) in vertices.iter().enumerate() {
+        let p1 = vertices[(idx + vertices.len() - 1) % vertices.len()];
+        let p3 = vertices[(idx + 1) % vertices.len()];
+        let area = (p1 - p2).cross(p3 - p2).length();
+
+        if area > best_area {
+            best_area = area;
+            best = Some([p1, p2, p3]);
+        }
+    }
+
+    let [p1, p2, p3] = best.unwrap_or_else(|| {
+        let origin = normal * dist;
+        let tangent = normal.any_orthonormal_vector();
+
+        [
+            origin + tangent * 64.,
+            origin,
+            origin + normal.cross(tangent) * 64.,
+        ]
+    });
+
+    if (p1 - p2).cross(p3 - p2).dot(normal) < 0. {
+        [p3, p2, p1]
+    } else {
+        [p1, p2, p3]
+    }
+}
+
+pub(crate) struct PathNode {
+    pub id: i32,
+    pub position: DVec3,
+    pub name_override: String,
+    pub angles: DVec3,
+    /// "Fire on pass", JMF only.
+    pub message: String,
+    pub attributes: Attributes,
+}
+
+pub(crate) const PATH_CIRCULAR: i32 = 1;
+
+/// Turns a path into the chain of entities the editors export it as.
+///
+/// Nodes are named `name` followed by the two digit id of the node unless they have their own name.
+pub(crate) fn expand_path(
+    name: &str,
+    classname: &str,
+    path_type: i32,
+    nodes: Vec<PathNode>,
+) -> Vec<Entity> {
+    let names = nodes
+        .iter()
+        .map(|node| {
+            if node.name_override.is_empty() {
+                format!("{}{:02}", name, node.id)
+            } else {
+                node.name_override.clone()
+            }
+        })
+        .collect::<Vec<_>>();
+
+    nodes
+        .into_iter()
+        .enumerate()
+        .map(|(idx, node)| {
+            let mut attributes = Attributes::new();
+
+            attributes.push("classname", classname);
+            attributes.push("targetname", &names[idx]);
+
+            let next = if idx + 1 < names.len() {
+                Some(&names[idx + 1])
+            } else if path_type == PATH_CIRCULAR {
+                names.first()
+            } else {
+                None
+            };
+
+            if let Some(next) = next {
+                attributes.push("target", next);
+            }
+
+            if node.angles != DVec3::ZERO {
+                attributes.push("angles", origin_string(node.angles));
+            }
+
+            if !node.message.is_empty() {
+                attributes.push("message", node.message);
+            }
+
+            for (key, value) in node.attributes {
+                attributes.push(key, value);
+            }
+
+            attributes.push("origin", origin_string(node.position));
+
+            Entity {
+                attributes,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
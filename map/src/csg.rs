@@ -0,0 +1,157 @@
+//! Cutting and joining brushes.
+//!
+//! Texture axes are in world space, so a plane keeps its texture alignment wherever it ends up. New faces
+//! take the texture of the plane that made them.
+use glam::DVec3;
+
+use crate::{Brush, BrushPlane};
+
+const EPSILON: f64 = 1e-4;
+
+impl BrushPlane {
+    /// Same plane facing the other way, with the same texture.
+    pub fn flipped(&self) -> Self {
+        Self {
+            p1: self.p3,
+            p3: self.p1,
+            ..self.clone()
+        }
+    }
+
+    /// Plane moved along its normal, with the same texture alignment.
+    pub fn offset(&self, distance: f64) -> Self {
+        let offset = self.normal() * distance;
+
+        Self {
+            p1: self.p1 + offset,
+            p2: self.p2 + offset,
+            p3: self.p3 + offset,
+            ..self.clone()
+        }
+    }
+}
+
+impl Brush {
+    /// Brush without the planes that are not on it, or `None` when nothing is left.
+    fn trimmed(mut self) -> Option<Self> {
+        let windings = self.windings();
+        let mut windings = windings.iter();
+
+        self.planes
+            .retain(|_| windings.next().is_some_and(|w| w.len() >= 3));
+
+        (self.planes.len() >= 4 && self.volume() > EPSILON).then_some(self)
+    }
+
+    /// Parts of the brush behind and in front of the plane.
+    ///
+    /// The cut face of the back part has the texture of the plane and the front part has it flipped.
+    pub fn split(&self, plane: &BrushPlane) -> (Option<Self>, Option<Self>) {
+        let normal = plane.normal();
+        let dist = plane.distance();
+
+        if normal == DVec3::ZERO {
+            return (Some(self.clone()), None);
+        }
+
+        let vertices = self.vertices();
+
+        if vertices.iter().all(|v| v.dot(normal) - dist <= EPSILON) {
+            return (Some(self.clone()), None);
+        }
+
+        if vertices.iter().all(|v| v.dot(normal) - dist >= -EPSILON) {
+            return (None, Some(self.clone()));
+        }
+
+        let mut back = self.clone();
+        back.planes.push(plane.clone());
+
+        let mut front = self.clone();
+        front.planes.push(plane.flipped());
+
+        (back.trimmed(), front.trimmed())
+    }
+
+    /// Part of the brush behind the plane.
+    pub fn clip(&self, plane: &BrushPlane) -> Option<Self> {
+        self.split(plane).0
+    }
+
+    /// Brushes left after cutting out the other brush, like carve in the editors.
+    ///
+    /// Returns the brush as is when the two do not overlap, and nothing when the other brush covers it.
+    pub fn subtract(&self, other: &Brush) -> Vec<Self> {
+        let mut res = vec![];
+        let mut inside = self.clone();
+
+        for plane in &other.planes {
+            match inside.split(plane) {
+                (Some(back), front) => {
+                    res.extend(front);
+                    inside = back;
+                }
+                // completely outside of the other brush
+                (None, _) => return vec![self.clone()],
+            }
+        }
+
+        res
+    }
+
+    /// Walls of the brush `thickness` units thick, like hollow in the editors.
+    ///
+    /// The walls do not overlap and the inner faces have the textures of the outer faces.
+    /// Returns `None` when the brush is too thin to have anything inside.
+    pub fn hollow(&self, thickness: f64) -> Option<Vec<Self>> {
+        let inner = Self {
+            planes: self
+                .planes
+                .iter()
+                .map(|plane| plane.offset(-thickness))
+                .collect(),
+            ..self.clone()
+        }
+        .trimmed()?;
+
+        Some(self.subtract(&inner))
+    }
+
+    /// One brush covering both brushes when together they make a convex shape.
+    ///
+    /// Faces come from the planes of the two brushes so textures stay where they are. The first brush wins
+    /// when both have the same plane.
+    pub fn merge(&self, other: &Brush) -> Option<Self> {
+        let vertices = [self.vertices(), other.vertices()].concat();
+
+        let planes = self
+            .planes
+            .iter()
+            .chain(&other.planes)
+            .filter(|plane| {
+                let normal = plane.normal();
+                let dist = plane.distance();
+
+                normal != DVec3::ZERO && vertices.iter().all(|v| v.dot(normal) - dist <= EPSILON)
+            })
+            .cloned()
+            .collect();
+
+        let merged = Self {
+            planes,
+            ..self.clone()
+        }
+        .trimmed()?;
+
+        let overlap = Self {
+            planes: [self.planes.as_slice(), other.planes.as_slice()].concat(),
+            ..Default::default()
+        }
+        .volume();
+
+        let union = self.volume() + other.volume() - overlap;
+
+        // anything more would fill a gap between the brushes
+        ((merged.volume() - union).abs() <= EPSILON * union.max(1.)).then_some(merged)
+    }
+}
@@ -0,0 +1,48 @@
+//! Editor state from RMF and JMF that text maps do not have.
+//!
+//! Text maps leave all of these empty. When written as text, everything here is dropped.
+use glam::DVec3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visgroup {
+    pub id: i32,
+    pub name: String,
+    pub color: [u8; 3],
+    pub visible: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub id: i32,
+    /// Group containing this group.
+    pub parent: Option<i32>,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub eye: DVec3,
+    pub look: DVec3,
+}
+
+/// Editor state of the whole map.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditorData {
+    pub visgroups: Vec<Visgroup>,
+    pub groups: Vec<Group>,
+    pub cameras: Vec<Camera>,
+    /// Index into `cameras`.
+    pub active_camera: Option<usize>,
+    /// Mins and maxs, only JMF has it.
+    pub cordon: Option<(DVec3, DVec3)>,
+}
+
+/// Editor state of an entity or a brush.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditorInfo {
+    /// Ids of [`Visgroup`]. RMF only keeps the first one.
+    pub visgroups: Vec<i32>,
+    /// Id of the innermost [`Group`].
+    pub group: Option<i32>,
+    pub color: Option<[u8; 3]>,
+}
@@ -0,0 +1,278 @@
+//! Polygons and other shapes of brushes made from their planes.
+//!
+//! A brush is the space behind all of its planes. Each face is found by clipping a huge polygon on the plane
+//! by every other plane, the same way the compilers do it.
+use std::fmt;
+
+use glam::{DVec2, DVec3, Vec4Swizzles};
+
+use crate::{Brush, BrushPlane};
+
+const EPSILON: f64 = 1e-4;
+// bigger than any map
+const WORLD_SIZE: f64 = 131072.;
+// vertices this far out come from planes that do not close the brush
+const MAX_COORDINATE: f64 = WORLD_SIZE / 2.;
+
+/// Why a brush cannot be compiled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushError {
+    /// Fewer than four planes.
+    TooFewPlanes,
+    /// The three points of the plane at this index are on a line.
+    DegeneratePlane(usize),
+    /// The plane at this index does not touch the brush, like a duplicate plane.
+    UnusedPlane(usize),
+    /// The planes do not close the brush, so it goes on forever.
+    Unbounded,
+    /// Some edges only belong to one face.
+    NotClosed,
+    /// The brush has no volume.
+    Empty,
+}
+
+impl fmt::Display for BrushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooFewPlanes => write!(f, "brush has fewer than 4 planes"),
+            Self::DegeneratePlane(idx) => write!(f, "plane {} has its points on a line", idx),
+            Self::UnusedPlane(idx) => write!(f, "plane {} is not on the brush", idx),
+            Self::Unbounded => write!(f, "brush is not closed by its planes"),
+            Self::NotClosed => write!(f, "brush has edges with only one face"),
+            Self::Empty => write!(f, "brush has no volume"),
+        }
+    }
+}
+
+impl std::error::Error for BrushError {}
+
+/// Base polygon on the plane covering the whole world, same as `BaseWindingForPlane`.
+pub fn base_winding(normal: DVec3, dist: f64) -> Vec<DVec3> {
+    let up = if normal.z.abs() > normal.x.abs().max(normal.y.abs()) {
+        DVec3::X
+    } else {
+        DVec3::Z
+    };
+
+    let up = (up - normal * up.dot(normal)).normalize() * WORLD_SIZE;
+    let right = up.cross(normal);
+    let origin = normal * dist;
+
+    vec![
+        origin - right + up,
+        origin + right + up,
+        origin + right - up,
+        origin - right - up,
+    ]
+}
+
+/// Keeps the part of the winding behind the plane.
+///
+/// Points closer to the plane than `epsilon` are kept as they are.
+pub fn clip_winding(winding: &[DVec3], normal: DVec3, dist: f64, epsilon: f64) -> Vec<DVec3> {
+    let mut res = vec![];
+
+    for (idx, &p1) in winding.iter().enumerate() {
+        let p2 = winding[(idx + 1) % winding.len()];
+        let d1 = p1.dot(normal) - dist;
+        let d2 = p2.dot(normal) - dist;
+
+        if d1 <= epsilon {
+            res.push(p1);
+        }
+
+        if (d1 > epsilon && d2 < -epsilon) || (d1 < -epsilon && d2 > epsilon) {
+            res.push(p1 + (p2 - p1) * (d1 / (d1 - d2)));
+        }
+    }
+
+    res
+}
+
+impl BrushPlane {
+    /// Distance of the plane from the world origin along [`BrushPlane::normal`].
+    pub fn distance(&self) -> f64 {
+        self.p1.dot(self.normal())
+    }
+
+    /// Texture coordinates of a point on the plane, in pixels.
+    ///
+    /// Divide by the texture size to get coordinates from 0 to 1, with V going down the image.
+    pub fn uv(&self, point: DVec3) -> DVec2 {
+        DVec2::new(
+            point.dot(self.u.xyz()) / self.u_scale + self.u.w,
+            point.dot(self.v.xyz()) / self.v_scale + self.v.w,
+        )
+    }
+}
+
+impl Brush {
+    /// Polygon of every plane, in the same order as the planes.
+    ///
+    /// Points go clockwise when looking at the face from outside, like in the compilers.
+    /// Planes that do not touch the brush have an empty polygon.
+    pub fn windings(&self) -> Vec<Vec<DVec3>> {
+        let planes = self
+            .planes
+            .iter()
+            .map(|plane| (plane.normal(), plane.distance()))
+            .collect::<Vec<_>>();
+
+        planes
+            .iter()
+            .enumerate()
+            .map(|(idx, &(normal, dist))| {
+                if normal == DVec3::ZERO {
+                    return vec![];
+                }
+
+                let mut winding = base_winding(normal, dist);
+
+                for (other_idx, &(other_normal, other_dist)) in planes.iter().enumerate() {
+                    if other_idx == idx || other_normal == DVec3::ZERO {
+                        continue;
+                    }
+
+                    // the same plane twice only keeps the first one
+                    if other_normal.abs_diff_eq(normal, EPSILON)
+                        && (other_dist - dist).abs() < EPSILON
+                    {
+                        if other_idx < idx {
+                            return vec![];
+                        }
+
+                        continue;
+                    }
+
+                    winding = clip_winding(&winding, other_normal, other_dist, EPSILON);
+
+                    if winding.len() < 3 {
+                        return vec![];
+                    }
+                }
+
+                winding
+            })
+            .collect()
+    }
+
+    /// Corners of the brush without duplicates.
+    pub fn vertices(&self) -> Vec<DVec3> {
+        let mut res: Vec<DVec3> = vec![];
+
+        for vertex in self.windings().into_iter().flatten() {
+            if !res.iter().any(|other| other.abs_diff_eq(vertex, EPSILON)) {
+                res.push(vertex);
+            }
+        }
+
+        res
+    }
+
+    /// Mins and maxs, `None` when no planes touch the brush.
+    pub fn bounds(&self) -> Option<(DVec3, DVec3)> {
+        let vertices = self.vertices();
+        let first = *vertices.first()?;
+
+        Some(
+            vertices
+                .iter()
+                .fold((first, first), |(mins, maxs), &vertex| {
+                    (mins.min(vertex), maxs.max(vertex))
+                }),
+        )
+    }
+
+    pub fn volume(&self) -> f64 {
+        let windings = self.windings();
+        let vertices = windings.iter().flatten().collect::<Vec<_>>();
+
+        if vertices.is_empty() {
+            return 0.;
+        }
+
+        // the brush is convex so the average of the corners is inside
+        let center = vertices.iter().copied().sum::<DVec3>() / vertices.len() as f64;
+
+        windings
+            .iter()
+            .filter(|winding| winding.len() >= 3)
+            .flat_map(|winding| {
+                (1..winding.len() - 1).map(|idx| {
+                    (winding[0] - center)
+                        .dot((winding[idx] - center).cross(winding[idx + 1] - center))
+                        .abs()
+                        / 6.
+                })
+            })
+            .sum()
+    }
+
+    /// Texture coordinates in pixels of every point of [`Brush::windings`].
+    pub fn uvs(&self) -> Vec<Vec<DVec2>> {
+        self.windings()
+            .iter()
+            .zip(&self.planes)
+            .map(|(winding, plane)| winding.iter().map(|&point| plane.uv(point)).collect())
+            .collect()
+    }
+
+    /// Checks that the brush is a closed convex solid where every plane is a face.
+    ///
+    /// A brush made of planes is always convex, so this is about planes that do not make a proper solid.
+    pub fn validate(&self) -> Result<(), BrushError> {
+        if self.planes.len() < 4 {
+            return Err(BrushError::TooFewPlanes);
+        }
+
+        if let Some(idx) = self
+            .planes
+            .iter()
+            .position(|plane| plane.normal() == DVec3::ZERO)
+        {
+            return Err(BrushError::DegeneratePlane(idx));
+        }
+
+        let windings = self.windings();
+
+        if windings
+            .iter()
+            .flatten()
+            .any(|vertex| vertex.abs().max_element() > MAX_COORDINATE)
+        {
+            return Err(BrushError::Unbounded);
+        }
+
+        if let Some(idx) = windings.iter().position(|winding| winding.is_empty()) {
+            return Err(BrushError::UnusedPlane(idx));
+        }
+
+        // every edge has the same edge going the other way on the next face
+        let edges = windings
+            .iter()
+            .flat_map(|winding| {
+                (0..winding.len()).map(|idx| (winding[idx], winding[(idx + 1) % winding.len()]))
+            })
+            .collect::<Vec<_>>();
+
+        let is_closed = edges.iter().all(|&(from, to)| {
+            edges.iter().any(|&(other_from, other_to)| {
+                other_from.abs_diff_eq(to, EPSILON) && other_to.abs_diff_eq(from, EPSILON)
+            })
+        });
+
+        if !is_closed {
+            return Err(BrushError::NotClosed);
+        }
+
+        if self.volume() < EPSILON {
+            return Err(BrushError::Empty);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+}
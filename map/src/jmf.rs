@@ -0,0 +1,566 @@
+//! J.A.C.K. `.jmf`, versions 121 and 122.
+//!
+//! Unlike RMF, groups have ids and objects can be in more than one visgroup.
+//! Faces store their polygon and plane equation instead of plane points so the points are made from the polygon.
+//! Curved patches are not supported.
+use byte_writer::ByteWriter;
+use eyre::eyre;
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::{fail, map},
+    multi::{count, many0},
+    number::complete::{le_f32, le_i16, le_i32, le_u8},
+    sequence::tuple,
+};
+
+use crate::{
+    binary::{
+        append_fixed_string, append_vector, bytes_to_string, color, expand_path, fixed_string,
+        is_special_key, orient_planes, origin_string, parse_vector_string, plane_points,
+        spawnflags, string_to_bytes, vector, IResult, PathNode,
+    },
+    editor::{Camera, EditorData, EditorInfo, Group, Visgroup},
+    Attributes, Brush, BrushPlane, Entity, Map, MapFormat,
+};
+
+const JMF_VERSION: i32 = 121;
+const JMF_BACKGROUND_VERSION: i32 = 122;
+const BACKGROUND_IMAGE_COUNT: usize = 3;
+// targetname, target and the like, they are in the key-value pairs as well
+const SPECIAL_STRING_COUNT: usize = 13;
+const CAMERA_ACTIVE: i32 = 1 << 1;
+const DEFAULT_COLOR: [u8; 3] = [0, 100, 220];
+
+pub(crate) fn is_jmf(i: &[u8]) -> bool {
+    i.starts_with(b"JHMF")
+}
+
+/// String with its length in front, the length counts the null.
+fn nstring(i: &[u8]) -> IResult<'_, String> {
+    let (i, len) = le_i32(i)?;
+
+    map(take(len.max(0) as usize), bytes_to_string)(i)
+}
+
+fn rgba(i: &[u8]) -> IResult<'_, [u8; 3]> {
+    map(tuple((color, le_u8)), |(color, _)| color)(i)
+}
+
+fn group_id(id: i32) -> Option<i32> {
+    (id != 0).then_some(id)
+}
+
+fn visgroup_ids(i: &[u8]) -> IResult<'_, Vec<i32>> {
+    let (i, visgroup_count) = le_i32(i)?;
+
+    count(le_i32, visgroup_count.max(0) as usize)(i)
+}
+
+fn parse_attributes(i: &[u8]) -> IResult<'_, Attributes> {
+    let (i, attribute_count) = le_i32(i)?;
+
+    map(
+        count(tuple((nstring, nstring)), attribute_count.max(0) as usize),
+        |pairs| pairs.into_iter().collect(),
+    )(i)
+}
+
+fn parse_background_image(i: &[u8]) -> IResult<'_, ()> {
+    map(
+        tuple((nstring, take(8usize), count(le_i32, 5), take(4usize))),
+        |_| (),
+    )(i)
+}
+
+fn parse_group(i: &[u8]) -> IResult<'_, Group> {
+    map(
+        tuple((le_i32, le_i32, le_i32, le_i32, rgba)),
+        |(id, parent, _, _, color)| Group {
+            id,
+            parent: group_id(parent),
+            color,
+        },
+    )(i)
+}
+
+fn parse_visgroup(i: &[u8]) -> IResult<'_, Visgroup> {
+    map(
+        tuple((nstring, le_i32, rgba, le_u8)),
+        |(name, id, color, visible)| Visgroup {
+            id,
+            name,
+            color,
+            visible: visible != 0,
+        },
+    )(i)
+}
+
+fn parse_camera(i: &[u8]) -> IResult<'_, (Camera, bool)> {
+    map(
+        tuple((vector, vector, le_i32, rgba)),
+        |(eye, look, flags, _)| (Camera { eye, look }, flags & CAMERA_ACTIVE != 0),
+    )(i)
+}
+
+fn parse_path(i: &[u8]) -> IResult<'_, Vec<Entity>> {
+    let (i, (classname, name, path_type, _, _, node_count)) =
+        tuple((nstring, nstring, le_i32, take(4usize), rgba, le_i32))(i)?;
+
+    let (i, nodes) = count(
+        tuple((
+            nstring,
+            nstring,
+            vector,
+            vector,
+            le_i32,
+            rgba,
+            parse_attributes,
+        )),
+        node_count.max(0) as usize,
+    )(i)?;
+
+    let nodes = nodes
+        .into_iter()
+        .enumerate()
+        .map(
+            |(idx, (name_override, message, position, angles, _, _, attributes))| PathNode {
+                id: idx as i32 + 1,
+                position,
+                name_override,
+                angles,
+                message,
+                attributes,
+            },
+        )
+        .collect();
+
+    Ok((i, expand_path(&name, &classname, path_type, nodes)))
+}
+
+fn parse_face(i: &[u8]) -> IResult<'_, BrushPlane> {
+    let (i, (_, vertex_count, u, x_shift, v, y_shift, u_scale, v_scale, rotation, _)) = tuple((
+        le_i32,
+        le_i32,
+        vector,
+        le_f32,
+        vector,
+        le_f32,
+        le_f32,
+        le_f32,
+        le_f32,
+        take(16usize),
+    ))(i)?;
+
+    let (i, (texture_name, normal, dist, _)) =
+        tuple((fixed_string(64), vector, le_f32, le_i32))(i)?;
+
+    let (i, vertices) = count(
+        map(tuple((vector, le_f32, le_f32, le_i32)), |(vertex, ..)| {
+            vertex
+        }),
+        vertex_count.max(0) as usize,
+    )(i)?;
+
+    let [p1, p2, p3] = plane_points(&vertices, normal, dist as f64);
+
+    Ok((
+        i,
+        BrushPlane {
+            p1,
+            p2,
+            p3,
+            texture_name,
+            u: u.extend(x_shift as f64),
+            v: v.extend(y_shift as f64),
+            rotation: rotation as f64,
+            u_scale: u_scale as f64,
+            v_scale: v_scale as f64,
+        },
+    ))
+}
+
+fn parse_solid(i: &[u8]) -> IResult<'_, Brush> {
+    let (i, (patch_count, _, visgroups, color, face_count)) =
+        tuple((le_i32, le_i32, visgroup_ids, rgba, le_i32))(i)?;
+
+    if patch_count > 0 {
+        return fail(i);
+    }
+
+    let (i, planes) = count(parse_face, face_count.max(0) as usize)(i)?;
+
+    let mut brush = Brush {
+        planes,
+        editor: EditorInfo {
+            visgroups,
+            group: None,
+            color: Some(color),
+        },
+        ..Default::default()
+    };
+
+    // points from the polygon already face the right way, this only catches broken normals
+    orient_planes(&mut brush, &[]);
+
+    Ok((i, brush))
+}
+
+fn parse_entity(i: &[u8]) -> IResult<'_, Entity> {
+    let (i, (classname, origin, _, group, _, color)) =
+        tuple((nstring, vector, le_i32, le_i32, le_i32, rgba))(i)?;
+
+    let (i, _) = count(nstring, SPECIAL_STRING_COUNT)(i)?;
+
+    // the rest of the entity properties are in the key-value pairs
+    let (i, (spawnflags, _, _, _, _, _, _, _, _, _, _, _, _)) = tuple((
+        le_i32,
+        vector,
+        le_i32,
+        rgba,
+        le_i32,
+        le_i32,
+        le_i16,
+        le_i16,
+        le_i32,
+        le_f32,
+        le_f32,
+        le_f32,
+        take(28usize),
+    ))(i)?;
+
+    let (i, (kvs, visgroups, brush_count)) = tuple((parse_attributes, visgroup_ids, le_i32))(i)?;
+    let (i, brushes) = count(parse_solid, brush_count.max(0) as usize)(i)?;
+
+    let mut attributes = Attributes::new();
+
+    attributes.push("classname", classname);
+
+    for (key, value) in kvs {
+        attributes.push(key, value);
+    }
+
+    if spawnflags != 0 {
+        attributes.insert("spawnflags", spawnflags.to_string());
+    }
+
+    if brushes.is_empty() && !attributes.contains_key("origin") {
+        attributes.push("origin", origin_string(origin));
+    }
+
+    Ok((
+        i,
+        Entity {
+            attributes,
+            brushes: (!brushes.is_empty()).then_some(brushes),
+            editor: EditorInfo {
+                visgroups,
+                group: group_id(group),
+                color: Some(color),
+            },
+            ..Default::default()
+        },
+    ))
+}
+
+fn parse_jmf(i: &[u8]) -> IResult<'_, Map> {
+    let (i, (_, version, export_count)) = tuple((tag("JHMF"), le_i32, le_i32))(i)?;
+
+    if version != JMF_VERSION && version != JMF_BACKGROUND_VERSION {
+        return fail(i);
+    }
+
+    let (i, _) = count(nstring, export_count.max(0) as usize)(i)?;
+
+    let (i, _) = if version == JMF_BACKGROUND_VERSION {
+        count(parse_background_image, BACKGROUND_IMAGE_COUNT)(i)?
+    } else {
+        (i, vec![])
+    };
+
+    let (i, group_count) = le_i32(i)?;
+    let (i, groups) = count(parse_group, group_count.max(0) as usize)(i)?;
+
+    let (i, visgroup_count) = le_i32(i)?;
+    let (i, visgroups) = count(parse_visgroup, visgroup_count.max(0) as usize)(i)?;
+
+    let (i, (cordon_mins, cordon_maxs, camera_count)) = tuple((vector, vector, le_i32))(i)?;
+    let (i, cameras) = count(parse_camera, camera_count.max(0) as usize)(i)?;
+
+    let (i, path_count) = le_i32(i)?;
+    let (i, paths) = count(parse_path, path_count.max(0) as usize)(i)?;
+
+    let (i, mut entities) = many0(parse_entity)(i)?;
+
+    if !i.is_empty() {
+        return fail(i);
+    }
+
+    // worldspawn goes first like in .map
+    if let Some(world_idx) = entities.iter().position(|entity| {
+        entity
+            .attributes
+            .get("classname")
+            .is_some_and(|classname| classname == "worldspawn")
+    }) {
+        let world = entities.remove(world_idx);
+        entities.insert(0, world);
+    }
+
+    entities.extend(paths.into_iter().flatten());
+
+    Ok((
+        i,
+        Map {
+            format: MapFormat::Valve220,
+            entities,
+            editor: EditorData {
+                visgroups,
+                groups,
+                active_camera: cameras.iter().position(|(_, active)| *active),
+                cameras: cameras.into_iter().map(|(camera, _)| camera).collect(),
+                cordon: (cordon_mins != cordon_maxs).then_some((cordon_mins, cordon_maxs)),
+            },
+            ..Default::default()
+        },
+    ))
+}
+
+pub(crate) fn read_jmf(i: &[u8]) -> eyre::Result<Map> {
+    match parse_jmf(i) {
+        Ok((_, res)) => Ok(res),
+        Err(err) => Err(eyre!("Cannot parse JMF: {}", err.to_string())),
+    }
+}
+
+fn append_nstring(writer: &mut ByteWriter, s: &str) {
+    let bytes = string_to_bytes(s);
+
+    writer.append_i32(bytes.len() as i32 + 1);
+    writer.append_u8_slice(&bytes);
+    writer.append_u8(0);
+}
+
+fn append_rgba(writer: &mut ByteWriter, color: [u8; 3]) {
+    writer.append_u8_slice(&color);
+    writer.append_u8(255);
+}
+
+fn append_attributes<'a>(
+    writer: &mut ByteWriter,
+    attributes: impl Iterator<Item = (&'a String, &'a String)>,
+) {
+    let attributes = attributes.collect::<Vec<_>>();
+
+    writer.append_i32(attributes.len() as i32);
+
+    for (key, value) in attributes {
+        append_nstring(writer, key);
+        append_nstring(writer, value);
+    }
+}
+
+fn append_visgroup_ids(writer: &mut ByteWriter, editor: &EditorInfo) {
+    writer.append_i32(editor.visgroups.len() as i32);
+    editor
+        .visgroups
+        .iter()
+        .for_each(|&id| writer.append_i32(id));
+}
+
+fn append_solid(writer: &mut ByteWriter, brush: &Brush) -> eyre::Result<()> {
+    // no patches and no flags
+    writer.append_i32(0);
+    writer.append_i32(0);
+    append_visgroup_ids(writer, &brush.editor);
+    append_rgba(writer, brush.editor.color.unwrap_or(DEFAULT_COLOR));
+
+    let windings = brush.windings();
+
+    writer.append_i32(brush.planes.len() as i32);
+
+    for (plane, winding) in brush.planes.iter().zip(windings) {
+        let normal = plane.normal();
+
+        writer.append_i32(0);
+        writer.append_i32(winding.len() as i32);
+        append_vector(writer, plane.u.truncate());
+        writer.append_f32(plane.u.w as f32);
+        append_vector(writer, plane.v.truncate());
+        writer.append_f32(plane.v.w as f32);
+        writer.append_f32(plane.u_scale as f32);
+        writer.append_f32(plane.v_scale as f32);
+        writer.append_f32(plane.rotation as f32);
+        writer.append_u8_slice(&[0; 16]);
+        append_fixed_string(writer, &plane.texture_name, 64)?;
+        append_vector(writer, normal);
+        writer.append_f32(plane.p1.dot(normal) as f32);
+        writer.append_i32(0);
+
+        for vertex in winding {
+            let uv = plane.uv(vertex);
+
+            append_vector(writer, vertex);
+            writer.append_f32(uv.x as f32);
+            writer.append_f32(uv.y as f32);
+            writer.append_i32(0);
+        }
+    }
+
+    Ok(())
+}
+
+/// Outermost group containing the group.
+fn root_group(map: &Map, group: i32) -> i32 {
+    let mut root = group;
+
+    // parents could loop in broken files
+    for _ in 0..map.editor.groups.len() {
+        let parent = map
+            .editor
+            .groups
+            .iter()
+            .find(|group| group.id == root)
+            .and_then(|group| group.parent);
+
+        match parent {
+            Some(parent) => root = parent,
+            None => break,
+        }
+    }
+
+    root
+}
+
+fn append_entity(writer: &mut ByteWriter, map: &Map, entity: &Entity) -> eyre::Result<()> {
+    let brushes = entity.brushes.as_deref().unwrap_or_default();
+    let is_point_entity = brushes.is_empty();
+    let attributes = &entity.attributes;
+
+    let vector_of = |key: &str| {
+        attributes
+            .get(key)
+            .and_then(|s| parse_vector_string(s))
+            .unwrap_or_default()
+    };
+    let number_of = |key: &str| {
+        attributes
+            .get(key)
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap_or_default()
+    };
+
+    append_nstring(
+        writer,
+        attributes
+            .get("classname")
+            .map(String::as_str)
+            .unwrap_or_default(),
+    );
+    append_vector(writer, vector_of("origin"));
+    writer.append_i32(0);
+    writer.append_i32(entity.editor.group.unwrap_or(0));
+    writer.append_i32(
+        entity
+            .editor
+            .group
+            .map_or(0, |group| root_group(map, group)),
+    );
+    append_rgba(writer, entity.editor.color.unwrap_or(DEFAULT_COLOR));
+
+    (0..SPECIAL_STRING_COUNT).for_each(|_| append_nstring(writer, ""));
+
+    writer.append_i32(spawnflags(entity));
+    append_vector(writer, vector_of("angles"));
+    writer.append_i32(0);
+    append_rgba(writer, vector_of("rendercolor").to_array().map(|c| c as u8));
+    writer.append_i32(number_of("rendermode") as i32);
+    writer.append_i32(number_of("renderfx") as i32);
+    writer.append_i16(number_of("body") as i16);
+    writer.append_i16(number_of("skin") as i16);
+    writer.append_i32(number_of("sequence") as i32);
+    writer.append_f32(number_of("framerate") as f32);
+    writer.append_f32(number_of("scale") as f32);
+    writer.append_f32(number_of("radius") as f32);
+    writer.append_u8_slice(&[0; 28]);
+
+    append_attributes(
+        writer,
+        attributes
+            .iter()
+            .filter(|(key, _)| !is_special_key(key, is_point_entity)),
+    );
+    append_visgroup_ids(writer, &entity.editor);
+
+    writer.append_i32(brushes.len() as i32);
+
+    for brush in brushes {
+        append_solid(writer, brush)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_jmf(map: &Map) -> eyre::Result<Vec<u8>> {
+    let mut writer = ByteWriter::new();
+
+    writer.append_string("JHMF");
+    writer.append_i32(JMF_VERSION);
+    // no recent export paths
+    writer.append_i32(0);
+
+    writer.append_i32(map.editor.groups.len() as i32);
+
+    for group in &map.editor.groups {
+        let object_count = map
+            .entities
+            .iter()
+            .flat_map(|entity| {
+                std::iter::once(&entity.editor)
+                    .chain(entity.brushes.iter().flatten().map(|brush| &brush.editor))
+            })
+            .filter(|editor| editor.group == Some(group.id))
+            .count();
+
+        writer.append_i32(group.id);
+        writer.append_i32(group.parent.unwrap_or(0));
+        writer.append_i32(0);
+        writer.append_i32(object_count as i32);
+        append_rgba(&mut writer, group.color);
+    }
+
+    writer.append_i32(map.editor.visgroups.len() as i32);
+
+    for visgroup in &map.editor.visgroups {
+        append_nstring(&mut writer, &visgroup.name);
+        writer.append_i32(visgroup.id);
+        append_rgba(&mut writer, visgroup.color);
+        writer.append_u8(visgroup.visible as u8);
+    }
+
+    let (cordon_mins, cordon_maxs) = map.editor.cordon.unwrap_or_default();
+
+    append_vector(&mut writer, cordon_mins);
+    append_vector(&mut writer, cordon_maxs);
+
+    writer.append_i32(map.editor.cameras.len() as i32);
+
+    for (idx, camera) in map.editor.cameras.iter().enumerate() {
+        append_vector(&mut writer, camera.eye);
+        append_vector(&mut writer, camera.look);
+        writer.append_i32(if map.editor.active_camera == Some(idx) {
+            CAMERA_ACTIVE
+        } else {
+            0
+        });
+        append_rgba(&mut writer, [255; 3]);
+    }
+
+    // paths are already entities
+    writer.append_i32(0);
+
+    for entity in &map.entities {
+        append_entity(&mut writer, map, entity)?;
+    }
+
+    Ok(writer.data)
+}
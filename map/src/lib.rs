@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    borrow::Cow,
     fs::OpenOptions,
     io::{self, BufWriter, Write},
     path::{Path, PathBuf},
@@ -7,17 +7,35 @@ use std::{
 
 use glam::{DVec3, DVec4};
 use nom::{
+    branch::alt,
     bytes::complete::{tag, take_till},
     character::complete::{multispace0, space0},
     combinator::{all_consuming, map, opt, recognize},
     multi::{fold_many1, many0, many1, many_m_n},
     number::complete::double as _double,
-    sequence::{delimited, preceded, terminated, tuple},
+    sequence::{preceded, terminated, tuple},
     IResult as _IResult,
 };
 
 use eyre::eyre;
 
+mod attributes;
+mod binary;
+mod csg;
+mod editor;
+mod geometry;
+mod jmf;
+mod rmf;
+mod standard;
+mod transform;
+
+pub use attributes::Attributes;
+pub use editor::{Camera, EditorData, EditorInfo, Group, Visgroup};
+pub use geometry::{base_winding, clip_winding, BrushError};
+pub use standard::StandardTexture;
+pub use transform::Transform;
+
+/// Brush planes are always kept in Valve 220, standard planes are converted when read.
 #[derive(Debug, Clone, PartialEq)]
 pub struct BrushPlane {
     pub p1: DVec3,
@@ -44,9 +62,13 @@ impl TryFrom<&str> for BrushPlane {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Brush {
     pub planes: Vec<BrushPlane>,
+    /// Comment lines right before the brush, without the `//`.
+    pub comments: Vec<String>,
+    /// Groups and visgroups from RMF and JMF.
+    pub editor: EditorInfo,
 }
 
 impl TryFrom<&str> for Brush {
@@ -60,14 +82,17 @@ impl TryFrom<&str> for Brush {
     }
 }
 
-// #[derive(Debug, Clone, PartialEq)]
-pub type Attributes = HashMap<String, String>;
-
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Entity {
     // All entities have attributes.
     pub attributes: Attributes,
     pub brushes: Option<Vec<Brush>>,
+    /// Comment lines right before the entity, without the `//`.
+    ///
+    /// Comments after the last brush of an entity are not kept.
+    pub comments: Vec<String>,
+    /// Groups and visgroups from RMF and JMF.
+    pub editor: EditorInfo,
 }
 
 impl TryFrom<&str> for Entity {
@@ -81,10 +106,28 @@ impl TryFrom<&str> for Entity {
     }
 }
 
+/// How brush planes are written in the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapFormat {
+    /// `[ Ux Uy Uz Uoffset ] [ Vx Vy Vz Voffset ] rotation uscale vscale`
+    #[default]
+    Valve220,
+    /// `xoff yoff rotation xscale yscale` from Quake.
+    ///
+    /// Planes with texture axes that [`BrushPlane::to_standard`] cannot convert are written with the closest projection.
+    Standard,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Map {
+    /// Format of the file when read, or the format to write the file as.
+    pub format: MapFormat,
     pub tb_header: Option<Vec<String>>,
     pub entities: Vec<Entity>,
+    /// Comment lines after the last entity, without the `//`.
+    pub trailing_comments: Vec<String>,
+    /// Visgroups, groups and cameras from RMF and JMF.
+    pub editor: EditorData,
 }
 
 impl Default for Map {
@@ -96,8 +139,11 @@ impl Default for Map {
 impl Map {
     pub fn new() -> Self {
         Self {
+            format: MapFormat::default(),
             tb_header: None,
             entities: vec![],
+            trailing_comments: vec![],
+            editor: EditorData::default(),
         }
     }
 
@@ -108,13 +154,48 @@ impl Map {
         }
     }
 
+    /// Reads RMF, JMF or text, told apart by the start of the file.
+    pub fn from_bytes(bytes: &[u8]) -> eyre::Result<Self> {
+        if rmf::is_rmf(bytes) {
+            Self::from_rmf(bytes)
+        } else if jmf::is_jmf(bytes) {
+            Self::from_jmf(bytes)
+        } else {
+            Self::from_text(std::str::from_utf8(bytes)?)
+        }
+    }
+
+    /// Hammer `.rmf`, paths become `path_corner` entities.
+    pub fn from_rmf(bytes: &[u8]) -> eyre::Result<Self> {
+        rmf::read_rmf(bytes)
+    }
+
+    /// J.A.C.K. `.jmf`, paths become `path_corner` entities.
+    pub fn from_jmf(bytes: &[u8]) -> eyre::Result<Self> {
+        jmf::read_jmf(bytes)
+    }
+
     pub fn from_file(path: impl AsRef<Path> + Into<PathBuf>) -> eyre::Result<Self> {
-        let text = std::fs::read_to_string(path)?;
+        let bytes = std::fs::read(path)?;
 
-        Self::from_text(&text)
+        Self::from_bytes(&bytes)
     }
 
+    /// Writes RMF or JMF when the file has that extension, otherwise text.
     pub fn write(&self, path: impl AsRef<Path> + Into<PathBuf>) -> io::Result<()> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        let bytes = match extension.as_deref() {
+            Some("rmf") => self.write_to_rmf(),
+            Some("jmf") => self.write_to_jmf(),
+            _ => Ok(self.write_to_string().into_bytes()),
+        }
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
         let file = OpenOptions::new()
             .create(true)
             .write(true)
@@ -123,30 +204,95 @@ impl Map {
 
         let mut file = BufWriter::new(file);
 
+        file.write_all(&bytes)?;
+
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Fails when a name is too long for the format.
+    pub fn write_to_rmf(&self) -> eyre::Result<Vec<u8>> {
+        rmf::write_rmf(self)
+    }
+
+    /// Fails when a texture name is too long for the format.
+    pub fn write_to_jmf(&self) -> eyre::Result<Vec<u8>> {
+        jmf::write_jmf(self)
+    }
+
+    pub fn write_to_string(&self) -> String {
+        let mut res = String::new();
+
         if let Some(tb_header) = &self.tb_header {
-            for s in tb_header {
-                file.write_all("//".as_bytes())?;
-                file.write_all(s.as_bytes())?;
-                file.write_all("\n".as_bytes())?;
-            }
+            write_comments(&mut res, tb_header);
         }
 
-        for (entity_index, entities) in self.entities.iter().enumerate() {
-            file.write_all(format!("// entity {}\n", entity_index).as_bytes())?;
+        for entities in &self.entities {
+            write_comments(&mut res, &entities.comments);
+
+            res += "{\n";
+
+            let is_world = entities
+                .attributes
+                .get("classname")
+                .is_some_and(|classname| classname == "worldspawn");
+
+            // hlcsg reads the planes as Valve 220 only when the key says so
+            let attributes = if is_world {
+                let mut attributes = entities.attributes.clone();
+
+                match self.format {
+                    MapFormat::Valve220 => {
+                        attributes.insert("mapversion", "220");
+                    }
+                    MapFormat::Standard => {
+                        attributes.remove("mapversion");
+                    }
+                }
 
-            file.write_all("{\n".as_bytes())?;
+                Cow::Owned(attributes)
+            } else {
+                Cow::Borrowed(&entities.attributes)
+            };
 
-            for (key, value) in &entities.attributes {
-                file.write_all(format!("\"{}\" \"{}\"\n", key, value).as_bytes())?;
+            for (key, value) in attributes.iter() {
+                res += format!("\"{}\" \"{}\"\n", key, value).as_str();
             }
 
             if let Some(brushes) = &entities.brushes {
-                for (brush_entity, brush) in brushes.iter().enumerate() {
-                    file.write_all(format!("// brush {}\n", brush_entity).as_bytes())?;
-                    file.write_all("{\n".as_bytes())?;
+                for brush in brushes {
+                    write_comments(&mut res, &brush.comments);
+                    res += "{\n";
 
                     for plane in &brush.planes {
-                        file.write_all(format!("( {} {} {} ) ( {} {} {} ) ( {} {} {} ) {} [ {} {} {} {} ] [ {} {} {} {} ] {} {} {}\n", 
+                        if self.format == MapFormat::Standard {
+                            let (texture, _) = plane.standard_fit();
+
+                            res += format!(
+                                "( {} {} {} ) ( {} {} {} ) ( {} {} {} ) {} {} {} {} {} {}\n",
+                                plane.p1.x,
+                                plane.p1.y,
+                                plane.p1.z,
+                                plane.p2.x,
+                                plane.p2.y,
+                                plane.p2.z,
+                                plane.p3.x,
+                                plane.p3.y,
+                                plane.p3.z,
+                                plane.texture_name,
+                                texture.x_offset,
+                                texture.y_offset,
+                                texture.rotation,
+                                texture.x_scale,
+                                texture.y_scale,
+                            )
+                            .as_str();
+
+                            continue;
+                        }
+
+                        res += format!("( {} {} {} ) ( {} {} {} ) ( {} {} {} ) {} [ {} {} {} {} ] [ {} {} {} {} ] {} {} {}\n", 
                     plane.p1.x,plane.p1.y,plane.p1.z,
                     plane.p2.x,plane.p2.y,plane.p2.z,
                     plane.p3.x,plane.p3.y,plane.p3.z,
@@ -155,18 +301,26 @@ impl Map {
                     plane.v.x,plane.v.y,plane.v.z,plane.v.w,
                     plane.rotation, plane.u_scale, plane.v_scale,
 
-                ).as_bytes())?;
+                ).as_str();
                     }
-                    file.write_all("}\n".as_bytes())?;
+                    res += "}\n";
                 }
             }
 
-            file.write_all("}\n".as_bytes())?;
+            res += "}\n";
         }
 
-        file.flush()?;
+        write_comments(&mut res, &self.trailing_comments);
 
-        Ok(())
+        res
+    }
+}
+
+fn write_comments(res: &mut String, comments: &[String]) {
+    for comment in comments {
+        *res += "//";
+        *res += comment;
+        *res += "\n";
     }
 }
 
@@ -174,7 +328,10 @@ type IResult<'a, T> = _IResult<&'a str, T>;
 
 fn take_comment_line(i: &str) -> IResult<&str> {
     terminated(
-        preceded(tuple((space0, tag("//"))), take_till(|c| c == '\n')),
+        preceded(
+            tuple((space0, tag("//"))),
+            take_till(|c| c == '\n' || c == '\r'),
+        ),
         multispace0,
     )(i)
 }
@@ -183,8 +340,12 @@ fn take_tb_header(i: &str) -> IResult<Vec<String>> {
     many_m_n(0, 2, map(take_comment_line, |i| i.to_string()))(i)
 }
 
-// TODO: make it not discard
 // Many 0 because it doesn't necessary have it every time.
+fn take_comment_lines(i: &str) -> IResult<Vec<String>> {
+    many0(map(take_comment_line, |i| i.to_string()))(i)
+}
+
+// Only for comments that have nothing to be attached to.
 fn discard_comment_lines(i: &str) -> IResult<&str> {
     map(many0(take_comment_line), |_| "")(i)
 }
@@ -241,47 +402,67 @@ fn parse_plane_uv(i: &str) -> IResult<DVec4> {
 }
 
 fn parse_brush_plane(i: &str) -> IResult<BrushPlane> {
-    map(
-        tuple((
-            parse_plane_coordinate,
-            parse_plane_coordinate,
-            parse_plane_coordinate,
-            map(terminated(take_till(|c| c == ' '), space0), |s: &str| {
-                s.to_string()
-            }),
-            parse_plane_uv,
-            parse_plane_uv,
-            double,
-            double,
-            double,
-        )),
-        |(p1, p2, p3, texture_name, u, v, rotation, u_scale, v_scale)| BrushPlane {
-            p1,
-            p2,
-            p3,
-            texture_name,
-            u,
-            v,
-            rotation,
-            u_scale,
-            v_scale,
-        },
-    )(i)
+    let (i, (p1, p2, p3, texture_name)) = tuple((
+        parse_plane_coordinate,
+        parse_plane_coordinate,
+        parse_plane_coordinate,
+        terminated(take_till(|c| c == ' '), space0),
+    ))(i)?;
+
+    alt((
+        map(
+            tuple((parse_plane_uv, parse_plane_uv, double, double, double)),
+            move |(u, v, rotation, u_scale, v_scale)| BrushPlane {
+                p1,
+                p2,
+                p3,
+                texture_name: texture_name.to_string(),
+                u,
+                v,
+                rotation,
+                u_scale,
+                v_scale,
+            },
+        ),
+        map(
+            tuple((double, double, double, double, double)),
+            move |(x_offset, y_offset, rotation, x_scale, y_scale)| {
+                BrushPlane::from_standard(
+                    p1,
+                    p2,
+                    p3,
+                    texture_name,
+                    StandardTexture {
+                        x_offset,
+                        y_offset,
+                        rotation,
+                        x_scale,
+                        y_scale,
+                    },
+                )
+            },
+        ),
+    ))(i)
 }
 
 fn parse_brush(i: &str) -> IResult<Brush> {
     map(
         many1(terminated(parse_brush_plane, multispace0)),
-        |planes| Brush { planes },
+        |planes| Brush {
+            planes,
+            ..Default::default()
+        },
     )(i)
 }
 
 fn parse_brushes(i: &str) -> IResult<Vec<Brush>> {
-    many1(delimited(
-        discard_comment_lines,
-        between_line_bracket(parse_brush),
+    terminated(
+        many1(map(
+            tuple((take_comment_lines, between_line_bracket(parse_brush))),
+            |(comments, brush)| Brush { comments, ..brush },
+        )),
         discard_comment_lines,
-    ))(i)
+    )(i)
 }
 
 // For attributes
@@ -294,7 +475,7 @@ fn parse_attributes(i: &str) -> IResult<Attributes> {
         terminated(parse_attribute, multispace0),
         Attributes::new,
         |mut acc: Attributes, (key, value)| {
-            acc.insert(key.to_owned(), value.to_owned());
+            acc.push(key, value);
             acc
         },
     )(i)
@@ -307,24 +488,46 @@ fn parse_entity(i: &str) -> IResult<Entity> {
         |(attributes, brushes)| Entity {
             attributes,
             brushes,
+            ..Default::default()
         },
     )(i)
 }
 
 fn parse_entities(i: &str) -> IResult<Vec<Entity>> {
-    many1(delimited(
-        discard_comment_lines,
-        between_line_bracket(parse_entity),
-        discard_comment_lines,
+    many1(map(
+        tuple((take_comment_lines, between_line_bracket(parse_entity))),
+        |(comments, entity)| Entity { comments, ..entity },
     ))(i)
 }
 
+/// Format of the first brush plane, maps without brushes are Valve 220.
+fn detect_format(i: &str) -> MapFormat {
+    i.lines()
+        .map(str::trim_start)
+        .find(|line| line.starts_with('('))
+        .map(|line| {
+            if line.contains('[') {
+                MapFormat::Valve220
+            } else {
+                MapFormat::Standard
+            }
+        })
+        .unwrap_or_default()
+}
+
 fn parse_map(i: &str) -> IResult<Map> {
     map(
-        all_consuming(tuple((opt(take_tb_header), parse_entities))),
-        |(tb_header, entities)| Map {
+        all_consuming(tuple((
+            opt(take_tb_header),
+            parse_entities,
+            take_comment_lines,
+        ))),
+        |(tb_header, entities, trailing_comments)| Map {
+            format: detect_format(i),
             tb_header,
             entities,
+            trailing_comments,
+            editor: EditorData::default(),
         },
     )(i)
 }
@@ -332,6 +535,7 @@ fn parse_map(i: &str) -> IResult<Map> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use glam::{DAffine3, DMat3, DVec2};
 
     #[test]
     fn inside_quote() {
@@ -467,6 +671,195 @@ a
         assert_eq!(brush.planes[3].u.x, 1.);
     }
 
+    #[test]
+    fn attribute_order() {
+        let i = "\
+{
+\"targetname\" \"mm\"
+\"classname\" \"multi_manager\"
+\"door\" \"1\"
+\"light\" \"0.5\"
+\"door\" \"2\"
+}
+";
+
+        let map = Map::from_text(i).unwrap();
+        let attributes = &map.entities[0].attributes;
+
+        assert_eq!(
+            attributes.keys().collect::<Vec<_>>(),
+            ["targetname", "classname", "door", "light", "door"]
+        );
+        assert_eq!(attributes.get("door").unwrap(), "2");
+        assert_eq!(attributes.get_all("door").collect::<Vec<_>>(), ["1", "2"]);
+
+        assert_eq!(map.write_to_string(), i);
+    }
+
+    #[test]
+    fn comment_round_trip() {
+        let i = "\
+// Game: Half-Life
+// Format: Valve
+// entity 0
+{
+\"mapversion\" \"220\"
+\"classname\" \"worldspawn\"
+// brush 0
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) __TB_empty [ 0 -1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) __TB_empty [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) __TB_empty [ -1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 192 ) ( 64 65 192 ) ( 65 64 192 ) __TB_empty [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) __TB_empty [ -1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) __TB_empty [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+}
+}
+// spawn point
+// entity 1
+{
+\"classname\" \"info_player_start\"
+\"origin\" \"0 0 64\"
+}
+// the end
+";
+
+        let map = Map::from_text(i).unwrap();
+
+        assert_eq!(map.entities[1].comments, [" spawn point", " entity 1"]);
+        assert_eq!(
+            map.entities[0].brushes.as_ref().unwrap()[0].comments,
+            [" brush 0"]
+        );
+        assert_eq!(map.trailing_comments, [" the end"]);
+
+        assert_eq!(map.write_to_string(), i);
+    }
+
+    #[test]
+    fn standard_round_trip() {
+        let i = "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"quake.wad\"
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) city4_6 0 0 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) city4_6 16 -8 90 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) city4_6 0 0 30 0.5 -2
+( 64 64 192 ) ( 64 65 192 ) ( 65 64 192 ) city4_6 0 0 450 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) city4_6 0 0 180 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) city4_6 0 0 -45 1 1
+}
+}
+";
+
+        let map = Map::from_text(i).unwrap();
+        assert_eq!(map.format, MapFormat::Standard);
+
+        let planes = &map.entities[0].brushes.as_ref().unwrap()[0].planes;
+
+        // top face, rotated 90 degrees from X
+        assert!(planes[3].u.truncate().abs_diff_eq(DVec3::Y, 1e-9));
+        assert_eq!(planes[1].u.w, 16.);
+        assert_eq!(planes[1].v.w, -8.);
+
+        assert_eq!(map.write_to_string(), i);
+    }
+
+    #[test]
+    fn standard_to_valve_mapversion() {
+        let mut map = Map::from_text(
+            "\
+{
+\"classname\" \"worldspawn\"
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) city4_6 0 0 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) city4_6 0 0 0 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) city4_6 0 0 0 1 1
+( 64 64 192 ) ( 64 65 192 ) ( 65 64 192 ) city4_6 0 0 0 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) city4_6 0 0 0 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) city4_6 0 0 0 1 1
+}
+}
+",
+        )
+        .unwrap();
+        assert_eq!(map.entities[0].attributes.get("mapversion"), None);
+
+        map.format = MapFormat::Valve220;
+        let map = Map::from_text(&map.write_to_string()).unwrap();
+
+        assert_eq!(map.format, MapFormat::Valve220);
+        assert_eq!(map.entities[0].attributes.get("mapversion").unwrap(), "220");
+    }
+
+    #[test]
+    fn valve_to_standard() {
+        let mut map = Map::from_file("./test/sky_vis.map").unwrap();
+        assert_eq!(map.format, MapFormat::Valve220);
+
+        let original = map.clone();
+
+        map.format = MapFormat::Standard;
+        let map = Map::from_text(&map.write_to_string()).unwrap();
+        assert_eq!(map.format, MapFormat::Standard);
+        assert_eq!(map.entities[0].attributes.get("mapversion"), None);
+
+        let planes = |map: &Map| {
+            map.entities
+                .iter()
+                .filter_map(|entity| entity.brushes.clone())
+                .flatten()
+                .flat_map(|brush| brush.planes)
+                .collect::<Vec<_>>()
+        };
+
+        planes(&original)
+            .iter()
+            .zip(planes(&map).iter())
+            .for_each(|(a, b)| {
+                assert!(a.to_standard().is_some());
+
+                let scaled = |plane: &BrushPlane| {
+                    (
+                        plane.u.truncate() / plane.u_scale,
+                        plane.v.truncate() / plane.v_scale,
+                    )
+                };
+
+                assert!(scaled(a).0.abs_diff_eq(scaled(b).0, 1e-9));
+                assert!(scaled(a).1.abs_diff_eq(scaled(b).1, 1e-9));
+                assert_eq!(a.u.w, b.u.w);
+                assert_eq!(a.v.w, b.v.w);
+            });
+    }
+
+    #[test]
+    fn skewed_is_not_standard() {
+        let plane = BrushPlane::try_from(
+            "( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) NULL [ 1 0 0 0 ] [ 1 -1 0 0 ] 0 1 1",
+        )
+        .unwrap();
+
+        assert!(plane.to_standard().is_none());
+
+        let plane = BrushPlane::try_from(
+            "( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) NULL 4 8 15 0.25 0.5",
+        )
+        .unwrap();
+
+        assert_eq!(
+            plane.to_standard(),
+            Some(StandardTexture {
+                x_offset: 4.,
+                y_offset: 8.,
+                rotation: 15.,
+                x_scale: 0.25,
+                y_scale: 0.5,
+            })
+        );
+    }
+
     #[test]
     fn file_read() {
         assert!(Map::from_file("./test/sky_vis.map").is_ok());
@@ -483,10 +876,471 @@ a
         assert_eq!(i, j);
     }
 
+    const CUBE: &str = "\
+( 0 0 0 ) ( 0 1 0 ) ( 0 0 1 ) NULL [ 0 1 0 8 ] [ 0 0 -1 -4 ] 0 0.5 0.5
+( 0 0 0 ) ( 0 0 1 ) ( 1 0 0 ) NULL [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 0 0 0 ) ( 1 0 0 ) ( 0 1 0 ) NULL [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 32 32 32 ) ( 32 33 32 ) ( 33 32 32 ) NULL [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 32 32 32 ) ( 33 32 32 ) ( 32 32 33 ) NULL [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 32 32 32 ) ( 32 32 33 ) ( 32 33 32 ) NULL [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+";
+
+    #[test]
+    fn brush_geometry() {
+        let brush = Brush::try_from(CUBE).unwrap();
+
+        let windings = brush.windings();
+        assert!(windings.iter().all(|winding| winding.len() == 4));
+
+        // same winding as the plane points
+        windings
+            .iter()
+            .zip(&brush.planes)
+            .for_each(|(winding, plane)| {
+                let normal = (winding[0] - winding[1]).cross(winding[2] - winding[1]);
+                assert!(normal.normalize().abs_diff_eq(plane.normal(), 1e-9));
+            });
+
+        assert_eq!(brush.vertices().len(), 8);
+        assert_eq!(brush.bounds(), Some((DVec3::ZERO, DVec3::splat(32.))));
+        assert!((brush.volume() - 32768.).abs() < 1e-6);
+        assert_eq!(brush.validate(), Ok(()));
+
+        // 0.5 scale doubles the pixels, offset is added after
+        let uvs = brush.uvs();
+        let corner = windings[0]
+            .iter()
+            .position(|vertex| *vertex == DVec3::new(0., 32., 32.))
+            .unwrap();
+        assert_eq!(uvs[0][corner], DVec2::new(72., -68.));
+    }
+
+    #[test]
+    fn invalid_brushes() {
+        let lines = CUBE.lines().collect::<Vec<_>>();
+        let brush = |lines: &[&str]| Brush::try_from(lines.join("\n").as_str()).unwrap();
+
+        assert_eq!(brush(&lines[..3]).validate(), Err(BrushError::TooFewPlanes));
+        assert_eq!(brush(&lines[..5]).validate(), Err(BrushError::Unbounded));
+
+        let mut duplicate = lines.clone();
+        duplicate.push(lines[0]);
+        assert_eq!(
+            brush(&duplicate).validate(),
+            Err(BrushError::UnusedPlane(6))
+        );
+
+        let mut degenerate = lines.clone();
+        degenerate[2] = "( 0 0 0 ) ( 1 0 0 ) ( 2 0 0 ) NULL [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1";
+        assert_eq!(
+            brush(&degenerate).validate(),
+            Err(BrushError::DegeneratePlane(2))
+        );
+
+        // top and bottom swapped, nothing is behind both
+        let mut inside_out = lines.clone();
+        inside_out[2] = "( 0 0 32 ) ( 1 0 32 ) ( 0 1 32 ) NULL [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1";
+        inside_out[3] = "( 0 0 0 ) ( 0 1 0 ) ( 1 0 0 ) NULL [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1";
+        assert!(!brush(&inside_out).is_valid());
+    }
+
+    #[test]
+    fn texture_lock() {
+        let brush = Brush::try_from(CUBE).unwrap();
+
+        let transforms = [
+            DAffine3::from_translation(DVec3::new(13., -7., 5.)),
+            DAffine3::from_mat3(DMat3::from_rotation_z(0.7)) * DAffine3::from_translation(DVec3::X),
+            DAffine3::from_mat3(DMat3::from_euler(glam::EulerRot::ZYX, 0.3, 0.9, -0.4)),
+            DAffine3::from_scale(DVec3::new(2., 1., 0.5)),
+            DAffine3::from_scale(DVec3::new(-1., 1., 1.)),
+        ];
+
+        for transform in transforms {
+            let mut moved = brush.clone();
+            moved.transform(&transform);
+
+            assert_eq!(moved.validate(), Ok(()));
+            assert!(
+                (moved.volume() - brush.volume() * transform.matrix3.determinant().abs()).abs()
+                    < 1e-6
+            );
+
+            for ((plane, moved_plane), winding) in
+                brush.planes.iter().zip(&moved.planes).zip(brush.windings())
+            {
+                assert!(moved_plane.normal().abs_diff_eq(
+                    (transform.matrix3.inverse().transpose() * plane.normal()).normalize(),
+                    1e-9
+                ));
+
+                for point in winding {
+                    let uv = plane.uv(point);
+                    let moved_uv = moved_plane.uv(transform.transform_point3(point));
+
+                    assert!(uv.abs_diff_eq(moved_uv, 1e-6));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn entity_transform() {
+        let mut map = Map::from_text(
+            "\
+{
+\"classname\" \"info_player_start\"
+\"angles\" \"0 90 0\"
+\"origin\" \"64 0 8\"
+}
+{
+\"classname\" \"light_spot\"
+\"angle\" \"0\"
+\"pitch\" \"-45\"
+\"origin\" \"0 0 0\"
+}
+{
+\"classname\" \"info_target\"
+\"origin\" \"0 0 0\"
+}
+{
+\"classname\" \"light_spot\"
+\"angle\" \"-1\"
+\"origin\" \"0 0 0\"
+}
+",
+        )
+        .unwrap();
+
+        map.rotate(DVec3::new(0., 90., 0.), DVec3::ZERO);
+
+        assert_eq!(map.entities[0].attributes.get("origin").unwrap(), "0 64 8");
+        assert_eq!(map.entities[0].attributes.get("angles").unwrap(), "0 180 0");
+        assert_eq!(map.entities[1].attributes.get("angle").unwrap(), "90");
+        assert_eq!(map.entities[1].attributes.get("pitch").unwrap(), "-45");
+        assert!(!map.entities[1].attributes.contains_key("angles"));
+        assert_eq!(map.entities[2].attributes.get("angles").unwrap(), "0 90 0");
+        assert_eq!(map.entities[3].attributes.get("angle").unwrap(), "-1");
+
+        map.mirror(DVec3::Y, DVec3::ZERO);
+
+        assert_eq!(map.entities[0].attributes.get("origin").unwrap(), "0 -64 8");
+        assert_eq!(map.entities[0].attributes.get("angles").unwrap(), "0 180 0");
+        assert_eq!(map.entities[2].attributes.get("angles").unwrap(), "0 270 0");
+
+        map.translate(DVec3::new(0., 0., 16.));
+        assert_eq!(
+            map.entities[0].attributes.get("origin").unwrap(),
+            "0 -64 24"
+        );
+    }
+
+    fn editor_map() -> Map {
+        let mut map = Map::from_text(
+            "\
+{
+\"classname\" \"worldspawn\"
+\"wad\" \"halflife.wad\"
+{
+( -64 -64 -16 ) ( -64 -63 -16 ) ( -64 -64 -15 ) __TB_empty [ 0 -1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -64 -64 -15 ) ( -63 -64 -16 ) __TB_empty [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( -64 -64 -16 ) ( -63 -64 -16 ) ( -64 -63 -16 ) __TB_empty [ -1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 192 ) ( 64 65 192 ) ( 65 64 192 ) __TB_empty [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 64 64 16 ) ( 65 64 16 ) ( 64 64 17 ) __TB_empty [ -1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 64 64 16 ) ( 64 64 17 ) ( 64 65 16 ) __TB_empty [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+}
+}
+{
+\"classname\" \"info_player_start\"
+\"angles\" \"0 90 0\"
+\"origin\" \"16 -8 36\"
+}
+{
+\"classname\" \"func_wall\"
+\"rendermode\" \"4\"
+\"spawnflags\" \"1\"
+{
+( 0 0 0 ) ( 0 1 0 ) ( 0 0 1 ) AAATRIGGER [ 0 1 0 8 ] [ 0 0 -1 -4 ] 0 0.5 0.5
+( 0 0 0 ) ( 0 0 1 ) ( 1 0 0 ) AAATRIGGER [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 0 0 0 ) ( 1 0 0 ) ( 0 1 0 ) AAATRIGGER [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 32 32 32 ) ( 32 33 32 ) ( 33 32 32 ) AAATRIGGER [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1
+( 32 32 32 ) ( 33 32 32 ) ( 32 32 33 ) AAATRIGGER [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1
+( 32 32 32 ) ( 32 32 33 ) ( 32 33 32 ) AAATRIGGER [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1
+}
+}
+",
+        )
+        .unwrap();
+
+        map.editor = EditorData {
+            visgroups: vec![Visgroup {
+                id: 3,
+                name: "walls".to_string(),
+                color: [255, 0, 0],
+                visible: false,
+            }],
+            groups: vec![
+                Group {
+                    id: 1,
+                    parent: None,
+                    color: [0, 255, 0],
+                },
+                Group {
+                    id: 2,
+                    parent: Some(1),
+                    color: [0, 0, 255],
+                },
+            ],
+            cameras: vec![Camera {
+                eye: DVec3::new(0., 0., 64.),
+                look: DVec3::new(128., 0., 64.),
+            }],
+            active_camera: Some(0),
+            cordon: None,
+        };
+
+        map.entities[2].editor = EditorInfo {
+            visgroups: vec![3],
+            group: Some(2),
+            color: Some([10, 20, 30]),
+        };
+
+        map
+    }
+
+    fn assert_same_map(a: &Map, b: &Map) {
+        assert_eq!(a.entities.len(), b.entities.len());
+        assert_eq!(a.editor, b.editor);
+
+        for (a, b) in a.entities.iter().zip(&b.entities) {
+            assert_eq!(a.attributes, b.attributes);
+            assert_eq!(a.editor.visgroups, b.editor.visgroups);
+            assert_eq!(a.editor.group, b.editor.group);
+
+            let a = a.brushes.as_deref().unwrap_or_default();
+            let b = b.brushes.as_deref().unwrap_or_default();
+
+            assert_eq!(a.len(), b.len());
+
+            for (a, b) in a
+                .iter()
+                .flat_map(|a| &a.planes)
+                .zip(b.iter().flat_map(|b| &b.planes))
+            {
+                assert_eq!(a.texture_name, b.texture_name);
+                assert!(a.normal().abs_diff_eq(b.normal(), 1e-6));
+                assert!((a.p1.dot(a.normal()) - b.p1.dot(b.normal())).abs() < 1e-4);
+                assert_eq!(a.u, b.u);
+                assert_eq!(a.v, b.v);
+                assert_eq!(a.u_scale, b.u_scale);
+            }
+        }
+    }
+
+    #[test]
+    fn rmf_round_trip() {
+        let map = editor_map();
+        let bytes = map.write_to_rmf().unwrap();
+
+        let rmf = Map::from_bytes(&bytes).unwrap();
+        assert_same_map(&map, &rmf);
+
+        // plane points are kept as they are
+        assert_eq!(
+            map.entities[2].brushes.as_ref().unwrap()[0].planes,
+            rmf.entities[2].brushes.as_ref().unwrap()[0].planes
+        );
+
+        assert!(rmf.write_to_rmf().unwrap() == bytes);
+    }
+
+    #[test]
+    fn jmf_round_trip() {
+        let map = editor_map();
+        let bytes = map.write_to_jmf().unwrap();
+
+        let jmf = Map::from_bytes(&bytes).unwrap();
+        assert_same_map(&map, &jmf);
+
+        // plane points made from the polygon stay the same
+        let again = Map::from_jmf(&jmf.write_to_jmf().unwrap()).unwrap();
+        assert_eq!(jmf.entities, again.entities);
+    }
+
+    // cube.rmf and cube.jmf are laid out by hand after the formats, not written by our writers
+    fn assert_cube_map(map: &Map) {
+        let world = &map.entities[0];
+        assert_eq!(world.attributes.get("classname").unwrap(), "worldspawn");
+        assert_eq!(world.attributes.get("mapversion").unwrap(), "220");
+
+        let brush = &world.brushes.as_ref().unwrap()[0];
+        assert!(brush.is_valid());
+        assert_eq!(
+            brush.bounds(),
+            Some((DVec3::new(-64., -64., -16.), DVec3::new(64., 64., 0.)))
+        );
+        assert!(brush
+            .planes
+            .iter()
+            .all(|plane| plane.texture_name == "CRATE01"));
+
+        let door = map
+            .entities
+            .iter()
+            .find(|entity| entity.attributes.get("classname").unwrap() == "func_door")
+            .unwrap();
+        assert_eq!(door.attributes.get("targetname").unwrap(), "door1");
+        assert_eq!(door.editor.visgroups, [1]);
+        assert_eq!(
+            door.brushes.as_ref().unwrap()[0].bounds(),
+            Some((DVec3::new(-8., -32., 0.), DVec3::new(8., 32., 96.)))
+        );
+
+        let light = map
+            .entities
+            .iter()
+            .find(|entity| entity.attributes.get("classname").unwrap() == "light")
+            .unwrap();
+        assert_eq!(light.attributes.get("origin").unwrap(), "0 0 64");
+        assert_eq!(light.attributes.get("_light").unwrap(), "255 255 128 200");
+
+        let corners = map
+            .entities
+            .iter()
+            .filter(|entity| entity.attributes.get("classname").unwrap() == "path_corner")
+            .count();
+        assert_eq!(corners, 2);
+
+        assert_eq!(map.editor.visgroups[0].name, "doors");
+        assert_eq!(map.editor.visgroups[0].color, [220, 30, 220]);
+        assert_eq!(map.editor.groups.len(), 1);
+        assert_eq!(map.editor.groups[0].id, 1);
+        assert_eq!(map.editor.cameras.len(), 1);
+        assert_eq!(map.editor.cameras[0].eye, DVec3::new(-256., -256., 128.));
+        assert_eq!(map.editor.active_camera, Some(0));
+    }
+
+    #[test]
+    fn rmf_file_read() {
+        let map = Map::from_file("./test/cube.rmf").unwrap();
+
+        assert_cube_map(&map);
+        // the group holds the world brush
+        assert_eq!(
+            map.entities[0].brushes.as_ref().unwrap()[0].editor.group,
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn jmf_file_read() {
+        let map = Map::from_file("./test/cube.jmf").unwrap();
+
+        assert_cube_map(&map);
+        assert_eq!(map.entities[1].editor.group, Some(1));
+    }
+
     #[test]
     fn fail_read() {
         let file = Map::from_file("./dunkin/do.nut");
 
         assert!(file.is_err());
     }
+
+    #[test]
+    fn brush_split() {
+        let brush = Brush::try_from(CUBE).unwrap();
+        let plane = BrushPlane::try_from(
+            "( 16 0 0 ) ( 16 0 1 ) ( 16 1 0 ) SKY [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1",
+        )
+        .unwrap();
+
+        let (back, front) = brush.split(&plane);
+        let (back, front) = (back.unwrap(), front.unwrap());
+
+        assert_eq!(back.validate(), Ok(()));
+        assert_eq!(front.validate(), Ok(()));
+        assert_eq!(
+            back.bounds(),
+            Some((DVec3::ZERO, DVec3::new(16., 32., 32.)))
+        );
+        assert_eq!(
+            front.bounds(),
+            Some((DVec3::new(16., 0., 0.), DVec3::splat(32.)))
+        );
+
+        // the face at x = 0 keeps its texture, the cut faces take the plane
+        assert!(back.planes.contains(&brush.planes[0]));
+        assert!(!front.planes.contains(&brush.planes[0]));
+        assert!(back.planes.contains(&plane));
+        assert!(front.planes.contains(&plane.flipped()));
+
+        assert_eq!(brush.clip(&plane), Some(back));
+        assert_eq!(brush.clip(&plane.offset(32.)), Some(brush.clone()));
+        assert_eq!(brush.clip(&plane.flipped().offset(-32.)), None);
+    }
+
+    #[test]
+    fn brush_subtract_hollow() {
+        let brush = Brush::try_from(CUBE).unwrap();
+
+        let mut cutter = brush.clone();
+        cutter
+            .scale(DVec3::splat(0.5), DVec3::ZERO)
+            .translate(DVec3::splat(8.));
+
+        let pieces = brush.subtract(&cutter);
+        assert_eq!(pieces.len(), 6);
+        assert!(pieces.iter().all(Brush::is_valid));
+        assert!((pieces.iter().map(Brush::volume).sum::<f64>() - (32768. - 4096.)).abs() < 1e-6);
+
+        let mut away = cutter.clone();
+        away.translate(DVec3::splat(100.));
+        assert_eq!(brush.subtract(&away), vec![brush.clone()]);
+        assert!(cutter.subtract(&brush).is_empty());
+
+        let walls = brush.hollow(4.).unwrap();
+        assert_eq!(walls.len(), 6);
+        assert!(walls.iter().all(Brush::is_valid));
+        assert!((walls.iter().map(Brush::volume).sum::<f64>() - (32768. - 13824.)).abs() < 1e-6);
+
+        // inner faces are aligned like the outer faces
+        assert!(walls.iter().flat_map(|wall| &wall.planes).all(|plane| {
+            brush
+                .planes
+                .iter()
+                .any(|outer| outer.u == plane.u && outer.v == plane.v)
+        }));
+
+        assert_eq!(brush.hollow(16.), None);
+    }
+
+    #[test]
+    fn brush_merge() {
+        let brush = Brush::try_from(CUBE).unwrap();
+
+        let moved = |offset: DVec3| {
+            let mut res = brush.clone();
+            res.translate(offset);
+            res
+        };
+
+        let merged = brush.merge(&moved(DVec3::new(32., 0., 0.))).unwrap();
+        assert_eq!(merged.planes.len(), 6);
+        assert_eq!(
+            merged.bounds(),
+            Some((DVec3::ZERO, DVec3::new(64., 32., 32.)))
+        );
+        assert!(merged.planes.contains(&brush.planes[0]));
+
+        let merged = brush.merge(&moved(DVec3::new(0., 0., 16.))).unwrap();
+        assert_eq!(
+            merged.bounds(),
+            Some((DVec3::ZERO, DVec3::new(32., 32., 48.)))
+        );
+
+        // would fill the corner between them
+        assert_eq!(brush.merge(&moved(DVec3::new(32., 32., 0.))), None);
+        assert_eq!(brush.merge(&moved(DVec3::new(48., 0., 0.))), None);
+    }
 }
@@ -0,0 +1,670 @@
+//! Hammer 3.4 `.rmf`, version 2.2.
+//!
+//! Solids and entities can sit inside groups, which can be nested. Each of them has at most one visgroup.
+//! Paths are read as the `path_corner` chains Hammer exports them as, so they are written back as entities.
+use byte_writer::ByteWriter;
+use eyre::eyre;
+use glam::DVec3;
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::{fail, map, opt},
+    multi::count,
+    number::complete::{le_f32, le_i32, le_u8},
+    sequence::tuple,
+};
+
+use crate::{
+    binary::{
+        append_fixed_string, append_vector, bytes_to_string, color, expand_path, fixed_string,
+        is_special_key, orient_planes, origin_string, parse_vector_string, spawnflags,
+        string_to_bytes, vector, IResult, PathNode,
+    },
+    editor::{Camera, EditorData, EditorInfo, Group, Visgroup},
+    Attributes, Brush, BrushPlane, Entity, Map, MapFormat,
+};
+
+const RMF_VERSION: f32 = 2.2;
+const DOCINFO: &[u8; 8] = b"DOCINFO\0";
+const CAMERA_VERSION: f32 = 0.2;
+const DEFAULT_COLOR: [u8; 3] = [0, 100, 220];
+
+pub(crate) fn is_rmf(i: &[u8]) -> bool {
+    i.get(4..7) == Some(b"RMF")
+}
+
+enum Object {
+    Solid(Brush),
+    Entity(Entity),
+    Group {
+        color: [u8; 3],
+        objects: Vec<Object>,
+    },
+}
+
+/// String with its length in front, the length counts the null.
+fn nstring(i: &[u8]) -> IResult<'_, String> {
+    let (i, len) = le_u8(i)?;
+
+    map(take(len), bytes_to_string)(i)
+}
+
+fn visgroup_id(id: i32) -> Vec<i32> {
+    if id == 0 {
+        vec![]
+    } else {
+        vec![id]
+    }
+}
+
+fn parse_visgroup(i: &[u8]) -> IResult<'_, Visgroup> {
+    map(
+        tuple((
+            fixed_string(128),
+            color,
+            take(1usize),
+            le_i32,
+            le_u8,
+            take(3usize),
+        )),
+        |(name, color, _, id, visible, _)| Visgroup {
+            id,
+            name,
+            color,
+            visible: visible != 0,
+        },
+    )(i)
+}
+
+fn parse_face(i: &[u8]) -> IResult<'_, (BrushPlane, Vec<DVec3>)> {
+    let (i, (texture_name, _, u, x_shift, v, y_shift, rotation, u_scale, v_scale, _)) = tuple((
+        fixed_string(256),
+        le_f32,
+        vector,
+        le_f32,
+        vector,
+        le_f32,
+        le_f32,
+        le_f32,
+        le_f32,
+        take(16usize),
+    ))(i)?;
+
+    let (i, vertex_count) = le_i32(i)?;
+    let (i, vertices) = count(vector, vertex_count.max(0) as usize)(i)?;
+    let (i, points) = count(vector, 3)(i)?;
+
+    Ok((
+        i,
+        (
+            BrushPlane {
+                p1: points[0],
+                p2: points[1],
+                p3: points[2],
+                texture_name,
+                u: u.extend(x_shift as f64),
+                v: v.extend(y_shift as f64),
+                rotation: rotation as f64,
+                u_scale: u_scale as f64,
+                v_scale: v_scale as f64,
+            },
+            vertices,
+        ),
+    ))
+}
+
+fn parse_solid(i: &[u8]) -> IResult<'_, Brush> {
+    let (i, (visgroup, color, _, face_count)) = tuple((le_i32, color, take(4usize), le_i32))(i)?;
+    let (i, faces) = count(parse_face, face_count.max(0) as usize)(i)?;
+
+    let vertices = faces
+        .iter()
+        .flat_map(|(_, vertices)| vertices.iter().copied())
+        .collect::<Vec<_>>();
+
+    let mut brush = Brush {
+        planes: faces.into_iter().map(|(plane, _)| plane).collect(),
+        editor: EditorInfo {
+            visgroups: visgroup_id(visgroup),
+            group: None,
+            color: Some(color),
+        },
+        ..Default::default()
+    };
+
+    orient_planes(&mut brush, &vertices);
+
+    Ok((i, brush))
+}
+
+fn parse_attributes(i: &[u8]) -> IResult<'_, Attributes> {
+    let (i, attribute_count) = le_i32(i)?;
+
+    map(
+        count(tuple((nstring, nstring)), attribute_count.max(0) as usize),
+        |pairs| pairs.into_iter().collect(),
+    )(i)
+}
+
+fn parse_entity(i: &[u8]) -> IResult<'_, Entity> {
+    let (i, (visgroup, color, solid_count)) = tuple((le_i32, color, le_i32))(i)?;
+    let (i, objects) = count(parse_object, solid_count.max(0) as usize)(i)?;
+
+    let brushes = objects
+        .into_iter()
+        .map(|object| match object {
+            Object::Solid(brush) => Some(brush),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+
+    let Some(brushes) = brushes else {
+        return fail(i);
+    };
+
+    let (i, (classname, _, spawnflags, kvs, _, origin, _)) = tuple((
+        nstring,
+        take(4usize),
+        le_i32,
+        parse_attributes,
+        take(14usize),
+        vector,
+        take(4usize),
+    ))(i)?;
+
+    let mut attributes = Attributes::new();
+
+    attributes.push("classname", classname);
+
+    for (key, value) in kvs {
+        attributes.push(key, value);
+    }
+
+    if spawnflags != 0 {
+        attributes.insert("spawnflags", spawnflags.to_string());
+    }
+
+    if brushes.is_empty() && !attributes.contains_key("origin") {
+        attributes.push("origin", origin_string(origin));
+    }
+
+    Ok((
+        i,
+        Entity {
+            attributes,
+            brushes: (!brushes.is_empty()).then_some(brushes),
+            editor: EditorInfo {
+                visgroups: visgroup_id(visgroup),
+                group: None,
+                color: Some(color),
+            },
+            ..Default::default()
+        },
+    ))
+}
+
+fn parse_group(i: &[u8]) -> IResult<'_, Object> {
+    let (i, (_, color, object_count)) = tuple((le_i32, color, le_i32))(i)?;
+
+    map(
+        count(parse_object, object_count.max(0) as usize),
+        move |objects| Object::Group { color, objects },
+    )(i)
+}
+
+fn parse_object(i: &[u8]) -> IResult<'_, Object> {
+    let (i, kind) = nstring(i)?;
+
+    match kind.as_str() {
+        "CMapSolid" => map(parse_solid, Object::Solid)(i),
+        "CMapEntity" => map(parse_entity, Object::Entity)(i),
+        "CMapGroup" => parse_group(i),
+        _ => fail(i),
+    }
+}
+
+fn parse_path(i: &[u8]) -> IResult<'_, Vec<Entity>> {
+    let (i, (name, classname, path_type, node_count)) =
+        tuple((fixed_string(128), fixed_string(128), le_i32, le_i32))(i)?;
+
+    let (i, nodes) = count(
+        map(
+            tuple((vector, le_i32, fixed_string(128), parse_attributes)),
+            |(position, id, name_override, attributes)| PathNode {
+                id,
+                position,
+                name_override,
+                angles: DVec3::ZERO,
+                message: String::new(),
+                attributes,
+            },
+        ),
+        node_count.max(0) as usize,
+    )(i)?;
+
+    Ok((i, expand_path(&name, &classname, path_type, nodes)))
+}
+
+fn parse_docinfo(i: &[u8]) -> IResult<'_, (Option<usize>, Vec<Camera>)> {
+    let (i, (_, _, active_camera, camera_count)) =
+        tuple((tag(DOCINFO), le_f32, le_i32, le_i32))(i)?;
+
+    map(
+        count(
+            map(tuple((vector, vector)), |(eye, look)| Camera { eye, look }),
+            camera_count.max(0) as usize,
+        ),
+        move |cameras| (usize::try_from(active_camera).ok(), cameras),
+    )(i)
+}
+
+/// Puts the objects into the map with the groups they are in.
+fn flatten_objects(
+    objects: Vec<Object>,
+    parent: Option<i32>,
+    world: &mut Entity,
+    entities: &mut Vec<Entity>,
+    groups: &mut Vec<Group>,
+) {
+    for object in objects {
+        match object {
+            Object::Solid(mut brush) => {
+                brush.editor.group = parent;
+                world.brushes.get_or_insert_with(Vec::new).push(brush);
+            }
+            Object::Entity(mut entity) => {
+                entity.editor.group = parent;
+                entities.push(entity);
+            }
+            Object::Group { color, objects } => {
+                // RMF groups have no id, the editor numbers them when loading
+                let id = groups.len() as i32 + 1;
+
+                groups.push(Group { id, parent, color });
+                flatten_objects(objects, Some(id), world, entities, groups);
+            }
+        }
+    }
+}
+
+fn parse_rmf(i: &[u8]) -> IResult<'_, Map> {
+    let (i, (version, _, visgroup_count)) = tuple((le_f32, tag("RMF"), le_i32))(i)?;
+
+    if (version - RMF_VERSION).abs() > 1e-3 {
+        return fail(i);
+    }
+
+    let (i, visgroups) = count(parse_visgroup, visgroup_count.max(0) as usize)(i)?;
+
+    let (i, (world_kind, _, _, object_count)) = tuple((nstring, le_i32, color, le_i32))(i)?;
+
+    if world_kind != "CMapWorld" {
+        return fail(i);
+    }
+
+    let (i, objects) = count(parse_object, object_count.max(0) as usize)(i)?;
+
+    let (i, (classname, _, spawnflags, kvs, _, path_count)) = tuple((
+        nstring,
+        take(4usize),
+        le_i32,
+        parse_attributes,
+        take(12usize),
+        le_i32,
+    ))(i)?;
+
+    let (i, paths) = count(parse_path, path_count.max(0) as usize)(i)?;
+    let (i, docinfo) = opt(parse_docinfo)(i)?;
+
+    let mut world = Entity::default();
+
+    world.attributes.push("classname", classname);
+
+    for (key, value) in kvs {
+        world.attributes.push(key, value);
+    }
+
+    if spawnflags != 0 {
+        world
+            .attributes
+            .insert("spawnflags", spawnflags.to_string());
+    }
+
+    let mut entities = vec![];
+    let mut groups = vec![];
+
+    flatten_objects(objects, None, &mut world, &mut entities, &mut groups);
+
+    entities.insert(0, world);
+    entities.extend(paths.into_iter().flatten());
+
+    let (active_camera, cameras) = docinfo.unwrap_or_default();
+
+    Ok((
+        i,
+        Map {
+            format: MapFormat::Valve220,
+            entities,
+            editor: EditorData {
+                visgroups,
+                groups,
+                cameras,
+                active_camera,
+                cordon: None,
+            },
+            ..Default::default()
+        },
+    ))
+}
+
+pub(crate) fn read_rmf(i: &[u8]) -> eyre::Result<Map> {
+    match parse_rmf(i) {
+        Ok((_, res)) => Ok(res),
+        Err(err) => Err(eyre!("Cannot parse RMF: {}", err.to_string())),
+    }
+}
+
+fn append_nstring(writer: &mut ByteWriter, s: &str) -> eyre::Result<()> {
+    let bytes = string_to_bytes(s);
+
+    if bytes.len() > 254 {
+        return Err(eyre!("\"{}\" is longer than 254 characters", s));
+    }
+
+    writer.append_u8(bytes.len() as u8 + 1);
+    writer.append_u8_slice(&bytes);
+    writer.append_u8(0);
+
+    Ok(())
+}
+
+fn append_editor_info(writer: &mut ByteWriter, editor: &EditorInfo) {
+    writer.append_i32(editor.visgroups.first().copied().unwrap_or(0));
+    writer.append_u8_slice(&editor.color.unwrap_or(DEFAULT_COLOR));
+}
+
+fn append_attributes<'a>(
+    writer: &mut ByteWriter,
+    attributes: impl Iterator<Item = (&'a String, &'a String)>,
+) -> eyre::Result<()> {
+    let attributes = attributes.collect::<Vec<_>>();
+
+    writer.append_i32(attributes.len() as i32);
+
+    for (key, value) in attributes {
+        append_nstring(writer, key)?;
+        append_nstring(writer, value)?;
+    }
+
+    Ok(())
+}
+
+fn append_solid(writer: &mut ByteWriter, brush: &Brush) -> eyre::Result<()> {
+    append_nstring(writer, "CMapSolid")?;
+    append_editor_info(writer, &brush.editor);
+    writer.append_u8_slice(&[0; 4]);
+
+    let windings = brush.windings();
+
+    writer.append_i32(brush.planes.len() as i32);
+
+    for (plane, winding) in brush.planes.iter().zip(windings) {
+        append_fixed_string(writer, &plane.texture_name, 256)?;
+        writer.append_f32(0.);
+        append_vector(writer, plane.u.truncate());
+        writer.append_f32(plane.u.w as f32);
+        append_vector(writer, plane.v.truncate());
+        writer.append_f32(plane.v.w as f32);
+        writer.append_f32(plane.rotation as f32);
+        writer.append_f32(plane.u_scale as f32);
+        writer.append_f32(plane.v_scale as f32);
+        writer.append_u8_slice(&[0; 16]);
+
+        writer.append_i32(winding.len() as i32);
+        winding
+            .into_iter()
+            .for_each(|vertex| append_vector(writer, vertex));
+
+        append_vector(writer, plane.p1);
+        append_vector(writer, plane.p2);
+        append_vector(writer, plane.p3);
+    }
+
+    Ok(())
+}
+
+fn append_entity(writer: &mut ByteWriter, entity: &Entity) -> eyre::Result<()> {
+    let brushes = entity.brushes.as_deref().unwrap_or_default();
+    let is_point_entity = brushes.is_empty();
+
+    append_nstring(writer, "CMapEntity")?;
+    append_editor_info(writer, &entity.editor);
+
+    writer.append_i32(brushes.len() as i32);
+
+    for brush in brushes {
+        append_solid(writer, brush)?;
+    }
+
+    append_nstring(
+        writer,
+        entity
+            .attributes
+            .get("classname")
+            .map(String::as_str)
+            .unwrap_or_default(),
+    )?;
+    writer.append_u8_slice(&[0; 4]);
+    writer.append_i32(spawnflags(entity));
+
+    append_attributes(
+        writer,
+        entity
+            .attributes
+            .iter()
+            .filter(|(key, _)| !is_special_key(key, is_point_entity)),
+    )?;
+
+    writer.append_u8_slice(&[0; 14]);
+
+    let origin = entity
+        .attributes
+        .get("origin")
+        .and_then(|s| parse_vector_string(s))
+        .unwrap_or_default();
+
+    append_vector(writer, origin);
+    writer.append_u8_slice(&[0; 4]);
+
+    Ok(())
+}
+
+/// Objects directly inside the group, or outside of every group with `None`.
+fn append_objects(
+    writer: &mut ByteWriter,
+    map: &Map,
+    world: Option<&Entity>,
+    group: Option<i32>,
+) -> eyre::Result<()> {
+    // objects in groups that do not exist go outside
+    let group_of = |editor: &EditorInfo| {
+        editor
+            .group
+            .filter(|id| map.editor.groups.iter().any(|group| group.id == *id))
+    };
+
+    let brushes = world
+        .and_then(|world| world.brushes.as_ref())
+        .into_iter()
+        .flatten()
+        .filter(|brush| group_of(&brush.editor) == group)
+        .collect::<Vec<_>>();
+    let entities = map
+        .entities
+        .iter()
+        .filter(|entity| !world.is_some_and(|world| std::ptr::eq(*entity, world)))
+        .filter(|entity| group_of(&entity.editor) == group)
+        .collect::<Vec<_>>();
+    let groups = map
+        .editor
+        .groups
+        .iter()
+        .filter(|child| child.parent == group && Some(child.id) != group)
+        .collect::<Vec<_>>();
+
+    writer.append_i32((brushes.len() + entities.len() + groups.len()) as i32);
+
+    for brush in brushes {
+        append_solid(writer, brush)?;
+    }
+
+    for entity in entities {
+        append_entity(writer, entity)?;
+    }
+
+    for child in groups {
+        append_nstring(writer, "CMapGroup")?;
+        writer.append_i32(0);
+        writer.append_u8_slice(&child.color);
+        append_objects(writer, map, world, Some(child.id))?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_rmf(map: &Map) -> eyre::Result<Vec<u8>> {
+    let mut writer = ByteWriter::new();
+
+    writer.append_f32(RMF_VERSION);
+    writer.append_string("RMF");
+
+    writer.append_i32(map.editor.visgroups.len() as i32);
+
+    for visgroup in &map.editor.visgroups {
+        append_fixed_string(&mut writer, &visgroup.name, 128)?;
+        writer.append_u8_slice(&visgroup.color);
+        writer.append_u8(0);
+        writer.append_i32(visgroup.id);
+        writer.append_u8(visgroup.visible as u8);
+        writer.append_u8_slice(&[0; 3]);
+    }
+
+    let world = map.entities.iter().find(|entity| {
+        entity
+            .attributes
+            .get("classname")
+            .is_some_and(|classname| classname == "worldspawn")
+    });
+
+    append_nstring(&mut writer, "CMapWorld")?;
+    writer.append_i32(0);
+    writer.append_u8_slice(&DEFAULT_COLOR);
+    append_objects(&mut writer, map, world, None)?;
+
+    let world_attributes = world.map(|world| &world.attributes);
+
+    append_nstring(&mut writer, "worldspawn")?;
+    writer.append_u8_slice(&[0; 4]);
+    writer.append_i32(world.map(spawnflags).unwrap_or(0));
+    append_attributes(
+        &mut writer,
+        world_attributes
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| !is_special_key(key, false)),
+    )?;
+    writer.append_u8_slice(&[0; 12]);
+
+    // paths are already entities
+    writer.append_i32(0);
+
+    writer.append_u8_slice(DOCINFO);
+    writer.append_f32(CAMERA_VERSION);
+    writer.append_i32(map.editor.active_camera.map_or(-1, |idx| idx as i32));
+    writer.append_i32(map.editor.cameras.len() as i32);
+
+    for camera in &map.editor.cameras {
+        append_vector(&mut writer, camera.eye);
+        append_vector(&mut writer, camera.look);
+    }
+
+    Ok(writer.data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn path_to_entities() {
+        let mut writer = ByteWriter::new();
+
+        append_fixed_string(&mut writer, "track", 128).unwrap();
+        append_fixed_string(&mut writer, "path_corner", 128).unwrap();
+        writer.append_i32(1);
+        writer.append_i32(2);
+
+        for (id, name) in [(1, ""), (2, "end")] {
+            append_vector(&mut writer, DVec3::splat(id as f64));
+            writer.append_i32(id);
+            append_fixed_string(&mut writer, name, 128).unwrap();
+            writer.append_i32(1);
+            append_nstring(&mut writer, "speed").unwrap();
+            append_nstring(&mut writer, "100").unwrap();
+        }
+
+        let (rest, entities) = parse_path(&writer.data).unwrap();
+
+        assert!(rest.is_empty());
+        assert_eq!(
+            entities[0].attributes.pairs(),
+            Attributes::from([
+                ("classname", "path_corner"),
+                ("targetname", "track01"),
+                ("target", "end"),
+                ("speed", "100"),
+                ("origin", "1 1 1"),
+            ])
+            .pairs()
+        );
+        // circular
+        assert_eq!(entities[1].attributes.get("target").unwrap(), "track01");
+    }
+
+    #[test]
+    fn solid_without_vertices() {
+        let map = Map::from_file("./test/cube.rmf").unwrap();
+        let brush = &map.entities[0].brushes.as_ref().unwrap()[0];
+
+        let mut writer = ByteWriter::new();
+
+        append_nstring(&mut writer, "CMapSolid").unwrap();
+        writer.append_i32(0);
+        writer.append_u8_slice(&DEFAULT_COLOR);
+        writer.append_u8_slice(&[0; 4]);
+        writer.append_i32(brush.planes.len() as i32);
+
+        for (idx, plane) in brush.planes.iter().enumerate() {
+            append_fixed_string(&mut writer, &plane.texture_name, 256).unwrap();
+            writer.append_u8_slice(&[0; 64]);
+            writer.append_i32(0);
+
+            // one plane faces the wrong way
+            let points = if idx == 0 {
+                [plane.p3, plane.p2, plane.p1]
+            } else {
+                [plane.p1, plane.p2, plane.p3]
+            };
+
+            points
+                .into_iter()
+                .for_each(|point| append_vector(&mut writer, point));
+        }
+
+        let (rest, Object::Solid(solid)) = parse_object(&writer.data).unwrap() else {
+            unreachable!()
+        };
+
+        assert!(rest.is_empty());
+        assert_eq!(solid.bounds(), brush.bounds());
+    }
+}
@@ -0,0 +1,216 @@
+//! Texture projection of the standard (idTech2) format.
+//!
+//! A standard face takes its texture axes from the world axis closest to the plane normal, then rotates
+//! and scales them. Valve 220 writes the axes out so every standard face can be turned into Valve 220
+//! without losing anything. The other way around only works when the axes are still the rotated world axes.
+use glam::{DVec3, DVec4, Vec4Swizzles};
+
+use crate::BrushPlane;
+
+/// Same as `baseaxis` in the compilers: plane normal, S axis, T axis.
+const BASE_AXES: [[DVec3; 3]; 6] = [
+    // floor
+    [DVec3::Z, DVec3::X, DVec3::NEG_Y],
+    // ceiling
+    [DVec3::NEG_Z, DVec3::X, DVec3::NEG_Y],
+    // west wall
+    [DVec3::X, DVec3::Y, DVec3::NEG_Z],
+    // east wall
+    [DVec3::NEG_X, DVec3::Y, DVec3::NEG_Z],
+    // south wall
+    [DVec3::Y, DVec3::X, DVec3::NEG_Z],
+    // north wall
+    [DVec3::NEG_Y, DVec3::X, DVec3::NEG_Z],
+];
+
+const EPSILON: f64 = 1e-6;
+
+/// `xoff yoff rot xscale yscale` of a standard face.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StandardTexture {
+    pub x_offset: f64,
+    pub y_offset: f64,
+    /// In degrees.
+    pub rotation: f64,
+    pub x_scale: f64,
+    pub y_scale: f64,
+}
+
+/// Same as `TextureAxisFromPlane`.
+fn texture_axes(normal: DVec3) -> (DVec3, DVec3) {
+    let mut best = 0.;
+    let mut best_axis = 0;
+
+    for (idx, axes) in BASE_AXES.iter().enumerate() {
+        let dot = normal.dot(axes[0]);
+
+        if dot > best {
+            best = dot;
+            best_axis = idx;
+        }
+    }
+
+    (BASE_AXES[best_axis][1], BASE_AXES[best_axis][2])
+}
+
+// index of the only non zero component of a base axis
+fn axis_index(axis: DVec3) -> usize {
+    if axis.x != 0. {
+        0
+    } else if axis.y != 0. {
+        1
+    } else {
+        2
+    }
+}
+
+// right angles are exact in the compilers
+fn sin_cos(rotation: f64) -> (f64, f64) {
+    if rotation == 0. {
+        (0., 1.)
+    } else if rotation == 90. {
+        (1., 0.)
+    } else if rotation == 180. {
+        (0., -1.)
+    } else if rotation == 270. {
+        (-1., 0.)
+    } else {
+        rotation.to_radians().sin_cos()
+    }
+}
+
+/// Texture axes of a standard face before scaling.
+fn standard_axes(normal: DVec3, rotation: f64) -> (DVec3, DVec3) {
+    let (s_axis, t_axis) = texture_axes(normal);
+    let (sv, tv) = (axis_index(s_axis), axis_index(t_axis));
+    let (sin, cos) = sin_cos(rotation);
+
+    let rotate = |mut axis: DVec3| {
+        let ns = cos * axis[sv] - sin * axis[tv];
+        let nt = sin * axis[sv] + cos * axis[tv];
+
+        axis[sv] = ns;
+        axis[tv] = nt;
+
+        axis
+    };
+
+    (rotate(s_axis), rotate(t_axis))
+}
+
+// scale of an axis that is meant to be a unit vector
+fn axis_length(axis: DVec3) -> f64 {
+    let length = axis.length();
+
+    if (length - 1.).abs() < EPSILON {
+        1.
+    } else {
+        length
+    }
+}
+
+impl BrushPlane {
+    /// Normal of the plane going out of the brush, same as `PlaneFromPoints`.
+    pub fn normal(&self) -> DVec3 {
+        (self.p1 - self.p2)
+            .cross(self.p3 - self.p2)
+            .normalize_or_zero()
+    }
+
+    /// Plane with the texture axes of a standard face.
+    pub fn from_standard(
+        p1: DVec3,
+        p2: DVec3,
+        p3: DVec3,
+        texture_name: impl Into<String>,
+        texture: StandardTexture,
+    ) -> Self {
+        let mut res = Self {
+            p1,
+            p2,
+            p3,
+            texture_name: texture_name.into(),
+            u: DVec4::ZERO,
+            v: DVec4::ZERO,
+            rotation: texture.rotation,
+            u_scale: texture.x_scale,
+            v_scale: texture.y_scale,
+        };
+
+        let (u, v) = standard_axes(res.normal(), texture.rotation);
+
+        res.u = u.extend(texture.x_offset);
+        res.v = v.extend(texture.y_offset);
+
+        res
+    }
+
+    /// Texture of the plane in the standard format.
+    ///
+    /// Returns `None` when the texture axes are not the rotated world axes, like with skewed textures,
+    /// because the texture would look different.
+    pub fn to_standard(&self) -> Option<StandardTexture> {
+        let (texture, exact) = self.standard_fit();
+
+        exact.then_some(texture)
+    }
+
+    /// Closest standard texture and whether it looks the same.
+    pub(crate) fn standard_fit(&self) -> (StandardTexture, bool) {
+        let normal = self.normal();
+        let (u, v) = (self.u.xyz(), self.v.xyz());
+        let (u_length, v_length) = (axis_length(u), axis_length(v));
+
+        if u_length < EPSILON || v_length < EPSILON {
+            let texture = StandardTexture {
+                x_offset: self.u.w,
+                y_offset: self.v.w,
+                rotation: 0.,
+                x_scale: self.u_scale,
+                y_scale: self.v_scale,
+            };
+
+            return (texture, false);
+        }
+
+        let (u, v) = (u / u_length, v / v_length);
+
+        // keep the written rotation if it is still right so 450 stays 450
+        let rotation = if standard_axes(normal, self.rotation)
+            .0
+            .abs_diff_eq(u, EPSILON)
+        {
+            self.rotation
+        } else {
+            let (s_axis, t_axis) = texture_axes(normal);
+            let (sv, tv) = (axis_index(s_axis), axis_index(t_axis));
+
+            // the base S axis is either 1 or -1 on its component
+            let cos = u[sv] * s_axis[sv];
+            let sin = u[tv] * s_axis[sv];
+
+            (sin.atan2(cos).to_degrees().rem_euclid(360.) / EPSILON).round() * EPSILON
+        };
+
+        let (s_axis, t_axis) = standard_axes(normal, rotation);
+
+        // flipped T axis is a negative scale
+        let y_sign = if v.abs_diff_eq(-t_axis, EPSILON) {
+            -1.
+        } else {
+            1.
+        };
+
+        let exact = s_axis.abs_diff_eq(u, EPSILON) && (t_axis * y_sign).abs_diff_eq(v, EPSILON);
+
+        let texture = StandardTexture {
+            x_offset: self.u.w,
+            y_offset: self.v.w,
+            rotation,
+            x_scale: self.u_scale / u_length,
+            y_scale: y_sign * self.v_scale / v_length,
+        };
+
+        (texture, exact)
+    }
+}
@@ -0,0 +1,276 @@
+//! Moving, rotating, scaling and mirroring geometry and entities.
+//!
+//! Textures stay locked to the faces: the Valve 220 axes and offsets are changed along with the planes
+//! so every point of a face keeps its texture coordinates.
+//!
+//! Entity `origin` is moved with the geometry. `angles`, `angle` and the `pitch` of lights are turned with it.
+//! Point entities without any of those get `angles` when the transform turns them.
+//! Origin brushes are brushes like any other so brush entities turn around the moved origin.
+use glam::{DAffine3, DMat3, DQuat, DVec3, EulerRot, Vec4Swizzles};
+
+use crate::{Brush, Entity, Map};
+
+const EPSILON: f64 = 1e-6;
+
+// transforms leave floating point noise on values that were round
+fn snap(value: f64) -> f64 {
+    let rounded = value.round();
+
+    if (value - rounded).abs() < EPSILON {
+        // no -0
+        rounded + 0.
+    } else {
+        value
+    }
+}
+
+fn snap_vector(v: DVec3) -> DVec3 {
+    DVec3::new(snap(v.x), snap(v.y), snap(v.z))
+}
+
+fn format_vector(v: DVec3) -> String {
+    let v = snap_vector(v);
+
+    format!("{} {} {}", v.x, v.y, v.z)
+}
+
+fn parse_vector(s: &str) -> Option<DVec3> {
+    let values = s
+        .split_whitespace()
+        .map(|n| n.parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    (values.len() == 3).then(|| DVec3::from_slice(&values))
+}
+
+/// Rotation of `pitch yaw roll` in degrees, same as `AngleVectors`.
+///
+/// The columns are forward, left and up. Positive pitch looks down.
+fn angles_to_matrix(angles: DVec3) -> DMat3 {
+    DMat3::from_euler(
+        EulerRot::ZYX,
+        angles.y.to_radians(),
+        angles.x.to_radians(),
+        angles.z.to_radians(),
+    )
+}
+
+fn matrix_to_angles(matrix: DMat3) -> DVec3 {
+    let forward = matrix.x_axis;
+    let left = matrix.y_axis;
+    let up = matrix.z_axis;
+
+    let pitch = (-forward.z).atan2(forward.truncate().length());
+
+    // looking straight up or down leaves roll and yaw on the same axis
+    let (yaw, roll) = if forward.truncate().length() < EPSILON {
+        ((-left.x).atan2(left.y), 0.)
+    } else {
+        (forward.y.atan2(forward.x), left.z.atan2(up.z))
+    };
+
+    let angles = snap_vector(DVec3::new(
+        pitch.to_degrees(),
+        yaw.to_degrees(),
+        roll.to_degrees(),
+    ));
+
+    DVec3::new(angles.x, angles.y.rem_euclid(360.), angles.z)
+}
+
+/// Transform applied to an orientation, mirrors keep forward and up and flip left.
+fn transform_rotation(transform: &DAffine3, rotation: DMat3) -> DMat3 {
+    let forward = transform.matrix3 * rotation.x_axis;
+    let up = transform.matrix3 * rotation.z_axis;
+
+    let forward = forward.normalize_or_zero();
+    let up = (up - forward * up.dot(forward)).normalize_or_zero();
+
+    if forward == DVec3::ZERO || up == DVec3::ZERO {
+        return rotation;
+    }
+
+    DMat3::from_cols(forward, up.cross(forward), up)
+}
+
+fn around(center: DVec3, transform: DAffine3) -> DAffine3 {
+    DAffine3::from_translation(center) * transform * DAffine3::from_translation(-center)
+}
+
+/// Reflection across the plane through the origin with this normal.
+fn reflection(normal: DVec3) -> DMat3 {
+    let n = normal.normalize();
+
+    DMat3::IDENTITY - 2. * DMat3::from_cols(n * n.x, n * n.y, n * n.z)
+}
+
+/// Geometry that can be moved around with texture lock.
+pub trait Transform {
+    /// Applies any affine transform, the other functions go through this one.
+    fn transform(&mut self, transform: &DAffine3) -> &mut Self;
+
+    fn translate(&mut self, offset: DVec3) -> &mut Self {
+        self.transform(&DAffine3::from_translation(offset))
+    }
+
+    /// Rotates by `pitch yaw roll` in degrees around the center, like entity `angles`.
+    fn rotate(&mut self, angles: DVec3, center: DVec3) -> &mut Self {
+        self.transform(&around(
+            center,
+            DAffine3::from_mat3(angles_to_matrix(angles)),
+        ))
+    }
+
+    /// Rotates around an axis by degrees.
+    fn rotate_axis(&mut self, axis: DVec3, degrees: f64, center: DVec3) -> &mut Self {
+        self.transform(&around(
+            center,
+            DAffine3::from_quat(DQuat::from_axis_angle(
+                axis.normalize(),
+                degrees.to_radians(),
+            )),
+        ))
+    }
+
+    /// Scales every axis away from the center. Negative values mirror.
+    fn scale(&mut self, scale: DVec3, center: DVec3) -> &mut Self {
+        self.transform(&around(center, DAffine3::from_scale(scale)))
+    }
+
+    /// Mirrors across the plane going through the center with this normal.
+    fn mirror(&mut self, normal: DVec3, center: DVec3) -> &mut Self {
+        self.transform(&around(center, DAffine3::from_mat3(reflection(normal))))
+    }
+}
+
+impl Transform for Brush {
+    fn transform(&mut self, transform: &DAffine3) -> &mut Self {
+        // texture axes go the other way around so the texture moves with the points
+        let axis_transform = transform.matrix3.inverse().transpose();
+        let is_mirror = transform.matrix3.determinant() < 0.;
+
+        for plane in &mut self.planes {
+            plane.p1 = snap_vector(transform.transform_point3(plane.p1));
+            plane.p2 = snap_vector(transform.transform_point3(plane.p2));
+            plane.p3 = snap_vector(transform.transform_point3(plane.p3));
+
+            // mirrored points wind the other way which would turn the plane inside out
+            if is_mirror {
+                std::mem::swap(&mut plane.p1, &mut plane.p3);
+            }
+
+            for (axis, scale) in [
+                (&mut plane.u, &mut plane.u_scale),
+                (&mut plane.v, &mut plane.v_scale),
+            ] {
+                let new_axis = axis_transform * axis.xyz();
+                let length = new_axis.length();
+
+                if length < EPSILON {
+                    continue;
+                }
+
+                let offset = axis.w - transform.translation.dot(new_axis) / *scale;
+
+                // keep the axis unit length, the stretch goes into the scale
+                *axis = snap_vector(new_axis / length).extend(snap(offset));
+                *scale = snap(*scale / length);
+            }
+        }
+
+        self
+    }
+}
+
+impl Transform for Entity {
+    fn transform(&mut self, transform: &DAffine3) -> &mut Self {
+        if let Some(brushes) = &mut self.brushes {
+            for brush in brushes {
+                brush.transform(transform);
+            }
+        }
+
+        let attributes = &mut self.attributes;
+
+        if let Some(origin) = attributes.get("origin").and_then(|s| parse_vector(s)) {
+            attributes.insert("origin", format_vector(transform.transform_point3(origin)));
+        }
+
+        let matrix = transform.matrix3;
+        let is_turned = [
+            matrix.x_axis.y,
+            matrix.x_axis.z,
+            matrix.y_axis.x,
+            matrix.y_axis.z,
+            matrix.z_axis.x,
+            matrix.z_axis.y,
+        ]
+        .iter()
+        .any(|value| value.abs() > EPSILON)
+            || matrix.determinant() < 0.;
+
+        let has_angles = attributes.contains_key("angles");
+        let has_angle = attributes.contains_key("angle");
+        let has_pitch = attributes.contains_key("pitch");
+        let is_point_entity = self.brushes.is_none()
+            && attributes
+                .get("classname")
+                .is_some_and(|classname| classname != "worldspawn");
+
+        if !is_turned || !(has_angles || has_angle || has_pitch || is_point_entity) {
+            return self;
+        }
+
+        let mut angles = attributes
+            .get("angles")
+            .and_then(|s| parse_vector(s))
+            .unwrap_or_default();
+
+        if let Some(angle) = attributes.get("angle").and_then(|s| s.parse::<f64>().ok()) {
+            angles = match angle {
+                -1. => DVec3::new(-90., 0., 0.),
+                -2. => DVec3::new(90., 0., 0.),
+                yaw => DVec3::new(angles.x, yaw, angles.z),
+            };
+        }
+
+        // lights have their own pitch where positive looks up
+        if let Some(pitch) = attributes.get("pitch").and_then(|s| s.parse::<f64>().ok()) {
+            angles.x = -pitch;
+        }
+
+        let angles = matrix_to_angles(transform_rotation(transform, angles_to_matrix(angles)));
+
+        if has_pitch {
+            attributes.insert("pitch", format!("{}", snap(-angles.x)));
+        }
+
+        if has_angle && !has_angles {
+            if angles.z == 0. && angles.x.abs() == 90. {
+                attributes.insert("angle", if angles.x < 0. { "-1" } else { "-2" });
+                return self;
+            }
+
+            if angles.z == 0. && (angles.x == 0. || has_pitch) {
+                attributes.insert("angle", format!("{}", angles.y));
+                return self;
+            }
+
+            attributes.remove("angle");
+        }
+
+        attributes.insert("angles", format_vector(angles));
+
+        self
+    }
+}
+
+impl Transform for Map {
+    fn transform(&mut self, transform: &DAffine3) -> &mut Self {
+        for entity in &mut self.entities {
+            entity.transform(transform);
+        }
+
+        self
+    }
+}
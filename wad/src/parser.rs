@@ -96,20 +96,28 @@ pub fn parse_miptex(i: &[u8]) -> IResult<MipTex> {
     }
 
     // offset relatively from where we start with the struct
-    let (_, miptex0) =
-        count(le_u8, (width * height) as usize)(&struct_start[(mip_offsets[0] as usize)..])?;
+    // the offsets and dimensions come from the file so nothing is trusted
+    let Some(mip0_len) = width.checked_mul(height) else {
+        return context("miptex dimensions are too big", fail)(i);
+    };
+
+    let mip_start = |level: usize| {
+        struct_start
+            .get(mip_offsets[level] as usize..)
+            .ok_or(nom::Err::Error(nom::error::Error::new(
+                i,
+                nom::error::ErrorKind::Eof,
+            )))
+    };
 
-    let (_, miptex1) =
-        count(le_u8, (width * height / 4) as usize)(&struct_start[(mip_offsets[1] as usize)..])?;
+    let (_, miptex0) = count(le_u8, mip0_len as usize)(mip_start(0)?)?;
 
-    let (_, miptex2) = count(le_u8, (width * height / 4 / 4) as usize)(
-        &struct_start[(mip_offsets[2] as usize)..],
-    )?;
+    let (_, miptex1) = count(le_u8, (mip0_len / 4) as usize)(mip_start(1)?)?;
+
+    let (_, miptex2) = count(le_u8, (mip0_len / 4 / 4) as usize)(mip_start(2)?)?;
 
     // we get the palette start from the end of 4th miptex
-    let (palette_start, miptex3) = count(le_u8, (width * height / 4 / 4 / 4) as usize)(
-        &struct_start[(mip_offsets[3] as usize)..],
-    )?;
+    let (palette_start, miptex3) = count(le_u8, (mip0_len / 4 / 4 / 4) as usize)(mip_start(3)?)?;
 
     // colors_used is always 256
     let (palette_start, colors_used) = le_i16(palette_start)?;
@@ -209,7 +217,9 @@ pub fn parse_wad(i: &[u8]) -> IResult<Wad> {
         return context("wad file is not WAD3", fail)(&[]);
     }
 
-    let dir_start = &i[(header.dir_offset as usize)..];
+    let Some(dir_start) = i.get(header.dir_offset as usize..) else {
+        return context("directory offset is outside of the file", fail)(b"");
+    };
     let (_, directory_entries) = count(parse_directory_entry, header.num_dirs as usize)(dir_start)?;
 
     if directory_entries.len() != header.num_dirs as usize {
@@ -248,7 +258,12 @@ pub fn parse_wad(i: &[u8]) -> IResult<Wad> {
         .enumerate()
         .map(|(entry_index, directory_entry)| {
             // the actual WAD data is from the beginning of the file, not the beginning of the directory entry
-            let file_entry_start = &file_start[directory_entry.entry_offset as usize..];
+            let Some(file_entry_start) = file_start.get(directory_entry.entry_offset as usize..)
+            else {
+                return Err(eyre!(
+                    "entry offset is outside of the file (entry {entry_index})"
+                ));
+            };
 
             match directory_entry.file_type {
                 0x42 => {